@@ -0,0 +1,335 @@
+//! Typed handles on a single parameter of an [ActiveDevice], for code that works with the
+//! same few parameters over and over, such as a UI control bound to a camera's gain.
+//! # Example
+//! ```no_run
+//! use indi::client::{active_parameter::{NumberParameter, SwitchParameter}, device::ActiveDevice};
+//! async fn active_parameter_usage_example(camera: ActiveDevice) {
+//!     let gain = NumberParameter::new(camera.clone(), "CCD_CONTROLS", "Gain");
+//!     gain.set(120.0).await.expect("Setting gain");
+//!
+//!     let frame_type = SwitchParameter::new(camera, "CCD_FRAME_TYPE");
+//!     frame_type.select("FRAME_DARK").await.expect("Taking darks");
+//! }
+//! ```
+
+use std::{collections::HashMap, time::Duration};
+
+use super::{
+    device::{ActiveDevice, BlobPolicy, ChangeValues},
+    notify::{self, wait_fn},
+    ChangeError,
+};
+use crate::{serialization::Command, Blob, BlobEnable, FromValue, Switch, SwitchState};
+
+/// Reads the value named `value` of the named parameter of `device`.
+async fn value_of<T: FromValue>(
+    device: &ActiveDevice,
+    parameter: &str,
+    value: &str,
+) -> Result<T, ChangeError<Command>> {
+    let param = device.get_parameter(parameter).await?;
+    let param = param.lock().await;
+    Ok(param.value_of(value)?)
+}
+
+/// One number of a number parameter, such as `CCD_TEMPERATURE`'s `CCD_TEMPERATURE_VALUE`.
+#[derive(Clone)]
+pub struct NumberParameter {
+    device: ActiveDevice,
+    parameter: String,
+    value: String,
+}
+
+impl NumberParameter {
+    pub fn new(
+        device: ActiveDevice,
+        parameter: impl Into<String>,
+        value: impl Into<String>,
+    ) -> NumberParameter {
+        NumberParameter {
+            device,
+            parameter: parameter.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Returns the number's current value.
+    pub async fn get(&self) -> Result<f64, ChangeError<Command>> {
+        value_of(&self.device, &self.parameter, &self.value).await
+    }
+
+    /// Changes the number, waiting for the server to confirm it, see [ActiveDevice::change].
+    pub async fn set(&self, value: f64) -> Result<(), ChangeError<Command>> {
+        self.device
+            .change(
+                &self.parameter,
+                ChangeValues::Numbers(vec![(self.value.clone(), value)]),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// A switch parameter with a `OneOfMany` rule, such as `CCD_FRAME_TYPE`, where choosing one
+/// switch turns off the others.
+#[derive(Clone)]
+pub struct SwitchParameter {
+    device: ActiveDevice,
+    parameter: String,
+}
+
+impl SwitchParameter {
+    pub fn new(device: ActiveDevice, parameter: impl Into<String>) -> SwitchParameter {
+        SwitchParameter {
+            device,
+            parameter: parameter.into(),
+        }
+    }
+
+    /// Returns the name of the switch that is on, if any.
+    pub async fn selected(&self) -> Result<Option<String>, ChangeError<Command>> {
+        let param = self.device.get_parameter(&self.parameter).await?;
+        let param = param.lock().await;
+        Ok(param
+            .get_values::<HashMap<String, Switch>>()?
+            .iter()
+            .find(|(_, switch)| switch.value == SwitchState::On)
+            .map(|(name, _)| name.clone()))
+    }
+
+    /// Turns the switch named `name` on, waiting for the server to confirm it.
+    pub async fn select(&self, name: &str) -> Result<(), ChangeError<Command>> {
+        self.device
+            .change(
+                &self.parameter,
+                ChangeValues::Switches(vec![(name.to_string(), SwitchState::On)]),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// One text of a text parameter, such as `FITS_HEADER`'s `FITS_OBJECT`.
+#[derive(Clone)]
+pub struct TextParameter {
+    device: ActiveDevice,
+    parameter: String,
+    value: String,
+}
+
+impl TextParameter {
+    pub fn new(
+        device: ActiveDevice,
+        parameter: impl Into<String>,
+        value: impl Into<String>,
+    ) -> TextParameter {
+        TextParameter {
+            device,
+            parameter: parameter.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Returns the text's current value.
+    pub async fn get(&self) -> Result<String, ChangeError<Command>> {
+        value_of(&self.device, &self.parameter, &self.value).await
+    }
+
+    /// Changes the text, waiting for the server to confirm it.
+    pub async fn set(&self, value: &str) -> Result<(), ChangeError<Command>> {
+        self.device
+            .change(
+                &self.parameter,
+                ChangeValues::Texts(vec![(self.value.clone(), value.to_string())]),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// A blob parameter, such as a camera's `CCD1`.
+#[derive(Clone)]
+pub struct BlobParameter {
+    device: ActiveDevice,
+    parameter: String,
+}
+
+impl BlobParameter {
+    pub fn new(device: ActiveDevice, parameter: impl Into<String>) -> BlobParameter {
+        BlobParameter {
+            device,
+            parameter: parameter.into(),
+        }
+    }
+
+    /// Sets whether the server sends this parameter's blobs, see [ActiveDevice::set_blob_policy].
+    pub async fn enable(&self, enabled: BlobEnable) -> Result<(), notify::Error<Command>> {
+        self.device
+            .set_blob_policy(BlobPolicy::parameter(self.parameter.clone(), enabled))
+            .await
+    }
+
+    /// Waits up to `timeout` for the parameter to be sent with data, returning its blobs by
+    ///  name.  Blobs need to be [enabled](BlobParameter::enable) first.
+    pub async fn next(
+        &self,
+        timeout: Duration,
+    ) -> Result<HashMap<String, Blob>, ChangeError<Command>> {
+        let param = self.device.get_parameter(&self.parameter).await?;
+        let blobs = wait_fn::<_, ChangeError<Command>, _, _>(param.changes(), timeout, |param| {
+            let blobs = param.get_values::<HashMap<String, Blob>>()?;
+            if blobs.values().any(|blob| blob.value.is_some()) {
+                Ok(notify::Status::Complete(blobs.clone()))
+            } else {
+                Ok(notify::Status::Pending)
+            }
+        })
+        .await?;
+        Ok(blobs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::{device::Device, Notify},
+        serialization::CommandIter,
+    };
+    use std::sync::Arc;
+    use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+    async fn camera(
+        defs: &str,
+    ) -> (
+        ActiveDevice,
+        Arc<Notify<Device>>,
+        UnboundedReceiver<Command>,
+    ) {
+        let mut device = Device::new(String::from("CCD Simulator"));
+        for command in CommandIter::new(std::io::Cursor::new(defs)) {
+            device.update(command.unwrap()).await.unwrap();
+        }
+        let device = Arc::new(Notify::new(device));
+        let (sender, sent) = mpsc::unbounded_channel();
+        let camera = ActiveDevice::new(String::from("CCD Simulator"), device.clone(), Some(sender));
+        (camera, device, sent)
+    }
+
+    async fn update(device: &Notify<Device>, xml: &str) {
+        for command in CommandIter::new(std::io::Cursor::new(xml)) {
+            device.lock().await.update(command.unwrap()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_number_and_text() {
+        let (camera, device, mut sent) = camera(
+            r#"<defNumberVector device="CCD Simulator" name="CCD_CONTROLS" state="Idle" perm="rw" timeout="5">
+    <defNumber name="Gain" format="%3.0f" min="0" max="400" step="1">100</defNumber>
+</defNumberVector>
+<defTextVector device="CCD Simulator" name="FITS_HEADER" state="Idle" perm="rw" timeout="5">
+    <defText name="FITS_OBJECT"></defText>
+</defTextVector>
+"#,
+        )
+        .await;
+        let gain = NumberParameter::new(camera.clone(), "CCD_CONTROLS", "Gain");
+        let object = TextParameter::new(camera, "FITS_HEADER", "FITS_OBJECT");
+        assert_eq!(gain.get().await.unwrap(), 100.0);
+        assert_eq!(object.get().await.unwrap(), "");
+
+        let set = tokio::spawn(async move { gain.set(120.0).await.map(|_| gain) });
+        assert!(matches!(
+            sent.recv().await.unwrap(),
+            Command::NewNumberVector(_)
+        ));
+        update(
+            &device,
+            r#"<setNumberVector device="CCD Simulator" name="CCD_CONTROLS" state="Ok">
+    <oneNumber name="Gain">120</oneNumber>
+</setNumberVector>
+"#,
+        )
+        .await;
+        let gain = set.await.unwrap().unwrap();
+        assert_eq!(gain.get().await.unwrap(), 120.0);
+
+        let set = tokio::spawn(async move { object.set("M31").await.map(|_| object) });
+        assert!(matches!(
+            sent.recv().await.unwrap(),
+            Command::NewTextVector(_)
+        ));
+        update(
+            &device,
+            r#"<setTextVector device="CCD Simulator" name="FITS_HEADER" state="Ok">
+    <oneText name="FITS_OBJECT">M31</oneText>
+</setTextVector>
+"#,
+        )
+        .await;
+        let object = set.await.unwrap().unwrap();
+        assert_eq!(object.get().await.unwrap(), "M31");
+    }
+
+    #[tokio::test]
+    async fn test_switch_and_blob() {
+        let (camera, device, mut sent) = camera(
+            r#"<defSwitchVector device="CCD Simulator" name="CCD_FRAME_TYPE" state="Idle" perm="rw" rule="OneOfMany" timeout="5">
+    <defSwitch name="FRAME_LIGHT">On</defSwitch>
+    <defSwitch name="FRAME_DARK">Off</defSwitch>
+</defSwitchVector>
+<defBLOBVector device="CCD Simulator" name="CCD1" state="Idle" perm="ro">
+    <defBLOB name="CCD1"/>
+</defBLOBVector>
+"#,
+        )
+        .await;
+        let frame_type = SwitchParameter::new(camera.clone(), "CCD_FRAME_TYPE");
+        let image = BlobParameter::new(camera, "CCD1");
+        assert_eq!(
+            frame_type.selected().await.unwrap().as_deref(),
+            Some("FRAME_LIGHT")
+        );
+
+        let select =
+            tokio::spawn(async move { frame_type.select("FRAME_DARK").await.map(|_| frame_type) });
+        match sent.recv().await.unwrap() {
+            // The rule turns the other switch off.
+            Command::NewSwitchVector(c) => assert_eq!(c.switches.len(), 2),
+            c => panic!("Unexpected command: {:?}", c),
+        }
+        update(
+            &device,
+            r#"<setSwitchVector device="CCD Simulator" name="CCD_FRAME_TYPE" state="Ok">
+    <oneSwitch name="FRAME_LIGHT">Off</oneSwitch>
+    <oneSwitch name="FRAME_DARK">On</oneSwitch>
+</setSwitchVector>
+"#,
+        )
+        .await;
+        let frame_type = select.await.unwrap().unwrap();
+        assert_eq!(
+            frame_type.selected().await.unwrap().as_deref(),
+            Some("FRAME_DARK")
+        );
+
+        image.enable(BlobEnable::Only).await.unwrap();
+        assert!(matches!(sent.recv().await.unwrap(), Command::EnableBlob(_)));
+        let next = tokio::spawn(async move { image.next(Duration::from_secs(5)).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        update(
+            &device,
+            r#"<setBLOBVector device="CCD Simulator" name="CCD1" state="Ok">
+    <oneBLOB name="CCD1" size="5" format=".fits">aGVsbG8=</oneBLOB>
+</setBLOBVector>
+"#,
+        )
+        .await;
+        let blobs = next.await.unwrap().unwrap();
+        assert_eq!(
+            blobs["CCD1"].value.as_deref().map(|v| v.as_slice()),
+            Some(&b"hello"[..])
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use tokio::sync::mpsc;
+
+use super::{AsyncClientConnection, AsyncReadConnection, AsyncWriteConnection};
+use crate::serialization::{Command, DeError};
+
+/// A connection to several INDI servers at once, such as cameras on one computer and the
+///  mount on another, so that a single [Client](super::Client) sees all of their devices.
+///
+/// Commands for a device go to the server that defined it.  Commands for devices no server
+///  has defined yet, or for no device, like the initial `getProperties`, go to every server.
+///  If more than one server has a device with the same name, the last to define it gets
+///  its commands.
+///
+/// # Example
+/// ```no_run
+/// use indi::client::aggregate::Aggregate;
+/// use tokio::net::TcpStream;
+/// async {
+///     let connections = vec![
+///         TcpStream::connect("cameras.local:7624").await.expect("Connecting to cameras"),
+///         TcpStream::connect("mount.local:7624").await.expect("Connecting to mount"),
+///     ];
+///     let client = indi::client::new(Aggregate::new(connections), None, None)
+///         .expect("Initializing connection");
+/// };
+/// ```
+pub struct Aggregate<T> {
+    connections: Vec<T>,
+}
+
+impl<T: AsyncClientConnection> Aggregate<T> {
+    pub fn new(connections: Vec<T>) -> Aggregate<T> {
+        Aggregate { connections }
+    }
+}
+
+/// Which server to send commands to.
+#[derive(Default)]
+struct Routes {
+    devices: HashMap<String, usize>,
+    // The server each unanswered `pingRequest` came from.
+    pings: HashMap<String, usize>,
+}
+
+impl Routes {
+    /// Returns the server `command` should go to, or `None` for all of them.
+    fn route(&mut self, command: &Command) -> Option<usize> {
+        match command {
+            Command::PingReply(reply) => self.pings.remove(&reply.uid),
+            command => command
+                .device_name()
+                .and_then(|device| self.devices.get(device))
+                .copied(),
+        }
+    }
+}
+
+impl<T: AsyncClientConnection> AsyncClientConnection for Aggregate<T> {
+    type Reader = AggregateReader;
+    type Writer = AggregateWriter<T::Writer>;
+
+    fn to_indi(self) -> (Self::Writer, Self::Reader) {
+        let routes: Arc<Mutex<Routes>> = Default::default();
+        let (sender, commands) = mpsc::unbounded_channel();
+        let mut writers = vec![];
+        for (i, connection) in self.connections.into_iter().enumerate() {
+            let (writer, mut reader) = connection.to_indi();
+            writers.push(writer);
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                while let Some(command) = reader.read().await {
+                    if sender.send((i, command)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        (
+            AggregateWriter {
+                writers,
+                routes: routes.clone(),
+            },
+            AggregateReader { commands, routes },
+        )
+    }
+}
+
+pub struct AggregateReader {
+    commands: mpsc::UnboundedReceiver<(usize, Result<Command, DeError>)>,
+    routes: Arc<Mutex<Routes>>,
+}
+
+impl AsyncReadConnection for AggregateReader {
+    async fn read(&mut self) -> Option<Result<Command, DeError>> {
+        let (i, command) = self.commands.recv().await?;
+        if let Ok(command) = &command {
+            let mut routes = self.routes.lock().unwrap_or_else(PoisonError::into_inner);
+            match command {
+                Command::PingRequest(request) => {
+                    routes.pings.insert(request.uid.clone(), i);
+                }
+                command => {
+                    if let Some(device) = command.device_name() {
+                        routes.devices.insert(device.clone(), i);
+                    }
+                }
+            }
+        }
+        Some(command)
+    }
+}
+
+pub struct AggregateWriter<W> {
+    writers: Vec<W>,
+    routes: Arc<Mutex<Routes>>,
+}
+
+impl<W: AsyncWriteConnection + Send> AsyncWriteConnection for AggregateWriter<W> {
+    async fn write(&mut self, cmd: Command) -> Result<(), DeError> {
+        let route = self
+            .routes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .route(&cmd);
+        match route {
+            Some(i) => self.writers[i].write(cmd).await,
+            None => {
+                // Every server gets the command even if writing to one fails.
+                let mut result = Ok(());
+                for writer in &mut self.writers {
+                    if let Err(e) = writer.write(cmd.clone()).await {
+                        result = Err(e);
+                    }
+                }
+                result
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<(), DeError> {
+        let mut result = Ok(());
+        for writer in &mut self.writers {
+            if let Err(e) = writer.shutdown().await {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    const CONNECTION: &str = r#"<defSwitchVector device="{}" name="CONNECTION" state="Idle" perm="rw" rule="OneOfMany" timeout="1">
+<defSwitch name="CONNECT">Off</defSwitch>
+<defSwitch name="DISCONNECT">On</defSwitch>
+</defSwitchVector>
+"#;
+
+    #[tokio::test]
+    async fn test_routes_by_device() {
+        let mut connections = vec![];
+        let mut servers = vec![];
+        for device in ["Camera", "Mount"] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            connections.push(
+                TcpStream::connect(listener.local_addr().unwrap())
+                    .await
+                    .unwrap(),
+            );
+            let (mut server, _) = listener.accept().await.unwrap();
+            server
+                .write_all(CONNECTION.replace("{}", device).as_bytes())
+                .await
+                .unwrap();
+            servers.push(BufReader::new(server).lines());
+        }
+        let client = crate::client::new(Aggregate::new(connections), None, None).unwrap();
+
+        for server in &mut servers {
+            let line = server.next_line().await.unwrap().unwrap();
+            assert!(line.starts_with("<getProperties"));
+        }
+
+        client.get_device::<()>("Camera").await.unwrap();
+        let mount = client.get_device::<()>("Mount").await.unwrap();
+        // `change` waits for the server to apply it, which never happens here.
+        tokio::spawn(async move { mount.change("CONNECTION", vec![("CONNECT", true)]).await });
+        let line = servers[1].next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<newSwitchVector device=\"Mount\""));
+
+        drop(client);
+        assert_eq!(servers[0].next_line().await.unwrap(), None);
+    }
+}
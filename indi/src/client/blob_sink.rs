@@ -0,0 +1,211 @@
+use std::{
+    fs::{create_dir_all, remove_file, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::serialization::{OneBlob, SetBlobVector};
+
+/// Names the file a blob is written to, given the command it arrived in.
+pub type BlobNamer = Box<dyn FnMut(&SetBlobVector, &OneBlob) -> PathBuf + Send>;
+
+/// Routes blobs from one device's parameters to files, see
+/// [save_blobs](super::Client::save_blobs).
+pub(crate) struct BlobSink {
+    pub(crate) device: String,
+    pub(crate) parameter: Option<String>,
+    pub(crate) name: BlobNamer,
+    pub(crate) saved: UnboundedSender<Result<PathBuf, std::io::Error>>,
+}
+
+pub(crate) type BlobSinks = Arc<Mutex<Vec<BlobSink>>>;
+
+impl BlobSink {
+    fn matches(&self, set: &SetBlobVector) -> bool {
+        self.device == set.device && self.parameter.as_ref().is_none_or(|p| *p == set.name)
+    }
+}
+
+/// Writes the blobs in `set` to disk if a sink wants them, emptying them so they aren't held
+/// in memory once the parameter is updated.  Files are written on the blocking thread pool,
+/// and a blob that couldn't be written is left as it was.  Sinks whose receiver was dropped
+/// are removed.
+pub(crate) async fn save(sinks: &BlobSinks, set: &mut SetBlobVector) {
+    let (paths, saved) = {
+        let mut sinks = match sinks.lock() {
+            Ok(sinks) => sinks,
+            Err(_) => return,
+        };
+        sinks.retain(|sink| !sink.saved.is_closed());
+        let Some(sink) = sinks.iter_mut().find(|sink| sink.matches(set)) else {
+            return;
+        };
+        let paths: Vec<PathBuf> = set
+            .blobs
+            .iter()
+            .map(|blob| (sink.name)(set, blob))
+            .collect();
+        (paths, sink.saved.clone())
+    };
+
+    for (blob, path) in set.blobs.iter_mut().zip(paths) {
+        let data = std::mem::take(&mut blob.value.0);
+        let decompress = cfg!(feature = "zlib") && blob.is_compressed();
+        let written = tokio::task::spawn_blocking(move || {
+            let result = write_blob(&path, &data, decompress);
+            (data, result.map(|()| path))
+        })
+        .await;
+        let result = match written {
+            Ok((data, result)) => {
+                match &result {
+                    Ok(_) if decompress => blob.format = blob.uncompressed_format().to_string(),
+                    Ok(_) => {}
+                    Err(_) => blob.value.0 = data,
+                }
+                result
+            }
+            Err(e) => Err(std::io::Error::other(e)),
+        };
+        saved.send(result).ok();
+    }
+}
+
+/// Writes `data` to `path`, decompressing it a chunk at a time if `decompress` is set.  A
+/// partly written file is removed.
+fn write_blob(path: &Path, data: &[u8], decompress: bool) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)?;
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    let result = copy_blob(data, decompress, &mut file).and_then(|()| file.flush());
+    if result.is_err() {
+        drop(file);
+        remove_file(path).ok();
+    }
+    result
+}
+
+fn copy_blob(data: &[u8], decompress: bool, file: &mut impl Write) -> std::io::Result<()> {
+    #[cfg(feature = "zlib")]
+    if decompress {
+        std::io::copy(&mut flate2::read::ZlibDecoder::new(data), file)?;
+        return Ok(());
+    }
+    #[cfg(not(feature = "zlib"))]
+    let _ = decompress;
+    file.write_all(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use tokio::io::AsyncWriteExt;
+
+    use crate::client::{new, notify, wait_fn};
+
+    #[tokio::test]
+    async fn test_save_blobs() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = new(
+            tokio::net::TcpStream::connect(addr).await.unwrap(),
+            None,
+            None,
+        )
+        .expect("Making client");
+
+        let dir = std::env::temp_dir().join(format!("indi_save_blobs_{}", std::process::id()));
+        let name_dir = dir.clone();
+        let mut saved = client.save_blobs("CCD Simulator", Some("CCD1"), move |set, blob| {
+            name_dir.join(format!("{}_{}{}", set.name, blob.name, blob.format))
+        });
+
+        let (mut server, _) = listener.accept().await.unwrap();
+        server
+            .write_all(
+                br#"<defBLOBVector device="CCD Simulator" name="CCD1" label="Image Data" group="Image Info" state="Idle" perm="ro">
+    <defBLOB name="CCD1" label="Image"/>
+</defBLOBVector>
+<setBLOBVector device="CCD Simulator" name="CCD1" state="Ok" timeout="60" timestamp="2022-09-06T01:41:22">
+    <oneBLOB name="CCD1" size="5" format=".fits">
+aGVsbG8=
+    </oneBLOB>
+</setBLOBVector>
+"#,
+            )
+            .await
+            .unwrap();
+
+        let path = tokio::time::timeout(Duration::from_secs(5), saved.recv())
+            .await
+            .expect("Waiting for blob")
+            .unwrap()
+            .unwrap();
+        assert_eq!(path, dir.join("CCD1_CCD1.fits"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+
+        let camera = client.get_device::<()>("CCD Simulator").await.unwrap();
+        let param = camera.get_parameter("CCD1").await.unwrap();
+        // The parameter is updated after the blob is written.
+        let blob =
+            wait_fn::<_, (), _, _>(param.subscribe().await, Duration::from_secs(5), |param| {
+                let blob = &param.get_values::<HashMap<String, crate::Blob>>().unwrap()["CCD1"];
+                Ok(match blob.value {
+                    Some(_) => notify::Status::Complete(blob.clone()),
+                    None => notify::Status::Pending,
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(blob.format, Some(String::from(".fits")));
+        assert_eq!(blob.value.as_deref(), Some(&vec![]));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[cfg(feature = "zlib")]
+    #[tokio::test]
+    async fn test_save_blobs_decompress_error() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = new(
+            tokio::net::TcpStream::connect(addr).await.unwrap(),
+            None,
+            None,
+        )
+        .expect("Making client");
+
+        let dir = std::env::temp_dir().join(format!("indi_save_bad_blobs_{}", std::process::id()));
+        let name_dir = dir.clone();
+        let mut saved = client.save_blobs("CCD Simulator", None, move |set, blob| {
+            name_dir.join(format!("{}_{}.fits", set.name, blob.name))
+        });
+
+        let (mut server, _) = listener.accept().await.unwrap();
+        server
+            .write_all(
+                br#"<setBLOBVector device="CCD Simulator" name="CCD1" state="Ok" timeout="60" timestamp="2022-09-06T01:41:22">
+    <oneBLOB name="CCD1" size="5" format=".fits.z">
+aGVsbG8=
+    </oneBLOB>
+</setBLOBVector>
+"#,
+            )
+            .await
+            .unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), saved.recv())
+            .await
+            .expect("Waiting for blob")
+            .unwrap();
+        assert!(result.is_err());
+        assert!(!dir.join("CCD1_CCD1.fits").exists());
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,170 @@
+//! A synchronous client for scripts and command line tools that would rather not set up an
+//! async runtime of their own.  Each [Client] runs one internally, which its calls block on.
+//!
+//! Clients, and the devices they return, must not be used or dropped from async code.
+//! # Example
+//! ```no_run
+//! use std::time::Duration;
+//! use indi::{client::blocking::Client, BlobEnable};
+//!
+//! fn main() {
+//!     let client = Client::connect("localhost:7624", None, None).expect("Connecting to server");
+//!     let camera = client.get_device("CCD Simulator").expect("Getting camera");
+//!     camera.enable_blob(Some("CCD1"), BlobEnable::Also).expect("Enabling images");
+//!     camera
+//!         .change("CCD_EXPOSURE", vec![("CCD_EXPOSURE_VALUE", 1.0)])
+//!         .expect("Starting exposure");
+//!     let image = camera
+//!         .next_blob("CCD1", Duration::from_secs(30))
+//!         .expect("Getting image");
+//!     println!("{:?} bytes", image["CCD1"].value.as_ref().map(|data| data.len()));
+//! }
+//! ```
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use tokio::runtime::Runtime;
+
+use super::{active_parameter::BlobParameter, device::ActiveDevice, notify, ChangeError};
+use crate::{serialization::Command, BlobEnable, DeError, Parameter, ToCommand, TryEq};
+
+/// A blocking version of [Client](super::Client).
+pub struct Client {
+    // Dropped before the runtime its tasks run on.
+    client: super::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    /// Connects to the INDI server at `addr`, tracking the optional `device` and `parameter`
+    ///  like [new](super::new).
+    pub fn connect(
+        addr: impl tokio::net::ToSocketAddrs,
+        device: Option<&str>,
+        parameter: Option<&str>,
+    ) -> Result<Client, DeError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let client = runtime.block_on(async {
+            let connection = tokio::net::TcpStream::connect(addr).await?;
+            super::new(connection, device, parameter)
+        })?;
+        Ok(Client {
+            client,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Waits up to 1 second for the device named `name` to be defined, see
+    ///  [get_device](super::Client::get_device).
+    pub fn get_device(&self, name: &str) -> Result<Device, notify::Error<()>> {
+        let device = self.runtime.block_on(self.client.get_device(name))?;
+        Ok(Device {
+            device,
+            runtime: self.runtime.clone(),
+        })
+    }
+}
+
+/// A blocking version of [ActiveDevice].
+pub struct Device {
+    device: ActiveDevice,
+    runtime: Arc<Runtime>,
+}
+
+impl Device {
+    /// Returns a copy of the named parameter's current value, waiting up to 1 second for it
+    ///  to be defined.
+    pub fn get_parameter(&self, param_name: &str) -> Result<Parameter, ChangeError<Command>> {
+        self.runtime.block_on(async {
+            let param = self.device.get_parameter(param_name).await?;
+            let param = param.lock().await;
+            Ok(param.clone())
+        })
+    }
+
+    /// Changes the named parameter and waits for the server to confirm it, see
+    ///  [ActiveDevice::change].
+    pub fn change<P: Clone + TryEq<Parameter> + ToCommand<P> + 'static>(
+        &self,
+        param_name: &str,
+        values: P,
+    ) -> Result<Arc<Parameter>, ChangeError<Command>> {
+        self.runtime
+            .block_on(self.device.change(param_name, values))
+    }
+
+    /// Sets whether the server sends blobs, see [ActiveDevice::enable_blob].
+    pub fn enable_blob(
+        &self,
+        name: Option<&str>,
+        enabled: BlobEnable,
+    ) -> Result<(), ChangeError<Command>> {
+        Ok(self
+            .runtime
+            .block_on(self.device.enable_blob(name, enabled))?)
+    }
+
+    /// Waits up to `timeout` for the named blob parameter to be sent with data, returning its
+    ///  blobs by name.  Blobs need to be enabled with [enable_blob](Device::enable_blob) first.
+    pub fn next_blob(
+        &self,
+        param_name: &str,
+        timeout: Duration,
+    ) -> Result<HashMap<String, crate::Blob>, ChangeError<Command>> {
+        self.runtime
+            .block_on(BlobParameter::new(self.device.clone(), param_name).next(timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn test_blocking_change() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            let mut stream = stream;
+            assert!(lines.next().unwrap().unwrap().starts_with("<getProperties"));
+            stream
+                .write_all(
+                    br#"<defSwitchVector device="CCD Simulator" name="CONNECTION" state="Idle" perm="rw" rule="OneOfMany" timeout="5">
+<defSwitch name="CONNECT">Off</defSwitch>
+<defSwitch name="DISCONNECT">On</defSwitch>
+</defSwitchVector>
+"#,
+                )
+                .unwrap();
+            let line = lines.next().unwrap().unwrap();
+            assert!(line.starts_with("<newSwitchVector"), "{}", line);
+            stream
+                .write_all(
+                    br#"<setSwitchVector device="CCD Simulator" name="CONNECTION" state="Ok">
+<oneSwitch name="CONNECT">On</oneSwitch>
+<oneSwitch name="DISCONNECT">Off</oneSwitch>
+</setSwitchVector>
+"#,
+                )
+                .unwrap();
+        });
+
+        let client = Client::connect(addr, None, None).unwrap();
+        let camera = client.get_device("CCD Simulator").unwrap();
+        camera
+            .change("CONNECTION", vec![("CONNECT", true)])
+            .unwrap();
+        let connection = camera.get_parameter("CONNECTION").unwrap();
+        assert_eq!(*connection.get_state(), crate::PropertyState::Ok);
+        server.join().unwrap();
+    }
+}
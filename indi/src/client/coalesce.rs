@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use tokio::{sync::mpsc, time::Instant};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+/// Limits `updates` to at most one value every `period`, passing on the latest value and
+/// dropping the ones in between.  The first value is passed on immediately, and the last one
+/// is always delivered once the period it arrived in is over.
+///
+/// Meant for fast changing parameters, such as a `CCD_EXPOSURE` countdown or mount
+/// coordinates, whose subscribers only need to show the current value.  Errors from `updates`,
+/// such as a lagging subscription, are skipped since a newer value follows them.
+///
+/// # Arguments
+/// * `updates` - The stream to limit, usually from subscribing to a parameter.
+/// * `period` - The minimum time between values.
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use indi::client::{coalesce::coalesce, device::ActiveDevice};
+/// use tokio_stream::StreamExt;
+/// async fn coalesce_usage_example(mount: ActiveDevice) {
+///     let coords = mount
+///         .get_parameter("EQUATORIAL_EOD_COORD")
+///         .await
+///         .expect("Getting coordinates");
+///     // At most 2 updates per second
+///     let mut updates = coalesce(coords.subscribe().await, Duration::from_millis(500));
+///     while let Some(coords) = updates.next().await {
+///         dbg!(coords);
+///     }
+/// }
+/// ```
+pub fn coalesce<T, E, S>(mut updates: S, period: Duration) -> ReceiverStream<T>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+    S: Stream<Item = Result<T, E>> + Unpin + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut latest = None;
+        let mut next_send = Instant::now();
+        loop {
+            tokio::select! {
+                // Sending first so a steady stream of updates can't hold values back.
+                biased;
+                _ = sender.closed() => return,
+                _ = tokio::time::sleep_until(next_send), if latest.is_some() => {
+                    if let Some(value) = latest.take() {
+                        if sender.send(value).await.is_err() {
+                            return;
+                        }
+                    }
+                    next_send = Instant::now() + period;
+                }
+                update = updates.next() => match update {
+                    Some(Ok(value)) => latest = Some(value),
+                    Some(Err(_)) => {}
+                    None => break,
+                },
+            }
+        }
+        if let Some(value) = latest {
+            sender.send(value).await.ok();
+        }
+    });
+    ReceiverStream::new(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_stream::StreamExt;
+
+    use super::coalesce;
+
+    #[tokio::test]
+    async fn test_coalesce() {
+        let (sender, receiver) = tokio::sync::mpsc::channel::<Result<i32, ()>>(100);
+        let mut updates = coalesce(
+            tokio_stream::wrappers::ReceiverStream::new(receiver),
+            Duration::from_millis(200),
+        );
+
+        sender.send(Ok(1)).await.unwrap();
+        assert_eq!(updates.next().await, Some(1));
+
+        // Values sent within one period are coalesced into the latest.
+        let start = tokio::time::Instant::now();
+        for i in 2..=10 {
+            sender.send(Ok(i)).await.unwrap();
+        }
+        sender.send(Err(())).await.unwrap();
+        assert_eq!(updates.next().await, Some(10));
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        // The last value is delivered when the source ends.
+        sender.send(Ok(11)).await.unwrap();
+        drop(sender);
+        assert_eq!(updates.next().await, Some(11));
+        assert_eq!(updates.next().await, None);
+    }
+}
@@ -1,22 +1,305 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fs::{create_dir_all, File},
     io::Write,
     num::Wrapping,
     ops::Deref,
     path::Path,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, PoisonError},
     time::Duration,
 };
 
 use fitsio::{headers::ReadsKey, FitsFile};
 
-use super::ChangeError;
+use super::{ChangeError, Timeouts};
 use crate::*;
 use ::twinkle_client::{
     notify::{self, wait_fn, Notify},
     OnDropFutureExt,
 };
+use futures::{Stream, StreamExt};
+
+/// A serializable copy of a [Device], see [Device::snapshot].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DeviceSnapshot {
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+}
+
+impl From<DeviceSnapshot> for Device {
+    fn from(snapshot: DeviceSnapshot) -> Self {
+        let mut device = Device::new(snapshot.name);
+        for param in snapshot.parameters {
+            let name = param.get_name().clone();
+            if !device.groups.contains(param.get_group()) {
+                device.groups.push(param.get_group().clone());
+            }
+            device.names.push(name.clone());
+            device.parameters.insert(name, Arc::new(Notify::new(param)));
+        }
+        device
+    }
+}
+
+/// How many of a device's most recent messages [Device::messages] keeps.
+pub const MESSAGE_HISTORY: usize = 100;
+
+/// A log message sent by a device's driver.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceMessage {
+    /// When the driver sent the message, or when it was received if the driver didn't say.
+    pub timestamp: DateTime<Utc>,
+    pub message: String,
+}
+
+bitflags::bitflags! {
+    /// The kinds of device a driver implements, as given by the `DRIVER_INTERFACE` value of
+    /// its `DRIVER_INFO` parameter, see [Device::interfaces].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct DeviceInterfaces: u32 {
+        const TELESCOPE = 1 << 0;
+        const CCD = 1 << 1;
+        const GUIDER = 1 << 2;
+        const FOCUSER = 1 << 3;
+        const FILTER = 1 << 4;
+        const DOME = 1 << 5;
+        const GPS = 1 << 6;
+        const WEATHER = 1 << 7;
+        const AO = 1 << 8;
+        const DUSTCAP = 1 << 9;
+        const LIGHTBOX = 1 << 10;
+        const DETECTOR = 1 << 11;
+        const ROTATOR = 1 << 12;
+        const SPECTROGRAPH = 1 << 13;
+        const CORRELATOR = 1 << 14;
+        const AUX = 1 << 15;
+        const OUTPUT = 1 << 16;
+        const INPUT = 1 << 17;
+        const POWER = 1 << 18;
+    }
+}
+
+/// The values for one parameter in [ActiveDevice::change_many], made from the same kinds of
+/// values as [ActiveDevice::change] takes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeValues {
+    Switches(Vec<(String, SwitchState)>),
+    Numbers(Vec<(String, f64)>),
+    Texts(Vec<(String, String)>),
+}
+
+impl<I: Into<SwitchState> + Copy> From<Vec<(&str, I)>> for ChangeValues {
+    fn from(values: Vec<(&str, I)>) -> Self {
+        ChangeValues::Switches(
+            values
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+impl From<Vec<(&str, f64)>> for ChangeValues {
+    fn from(values: Vec<(&str, f64)>) -> Self {
+        ChangeValues::Numbers(
+            values
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+}
+
+impl From<Vec<(&str, &str)>> for ChangeValues {
+    fn from(values: Vec<(&str, &str)>) -> Self {
+        ChangeValues::Texts(
+            values
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+}
+
+impl TryEq<Parameter> for ChangeValues {
+    fn try_eq(&self, other: &Parameter) -> Result<bool, TypeError> {
+        match self {
+            ChangeValues::Switches(values) => values
+                .iter()
+                .map(|(name, value)| (name.as_str(), *value))
+                .collect::<Vec<_>>()
+                .try_eq(other),
+            ChangeValues::Numbers(values) => values
+                .iter()
+                .map(|(name, value)| (name.as_str(), *value))
+                .collect::<Vec<_>>()
+                .try_eq(other),
+            ChangeValues::Texts(values) => values
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect::<Vec<_>>()
+                .try_eq(other),
+        }
+    }
+}
+
+impl ToCommand<ChangeValues> for ChangeValues {
+    fn to_command(self, device_name: String, param_name: String) -> Command {
+        match self {
+            ChangeValues::Switches(values) => values
+                .iter()
+                .map(|(name, value)| (name.as_str(), *value))
+                .collect::<Vec<_>>()
+                .to_command(device_name, param_name),
+            ChangeValues::Numbers(values) => values
+                .iter()
+                .map(|(name, value)| (name.as_str(), *value))
+                .collect::<Vec<_>>()
+                .to_command(device_name, param_name),
+            ChangeValues::Texts(values) => values
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect::<Vec<_>>()
+                .to_command(device_name, param_name),
+        }
+    }
+}
+
+/// A kind of device a driver can follow through its `ACTIVE_DEVICES` parameter, snooping on
+/// that device's properties, see [ActiveDevice::snoop].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SnoopRole {
+    Telescope,
+    Rotator,
+    Focuser,
+    Filter,
+    SkyQuality,
+    Dome,
+    Gps,
+    Weather,
+}
+
+impl SnoopRole {
+    /// Returns the name of this role's text in `ACTIVE_DEVICES`.
+    pub fn value_name(self) -> &'static str {
+        match self {
+            SnoopRole::Telescope => "ACTIVE_TELESCOPE",
+            SnoopRole::Rotator => "ACTIVE_ROTATOR",
+            SnoopRole::Focuser => "ACTIVE_FOCUSER",
+            SnoopRole::Filter => "ACTIVE_FILTER",
+            SnoopRole::SkyQuality => "ACTIVE_SKYQUALITY",
+            SnoopRole::Dome => "ACTIVE_DOME",
+            SnoopRole::Gps => "ACTIVE_GPS",
+            SnoopRole::Weather => "ACTIVE_WEATHER",
+        }
+    }
+}
+
+/// Whether a device sends blobs, for one of its blob parameters or for all of them, see
+/// [ActiveDevice::set_blob_policy].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlobPolicy {
+    /// The blob parameter this applies to, or `None` for every blob parameter of the device.
+    pub parameter: Option<String>,
+    pub enabled: BlobEnable,
+}
+
+impl BlobPolicy {
+    /// Applies `enabled` to every blob parameter of the device.
+    pub fn device(enabled: BlobEnable) -> BlobPolicy {
+        BlobPolicy {
+            parameter: None,
+            enabled,
+        }
+    }
+
+    /// Applies `enabled` to the blob parameter named `parameter`.
+    pub fn parameter(parameter: impl Into<String>, enabled: BlobEnable) -> BlobPolicy {
+        BlobPolicy {
+            parameter: Some(parameter.into()),
+            enabled,
+        }
+    }
+}
+
+/// Returns the message a driver attached to a vector, which INDI treats the same as a
+/// `message` command.
+fn vector_message(command: &Command) -> Option<Message> {
+    let (device, timestamp, message) = match command {
+        Command::DefTextVector(DefTextVector {
+            device,
+            timestamp,
+            message,
+            ..
+        })
+        | Command::SetTextVector(SetTextVector {
+            device,
+            timestamp,
+            message,
+            ..
+        })
+        | Command::DefNumberVector(DefNumberVector {
+            device,
+            timestamp,
+            message,
+            ..
+        })
+        | Command::SetNumberVector(SetNumberVector {
+            device,
+            timestamp,
+            message,
+            ..
+        })
+        | Command::DefSwitchVector(DefSwitchVector {
+            device,
+            timestamp,
+            message,
+            ..
+        })
+        | Command::SetSwitchVector(SetSwitchVector {
+            device,
+            timestamp,
+            message,
+            ..
+        })
+        | Command::DefLightVector(DefLightVector {
+            device,
+            timestamp,
+            message,
+            ..
+        })
+        | Command::SetLightVector(SetLightVector {
+            device,
+            timestamp,
+            message,
+            ..
+        })
+        | Command::DefBlobVector(DefBlobVector {
+            device,
+            timestamp,
+            message,
+            ..
+        })
+        | Command::SetBlobVector(SetBlobVector {
+            device,
+            timestamp,
+            message,
+            ..
+        })
+        | Command::DelProperty(DelProperty {
+            device,
+            timestamp,
+            message,
+            ..
+        }) => (device, timestamp, message),
+        _ => return None,
+    };
+    Some(Message {
+        device: Some(device.clone()),
+        timestamp: *timestamp,
+        message: Some(message.clone()?),
+    })
+}
 
 /// Internal representation of a device.
 #[derive(Debug, Clone)]
@@ -25,6 +308,11 @@ pub struct Device {
     parameters: HashMap<String, Arc<Notify<Parameter>>>,
     names: Vec<String>,
     groups: Vec<Option<String>>,
+    blob_policy: HashMap<Option<String>, BlobEnable>,
+    messages: VecDeque<DeviceMessage>,
+    // Every message ever received, including those dropped from `messages`.
+    message_count: usize,
+    drop_stale_updates: bool,
 }
 
 impl Device {
@@ -35,16 +323,34 @@ impl Device {
             parameters: HashMap::new(),
             names: vec![],
             groups: vec![],
+            blob_policy: HashMap::new(),
+            messages: VecDeque::new(),
+            message_count: 0,
+            drop_stale_updates: false,
         }
     }
 
+    /// Sets whether `set*Vector`s with a timestamp older than their parameter's are dropped
+    ///  and returned as [ParamUpdateResult::StaleUpdate], for setups where a proxy can
+    ///  deliver updates out of order.  Off by default, since a driver whose clock jumps
+    ///  backward would otherwise have every later update dropped.
+    pub fn set_drop_stale_updates(&mut self, drop: bool) {
+        self.drop_stale_updates = drop;
+    }
+
     /// Updates the current device based on `command`.
     pub async fn update<'a>(
         &'a mut self,
         command: serialization::Command,
     ) -> Result<ParamUpdateResult<'a>, UpdateError> {
+        if let Some(message) = vector_message(&command) {
+            self.add_message(message);
+        }
         match command {
-            Command::Message(_) => Ok(ParamUpdateResult::NoUpdate),
+            Command::Message(command) => {
+                self.add_message(command);
+                Ok(ParamUpdateResult::NoUpdate)
+            }
             Command::GetProperties(_) => Ok(ParamUpdateResult::NoUpdate),
             Command::DefSwitchVector(command) => self.new_param(command).await,
             Command::SetSwitchVector(command) => self.update_param(command).await,
@@ -60,7 +366,12 @@ impl Device {
             Command::DefLightVector(command) => self.new_param(command).await,
             Command::SetLightVector(command) => self.update_param(command).await,
             Command::DelProperty(command) => self.delete_param(command.name),
-            Command::EnableBlob(_) => Ok(ParamUpdateResult::NoUpdate),
+            Command::EnableBlob(command) => {
+                self.set_blob_enabled(command.name, command.enabled);
+                Ok(ParamUpdateResult::NoUpdate)
+            }
+            Command::PingRequest(_) => Ok(ParamUpdateResult::NoUpdate),
+            Command::PingReply(_) => Ok(ParamUpdateResult::NoUpdate),
         }
     }
 
@@ -79,18 +390,167 @@ impl Device {
         return &self.parameters;
     }
 
+    /// Returns the kinds of device the driver says this is, or none until it defines
+    /// `DRIVER_INFO`.  Bits this version doesn't know of are kept.
+    pub async fn interfaces(&self) -> DeviceInterfaces {
+        let Some(info) = self.parameters.get("DRIVER_INFO") else {
+            return DeviceInterfaces::empty();
+        };
+        let interfaces = info.lock().await.value_of::<String>("DRIVER_INTERFACE");
+        interfaces
+            .ok()
+            .and_then(|bits| bits.trim().parse().ok())
+            .map(DeviceInterfaces::from_bits_retain)
+            .unwrap_or(DeviceInterfaces::empty())
+    }
+
+    /// Returns up to the last [MESSAGE_HISTORY] messages sent by the device's driver, oldest
+    /// first.
+    pub fn messages(&self) -> &VecDeque<DeviceMessage> {
+        &self.messages
+    }
+
+    fn add_message(&mut self, message: Message) {
+        let Some(text) = message.message else {
+            return;
+        };
+        if self.messages.len() == MESSAGE_HISTORY {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(DeviceMessage {
+            timestamp: message.timestamp.map(|t| t.0).unwrap_or_else(Utc::now),
+            message: text,
+        });
+        self.message_count += 1;
+    }
+
+    /// Returns the messages received after the first `seen`, as far as they are still kept.
+    fn messages_after(&self, seen: usize) -> impl Iterator<Item = &DeviceMessage> {
+        let new = self
+            .message_count
+            .saturating_sub(seen)
+            .min(self.messages.len());
+        self.messages.range(self.messages.len() - new..)
+    }
+
+    /// Records that `enabled` was sent for the blob parameter `name`, or for the whole device
+    /// when `name` is `None`.  A device wide setting replaces any per parameter ones, as it does
+    /// on the INDI server.
+    pub fn set_blob_enabled(&mut self, name: Option<String>, enabled: BlobEnable) {
+        if name.is_none() {
+            self.blob_policy.clear();
+        }
+        self.blob_policy.insert(name, enabled);
+    }
+
+    /// Returns the [BlobEnable] last sent for the parameter `name`, falling back to the
+    /// device wide setting and then to [BlobEnable::Never], INDI's default.
+    pub fn blob_enabled(&self, name: &str) -> BlobEnable {
+        self.blob_policy
+            .get(&Some(String::from(name)))
+            .or_else(|| self.blob_policy.get(&None))
+            .copied()
+            .unwrap_or(BlobEnable::Never)
+    }
+
+    /// Returns every [BlobEnable] sent for this device, keyed by parameter name, or `None` for
+    /// the device wide setting.
+    pub fn blob_policy(&self) -> &HashMap<Option<String>, BlobEnable> {
+        &self.blob_policy
+    }
+
+    /// Returns every [BlobPolicy] set for this device, the device wide one first.
+    pub fn blob_policies(&self) -> Vec<BlobPolicy> {
+        let mut policies: Vec<BlobPolicy> = self
+            .blob_policy
+            .iter()
+            .map(|(parameter, enabled)| BlobPolicy {
+                parameter: parameter.clone(),
+                enabled: *enabled,
+            })
+            .collect();
+        policies.sort_by(|a, b| a.parameter.cmp(&b.parameter));
+        policies
+    }
+
+    /// Returns the `enableBLOB` commands that recreate this device's blob settings, such as
+    /// after reconnecting to the INDI server.  The device wide setting comes first.
+    pub fn enable_blob_commands(&self) -> Vec<EnableBlob> {
+        self.blob_policies()
+            .into_iter()
+            .map(|policy| EnableBlob {
+                device: self.name.clone(),
+                name: policy.parameter,
+                enabled: policy.enabled,
+            })
+            .collect()
+    }
+
+    /// Returns a copy of the device's current parameters, in the order they were defined, that
+    /// can be serialized.  Each parameter is locked in turn while it is copied.
+    pub async fn snapshot(&self) -> DeviceSnapshot {
+        let mut parameters = Vec::with_capacity(self.parameters.len());
+        for name in &self.names {
+            if let Some(param) = self.parameters.get(name) {
+                parameters.push(param.lock().await.deref().clone());
+            }
+        }
+        DeviceSnapshot {
+            name: self.name.clone(),
+            parameters,
+        }
+    }
+
+    /// Sets the device's parameters to those in `snapshot`, see [Device::snapshot].  Existing
+    ///  parameters are updated in place so their subscribers see the restored values, and
+    ///  parameters missing from `snapshot` are removed.  Blob settings and messages are kept.
+    pub async fn restore(&mut self, snapshot: DeviceSnapshot) {
+        let restored = |name: &String| snapshot.parameters.iter().any(|p| p.get_name() == name);
+        self.names.retain(|name| restored(name));
+        self.parameters.retain(|name, _| restored(name));
+        self.groups
+            .retain(|group| snapshot.parameters.iter().any(|p| p.get_group() == group));
+
+        for param in snapshot.parameters {
+            let name = param.get_name().clone();
+            if !self.groups.contains(param.get_group()) {
+                self.groups.push(param.get_group().clone());
+            }
+            match self.parameters.get(&name) {
+                Some(existing) => {
+                    let mut existing = existing.lock().await;
+                    let gen = existing.gen() + Wrapping(1);
+                    *existing = param;
+                    *existing.gen_mut() = gen;
+                }
+                None => {
+                    self.names.push(name.clone());
+                    self.parameters.insert(name, Arc::new(Notify::new(param)));
+                }
+            }
+        }
+    }
+
     async fn new_param<'a, T: CommandtoParam + std::fmt::Debug>(
         &'a mut self,
         def: T,
     ) -> Result<ParamUpdateResult<'a>, UpdateError> {
         let name = def.get_name().clone();
 
-        self.names.push(name.clone());
+        if !self.names.contains(&name) {
+            self.names.push(name.clone());
+        }
         if let None = self.groups.iter().find(|&x| x == def.get_group()) {
             self.groups.push(def.get_group().clone());
         }
 
-        if !self.parameters.contains_key(&name) {
+        if let Some(param) = self.parameters.get(&name) {
+            // Redefined, such as after reconnecting, so the current values replace the old
+            //  ones without losing the parameter's subscribers.
+            let mut param = param.lock().await;
+            let gen = param.gen() + Wrapping(1);
+            *param = def.to_param(gen);
+        } else {
             let param = def.to_param(Wrapping(0));
             self.parameters
                 .insert(name.clone(), Arc::new(Notify::new(param)));
@@ -106,6 +566,18 @@ impl Device {
         match self.parameters.get_mut(&new_command.get_name().clone()) {
             Some(param) => {
                 let mut param = param.lock().await;
+                // Updates can arrive out of order through proxies, only newer ones are applied.
+                if let (true, Some(new), Some(current)) = (
+                    self.drop_stale_updates,
+                    new_command.get_timestamp(),
+                    param.get_timestamp(),
+                ) {
+                    if new.into_inner() < *current {
+                        return Ok(ParamUpdateResult::StaleUpdate(
+                            new_command.get_name().clone(),
+                        ));
+                    }
+                }
                 *param.gen_mut() += Wrapping(1);
                 new_command.update_param(&mut param)?;
                 Ok(ParamUpdateResult::ExistingParam(param))
@@ -140,6 +612,9 @@ pub enum ParamUpdateResult<'a> {
     DefParam(notify::NotifyMutexGuard<'a, Parameter>),
     ExistingParam(notify::NotifyMutexGuard<'a, Parameter>),
     DeletedParams(Vec<Arc<Notify<Parameter>>>),
+    /// A `set*Vector` for the named parameter timestamped before its current value, which was
+    /// dropped, see [Device::set_drop_stale_updates].
+    StaleUpdate(String),
 }
 
 /// A struct wrapping the raw bytes of a FitsImage.
@@ -236,12 +711,31 @@ impl FitsImage {
 #[derive(Debug)]
 pub enum SendError<T> {
     Disconnected,
-    SendError(tokio::sync::mpsc::error::SendError<T>),
+    /// Boxed since it holds the command that wasn't sent.
+    SendError(Box<tokio::sync::mpsc::error::SendError<T>>),
 }
 
 impl<T> From<tokio::sync::mpsc::error::SendError<T>> for SendError<T> {
     fn from(value: tokio::sync::mpsc::error::SendError<T>) -> Self {
-        SendError::SendError(value)
+        SendError::SendError(Box::new(value))
+    }
+}
+
+impl<T> std::fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Disconnected => write!(f, "disconnected from the INDI server"),
+            SendError::SendError(_) => write!(f, "connection to the INDI server closed"),
+        }
+    }
+}
+
+impl<T: std::fmt::Debug + 'static> std::error::Error for SendError<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SendError::Disconnected => None,
+            SendError::SendError(e) => Some(e.as_ref()),
+        }
     }
 }
 
@@ -251,6 +745,47 @@ pub struct ActiveDevice {
     name: String,
     device: Arc<Notify<Device>>,
     command_sender: Option<tokio::sync::mpsc::UnboundedSender<serialization::Command>>,
+    validate_numbers: bool,
+    revert_on_cancel: bool,
+    throttle: Option<Arc<Throttle>>,
+    timeouts: Timeouts,
+}
+
+/// Limits how often [ActiveDevice::change] sends each parameter, see
+///  [ActiveDevice::with_change_interval].
+struct Throttle {
+    interval: Duration,
+    params: Mutex<HashMap<String, ThrottledParam>>,
+}
+
+#[derive(Default)]
+struct ThrottledParam {
+    last_sent: Option<tokio::time::Instant>,
+    // Numbers the calls to `change`, only the latest waiting call gets sent.
+    latest: u64,
+}
+
+impl Throttle {
+    /// Waits until `param_name` may be sent again, returning false if another change to it
+    ///  came in while waiting.
+    async fn wait_turn(&self, param_name: &str) -> bool {
+        let (ticket, ready_at) = {
+            let mut params = self.params.lock().unwrap_or_else(PoisonError::into_inner);
+            let param = params.entry(param_name.to_string()).or_default();
+            param.latest += 1;
+            (param.latest, param.last_sent.map(|t| t + self.interval))
+        };
+        if let Some(ready_at) = ready_at {
+            tokio::time::sleep_until(ready_at).await;
+        }
+        let mut params = self.params.lock().unwrap_or_else(PoisonError::into_inner);
+        let param = params.entry(param_name.to_string()).or_default();
+        if param.latest != ticket {
+            return false;
+        }
+        param.last_sent = Some(tokio::time::Instant::now());
+        true
+    }
 }
 
 impl ActiveDevice {
@@ -263,9 +798,54 @@ impl ActiveDevice {
             name,
             device,
             command_sender,
+            validate_numbers: false,
+            revert_on_cancel: false,
+            throttle: None,
+            timeouts: Default::default(),
         }
     }
 
+    pub(crate) fn with_timeouts(mut self, timeouts: Timeouts) -> ActiveDevice {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Sets the shortest time between two [change](ActiveDevice::change)s sent for the same
+    ///  parameter, for values that change faster than the driver should be told about them,
+    ///  like a number tied to a slider.  A change made too soon waits its turn and is then
+    ///  sent only if no newer change to the parameter came in meanwhile, otherwise it returns
+    ///  [ChangeError::Superseded].  Clones of the returned device share the same limit.
+    pub fn with_change_interval(mut self, interval: Duration) -> ActiveDevice {
+        self.throttle = Some(Arc::new(Throttle {
+            interval,
+            params: Default::default(),
+        }));
+        self
+    }
+
+    /// Sets whether [change](ActiveDevice::change) checks numbers against their parameter's
+    /// `min`, `max` and `step` before sending them, see [NewNumberVector::validate].  Invalid
+    /// values return a [ChangeError::InvalidNumber] instead of being clamped or rejected by the
+    /// driver.  Off by default.
+    pub fn with_number_validation(mut self, validate: bool) -> ActiveDevice {
+        self.validate_numbers = validate;
+        self
+    }
+
+    /// Sets whether a [change](ActiveDevice::change) that is dropped after sending its
+    /// values, but before the server confirms them, sends the values the parameter had
+    /// before.  This includes the other changes of a [change_many](ActiveDevice::change_many)
+    /// when one of them fails.  Off by default, leaving the server to finish the change.
+    pub fn with_revert_on_cancel(mut self, revert: bool) -> ActiveDevice {
+        self.revert_on_cancel = revert;
+        self
+    }
+
+    /// Returns the name of the device.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     /// Returns the sender used to send commands
     ///  to the associated INDI server connection.
     pub fn send(&self, c: Command) -> Result<(), SendError<Command>> {
@@ -276,6 +856,46 @@ impl ActiveDevice {
     }
 }
 
+/// Sends `command` when dropped, see [ActiveDevice::with_revert_on_cancel].
+struct Revert<'a> {
+    device: &'a ActiveDevice,
+    command: Option<Command>,
+}
+
+impl Drop for Revert<'_> {
+    fn drop(&mut self) {
+        if let Some(command) = self.command.take() {
+            if let Err(e) = self.device.send(command) {
+                log::warn!("Reverting cancelled change: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Returns the command setting the values changed by `command` back to those `param` has.
+fn revert_command(command: &Command, param: &Parameter) -> Option<Command> {
+    let mut revert = command.clone();
+    match (&mut revert, param) {
+        (Command::NewNumberVector(c), Parameter::NumberVector(param)) => {
+            for number in &mut c.numbers {
+                number.value = param.values.get(&number.name)?.value.clone();
+            }
+        }
+        (Command::NewSwitchVector(c), Parameter::SwitchVector(param)) => {
+            for switch in &mut c.switches {
+                switch.value = param.values.get(&switch.name)?.value;
+            }
+        }
+        (Command::NewTextVector(c), Parameter::TextVector(param)) => {
+            for text in &mut c.texts {
+                text.value = param.values.get(&text.name)?.value.clone();
+            }
+        }
+        _ => return None,
+    }
+    Some(revert)
+}
+
 impl Deref for ActiveDevice {
     type Target = Arc<Notify<Device>>;
 
@@ -285,14 +905,15 @@ impl Deref for ActiveDevice {
 }
 
 impl ActiveDevice {
-    /// Returns the requested parameter, waiting up to 1 second for it to be defined
-    ///  by the connected INDI server.  
+    /// Returns the requested parameter, waiting up to 1 second, or the
+    ///  [parameter_timeout](super::ClientBuilder::parameter_timeout) of the client it came from,
+    ///  for it to be defined by the connected INDI server.
     pub async fn get_parameter(
         &self,
         param_name: &str,
     ) -> Result<Arc<Notify<Parameter>>, notify::Error<Command>> {
         let subs = self.device.subscribe().await;
-        wait_fn(subs, Duration::from_secs(1), |device| {
+        wait_fn(subs, self.timeouts.parameter, |device| {
             Ok(match device.get_parameters().get(param_name) {
                 Some(param) => notify::Status::Complete(param.clone()),
                 None => notify::Status::Pending,
@@ -305,7 +926,8 @@ impl ActiveDevice {
     /// If the INDI server's value does not match the `values` given, it will send the
     /// INDI server commands necessary to change values, and wait for the server
     /// to confirm the desired values.  This method will wait for the parameter's
-    /// `timeout` (or 60 seconds if not defined by the server) for the parameter value to match
+    /// `timeout` (or 60 seconds, see [change_timeout](super::ClientBuilder::change_timeout), if not
+    ///  defined by the server) for the parameter value to match
     ///  the desired value before timing out.
     /// # Arguments
     /// * `param_name` - The name of the parameter you wish to change.  If the parameter does not exist,
@@ -315,6 +937,12 @@ impl ActiveDevice {
     ///              See [crate::TryEq] and [crate::ToCommand] for type conversions.  If the given values do not
     ///              match the parameter types nothing be communicated to the server and aa [ChangeError::TypeMismatch]
     ///              will be returned.
+    /// # Cancellation
+    /// The returned future can be dropped at any point.  The values are either sent in a
+    ///  single command or not at all, and once sent the server carries on with the change, so
+    ///  the parameter ends up with the new values or whatever the driver does when it fails.
+    ///  Devices made [with_revert_on_cancel](ActiveDevice::with_revert_on_cancel) send the
+    ///  previous values instead when dropped while waiting for the server.
     /// # Example
     /// ```no_run
     /// use indi::*;
@@ -334,30 +962,214 @@ impl ActiveDevice {
         let device_name = self.name.clone();
 
         let param = self.get_parameter(param_name).await?;
+        if let Some(throttle) = &self.throttle {
+            if !throttle.wait_turn(param_name).await {
+                return Err(ChangeError::Superseded);
+            }
+        }
 
         let subscription = param.subscribe().await;
+        let mut revert = Revert {
+            device: self,
+            command: None,
+        };
         let timeout = {
             let param = param.lock().await;
 
             if !values.try_eq(&param)? {
-                let c = values
+                let mut c = values
                     .clone()
                     .to_command(device_name, String::from(param_name));
+                if let (Command::NewNumberVector(c), Parameter::NumberVector(param)) =
+                    (&mut c, &*param)
+                {
+                    if self.validate_numbers {
+                        c.validate(param)?;
+                    }
+                    c.apply_formats(param);
+                }
+                if let (Command::NewSwitchVector(c), Parameter::SwitchVector(param)) =
+                    (&mut c, &*param)
+                {
+                    c.apply_rule(param)?;
+                }
+                let previous = if self.revert_on_cancel {
+                    revert_command(&c, &param)
+                } else {
+                    None
+                };
                 self.send(c)?;
+                revert.command = previous;
+            }
+
+            match param.get_timeout() {
+                Some(timeout) => Duration::from_secs((*timeout).max(1).into()),
+                None => self.timeouts.change,
+            }
+        };
+
+        let res = wait_fn::<_, ChangeError<Command>, _, _>(subscription, timeout, move |next| {
+            if *next.get_state() == PropertyState::Alert {
+                return Err(ChangeError::PropertyError);
+            }
+            if values.try_eq(&next)? {
+                Ok(notify::Status::Complete(next.clone()))
+            } else {
+                Ok(notify::Status::Pending)
             }
+        })
+        .await;
+        // Finished, with or without an error, rather than cancelled.
+        revert.command = None;
+
+        Ok(res?)
+    }
+
+    /// Changes several parameters at once, waiting until the server confirms all of them or
+    ///  any of them fails, see [change](ActiveDevice::change).  Every parameter is checked to
+    ///  exist and to match its values' type before anything is sent.  Failures are returned as
+    ///  [ChangeError::ParameterFailed], naming the parameter, and stop the waits for the others.
+    /// # Example
+    /// ```no_run
+    /// use indi::client::device::ActiveDevice;
+    /// async fn change_many_usage_example(camera: ActiveDevice) {
+    ///     camera
+    ///         .change_many([
+    ///             ("CCD_CAPTURE_FORMAT", vec![("ASI_IMG_RAW16", true)].into()),
+    ///             ("CCD_CONTROLS", vec![("Offset", 10.0), ("Gain", 240.0)].into()),
+    ///             ("FITS_HEADER", vec![("FITS_OBJECT", "M31")].into()),
+    ///         ])
+    ///         .await
+    ///         .expect("Configuring camera");
+    /// }
+    /// ```
+    pub async fn change_many<'a>(
+        &self,
+        changes: impl IntoIterator<Item = (&'a str, ChangeValues)>,
+    ) -> Result<Vec<Arc<Parameter>>, ChangeError<Command>> {
+        let changes: Vec<(&str, ChangeValues)> = changes.into_iter().collect();
+        let failed = |name: &str, error: ChangeError<Command>| ChangeError::ParameterFailed {
+            name: name.to_string(),
+            error: Box::new(error),
+        };
+        for (name, values) in &changes {
+            let param = self
+                .get_parameter(name)
+                .await
+                .map_err(|e| failed(name, e.into()))?;
+            let param = param.lock().await;
+            values.try_eq(&param).map_err(|e| failed(name, e.into()))?;
+        }
+        futures::future::try_join_all(changes.into_iter().map(|(name, values)| async move {
+            self.change(name, values).await.map_err(|e| failed(name, e))
+        }))
+        .await
+    }
 
-            param.get_timeout().unwrap_or(60)
+    /// Tells the driver to follow the device named `device` in `role`, such as a camera
+    ///  snooping on the mount's coordinates for its FITS headers.  An empty name stops it.
+    /// # Example
+    /// ```no_run
+    /// use indi::client::device::{ActiveDevice, SnoopRole};
+    /// async fn snoop_usage_example(camera: ActiveDevice, mount: ActiveDevice) {
+    ///     camera
+    ///         .snoop(SnoopRole::Telescope, mount.name())
+    ///         .await
+    ///         .expect("Adding mount coordinates to images");
+    /// }
+    /// ```
+    pub async fn snoop(
+        &self,
+        role: SnoopRole,
+        device: &str,
+    ) -> Result<Arc<Parameter>, ChangeError<Command>> {
+        self.change(
+            "ACTIVE_DEVICES",
+            ChangeValues::Texts(vec![(role.value_name().to_string(), device.to_string())]),
+        )
+        .await
+    }
+
+    /// Returns the name of the device the driver follows in `role`, if any.
+    pub async fn snooped(&self, role: SnoopRole) -> Result<Option<String>, ChangeError<Command>> {
+        let param = self.get_parameter("ACTIVE_DEVICES").await?;
+        let device: String = param.lock().await.value_of(role.value_name())?;
+        Ok(Some(device).filter(|device| !device.is_empty()))
+    }
+
+    /// Waits up to `timeout` for the named parameter's state to be [PropertyState::Ok], such as
+    ///  after a slew or an exposure.  If the driver sets it to [PropertyState::Alert] instead
+    ///  this returns [ChangeError::Alert] straight away, with the last message the driver sent
+    ///  while waiting.
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use indi::client::device::ActiveDevice;
+    /// async fn wait_for_ok_usage_example(telescope: ActiveDevice) {
+    ///     telescope
+    ///         .wait_for_ok("EQUATORIAL_EOD_COORD", Duration::from_secs(120))
+    ///         .await
+    ///         .expect("Slewing");
+    /// }
+    /// ```
+    pub async fn wait_for_ok(
+        &self,
+        param_name: &str,
+        timeout: Duration,
+    ) -> Result<Arc<Parameter>, ChangeError<Command>> {
+        let param = self.get_parameter(param_name).await?;
+        let seen = self.device.lock().await.message_count;
+        let subscription = param.subscribe().await;
+        let res =
+            wait_fn::<_, ChangeError<Command>, _, _>(subscription, timeout, |next| {
+                match next.get_state() {
+                    PropertyState::Ok => Ok(notify::Status::Complete(next.clone())),
+                    PropertyState::Alert => Err(ChangeError::Alert(None)),
+                    _ => Ok(notify::Status::Pending),
+                }
+            })
+            .await;
+        match res {
+            Err(notify::Error::Abort(ChangeError::Alert(_))) => {
+                // The alert's message is recorded in the same update as the alert.
+                let device = self.device.lock().await;
+                let message = device
+                    .messages_after(seen)
+                    .last()
+                    .map(|m| m.message.clone());
+                Err(ChangeError::Alert(message))
+            }
+            res => Ok(res?),
         }
-        .max(1);
+    }
 
+    /// Waits for every light of the named light parameter to be in `state`, such as a weather
+    ///  station reporting that it is safe to open.  Gives up after the change timeout, 60
+    ///  seconds unless set by [ClientBuilder::change_timeout](super::ClientBuilder::change_timeout).
+    /// # Example
+    /// ```no_run
+    /// use indi::*;
+    /// use indi::client::device::ActiveDevice;
+    /// async fn wait_for_light_usage_example(weather: ActiveDevice) {
+    ///     weather
+    ///         .wait_for_light("WEATHER_STATUS", PropertyState::Ok)
+    ///         .await
+    ///         .expect("Waiting for safe weather");
+    /// }
+    /// ```
+    pub async fn wait_for_light(
+        &self,
+        param_name: &str,
+        state: PropertyState,
+    ) -> Result<Arc<Parameter>, ChangeError<Command>> {
+        let param = self.get_parameter(param_name).await?;
+        let subscription = param.subscribe().await;
         let res = wait_fn::<_, ChangeError<Command>, _, _>(
             subscription,
-            Duration::from_secs(timeout.into()),
+            self.timeouts.change,
             move |next| {
-                if *next.get_state() == PropertyState::Alert {
-                    return Err(ChangeError::PropertyError);
-                }
-                if values.try_eq(&next)? {
+                let lights = next.get_values::<HashMap<String, Light>>()?;
+                if lights.values().all(|light| light.value == state) {
                     Ok(notify::Status::Complete(next.clone()))
                 } else {
                     Ok(notify::Status::Pending)
@@ -365,13 +1177,13 @@ impl ActiveDevice {
             },
         )
         .await?;
-
         Ok(res)
     }
 
     /// Sends an `EnableBlob` command to the connected INDI server for the named parameter.  This must be called
     ///  on a Blob parameter with a value of either [crate::BlobEnable::Only] or [crate::BlobEnable::Also] for
-    ///  the server to send image data.
+    ///  the server to send image data.  The setting is sent again after reconnecting, and when the
+    ///  driver redefines the parameter.
     /// # Arguments
     /// * `param_name` - The optional name of the blob parameter to configure.  If `Some(param_name)` is provided
     ///                  and the parameter does not exist, this method will wait up to 1 second for it to exist
@@ -393,21 +1205,71 @@ impl ActiveDevice {
         name: Option<&str>,
         enabled: crate::BlobEnable,
     ) -> Result<(), notify::Error<Command>> {
+        self.set_blob_policy(BlobPolicy {
+            parameter: name.map(String::from),
+            enabled,
+        })
+        .await
+    }
+
+    /// Sends `policy` to the INDI server and remembers it, see [enable_blob](Self::enable_blob).
+    /// # Example
+    /// ```no_run
+    /// use indi::client::device::{ActiveDevice, BlobPolicy};
+    /// use indi::BlobEnable;
+    /// async fn set_blob_policy_usage_example(camera: ActiveDevice) {
+    ///     camera
+    ///         .set_blob_policy(BlobPolicy::parameter("CCD1", BlobEnable::Only))
+    ///         .await
+    ///         .expect("Enabling blobs");
+    /// }
+    /// ```
+    pub async fn set_blob_policy(&self, policy: BlobPolicy) -> Result<(), notify::Error<Command>> {
         // Wait for device and paramater to exist
-        if let Some(name) = name {
+        if let Some(name) = &policy.parameter {
             let _ = self.get_parameter(name).await?;
         }
-        let device_name = self.device.lock().await.name.clone();
+        let mut device = self.device.lock().await;
         if let Err(_) = self.send(Command::EnableBlob(EnableBlob {
-            device: device_name,
-            name: name.map(|x| String::from(x)),
-            enabled,
+            device: device.name.clone(),
+            name: policy.parameter.clone(),
+            enabled: policy.enabled,
         })) {
             return Err(notify::Error::Canceled);
         };
+        device.set_blob_enabled(policy.parameter, policy.enabled);
         Ok(())
     }
 
+    /// Returns a stream of the messages the device's driver sends from now on.  Messages
+    ///  already received are in [Device::messages].  A subscriber that falls too far behind
+    ///  skips the messages it missed.
+    /// # Example
+    /// ```no_run
+    /// use indi::client::device::ActiveDevice;
+    /// use futures::StreamExt;
+    /// async fn messages_usage_example(camera: ActiveDevice) {
+    ///     let mut messages = Box::pin(camera.messages().await);
+    ///     while let Some(message) = messages.next().await {
+    ///         println!("{}: {}", message.timestamp, message.message);
+    ///     }
+    /// }
+    /// ```
+    pub async fn messages(&self) -> impl Stream<Item = DeviceMessage> {
+        // Subscribe while locked so nothing sent in between is missed.
+        let device = self.device.lock().await;
+        let mut seen = device.message_count;
+        let changes = self.device.changes();
+        drop(device);
+        changes
+            .filter_map(|device| async move { device.ok() })
+            .flat_map(move |device| {
+                let new: Vec<DeviceMessage> = device.messages_after(seen).cloned().collect();
+                seen = device.message_count;
+                futures::stream::iter(new)
+            })
+    }
+
     /// Returns a [FitsImage] after exposing the camera device for `exposure` seconds.
     ///   Currently this method is only tested on the ZWO ASI 294MM Pro.  `enable_blob` must be
     ///   called against the `"CCD1"` parameter prior to the usage of this method.
@@ -517,11 +1379,7 @@ impl ActiveDevice {
                     dbg!("Exposure was canceled");
                     return Err(ChangeError::<Command>::Canceled);
                 }
-                let remaining_exposure: f64 = exposure_param
-                    .get_values::<HashMap<String, Number>>()?
-                    .get("CCD_EXPOSURE_VALUE")
-                    .and_then(|x| Some(x.value.into()))
-                    .expect("Missing CCD_EXPOSURE_VALUE from CCD_EXPOSURE parameter");
+                let remaining_exposure: f64 = exposure_param.value_of("CCD_EXPOSURE_VALUE")?;
                 // Image is done exposing, new image data should be sent very soon
                 if remaining_exposure == 0.0 {
                     *exposing.lock().unwrap() = false;
@@ -586,45 +1444,31 @@ impl ActiveDevice {
 
         let binning: f64 = {
             let ccd_binning_lock = ccd_binning.lock().await;
-            ccd_binning_lock
-                .get_values::<HashMap<String, Number>>()
-                .unwrap()
-                .get("HOR_BIN")
-                .unwrap()
-                .value
-                .into()
+            ccd_binning_lock.value_of("HOR_BIN").unwrap()
         };
         let pixel_scale = {
             let ccd_info_lock = ccd_info.lock().await;
-            let ccd_pixel_size: f64 = ccd_info_lock
-                .get_values::<HashMap<String, Number>>()
-                .unwrap()
-                .get("CCD_PIXEL_SIZE")
-                .unwrap()
-                .value
-                .into();
+            let ccd_pixel_size: f64 = ccd_info_lock.value_of("CCD_PIXEL_SIZE").unwrap();
             binning * ccd_pixel_size / 800.0 * 180.0 / std::f64::consts::PI * 3.6
         };
 
         pixel_scale
     }
 
+    /// Returns the filter wheel's slot numbers keyed by filter name, from its `FILTER_NAME`
+    ///  parameter.  Fails with [ChangeError::PropertyError] if a value isn't named
+    ///  `FILTER_SLOT_NAME_` followed by the slot number.
     pub async fn filter_names(&self) -> Result<HashMap<String, usize>, ChangeError<Command>> {
-        let filter_names: HashMap<String, usize> = {
-            let filter_names_param = self.get_parameter("FILTER_NAME").await?;
-            let l = filter_names_param.lock().await;
-            l.get_values::<HashMap<String, Text>>()?
-                .iter()
-                .map(|(slot, name)| {
-                    let slot = slot
-                        .split("_")
-                        .last()
-                        .map(|x| x.parse::<usize>().unwrap())
-                        .unwrap();
-                    (name.value.clone(), slot)
-                })
-                .collect()
-        };
+        let param = self.get_parameter("FILTER_NAME").await?;
+        let param = param.lock().await;
+        let mut filter_names = HashMap::new();
+        for slot in param.get_values::<HashMap<String, Text>>()?.keys() {
+            let number = slot
+                .strip_prefix("FILTER_SLOT_NAME_")
+                .and_then(|number| number.parse::<usize>().ok())
+                .ok_or(ChangeError::PropertyError)?;
+            filter_names.insert(param.value_of::<String>(slot)?, number);
+        }
         Ok(filter_names)
     }
 
@@ -649,6 +1493,722 @@ mod tests {
 
     use super::*;
 
+    #[tokio::test]
+    async fn test_snapshot() {
+        let mut device = Device::new(String::from("CCD Simulator"));
+        let commands = CommandIter::new(std::io::Cursor::new(
+            r#"<defBLOBVector device="CCD Simulator" name="CCD1" label="Image Data" group="Image Info" state="Idle" perm="ro">
+    <defBLOB name="CCD1" label="Image"/>
+</defBLOBVector>
+<setBLOBVector device="CCD Simulator" name="CCD1" state="Ok" timeout="60" timestamp="2022-09-06T01:41:22">
+    <oneBLOB name="CCD1" size="5" format=".fits">
+aGVsbG8=
+    </oneBLOB>
+</setBLOBVector>
+<defNumberVector device="CCD Simulator" name="EQUATORIAL_PE" label="EQ PE" group="Simulator Config" state="Idle" perm="rw" timeout="60" timestamp="2022-09-06T01:41:22">
+    <defNumber name="RA_PE" label="RA (hh:mm:ss)" format="%010.6m" min="0" max="24" step="0">
+5.00:30:15
+    </defNumber>
+</defNumberVector>
+"#,
+        ));
+        for command in commands {
+            device.update(command.unwrap()).await.unwrap();
+        }
+
+        let snapshot = device.snapshot().await;
+        assert_eq!(
+            snapshot
+                .parameters
+                .iter()
+                .map(|p| p.get_name().as_str())
+                .collect::<Vec<_>>(),
+            vec!["CCD1", "EQUATORIAL_PE"]
+        );
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(json.contains("\"aGVsbG8=\""));
+        let parsed: DeviceSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, snapshot);
+
+        let restored = Device::from(parsed);
+        assert_eq!(restored.parameter_names(), device.parameter_names());
+        assert_eq!(restored.parameter_groups(), device.parameter_groups());
+        assert_eq!(restored.snapshot().await, snapshot);
+    }
+
+    #[tokio::test]
+    async fn test_stale_update() {
+        let mut device = Device::new(String::from("CCD Simulator"));
+        device.set_drop_stale_updates(true);
+        let mut commands = CommandIter::new(std::io::Cursor::new(
+            r#"<defNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" label="Temperature" group="Main Control" state="Idle" perm="rw" timeout="60" timestamp="2022-09-06T01:41:22">
+    <defNumber name="CCD_TEMPERATURE_VALUE" label="Temperature (C)" format="%5.2f" min="-50" max="50" step="0">
+0
+    </defNumber>
+</defNumberVector>
+<setNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" state="Busy" timeout="60" timestamp="2022-09-06T01:41:30">
+    <oneNumber name="CCD_TEMPERATURE_VALUE">
+-10
+    </oneNumber>
+</setNumberVector>
+<setNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" state="Busy" timeout="60" timestamp="2022-09-06T01:41:25">
+    <oneNumber name="CCD_TEMPERATURE_VALUE">
+-5
+    </oneNumber>
+</setNumberVector>
+<setNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" state="Ok" timeout="60" timestamp="2022-09-06T01:41:30">
+    <oneNumber name="CCD_TEMPERATURE_VALUE">
+-15
+    </oneNumber>
+</setNumberVector>
+"#,
+        ))
+        .map(Result::unwrap);
+        async fn stale(device: &mut Device, command: Command) -> Option<String> {
+            match device.update(command).await.unwrap() {
+                ParamUpdateResult::StaleUpdate(name) => Some(name),
+                _ => None,
+            }
+        }
+
+        assert_eq!(stale(&mut device, commands.next().unwrap()).await, None);
+        assert_eq!(stale(&mut device, commands.next().unwrap()).await, None);
+        assert_eq!(
+            stale(&mut device, commands.next().unwrap()).await,
+            Some(String::from("CCD_TEMPERATURE"))
+        );
+        // Updates with the same timestamp as the current value are applied.
+        assert_eq!(stale(&mut device, commands.next().unwrap()).await, None);
+
+        let param = device.get_parameters()["CCD_TEMPERATURE"].lock().await;
+        let values = param.get_values::<HashMap<String, Number>>().unwrap();
+        assert_eq!(f64::from(&values["CCD_TEMPERATURE_VALUE"].value), -15.0);
+        assert_eq!(param.gen(), Wrapping(2));
+    }
+
+    #[tokio::test]
+    async fn test_clock_jump_backward() {
+        let mut device = Device::new(String::from("CCD Simulator"));
+        let commands = CommandIter::new(std::io::Cursor::new(
+            r#"<defNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" label="Temperature" group="Main Control" state="Idle" perm="rw" timeout="60" timestamp="2022-09-06T01:41:22">
+    <defNumber name="CCD_TEMPERATURE_VALUE" label="Temperature (C)" format="%5.2f" min="-50" max="50" step="0">
+0
+    </defNumber>
+</defNumberVector>
+<setNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" state="Busy" timeout="60" timestamp="2022-09-06T01:41:30">
+    <oneNumber name="CCD_TEMPERATURE_VALUE">
+-10
+    </oneNumber>
+</setNumberVector>
+<setNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" state="Ok" timeout="60" timestamp="2021-01-01T00:00:00">
+    <oneNumber name="CCD_TEMPERATURE_VALUE">
+-15
+    </oneNumber>
+</setNumberVector>
+<setNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" state="Ok" timeout="60" timestamp="2021-01-01T00:00:01">
+    <oneNumber name="CCD_TEMPERATURE_VALUE">
+-20
+    </oneNumber>
+</setNumberVector>
+"#,
+        ));
+        // Without opting in, updates keep being applied after the driver's clock jumps back.
+        for command in commands {
+            assert!(!matches!(
+                device.update(command.unwrap()).await.unwrap(),
+                ParamUpdateResult::StaleUpdate(_)
+            ));
+        }
+
+        let param = device.get_parameters()["CCD_TEMPERATURE"].lock().await;
+        let values = param.get_values::<HashMap<String, Number>>().unwrap();
+        assert_eq!(f64::from(&values["CCD_TEMPERATURE_VALUE"].value), -20.0);
+        assert_eq!(param.gen(), Wrapping(3));
+    }
+
+    #[tokio::test]
+    async fn test_blob_policy() {
+        let mut device = Device::new(String::from("CCD Simulator"));
+        assert_eq!(device.blob_enabled("CCD1"), BlobEnable::Never);
+
+        device.set_blob_enabled(Some(String::from("CCD1")), BlobEnable::Also);
+        device
+            .update(Command::EnableBlob(EnableBlob {
+                device: String::from("CCD Simulator"),
+                name: Some(String::from("CCD2")),
+                enabled: BlobEnable::Only,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(device.blob_enabled("CCD1"), BlobEnable::Also);
+        assert_eq!(device.blob_enabled("CCD2"), BlobEnable::Only);
+        assert_eq!(device.blob_enabled("CCD3"), BlobEnable::Never);
+        assert_eq!(
+            device
+                .enable_blob_commands()
+                .iter()
+                .map(|c| c.name.as_deref())
+                .collect::<Vec<_>>(),
+            vec![Some("CCD1"), Some("CCD2")]
+        );
+
+        device.set_blob_enabled(None, BlobEnable::Also);
+        assert_eq!(device.blob_enabled("CCD2"), BlobEnable::Also);
+        assert_eq!(
+            device.enable_blob_commands(),
+            vec![EnableBlob {
+                device: String::from("CCD Simulator"),
+                name: None,
+                enabled: BlobEnable::Also,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_change_interval() {
+        let mut device = Device::new(String::from("Focuser Simulator"));
+        let def = CommandIter::new(std::io::Cursor::new(
+            r#"<defNumberVector device="Focuser Simulator" name="ABS_FOCUS_POSITION" label="Absolute Position" group="Main Control" state="Idle" perm="rw" timeout="60" timestamp="2022-09-06T01:41:22">
+    <defNumber name="FOCUS_ABSOLUTE_POSITION" label="Steps" format="%6.0f" min="0" max="100000" step="0">
+0
+    </defNumber>
+</defNumberVector>
+"#,
+        ))
+        .next()
+        .unwrap()
+        .unwrap();
+        device.update(def).await.unwrap();
+
+        let (sender, mut sent) = tokio::sync::mpsc::unbounded_channel();
+        let focuser = ActiveDevice::new(
+            String::from("Focuser Simulator"),
+            Arc::new(Notify::new(device)),
+            Some(sender),
+        )
+        .with_change_interval(Duration::from_millis(200));
+
+        let mut changes = vec![];
+        for position in [1.0, 2.0, 3.0] {
+            let focuser = focuser.clone();
+            changes.push(tokio::spawn(async move {
+                focuser
+                    .change(
+                        "ABS_FOCUS_POSITION",
+                        vec![("FOCUS_ABSOLUTE_POSITION", position)],
+                    )
+                    .await
+            }));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let position = |c: Command| match c {
+            Command::NewNumberVector(c) => f64::from(&c.numbers[0].value),
+            c => panic!("Unexpected command: {:?}", c),
+        };
+        assert_eq!(position(sent.recv().await.unwrap()), 1.0);
+        let start = tokio::time::Instant::now();
+        assert_eq!(position(sent.recv().await.unwrap()), 3.0);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(matches!(
+            changes.remove(1).await.unwrap(),
+            Err(ChangeError::Superseded)
+        ));
+        for change in changes {
+            change.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_messages() {
+        let device = Arc::new(Notify::new(Device::new(String::from("CCD Simulator"))));
+        let camera = ActiveDevice::new(String::from("CCD Simulator"), device.clone(), None);
+        let message = |i: usize| {
+            Command::Message(Message {
+                device: Some(String::from("CCD Simulator")),
+                timestamp: Some(Timestamp(
+                    DateTime::from_str("2022-10-13T07:41:56.301Z").unwrap(),
+                )),
+                message: Some(format!("Message {}", i)),
+            })
+        };
+        device.lock().await.update(message(0)).await.unwrap();
+
+        let mut messages = Box::pin(camera.messages().await);
+        for i in 1..=MESSAGE_HISTORY {
+            device.lock().await.update(message(i)).await.unwrap();
+        }
+        let first = messages.next().await.unwrap();
+        assert_eq!(first.message, "Message 1");
+        assert_eq!(
+            first.timestamp,
+            DateTime::<Utc>::from_str("2022-10-13T07:41:56.301Z").unwrap()
+        );
+        assert_eq!(messages.next().await.unwrap().message, "Message 2");
+
+        let device = device.lock().await;
+        assert_eq!(device.messages().len(), MESSAGE_HISTORY);
+        assert_eq!(device.messages()[0].message, "Message 1");
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_light() {
+        let mut device = Device::new(String::from("Weather Simulator"));
+        let def = CommandIter::new(std::io::Cursor::new(
+            r#"<defLightVector device="Weather Simulator" name="WEATHER_STATUS" label="Status" group="Main Control" state="Alert" timestamp="2022-09-06T01:41:22">
+    <defLight name="WEATHER_RAIN_HOUR" label="Rain">Alert</defLight>
+    <defLight name="WEATHER_WIND_SPEED" label="Wind">Ok</defLight>
+</defLightVector>
+"#,
+        ))
+        .next()
+        .unwrap()
+        .unwrap();
+        device.update(def).await.unwrap();
+        let device = Arc::new(Notify::new(device));
+        let weather = ActiveDevice::new(String::from("Weather Simulator"), device.clone(), None);
+
+        {
+            let device = device.lock().await;
+            let status = device.get_parameters()["WEATHER_STATUS"].lock().await;
+            let lights = status.get_values::<HashMap<String, Light>>().unwrap();
+            assert_eq!(lights["WEATHER_RAIN_HOUR"].value, PropertyState::Alert);
+            assert!(vec![("WEATHER_WIND_SPEED", PropertyState::Ok)]
+                .try_eq(&status)
+                .unwrap());
+        }
+
+        let safe = tokio::spawn(async move {
+            weather
+                .wait_for_light("WEATHER_STATUS", PropertyState::Ok)
+                .await
+        });
+        let set = CommandIter::new(std::io::Cursor::new(
+            r#"<setLightVector device="Weather Simulator" name="WEATHER_STATUS" state="Ok">
+    <oneLight name="WEATHER_RAIN_HOUR">Ok</oneLight>
+</setLightVector>
+"#,
+        ))
+        .next()
+        .unwrap()
+        .unwrap();
+        device.lock().await.update(set).await.unwrap();
+        let status = tokio::time::timeout(Duration::from_secs(5), safe)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(vec![
+            ("WEATHER_RAIN_HOUR", PropertyState::Ok),
+            ("WEATHER_WIND_SPEED", PropertyState::Ok)
+        ]
+        .try_eq(&status)
+        .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_value_of() {
+        let mut device = Device::new(String::from("CCD Simulator"));
+        let commands = CommandIter::new(std::io::Cursor::new(
+            r#"<defNumberVector device="CCD Simulator" name="CCD_EXPOSURE" label="Expose" group="Main Control" state="Idle" perm="rw" timeout="60">
+    <defNumber name="CCD_EXPOSURE_VALUE" label="Duration (s)" format="%5.2f" min="0.01" max="3600" step="1">2.5</defNumber>
+</defNumberVector>
+<defNumberVector device="CCD Simulator" name="EQUATORIAL_PE" label="EQ PE" group="Simulator Config" state="Idle" perm="rw" timeout="60">
+    <defNumber name="RA_PE" label="RA (hh:mm:ss)" format="%010.6m" min="0" max="24" step="0">5:30:15</defNumber>
+</defNumberVector>
+<defSwitchVector device="CCD Simulator" name="CONNECTION" state="Idle" perm="rw" rule="OneOfMany" timeout="60">
+    <defSwitch name="CONNECT">On</defSwitch>
+    <defSwitch name="DISCONNECT">Off</defSwitch>
+</defSwitchVector>
+<defTextVector device="CCD Simulator" name="DRIVER_INFO" state="Idle" perm="ro">
+    <defText name="DRIVER_NAME">CCD Simulator</defText>
+</defTextVector>
+"#,
+        ));
+        for command in commands {
+            device.update(command.unwrap()).await.unwrap();
+        }
+        let param = |name: &str| device.get_parameters()[name].clone();
+
+        let exposure = param("CCD_EXPOSURE");
+        let exposure = exposure.lock().await;
+        assert_eq!(exposure.value_of::<f64>("CCD_EXPOSURE_VALUE").unwrap(), 2.5);
+        assert_eq!(
+            exposure.value_of::<Duration>("CCD_EXPOSURE_VALUE").unwrap(),
+            Duration::from_millis(2500)
+        );
+        assert!(matches!(
+            exposure.value_of::<f64>("CCD_EXPOSURE_MISSING"),
+            Err(TypeError::MissingValue(name)) if name == "CCD_EXPOSURE_MISSING"
+        ));
+        assert!(matches!(
+            exposure.value_of::<bool>("CCD_EXPOSURE_VALUE"),
+            Err(TypeError::TypeMismatch)
+        ));
+
+        let pe = param("EQUATORIAL_PE");
+        let pe = pe.lock().await;
+        let ra = pe.value_of::<Sexagesimal>("RA_PE").unwrap();
+        assert_eq!(
+            (ra.hour, ra.minute, ra.second),
+            (5.0, Some(30.0), Some(15.0))
+        );
+
+        let connection = param("CONNECTION");
+        let connection = connection.lock().await;
+        assert!(connection.value_of::<bool>("CONNECT").unwrap());
+        assert!(!connection.value_of::<bool>("DISCONNECT").unwrap());
+
+        let info = param("DRIVER_INFO");
+        let info = info.lock().await;
+        assert_eq!(
+            info.value_of::<String>("DRIVER_NAME").unwrap(),
+            "CCD Simulator"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_ok() {
+        let mut device = Device::new(String::from("Telescope Simulator"));
+        let def = CommandIter::new(std::io::Cursor::new(
+            r#"<defSwitchVector device="Telescope Simulator" name="TELESCOPE_PARK" state="Idle" perm="rw" rule="OneOfMany" timeout="60">
+    <defSwitch name="PARK">Off</defSwitch>
+    <defSwitch name="UNPARK">On</defSwitch>
+</defSwitchVector>
+"#,
+        ))
+        .next()
+        .unwrap()
+        .unwrap();
+        device.update(def).await.unwrap();
+        let device = Arc::new(Notify::new(device));
+        let telescope =
+            ActiveDevice::new(String::from("Telescope Simulator"), device.clone(), None);
+
+        let set = |state: &str, message: &str| {
+            CommandIter::new(std::io::Cursor::new(format!(
+                r#"<setSwitchVector device="Telescope Simulator" name="TELESCOPE_PARK" state="{}" message="{}">
+    <oneSwitch name="PARK">On</oneSwitch>
+    <oneSwitch name="UNPARK">Off</oneSwitch>
+</setSwitchVector>
+"#,
+                state, message
+            )))
+            .next()
+            .unwrap()
+            .unwrap()
+        };
+
+        let wait = {
+            let telescope = telescope.clone();
+            tokio::spawn(async move {
+                telescope
+                    .wait_for_ok("TELESCOPE_PARK", Duration::from_secs(5))
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        device
+            .lock()
+            .await
+            .update(set("Busy", "Parking"))
+            .await
+            .unwrap();
+        device
+            .lock()
+            .await
+            .update(set("Alert", "Mount is stuck"))
+            .await
+            .unwrap();
+        match wait.await.unwrap() {
+            Err(ChangeError::Alert(Some(message))) => assert_eq!(message, "Mount is stuck"),
+            e => panic!("Unexpected: {:?}", e),
+        }
+
+        device
+            .lock()
+            .await
+            .update(set("Busy", "Parking"))
+            .await
+            .unwrap();
+        let wait = tokio::spawn(async move {
+            telescope
+                .wait_for_ok("TELESCOPE_PARK", Duration::from_secs(5))
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        device
+            .lock()
+            .await
+            .update(set("Ok", "Parked"))
+            .await
+            .unwrap();
+        let park = wait.await.unwrap().unwrap();
+        assert_eq!(*park.get_state(), PropertyState::Ok);
+        assert_eq!(device.lock().await.messages().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_change_many() {
+        let mut device = Device::new(String::from("CCD Simulator"));
+        let commands = CommandIter::new(std::io::Cursor::new(
+            r#"<defSwitchVector device="CCD Simulator" name="CCD_FRAME_TYPE" state="Idle" perm="rw" rule="OneOfMany" timeout="5">
+    <defSwitch name="FRAME_LIGHT">On</defSwitch>
+    <defSwitch name="FRAME_FLAT">Off</defSwitch>
+</defSwitchVector>
+<defNumberVector device="CCD Simulator" name="CCD_BINNING" state="Idle" perm="rw" timeout="5">
+    <defNumber name="HOR_BIN" format="%2.0f" min="1" max="4" step="1">1</defNumber>
+    <defNumber name="VER_BIN" format="%2.0f" min="1" max="4" step="1">1</defNumber>
+</defNumberVector>
+"#,
+        ));
+        for command in commands {
+            device.update(command.unwrap()).await.unwrap();
+        }
+        let device = Arc::new(Notify::new(device));
+        let (sender, mut sent) = tokio::sync::mpsc::unbounded_channel();
+        let camera = ActiveDevice::new(String::from("CCD Simulator"), device.clone(), Some(sender));
+
+        // Nothing is sent when any of the values don't match their parameter.
+        let mismatched = camera
+            .change_many([
+                ("CCD_FRAME_TYPE", vec![("FRAME_FLAT", true)].into()),
+                ("CCD_BINNING", vec![("HOR_BIN", true)].into()),
+            ])
+            .await;
+        assert!(matches!(
+            mismatched,
+            Err(ChangeError::ParameterFailed { name, error })
+                if name == "CCD_BINNING" && matches!(*error, ChangeError::TypeMismatch)
+        ));
+        assert!(sent.try_recv().is_err());
+
+        let change = tokio::spawn(async move {
+            camera
+                .change_many([
+                    ("CCD_FRAME_TYPE", vec![("FRAME_FLAT", true)].into()),
+                    (
+                        "CCD_BINNING",
+                        vec![("HOR_BIN", 2.0), ("VER_BIN", 2.0)].into(),
+                    ),
+                ])
+                .await
+        });
+        let mut names = vec![];
+        for _ in 0..2 {
+            match sent.recv().await.unwrap() {
+                Command::NewSwitchVector(c) => names.push(c.name),
+                Command::NewNumberVector(c) => names.push(c.name),
+                c => panic!("Unexpected command: {:?}", c),
+            }
+        }
+        names.sort();
+        assert_eq!(names, vec!["CCD_BINNING", "CCD_FRAME_TYPE"]);
+
+        let updates = CommandIter::new(std::io::Cursor::new(
+            r#"<setSwitchVector device="CCD Simulator" name="CCD_FRAME_TYPE" state="Ok">
+    <oneSwitch name="FRAME_LIGHT">Off</oneSwitch>
+    <oneSwitch name="FRAME_FLAT">On</oneSwitch>
+</setSwitchVector>
+<setNumberVector device="CCD Simulator" name="CCD_BINNING" state="Alert">
+    <oneNumber name="HOR_BIN">1</oneNumber>
+    <oneNumber name="VER_BIN">1</oneNumber>
+</setNumberVector>
+"#,
+        ));
+        for command in updates {
+            device.lock().await.update(command.unwrap()).await.unwrap();
+        }
+        match change.await.unwrap() {
+            Err(ChangeError::ParameterFailed { name, error }) => {
+                assert_eq!(name, "CCD_BINNING");
+                assert!(matches!(*error, ChangeError::PropertyError));
+            }
+            r => panic!("Unexpected: {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_revert_on_cancel() {
+        let mut device = Device::new(String::from("Focuser Simulator"));
+        let def = CommandIter::new(std::io::Cursor::new(
+            r#"<defNumberVector device="Focuser Simulator" name="ABS_FOCUS_POSITION" state="Idle" perm="rw" timeout="60">
+    <defNumber name="FOCUS_ABSOLUTE_POSITION" format="%6.0f" min="0" max="100000" step="1">1200</defNumber>
+</defNumberVector>
+"#,
+        ))
+        .next()
+        .unwrap()
+        .unwrap();
+        device.update(def).await.unwrap();
+        let device = Arc::new(Notify::new(device));
+        let (sender, mut sent) = tokio::sync::mpsc::unbounded_channel();
+        let focuser = ActiveDevice::new(
+            String::from("Focuser Simulator"),
+            device.clone(),
+            Some(sender),
+        );
+        let position = |command: Command| match command {
+            Command::NewNumberVector(c) => f64::from(&c.numbers[0].value),
+            c => panic!("Unexpected command: {:?}", c),
+        };
+
+        // Without reverting, the server is left to finish the change.
+        let cancelled = tokio::time::timeout(
+            Duration::from_millis(50),
+            focuser.change(
+                "ABS_FOCUS_POSITION",
+                vec![("FOCUS_ABSOLUTE_POSITION", 1500.0)],
+            ),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        assert_eq!(position(sent.try_recv().unwrap()), 1500.0);
+        assert!(sent.try_recv().is_err());
+
+        let focuser = focuser.with_revert_on_cancel(true);
+        let cancelled = tokio::time::timeout(
+            Duration::from_millis(50),
+            focuser.change(
+                "ABS_FOCUS_POSITION",
+                vec![("FOCUS_ABSOLUTE_POSITION", 1500.0)],
+            ),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        assert_eq!(position(sent.try_recv().unwrap()), 1500.0);
+        assert_eq!(position(sent.try_recv().unwrap()), 1200.0);
+
+        // Changes that finish, even with an error, aren't reverted.
+        let change = {
+            let focuser = focuser.clone();
+            tokio::spawn(async move {
+                focuser
+                    .change(
+                        "ABS_FOCUS_POSITION",
+                        vec![("FOCUS_ABSOLUTE_POSITION", 1500.0)],
+                    )
+                    .await
+                    .map(|_| ())
+            })
+        };
+        assert_eq!(position(sent.recv().await.unwrap()), 1500.0);
+        let set = CommandIter::new(std::io::Cursor::new(
+            r#"<setNumberVector device="Focuser Simulator" name="ABS_FOCUS_POSITION" state="Alert">
+    <oneNumber name="FOCUS_ABSOLUTE_POSITION">1200</oneNumber>
+</setNumberVector>
+"#,
+        ))
+        .next()
+        .unwrap()
+        .unwrap();
+        device.lock().await.update(set).await.unwrap();
+        assert!(matches!(
+            change.await.unwrap(),
+            Err(ChangeError::PropertyError)
+        ));
+        assert!(sent.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_filter_names() {
+        async fn filter_wheel(names: &str) -> ActiveDevice {
+            let mut device = Device::new(String::from("Filter Simulator"));
+            let def = CommandIter::new(std::io::Cursor::new(format!(
+                r#"<defTextVector device="Filter Simulator" name="FILTER_NAME" label="Filter" group="Filter Wheel" state="Idle" perm="rw" timeout="60">
+{}
+</defTextVector>
+"#,
+                names
+            )))
+            .next()
+            .unwrap()
+            .unwrap();
+            device.update(def).await.unwrap();
+            ActiveDevice::new(
+                String::from("Filter Simulator"),
+                Arc::new(Notify::new(device)),
+                None,
+            )
+        }
+
+        let wheel = filter_wheel(
+            r#"<defText name="FILTER_SLOT_NAME_1" label="Filter#1">Red</defText>
+<defText name="FILTER_SLOT_NAME_2" label="Filter#2">Green</defText>"#,
+        )
+        .await;
+        assert_eq!(
+            wheel.filter_names().await.unwrap(),
+            HashMap::from([(String::from("Red"), 1), (String::from("Green"), 2)])
+        );
+
+        let wheel =
+            filter_wheel(r#"<defText name="FILTER_SLOT_NAME_X" label="Filter">Red</defText>"#)
+                .await;
+        assert!(matches!(
+            wheel.filter_names().await,
+            Err(ChangeError::PropertyError)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_snoop() {
+        let mut device = Device::new(String::from("CCD Simulator"));
+        let def = CommandIter::new(std::io::Cursor::new(
+            r#"<defTextVector device="CCD Simulator" name="ACTIVE_DEVICES" label="Snoop devices" group="Options" state="Idle" perm="rw" timeout="60">
+    <defText name="ACTIVE_TELESCOPE" label="Telescope">Telescope Simulator</defText>
+    <defText name="ACTIVE_FOCUSER" label="Focuser"></defText>
+</defTextVector>
+"#,
+        ))
+        .next()
+        .unwrap()
+        .unwrap();
+        device.update(def).await.unwrap();
+        let device = Arc::new(Notify::new(device));
+        let (sender, mut sent) = tokio::sync::mpsc::unbounded_channel();
+        let camera = ActiveDevice::new(String::from("CCD Simulator"), device.clone(), Some(sender));
+
+        assert_eq!(
+            camera
+                .snooped(SnoopRole::Telescope)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("Telescope Simulator")
+        );
+        assert_eq!(camera.snooped(SnoopRole::Focuser).await.unwrap(), None);
+
+        let snoop = {
+            let camera = camera.clone();
+            tokio::spawn(async move { camera.snoop(SnoopRole::Focuser, "Focuser Simulator").await })
+        };
+        match sent.recv().await.unwrap() {
+            Command::NewTextVector(c) => {
+                assert_eq!(c.name, "ACTIVE_DEVICES");
+                assert_eq!(c.texts[0].name, "ACTIVE_FOCUSER");
+                assert_eq!(c.texts[0].value, "Focuser Simulator");
+            }
+            c => panic!("Unexpected command: {:?}", c),
+        }
+        let set = CommandIter::new(std::io::Cursor::new(
+            r#"<setTextVector device="CCD Simulator" name="ACTIVE_DEVICES" state="Ok">
+    <oneText name="ACTIVE_FOCUSER">Focuser Simulator</oneText>
+</setTextVector>
+"#,
+        ))
+        .next()
+        .unwrap()
+        .unwrap();
+        device.lock().await.update(set).await.unwrap();
+        snoop.await.unwrap().unwrap();
+        assert_eq!(
+            camera.snooped(SnoopRole::Focuser).await.unwrap().as_deref(),
+            Some("Focuser Simulator")
+        );
+    }
+
     #[tokio::test]
     async fn test_update_switch() {
         let mut device = Device::new(String::from("CCD Simulator"));
@@ -0,0 +1,171 @@
+use std::collections::BTreeSet;
+
+use tokio::sync::broadcast;
+
+use super::MemoryDeviceStore;
+use crate::Command;
+
+/// A device or parameter appearing or disappearing on the INDI server, see
+/// [device_events](super::Client::device_events).
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceEvent {
+    /// The device defined its first parameter.
+    DeviceAdded(String),
+    /// The device deleted all of its parameters.
+    DeviceRemoved(String),
+    ParameterDefined {
+        device: String,
+        parameter: String,
+    },
+    ParameterDeleted {
+        device: String,
+        parameter: String,
+    },
+}
+
+pub(crate) type DeviceEvents = broadcast::Sender<DeviceEvent>;
+
+/// The parameters a device had before a command that can define or delete them.
+pub(crate) struct Lifecycle {
+    device: String,
+    before: BTreeSet<String>,
+}
+
+impl Lifecycle {
+    /// Records the parameters of the device `command` is for, if the command defines or
+    /// deletes parameters.  Other commands return `None` so updates don't pay for this.
+    pub(crate) async fn before(
+        devices: &MemoryDeviceStore,
+        command: &Command,
+    ) -> Option<Lifecycle> {
+        let defines_or_deletes = matches!(
+            command,
+            Command::DefTextVector(_)
+                | Command::DefNumberVector(_)
+                | Command::DefSwitchVector(_)
+                | Command::DefLightVector(_)
+                | Command::DefBlobVector(_)
+                | Command::DelProperty(_)
+        );
+        if !defines_or_deletes {
+            return None;
+        }
+        let device = command.device_name()?.clone();
+        let before = parameters(devices, &device).await;
+        Some(Lifecycle { device, before })
+    }
+
+    /// Sends the events for the difference between the device's parameters now and before.
+    pub(crate) async fn send(self, devices: &MemoryDeviceStore, events: &DeviceEvents) {
+        let after = parameters(devices, &self.device).await;
+        if self.before.is_empty() && !after.is_empty() {
+            events
+                .send(DeviceEvent::DeviceAdded(self.device.clone()))
+                .ok();
+        }
+        for parameter in after.difference(&self.before) {
+            events
+                .send(DeviceEvent::ParameterDefined {
+                    device: self.device.clone(),
+                    parameter: parameter.clone(),
+                })
+                .ok();
+        }
+        for parameter in self.before.difference(&after) {
+            events
+                .send(DeviceEvent::ParameterDeleted {
+                    device: self.device.clone(),
+                    parameter: parameter.clone(),
+                })
+                .ok();
+        }
+        if !self.before.is_empty() && after.is_empty() {
+            events.send(DeviceEvent::DeviceRemoved(self.device)).ok();
+        }
+    }
+}
+
+async fn parameters(devices: &MemoryDeviceStore, device: &str) -> BTreeSet<String> {
+    match devices.get(device) {
+        Some(device) => device
+            .lock()
+            .await
+            .get_parameters()
+            .keys()
+            .cloned()
+            .collect(),
+        None => BTreeSet::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+
+    use super::DeviceEvent;
+    use crate::client::new;
+
+    #[tokio::test]
+    async fn test_device_events() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = new(
+            tokio::net::TcpStream::connect(addr).await.unwrap(),
+            None,
+            None,
+        )
+        .expect("Making client");
+        let mut events = client.device_events();
+
+        let (mut server, _) = listener.accept().await.unwrap();
+        server
+            .write_all(
+                br#"<defSwitchVector device="CCD Simulator" name="CONNECTION" label="Connection" group="Main Control" state="Idle" perm="rw" rule="OneOfMany" timeout="60">
+    <defSwitch name="CONNECT" label="Connect">
+On
+    </defSwitch>
+</defSwitchVector>
+<defTextVector device="CCD Simulator" name="DRIVER_INFO" label="Driver Info" group="General Info" state="Idle" perm="ro" timeout="60">
+    <defText name="DRIVER_NAME" label="Name">
+CCD Simulator
+    </defText>
+</defTextVector>
+<setSwitchVector device="CCD Simulator" name="CONNECTION" state="Ok" timeout="60">
+    <oneSwitch name="CONNECT">
+Off
+    </oneSwitch>
+</setSwitchVector>
+<delProperty device="CCD Simulator" name="DRIVER_INFO"/>
+<delProperty device="CCD Simulator"/>
+"#,
+            )
+            .await
+            .unwrap();
+
+        let defined = |parameter: &str| DeviceEvent::ParameterDefined {
+            device: String::from("CCD Simulator"),
+            parameter: String::from(parameter),
+        };
+        let deleted = |parameter: &str| DeviceEvent::ParameterDeleted {
+            device: String::from("CCD Simulator"),
+            parameter: String::from(parameter),
+        };
+        let expected = vec![
+            DeviceEvent::DeviceAdded(String::from("CCD Simulator")),
+            defined("CONNECTION"),
+            defined("DRIVER_INFO"),
+            deleted("DRIVER_INFO"),
+            deleted("CONNECTION"),
+            DeviceEvent::DeviceRemoved(String::from("CCD Simulator")),
+        ];
+        for expected in expected {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("Waiting for event")
+                .unwrap();
+            assert_eq!(event, expected);
+        }
+    }
+}
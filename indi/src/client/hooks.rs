@@ -0,0 +1,89 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::serialization::Command;
+
+/// Which way a command passing through a [Hook] is going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Read from the INDI server, before the client handles it.
+    Incoming,
+    /// Written to the INDI server, after leaving an [ActiveDevice](super::device::ActiveDevice)
+    ///  or [Client](super::Client).
+    Outgoing,
+}
+
+/// Called with every command going through a client, see [add_hook](super::Client::add_hook).
+///  Returns the command to pass on, which may be changed, or `None` to drop it.
+pub type Hook = Box<dyn FnMut(Direction, Command) -> Option<Command> + Send>;
+
+pub(crate) type Hooks = Arc<Mutex<Vec<Hook>>>;
+
+/// Passes `command` through each hook in the order they were added, stopping at the first
+///  one that drops it.
+pub(crate) fn run(hooks: &Hooks, direction: Direction, command: Command) -> Option<Command> {
+    let mut hooks = hooks.lock().unwrap_or_else(PoisonError::into_inner);
+    hooks
+        .iter_mut()
+        .try_fold(command, |command, hook| hook(direction, command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    const DEVICE: &str = r#"<defNumberVector device="CCD Simulator" name="CCD_EXPOSURE" state="Idle" perm="rw" timeout="1">
+<defNumber name="CCD_EXPOSURE_VALUE" format="%5.2f" min="0" max="3600" step="0">0</defNumber>
+</defNumberVector>
+<defNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" state="Idle" perm="rw" timeout="1">
+<defNumber name="CCD_TEMPERATURE_VALUE" format="%5.2f" min="-50" max="50" step="0">0</defNumber>
+</defNumberVector>
+<message device="CCD Simulator" message="hidden"/>
+"#;
+
+    #[tokio::test]
+    async fn test_hooks() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = crate::client::new(TcpStream::connect(addr).await.unwrap(), None, None)
+            .expect("Making client");
+        let (server, _) = listener.accept().await.unwrap();
+        let (read, mut write) = server.into_split();
+        let mut lines = BufReader::new(read).lines();
+        assert!(lines
+            .next_line()
+            .await
+            .unwrap()
+            .unwrap()
+            .starts_with("<getProperties"));
+
+        let (seen, mut messages) = tokio::sync::mpsc::unbounded_channel();
+        client.add_hook(move |direction, command| match (direction, &command) {
+            (Direction::Outgoing, Command::NewNumberVector(new)) if new.name == "CCD_EXPOSURE" => {
+                None
+            }
+            (Direction::Incoming, Command::Message(message)) => {
+                seen.send(message.message.clone()).ok();
+                None
+            }
+            _ => Some(command),
+        });
+        write.write_all(DEVICE.as_bytes()).await.unwrap();
+        assert_eq!(messages.recv().await.unwrap().as_deref(), Some("hidden"));
+
+        let camera = client.get_device::<()>("CCD Simulator").await.unwrap();
+        for (param, value) in [
+            ("CCD_EXPOSURE", "CCD_EXPOSURE_VALUE"),
+            ("CCD_TEMPERATURE", "CCD_TEMPERATURE_VALUE"),
+        ] {
+            let camera = camera.clone();
+            // Nothing answers the changes, so they are left to time out.
+            tokio::spawn(async move { camera.change(param, vec![(value, 1.0)]).await });
+        }
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.contains("name=\"CCD_TEMPERATURE\""), "{}", line);
+    }
+}
@@ -1,34 +1,73 @@
+pub mod active_parameter;
+pub mod aggregate;
+pub mod blob_sink;
+pub mod blocking;
+pub mod coalesce;
 pub mod device;
+pub mod events;
+pub mod hooks;
+pub mod snapshot;
 pub mod tcpstream;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(unix)]
+pub mod unix;
 pub mod websocket;
 
 use twinkle_client;
 
 use std::{
     collections::HashMap,
+    future::Future,
+    path::PathBuf,
     sync::{Arc, PoisonError},
     time::Duration,
 };
 
-use self::device::ParamUpdateResult;
+use self::{
+    blob_sink::{BlobSink, BlobSinks},
+    device::ParamUpdateResult,
+    events::{DeviceEvent, DeviceEvents, Lifecycle},
+    hooks::{Direction, Hooks},
+};
 use crate::{
-    serialization, Command, DeError, GetProperties, TypeError, UpdateError, INDI_PROTOCOL_VERSION,
+    serialization::{
+        self, lenient::ParseWarning, number_vector::InvalidNumber, switch_vector::InvalidSwitches,
+    },
+    Command, DeError, GetProperties, TypeError, UpdateError, INDI_PROTOCOL_VERSION,
 };
+use tokio::sync::mpsc::{UnboundedReceiver, WeakUnboundedSender};
 pub use twinkle_client::notify::{self, wait_fn, Notify};
 
 #[derive(Debug)]
 pub enum ChangeError<E> {
-    NotifyError(notify::Error<E>),
+    // The variants holding a command are boxed to keep results small.
+    NotifyError(Box<notify::Error<E>>),
     DeError(serialization::DeError),
     IoError(std::io::Error),
-    Disconnected(crossbeam_channel::SendError<Command>),
+    Disconnected(Box<crossbeam_channel::SendError<Command>>),
     SendError(device::SendError<Command>),
     Canceled,
+    /// A newer change to the same parameter was sent instead, see
+    ///  [ActiveDevice::with_change_interval](device::ActiveDevice::with_change_interval).
+    Superseded,
     Timeout,
     EndOfStream,
     PropertyError,
+    /// The driver set the parameter to [PropertyState::Alert](crate::PropertyState::Alert),
+    ///  with the message it sent if any, see
+    ///  [ActiveDevice::wait_for_ok](device::ActiveDevice::wait_for_ok).
+    Alert(Option<String>),
+    /// Changing the named parameter failed, see
+    ///  [ActiveDevice::change_many](device::ActiveDevice::change_many).
+    ParameterFailed {
+        name: String,
+        error: Box<ChangeError<E>>,
+    },
     TypeMismatch,
     PoisonError,
+    InvalidNumber(InvalidNumber),
+    InvalidSwitches(InvalidSwitches),
 }
 
 impl<T> From<notify::Error<ChangeError<T>>> for ChangeError<T> {
@@ -54,7 +93,7 @@ impl<E> From<device::SendError<Command>> for ChangeError<E> {
 }
 impl<E> From<notify::Error<E>> for ChangeError<E> {
     fn from(value: notify::Error<E>) -> Self {
-        ChangeError::NotifyError(value)
+        ChangeError::NotifyError(Box::new(value))
     }
 }
 impl<E> From<DeError> for ChangeError<E> {
@@ -69,7 +108,19 @@ impl<E> From<TypeError> for ChangeError<E> {
 }
 impl<E> From<crossbeam_channel::SendError<Command>> for ChangeError<E> {
     fn from(value: crossbeam_channel::SendError<Command>) -> Self {
-        ChangeError::Disconnected(value)
+        ChangeError::Disconnected(Box::new(value))
+    }
+}
+
+impl<E> From<InvalidNumber> for ChangeError<E> {
+    fn from(value: InvalidNumber) -> Self {
+        ChangeError::InvalidNumber(value)
+    }
+}
+
+impl<E> From<InvalidSwitches> for ChangeError<E> {
+    fn from(value: InvalidSwitches) -> Self {
+        ChangeError::InvalidSwitches(value)
     }
 }
 
@@ -79,6 +130,54 @@ impl<E, T> From<PoisonError<T>> for ChangeError<E> {
     }
 }
 
+impl<E: std::fmt::Debug> std::fmt::Display for ChangeError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChangeError::NotifyError(e) => match e.as_ref() {
+                notify::Error::Timeout => write!(f, "timed out waiting for the change"),
+                notify::Error::Canceled => write!(f, "canceled"),
+                notify::Error::EndOfStream => write!(f, "parameter stopped updating"),
+                notify::Error::Abort(e) => write!(f, "aborted: {:?}", e),
+            },
+            ChangeError::DeError(e) => write!(f, "{}", e),
+            ChangeError::IoError(e) => write!(f, "io error: {}", e),
+            ChangeError::Disconnected(_) => write!(f, "disconnected from the INDI server"),
+            ChangeError::SendError(e) => write!(f, "{}", e),
+            ChangeError::Canceled => write!(f, "canceled"),
+            ChangeError::Superseded => write!(f, "superseded by a newer change"),
+            ChangeError::Timeout => write!(f, "timed out waiting for the change"),
+            ChangeError::EndOfStream => write!(f, "parameter stopped updating"),
+            ChangeError::PropertyError => write!(f, "the device reported an error"),
+            ChangeError::Alert(Some(message)) => {
+                write!(f, "the device reported an error: {}", message)
+            }
+            ChangeError::Alert(None) => write!(f, "the device reported an error"),
+            ChangeError::ParameterFailed { name, error } => {
+                write!(f, "changing {}: {}", name, error)
+            }
+            ChangeError::TypeMismatch => write!(f, "parameter is a different type"),
+            ChangeError::PoisonError => write!(f, "lock poisoned"),
+            ChangeError::InvalidNumber(e) => write!(f, "invalid number: {}", e),
+            ChangeError::InvalidSwitches(e) => write!(f, "invalid switches: {}", e),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + 'static> std::error::Error for ChangeError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChangeError::DeError(e) => Some(e),
+            ChangeError::IoError(e) => Some(e),
+            ChangeError::Disconnected(e) => Some(e.as_ref()),
+            ChangeError::SendError(e) => Some(e),
+            ChangeError::InvalidNumber(e) => Some(e),
+            ChangeError::InvalidSwitches(e) => Some(e),
+            ChangeError::ParameterFailed { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
 /// Create a new Client object that will stay in sync with the INDI server
 /// on the other end of `connection`.
 ///
@@ -120,71 +219,506 @@ pub fn new<T: AsyncClientConnection>(
     device: Option<&str>,
     parameter: Option<&str>,
 ) -> Result<Client, serialization::DeError> {
-    let (feedback, mut incoming_commands) = tokio::sync::mpsc::unbounded_channel::<Command>();
-
-    let (mut writer, mut reader) = connection.to_indi();
-    let writer_device = device.map(|x| String::from(x));
-    let writer_parameter = parameter.map(|x| String::from(x));
-    let writer_thread = tokio::task::spawn(async move {
-        writer
-            .write(serialization::Command::GetProperties(GetProperties {
-                version: INDI_PROTOCOL_VERSION.to_string(),
-                device: writer_device,
-                name: writer_parameter,
-            }))
-            .await?;
+    let mut builder = ClientBuilder::new();
+    if let Some(device) = device {
+        builder = builder.device(device);
+    }
+    if let Some(parameter) = parameter {
+        builder = builder.parameter(parameter);
+    }
+    builder.connect(connection)
+}
+
+/// How long a [Client] and its [ActiveDevice](device::ActiveDevice)s wait for the INDI server,
+///  see [ClientBuilder].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Timeouts {
+    pub(crate) device: Duration,
+    pub(crate) parameter: Duration,
+    pub(crate) change: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            device: Duration::from_secs(1),
+            parameter: Duration::from_secs(1),
+            change: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Options for creating a [Client], for when the defaults used by [new] don't fit.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use indi::client::ClientBuilder;
+/// use tokio::net::TcpStream;
+/// async {
+///     // A slow link to a remote observatory.
+///     let client = ClientBuilder::new()
+///         .device_timeout(Duration::from_secs(10))
+///         .change_timeout(Duration::from_secs(120))
+///         .connect(TcpStream::connect("observatory.local:7624").await.expect("Connecting to server"))
+///         .expect("Initializing connection to INDI server");
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    device: Option<String>,
+    parameter: Option<String>,
+    get_properties: bool,
+    timeouts: Timeouts,
+    device_events_capacity: usize,
+    parse_errors_capacity: usize,
+    reconnect_delay: Duration,
+    lenient: bool,
+    drop_stale_updates: bool,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder::new()
+    }
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        ClientBuilder {
+            device: None,
+            parameter: None,
+            get_properties: true,
+            timeouts: Default::default(),
+            device_events_capacity: 1024,
+            parse_errors_capacity: 64,
+            reconnect_delay: Duration::from_secs(1),
+            lenient: false,
+            drop_stale_updates: false,
+        }
+    }
+
+    /// Only tracks the device named `device`, see [new].
+    pub fn device(mut self, device: impl Into<String>) -> Self {
+        self.device = Some(device.into());
+        self
+    }
+
+    /// Only tracks the parameter named `parameter` of the [device](ClientBuilder::device).
+    pub fn parameter(mut self, parameter: impl Into<String>) -> Self {
+        self.parameter = Some(parameter.into());
+        self
+    }
+
+    /// Sets whether the client asks for the device and parameter to track with a
+    ///  `getProperties` when it connects.  Without it the server defines nothing until
+    ///  [get_properties](Client::get_properties) is called.  Defaults to true.
+    pub fn get_properties(mut self, get_properties: bool) -> Self {
+        self.get_properties = get_properties;
+        self
+    }
+
+    /// Sets how long [get_device](Client::get_device) waits for the device to be defined,
+    ///  defaults to 1 second.
+    pub fn device_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.device = timeout;
+        self
+    }
+
+    /// Sets how long [get_parameter](device::ActiveDevice::get_parameter) waits for the
+    ///  parameter to be defined, defaults to 1 second.
+    pub fn parameter_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.parameter = timeout;
+        self
+    }
+
+    /// Sets how long [change](device::ActiveDevice::change) waits for parameters that don't
+    ///  have a `timeout` of their own, defaults to 60 seconds.
+    pub fn change_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.change = timeout;
+        self
+    }
+
+    /// Sets how many events [device_events](Client::device_events) receivers can fall behind
+    ///  by, defaults to 1024.  Must be more than 0.
+    pub fn device_events_capacity(mut self, capacity: usize) -> Self {
+        self.device_events_capacity = capacity;
+        self
+    }
+
+    /// Sets how many errors [parse_errors](Client::parse_errors) and warnings
+    ///  [parse_warnings](Client::parse_warnings) receivers can fall behind by, defaults to 64.
+    ///  Must be more than 0.
+    pub fn parse_errors_capacity(mut self, capacity: usize) -> Self {
+        self.parse_errors_capacity = capacity;
+        self
+    }
+
+    /// Sets how long to wait before connecting again after the connection of a client made
+    ///  with [connect_with](ClientBuilder::connect_with) drops or fails, defaults to 1 second.
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+        self
+    }
+
+    /// Sets whether commands the server sends that aren't quite valid, such as with a
+    ///  missing `perm` or a state of `OK`, are parsed as best they can be, see
+    ///  [lenient](serialization::lenient).  What was fixed is reported by
+    ///  [parse_warnings](Client::parse_warnings).  Defaults to false, skipping them.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Sets whether `set*Vector`s older than their parameter's current value are dropped,
+    ///  see [set_drop_stale_updates](device::Device::set_drop_stale_updates).  Defaults to
+    ///  false, applying updates in the order they arrive.
+    pub fn drop_stale_updates(mut self, drop: bool) -> Self {
+        self.drop_stale_updates = drop;
+        self
+    }
+
+    /// Creates a client that will stay in sync with the INDI server on the other end of
+    ///  `connection`.
+    pub fn connect<T: AsyncClientConnection>(
+        self,
+        connection: T,
+    ) -> Result<Client, serialization::DeError> {
+        let initial = self.initial_commands();
+        let (mut client, shared, mut commands) = self.build(ConnectionStatus::Connected);
+
+        let (mut writer, reader) = connection.to_indi();
+        let writer_shared = shared.clone();
+        let writer_thread = tokio::task::spawn(async move {
+            let written = writer_shared
+                .write(&mut writer, initial, &mut commands)
+                .await;
+            writer_shared.write_finished(&written).await;
+            written?;
+            writer.shutdown().await?;
+            Ok(())
+        });
+        let reader_thread = tokio::spawn(async move {
+            shared.read(reader).await;
+            shared
+                .disconnected(String::from("closed by the server"))
+                .await;
+            Ok(())
+        });
+        client._workers = vec![writer_thread, reader_thread];
+        Ok(client)
+    }
+
+    /// Creates a client that connects to the INDI server with `connect`, and connects again
+    ///  the same way whenever the connection drops or can't be made, see
+    ///  [reconnect_delay](ClientBuilder::reconnect_delay).
+    ///
+    /// The devices are kept while disconnected, so [ActiveDevice](device::ActiveDevice)s and
+    ///  subscriptions carry on once the server defines them again.  Each new connection asks
+    ///  for them with the same `getProperties` as the first, followed by the `enableBLOB`s
+    ///  the devices had.  Commands sent while disconnected wait for the next connection.
+    /// # Example
+    /// ```no_run
+    /// use indi::client::ClientBuilder;
+    /// use tokio::net::TcpStream;
+    /// async {
+    ///     let client = ClientBuilder::new().connect_with(|| TcpStream::connect("localhost:7624"));
+    ///     let camera = client
+    ///         .get_device::<()>("CCD Simulator")
+    ///         .await
+    ///         .expect("Getting camera");
+    /// };
+    /// ```
+    pub fn connect_with<F, Fut, T>(self, mut connect: F) -> Client
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = std::io::Result<T>> + Send,
+        T: AsyncClientConnection + Send,
+    {
+        let get_properties = self.initial_commands();
+        let delay = self.reconnect_delay;
+        let (mut client, shared, mut commands) = self.build(ConnectionStatus::Connecting);
+
+        client._workers = vec![tokio::spawn(async move {
+            loop {
+                shared.set_status(ConnectionStatus::Connecting).await;
+                match connect().await {
+                    Ok(connection) => {
+                        shared.set_status(ConnectionStatus::Connected).await;
+                        let (mut writer, reader) = connection.to_indi();
+                        let mut initial = get_properties.clone();
+                        initial.extend(shared.enable_blob_commands().await);
+                        tokio::select! {
+                            written = shared.write(&mut writer, initial, &mut commands) => {
+                                shared.write_finished(&written).await;
+                                // Writing only finishes without an error once the client is dropped.
+                                if written.is_ok() {
+                                    return writer.shutdown().await;
+                                }
+                            }
+                            _ = shared.read(reader) => {
+                                shared.disconnected(String::from("closed by the server")).await;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        log::warn!("Connecting to INDI server: {}", e);
+                        shared.disconnected(e.to_string()).await;
+                    }
+                }
+                if commands.is_closed() {
+                    return Ok(());
+                }
+                tokio::time::sleep(delay).await;
+            }
+        })];
+        client
+    }
+
+    /// Returns the commands sent as soon as the client connects.
+    fn initial_commands(&self) -> Vec<Command> {
+        if !self.get_properties {
+            return vec![];
+        }
+        vec![Command::GetProperties(GetProperties {
+            version: INDI_PROTOCOL_VERSION.to_string(),
+            device: self.device.clone(),
+            name: self.parameter.clone(),
+        })]
+    }
 
+    /// Returns a client without any tasks, along with the state the tasks share with it and
+    ///  the receiver for the commands it sends.
+    fn build(self, status: ConnectionStatus) -> (Client, Shared, UnboundedReceiver<Command>) {
+        let (feedback, commands) = tokio::sync::mpsc::unbounded_channel::<Command>();
+        let (device_events, _) = tokio::sync::broadcast::channel(self.device_events_capacity);
+        let (parse_errors, _) = tokio::sync::broadcast::channel(self.parse_errors_capacity);
+        let (parse_warnings, _) = tokio::sync::broadcast::channel(self.parse_errors_capacity);
+        let shared = Shared {
+            devices: Arc::new(Notify::new(HashMap::new())),
+            blob_sinks: Default::default(),
+            hooks: Default::default(),
+            device_events,
+            parse_errors,
+            parse_warnings,
+            lenient: self.lenient,
+            drop_stale_updates: self.drop_stale_updates,
+            replies: feedback.downgrade(),
+            status: Arc::new(Notify::new(status)),
+        };
+        let client = Client {
+            devices: shared.devices.clone(),
+            blob_sinks: shared.blob_sinks.clone(),
+            hooks: shared.hooks.clone(),
+            device_events: shared.device_events.clone(),
+            parse_errors: shared.parse_errors.clone(),
+            parse_warnings: shared.parse_warnings.clone(),
+            status: shared.status.clone(),
+            timeouts: self.timeouts,
+            feedback: Some(feedback),
+            _workers: vec![],
+        };
+        (client, shared, commands)
+    }
+}
+
+/// The state a [Client] shares with the tasks talking to the INDI server.
+#[derive(Clone)]
+struct Shared {
+    devices: Arc<Notify<MemoryDeviceStore>>,
+    blob_sinks: BlobSinks,
+    hooks: Hooks,
+    device_events: DeviceEvents,
+    parse_errors: ParseErrors,
+    parse_warnings: ParseWarnings,
+    lenient: bool,
+    drop_stale_updates: bool,
+    // Commands sent in response to the server's.  Weak so the writer still shuts down once
+    // the client drops its sender.
+    replies: WeakUnboundedSender<Command>,
+    status: Arc<Notify<ConnectionStatus>>,
+}
+
+impl Shared {
+    /// Writes `initial`, then the commands sent by the client until it's dropped.
+    async fn write<W: AsyncWriteConnection>(
+        &self,
+        writer: &mut W,
+        initial: Vec<Command>,
+        commands: &mut UnboundedReceiver<Command>,
+    ) -> Result<(), DeError> {
+        for command in initial {
+            writer.write(command).await?;
+        }
         loop {
-            let command = match incoming_commands.recv().await {
+            let command = match commands.recv().await {
                 Some(c) => c,
                 None => break,
             };
-            writer.write(command).await?;
+            if let Some(command) = hooks::run(&self.hooks, Direction::Outgoing, command) {
+                writer.write(command).await?;
+            }
         }
-        writer.shutdown().await?;
         Ok(())
-    });
-    let devices = Arc::new(Notify::new(HashMap::new()));
-    let thread_devices = devices.clone();
-    let reader_thread = tokio::spawn(async move {
+    }
+
+    /// Keeps the devices in sync with the commands read from `reader` until the connection
+    ///  closes.
+    async fn read<R: AsyncReadConnection>(&self, mut reader: R) {
         loop {
             let command = match reader.read().await {
                 Some(c) => c,
                 None => break,
             };
+            let command = match command {
+                Err(DeError::Skipped { fragment, error }) if self.lenient => {
+                    match serialization::lenient::parse(&fragment) {
+                        Ok((command, warnings)) => {
+                            for warning in warnings {
+                                self.parse_warnings.send(warning).ok();
+                            }
+                            Ok(command)
+                        }
+                        Err(_) => Err(DeError::Skipped { fragment, error }),
+                    }
+                }
+                command => command,
+            };
+            let command = match command {
+                Ok(c) => match hooks::run(&self.hooks, Direction::Incoming, c) {
+                    Some(c) => Ok(c),
+                    None => continue,
+                },
+                Err(e) => Err(e),
+            };
             match command {
-                Ok(command) => {
-                    let mut locked_devices = thread_devices.lock().await;
+                Ok(serialization::Command::PingRequest(request)) => {
+                    self.reply(Command::PingReply(request.reply()));
+                }
+                Ok(mut command) => {
+                    if let serialization::Command::SetBlobVector(set) = &mut command {
+                        blob_sink::save(&self.blob_sinks, set).await;
+                    }
+                    let mut locked_devices = self.devices.lock().await;
 
+                    let redefined_blob = match &command {
+                        Command::DefBlobVector(def) => Some((def.device.clone(), def.name.clone())),
+                        _ => None,
+                    };
+                    let lifecycle = Lifecycle::before(&locked_devices, &command).await;
+                    let new_device = command
+                        .device_name()
+                        .filter(|name| {
+                            self.drop_stale_updates && !locked_devices.contains_key(*name)
+                        })
+                        .cloned();
                     let update_result = locked_devices.update(command, |_param| {}).await;
                     if let Err(e) = update_result {
                         dbg!(e);
                     }
+                    if let Some(device) = new_device.and_then(|name| locked_devices.get(&name)) {
+                        device.lock().await.set_drop_stale_updates(true);
+                    }
+                    // A driver that redefines a blob, such as after restarting, may have
+                    //  forgotten it was enabled.
+                    if let Some((device_name, name)) = redefined_blob {
+                        if let Some(device) = locked_devices.get(&device_name) {
+                            let policy = device
+                                .lock()
+                                .await
+                                .blob_policy()
+                                .get(&Some(name.clone()))
+                                .copied();
+                            if let Some(enabled) = policy {
+                                self.reply(Command::EnableBlob(serialization::EnableBlob {
+                                    device: device_name,
+                                    name: Some(name),
+                                    enabled,
+                                }));
+                            }
+                        }
+                    }
+                    if let Some(lifecycle) = lifecycle {
+                        lifecycle.send(&locked_devices, &self.device_events).await;
+                    }
                 }
                 Err(e) => {
                     dbg!(&e);
+                    self.parse_errors.send(Arc::new(e)).ok();
                 }
             }
         }
-    });
-    let c = Client {
-        devices,
-        feedback: Some(feedback),
-        _workers: Some((writer_thread, reader_thread)),
-    };
-    Ok(c)
+    }
+
+    fn reply(&self, command: Command) {
+        if let Some(replies) = self.replies.upgrade() {
+            replies.send(command).ok();
+        }
+    }
+
+    async fn set_status(&self, status: ConnectionStatus) {
+        *self.status.lock().await = status;
+    }
+
+    /// Marks the connection as lost, unless the other task already did so the first reason
+    ///  is kept.
+    async fn disconnected(&self, reason: String) {
+        let mut status = self.status.lock().await;
+        if !matches!(*status, ConnectionStatus::Disconnected { .. }) {
+            *status = ConnectionStatus::Disconnected { reason };
+        }
+    }
+
+    async fn write_finished(&self, written: &Result<(), DeError>) {
+        let reason = match written {
+            Ok(()) => String::from("client shut down"),
+            Err(e) => e.to_string(),
+        };
+        self.disconnected(reason).await;
+    }
+
+    /// Returns the `enableBLOB` commands that recreate every device's blob settings.
+    async fn enable_blob_commands(&self) -> Vec<Command> {
+        let mut commands = vec![];
+        for device in self.devices.lock().await.values() {
+            let device = device.lock().await;
+            commands.extend(
+                device
+                    .enable_blob_commands()
+                    .into_iter()
+                    .map(Command::EnableBlob),
+            );
+        }
+        commands
+    }
+}
+
+/// Whether a [Client] is connected to its INDI server, see
+///  [connection_status](Client::connection_status).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// Waiting for a connection made by [connect_with](ClientBuilder::connect_with).
+    Connecting,
+    Connected,
+    Disconnected {
+        reason: String,
+    },
 }
 
 /// Struct used to keep track of a the devices and their properties.
 pub struct Client {
     devices: Arc<Notify<MemoryDeviceStore>>,
+    blob_sinks: BlobSinks,
+    hooks: Hooks,
+    device_events: DeviceEvents,
+    parse_errors: ParseErrors,
+    parse_warnings: ParseWarnings,
+    status: Arc<Notify<ConnectionStatus>>,
+    timeouts: Timeouts,
     feedback: Option<tokio::sync::mpsc::UnboundedSender<Command>>,
     // connection: T,
     // Used for testing
-    _workers: Option<(
-        tokio::task::JoinHandle<Result<(), DeError>>,
-        tokio::task::JoinHandle<()>,
-    )>,
+    _workers: Vec<tokio::task::JoinHandle<Result<(), DeError>>>,
 }
 
 impl Drop for Client {
@@ -194,7 +728,8 @@ impl Drop for Client {
 }
 
 impl Client {
-    /// Async method that will wait up to 1 second for the device named `name` to be defined
+    /// Async method that will wait up to 1 second, or the [device_timeout](ClientBuilder::device_timeout)
+    ///  the client was built with, for the device named `name` to be defined
     ///  by the INDI server.  The returned `ActiveDevice` (if present) will be associated with
     ///  the `self` client for communicating changes with the INDI server it came from.
     ///
@@ -219,13 +754,16 @@ impl Client {
         name: &str,
     ) -> Result<device::ActiveDevice, notify::Error<E>> {
         let subs = self.devices.subscribe().await;
-        wait_fn(subs, Duration::from_secs(1), |devices| {
+        wait_fn(subs, self.timeouts.device, |devices| {
             if let Some(device) = devices.get(name) {
-                return Ok(notify::Status::Complete(device::ActiveDevice::new(
-                    String::from(name),
-                    device.clone(),
-                    self.feedback.clone(),
-                )));
+                return Ok(notify::Status::Complete(
+                    device::ActiveDevice::new(
+                        String::from(name),
+                        device.clone(),
+                        self.feedback.clone(),
+                    )
+                    .with_timeouts(self.timeouts),
+                ));
             }
 
             Ok(notify::Status::Pending)
@@ -233,16 +771,223 @@ impl Client {
         .await
     }
 
+    /// Returns the devices currently defined whose drivers implement all of `interfaces`,
+    ///  sorted by name, such as to find the cameras without configuring their names.
+    ///  Devices are only included once they define `DRIVER_INFO`, this does not wait for them.
+    /// # Example
+    /// ```no_run
+    /// use indi::client::{device::DeviceInterfaces, Client};
+    /// async fn devices_with_usage_example(client: Client) {
+    ///     let cameras = client.devices_with(DeviceInterfaces::CCD).await;
+    ///     let mounts = client.devices_with(DeviceInterfaces::TELESCOPE).await;
+    /// }
+    /// ```
+    pub async fn devices_with(
+        &self,
+        interfaces: device::DeviceInterfaces,
+    ) -> Vec<device::ActiveDevice> {
+        let devices = self.devices.lock().await;
+        let mut names = vec![];
+        for (name, device) in devices.iter() {
+            if device.lock().await.interfaces().await.contains(interfaces) {
+                names.push(name);
+            }
+        }
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| {
+                device::ActiveDevice::new(
+                    name.clone(),
+                    devices[name].clone(),
+                    self.feedback.clone(),
+                )
+                .with_timeouts(self.timeouts)
+            })
+            .collect()
+    }
+
+    /// Returns whether the client is connected to the INDI server, which changes as the
+    ///  connection is lost and, for clients made with
+    ///  [connect_with](ClientBuilder::connect_with), made again.  Devices stay in
+    ///  [get_devices](Client::get_devices) while disconnected.
+    /// # Example
+    /// ```no_run
+    /// use indi::client::{Client, ConnectionStatus};
+    /// use tokio_stream::StreamExt;
+    /// async fn connection_status_usage_example(client: Client) {
+    ///     let mut status = client.connection_status().subscribe().await;
+    ///     while let Some(Ok(status)) = status.next().await {
+    ///         if let ConnectionStatus::Disconnected { reason } = &*status {
+    ///             println!("Lost connection: {}", reason);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn connection_status(&self) -> Arc<Notify<ConnectionStatus>> {
+        self.status.clone()
+    }
+
     /// Returns the a read-only lock on client's MemoryDeviceStore.
     pub fn get_devices(&self) -> Arc<Notify<MemoryDeviceStore>> {
         self.devices.clone()
     }
 
+    /// Writes blobs for `device` to files instead of keeping them in memory, which matters
+    ///  for long sessions on machines without much of it.  The parameter still updates when
+    ///  a blob arrives, but with empty data.  Compressed blobs are decompressed as they're
+    ///  written when the `zlib` feature is enabled.  A blob that couldn't be written, such as
+    ///  one that fails to decompress, is kept in the parameter as usual.
+    ///
+    /// # Arguments
+    /// * `device` - Name of the device whose blobs should be saved.
+    /// * `parameter` - The blob parameter to save, or `None` for all of the device's blob parameters.
+    /// * `name` - Returns the path to write each blob to.  Missing directories are created.
+    ///
+    /// Returns a receiver for the path each blob was written to.  Blobs stop being saved once
+    ///  it is dropped.
+    /// # Example
+    /// ```no_run
+    /// use indi::client::Client;
+    /// async fn save_blobs_usage_example(client: Client) {
+    ///     let mut saved = client.save_blobs("CCD Simulator", Some("CCD1"), |set, blob| {
+    ///         let time = set.timestamp.map(|t| t.timestamp()).unwrap_or_default();
+    ///         format!("images/{}{}", time, blob.format).into()
+    ///     });
+    ///     while let Some(path) = saved.recv().await {
+    ///         println!("Saved {:?}", path.expect("Saving image"));
+    ///     }
+    /// }
+    /// ```
+    pub fn save_blobs(
+        &self,
+        device: &str,
+        parameter: Option<&str>,
+        name: impl FnMut(&serialization::SetBlobVector, &serialization::OneBlob) -> PathBuf
+            + Send
+            + 'static,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<Result<PathBuf, std::io::Error>> {
+        let (saved, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let sink = BlobSink {
+            device: device.to_string(),
+            parameter: parameter.map(String::from),
+            name: Box::new(name),
+            saved,
+        };
+        self.blob_sinks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(sink);
+        receiver
+    }
+
+    /// Adds a hook that sees every command read from or written to the INDI server from now
+    ///  on, and may change or drop it, for logging traffic or refusing commands that shouldn't
+    ///  be sent.  Hooks run in the order they were added.  Changes whose command is dropped
+    ///  wait for the server until they time out.
+    /// # Example
+    /// ```no_run
+    /// use indi::{client::{hooks::Direction, Client}, serialization::Command};
+    /// fn add_hook_usage_example(client: Client) {
+    ///     client.add_hook(|direction, command| {
+    ///         if let (Direction::Outgoing, Command::NewNumberVector(new)) = (direction, &command) {
+    ///             if new.name == "CCD_EXPOSURE" {
+    ///                 // The dome is closed.
+    ///                 return None;
+    ///             }
+    ///         }
+    ///         Some(command)
+    ///     });
+    /// }
+    /// ```
+    pub fn add_hook(
+        &self,
+        hook: impl FnMut(Direction, Command) -> Option<Command> + Send + 'static,
+    ) {
+        self.hooks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(hook));
+    }
+
+    /// Returns a receiver for devices and parameters being defined and deleted by the INDI
+    ///  server from now on, so that they can be followed without diffing
+    ///  [get_devices](Client::get_devices) on every change.
+    ///
+    /// The receiver holds the most recent 1024 events, if it falls further behind
+    ///  `recv` returns [Lagged](tokio::sync::broadcast::error::RecvError::Lagged).
+    /// # Example
+    /// ```no_run
+    /// use indi::client::{Client, events::DeviceEvent};
+    /// async fn device_events_usage_example(client: Client) {
+    ///     let mut events = client.device_events();
+    ///     while let Ok(event) = events.recv().await {
+    ///         if let DeviceEvent::DeviceAdded(name) = event {
+    ///             println!("{} connected", name);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn device_events(&self) -> tokio::sync::broadcast::Receiver<DeviceEvent> {
+        self.device_events.subscribe()
+    }
+
+    /// Sends a `getProperties` command, asking the INDI server to define the matching
+    ///  parameters again, such as after a driver was restarted.  The client already sends one
+    ///  for the `device` and `parameter` it was created with when it connects.
+    ///
+    /// # Arguments
+    /// * `device` - The device whose parameters to get, or `None` for all devices.
+    /// * `name` - The parameter to get, or `None` for all of them.  Only used with a `device`.
+    /// # Example
+    /// ```no_run
+    /// use indi::client::Client;
+    /// fn get_properties_usage_example(client: Client) {
+    ///     client
+    ///         .get_properties(Some("CCD Simulator"), Some("CCD_EXPOSURE"))
+    ///         .expect("Sending getProperties");
+    /// }
+    /// ```
+    pub fn get_properties(
+        &self,
+        device: Option<&str>,
+        name: Option<&str>,
+    ) -> Result<(), device::SendError<Command>> {
+        let feedback = self
+            .feedback
+            .as_ref()
+            .ok_or(device::SendError::Disconnected)?;
+        feedback.send(Command::GetProperties(GetProperties {
+            version: INDI_PROTOCOL_VERSION.to_string(),
+            device: device.map(String::from),
+            name: device.and(name).map(String::from),
+        }))?;
+        Ok(())
+    }
+
+    /// Returns a receiver for errors reading commands from the INDI server from now on.
+    ///  Malformed xml is reported as a [DeError::Skipped] holding the skipped text, after which
+    ///  the client carries on from the next command.  The receiver holds the most recent 64
+    ///  errors.
+    pub fn parse_errors(&self) -> tokio::sync::broadcast::Receiver<Arc<DeError>> {
+        self.parse_errors.subscribe()
+    }
+
+    /// Returns a receiver for the problems worked around while parsing commands from the
+    ///  INDI server from now on, for clients built with [lenient](ClientBuilder::lenient)
+    ///  parsing.  The receiver holds the most recent 64 warnings.
+    pub fn parse_warnings(&self) -> tokio::sync::broadcast::Receiver<ParseWarning> {
+        self.parse_warnings.subscribe()
+    }
+
     pub fn shutdown(&mut self) {
         self.feedback.take();
     }
 }
 
+type ParseErrors = tokio::sync::broadcast::Sender<Arc<DeError>>;
+type ParseWarnings = tokio::sync::broadcast::Sender<ParseWarning>;
+
 pub type MemoryDeviceStore = HashMap<String, Arc<Notify<device::Device>>>;
 
 pub trait DeviceStore {
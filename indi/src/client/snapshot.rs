@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use super::{
+    device::{Device, DeviceSnapshot},
+    MemoryDeviceStore, Notify,
+};
+
+/// Exports and imports the state of every device in a store as JSON, so a UI can show the
+/// last known state before it reconnects, or a test can compare against a golden file.
+#[allow(async_fn_in_trait)]
+pub trait Snapshot {
+    /// Returns the state of every device and parameter as pretty printed JSON.  Devices are
+    /// sorted by name and object keys are sorted, so the same state always gives the same JSON.
+    async fn snapshot(&self) -> Result<String, serde_json::Error>;
+
+    /// Replaces the state of the store with the devices in `json`, as returned by
+    /// [snapshot](Snapshot::snapshot).  Devices missing from `json` are removed.  Existing
+    /// devices and parameters are updated in place with [Device::restore], so subscribers and
+    /// pending changes keep working.
+    ///
+    /// # Arguments
+    /// * `json` - A snapshot previously returned by [snapshot](Snapshot::snapshot).
+    async fn restore(&mut self, json: &str) -> Result<(), serde_json::Error>;
+}
+
+impl Snapshot for MemoryDeviceStore {
+    async fn snapshot(&self) -> Result<String, serde_json::Error> {
+        let mut devices = Vec::with_capacity(self.len());
+        for device in self.values() {
+            devices.push(device.lock().await.snapshot().await);
+        }
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        // Going through a Value sorts the keys of the parameters' value maps.
+        serde_json::to_string_pretty(&serde_json::to_value(devices)?)
+    }
+
+    async fn restore(&mut self, json: &str) -> Result<(), serde_json::Error> {
+        let devices: Vec<DeviceSnapshot> = serde_json::from_str(json)?;
+        self.retain(|name, _| devices.iter().any(|d| d.name == *name));
+        for snapshot in devices {
+            match self.get(&snapshot.name) {
+                Some(device) => device.lock().await.restore(snapshot).await,
+                None => {
+                    self.insert(
+                        snapshot.name.clone(),
+                        Arc::new(Notify::new(Device::from(snapshot))),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::{client::DeviceStore, serialization::CommandIter};
+
+    #[tokio::test]
+    async fn test_snapshot_restore() {
+        let mut store = MemoryDeviceStore::new();
+        let commands = CommandIter::new(Cursor::new(
+            r#"<defSwitchVector device="CCD Simulator" name="CONNECTION" label="Connection" group="Main Control" state="Idle" perm="rw" rule="OneOfMany" timeout="60" timestamp="2022-09-06T01:41:22">
+    <defSwitch name="CONNECT" label="Connect">
+On
+    </defSwitch>
+    <defSwitch name="DISCONNECT" label="Disconnect">
+Off
+    </defSwitch>
+</defSwitchVector>
+<defTextVector device="Telescope Simulator" name="DRIVER_INFO" label="Driver Info" group="General Info" state="Idle" perm="ro" timeout="60" timestamp="2022-09-06T01:41:22">
+    <defText name="DRIVER_NAME" label="Name">
+Telescope Simulator
+    </defText>
+</defTextVector>
+"#,
+        ));
+        for command in commands {
+            store.update(command.unwrap(), |_| ()).await.unwrap();
+        }
+
+        let json = store.snapshot().await.unwrap();
+        assert!(
+            json.find("\"CONNECT\"").unwrap() < json.find("\"DISCONNECT\"").unwrap(),
+            "{}",
+            json
+        );
+        assert!(json.find("CCD Simulator").unwrap() < json.find("Telescope Simulator").unwrap());
+
+        let mut restored = MemoryDeviceStore::new();
+        let commands = CommandIter::new(Cursor::new(
+            r#"<defTextVector device="Telescope Simulator" name="DRIVER_INFO" label="Driver Info" group="General Info" state="Idle" perm="ro" timeout="60" timestamp="2022-09-06T01:41:22">
+    <defText name="DRIVER_NAME" label="Name">
+Old Name
+    </defText>
+</defTextVector>
+<defTextVector device="Telescope Simulator" name="DRIVER_EXEC" label="Driver Exec" group="General Info" state="Idle" perm="ro" timeout="60" timestamp="2022-09-06T01:41:22">
+    <defText name="DRIVER_EXEC" label="Exec">
+indi_simulator_telescope
+    </defText>
+</defTextVector>
+<message device="Telescope Simulator" timestamp="2022-09-06T01:41:22" message="Hello"/>
+<defSwitchVector device="Focuser Simulator" name="CONNECTION" label="Connection" group="Main Control" state="Idle" perm="rw" rule="OneOfMany" timeout="60" timestamp="2022-09-06T01:41:22">
+    <defSwitch name="CONNECT" label="Connect">
+Off
+    </defSwitch>
+</defSwitchVector>
+"#,
+        ));
+        for command in commands {
+            restored.update(command.unwrap(), |_| ()).await.unwrap();
+        }
+        let existing = restored["Telescope Simulator"].clone();
+        let driver_info = existing.lock().await.get_parameters()["DRIVER_INFO"].clone();
+        let mut subscription = driver_info.subscribe().await;
+        subscription.next().await;
+
+        restored.restore(&json).await.unwrap();
+
+        assert_eq!(restored.len(), 2);
+        assert!(!restored.contains_key("Focuser Simulator"));
+        assert!(Arc::ptr_eq(&restored["Telescope Simulator"], &existing));
+        {
+            let device = existing.lock().await;
+            assert_eq!(device.parameter_names(), &vec![String::from("DRIVER_INFO")]);
+            assert!(Arc::ptr_eq(
+                &device.get_parameters()["DRIVER_INFO"],
+                &driver_info
+            ));
+            assert_eq!(device.messages().len(), 1);
+        }
+        // Subscribers to existing parameters see the restored values.
+        let updated = subscription.next().await.unwrap().unwrap();
+        let texts = updated
+            .get_values::<std::collections::HashMap<String, crate::Text>>()
+            .unwrap();
+        assert_eq!(texts["DRIVER_NAME"].value, "Telescope Simulator");
+        assert_eq!(
+            restored["CCD Simulator"].lock().await.parameter_names(),
+            &vec![String::from("CONNECTION")]
+        );
+    }
+}
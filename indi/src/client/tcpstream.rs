@@ -1,6 +1,9 @@
-use quick_xml::{events::Event, NsReader};
+use quick_xml::{
+    events::{attributes::AttrError, BytesStart, Event},
+    NsReader,
+};
 use tokio::{
-    io::{AsyncRead, AsyncWriteExt},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
     net::{
         tcp::{OwnedReadHalf, OwnedWriteHalf},
         TcpStream,
@@ -18,96 +21,196 @@ impl AsyncClientConnection for TcpStream {
 
     fn to_indi(self) -> (Self::Writer, Self::Reader) {
         let (reader, writer) = self.into_split();
-        let reader = NsReader::from_reader(BufReader::new(reader));
-
-        (AsyncIndiWriter { writer }, AsyncIndiReader::new(reader))
+        split(reader, writer)
     }
 }
 
+/// Returns the INDI writer and reader for a connection split into `reader` and `writer`.
+pub(crate) fn split<R: AsyncRead + Unpin, W>(
+    reader: R,
+    writer: W,
+) -> (AsyncIndiWriter<W>, AsyncIndiReader<R>) {
+    let reader = NsReader::from_reader(BufReader::new(reader));
+    (AsyncIndiWriter { writer }, AsyncIndiReader::new(reader))
+}
+
 pub struct AsyncIndiReader<T> {
     reader: NsReader<BufReader<T>>,
+    // Reused across reads so a steady stream of commands doesn't allocate once
+    // these have grown to the size of the largest command seen.
+    buffer: Vec<u8>,
+    document: Vec<u8>,
+    // Set after malformed xml, until the start of the next command is found.
+    resync: bool,
 }
 
 impl<T: AsyncRead + Unpin> AsyncIndiReader<T> {
     fn new(reader: quick_xml::reader::NsReader<BufReader<T>>) -> AsyncIndiReader<T> {
-        AsyncIndiReader { reader }
+        AsyncIndiReader {
+            reader,
+            buffer: Vec::new(),
+            document: Vec::new(),
+            resync: false,
+        }
     }
 
-    async fn read_xml_documents(&mut self) -> Option<Result<String, crate::DeError>> {
-        let mut buffer = Vec::new();
-        let mut document = Vec::new();
+    /// Reads the next top level element into `self.document`, returning it as a `str`
+    /// borrowed from the reader rather than a newly allocated `String`.
+    ///
+    /// If the xml is malformed the error is returned along with what was read of the
+    /// element, and the following reads skip ahead to the start of the next INDI command.
+    async fn read_xml_document(&mut self) -> Option<Result<&str, crate::DeError>> {
+        self.document.clear();
         let mut depth = 0;
         loop {
-            let event = match self.reader.read_event_into_async(&mut buffer).await {
+            self.buffer.clear();
+            let event = match self.reader.read_event_into_async(&mut self.buffer).await {
                 Ok(e) => e,
-                Err(e) => return Some(Err(e.into())),
+                Err(e) => return Some(Err(skip(&mut self.resync, &self.document, e.into()))),
             };
             match event {
                 Event::Start(e) => {
+                    if depth == 0 && !starts_command(&mut self.resync, &e) {
+                        continue;
+                    }
                     depth += 1;
-                    document.extend_from_slice(b"<");
-                    document.extend_from_slice(e.name().as_ref());
-                    for attr in e.attributes() {
-                        let attr = match attr {
-                            Ok(d) => d,
-                            Err(e) => return Some(Err(e.into())),
-                        };
-                        document.extend_from_slice(b" ");
-                        document.extend_from_slice(attr.key.as_ref());
-                        document.extend_from_slice(b"=\"");
-                        document.extend_from_slice(&attr.value);
-                        document.extend_from_slice(b"\"");
+                    if let Err(e) = write_tag(&mut self.document, &e) {
+                        return Some(Err(skip(&mut self.resync, &self.document, e.into())));
+                    }
+                    self.document.extend_from_slice(b">");
+                }
+                Event::Empty(e) => {
+                    if depth == 0 && !starts_command(&mut self.resync, &e) {
+                        continue;
+                    }
+                    if let Err(e) = write_tag(&mut self.document, &e) {
+                        return Some(Err(skip(&mut self.resync, &self.document, e.into())));
+                    }
+                    self.document.extend_from_slice(b"/>");
+                    // Self-closing commands such as `pingRequest` are complete documents.
+                    if depth == 0 {
+                        break;
                     }
-                    document.extend_from_slice(b">");
                 }
                 Event::End(e) => {
+                    // The end of an element skipped while resynchronizing.
+                    if depth == 0 {
+                        continue;
+                    }
                     depth -= 1;
-                    document.extend_from_slice(b"</");
-                    document.extend_from_slice(e.name().as_ref());
-                    document.extend_from_slice(b">");
+                    self.document.extend_from_slice(b"</");
+                    self.document.extend_from_slice(e.name().as_ref());
+                    self.document.extend_from_slice(b">");
                     if depth == 0 {
-                        let doc = match String::from_utf8(document) {
-                            Ok(d) => d,
-                            Err(e) => return Some(Err(e.into())),
-                        };
-                        return Some(Ok(doc));
+                        break;
                     }
                 }
-                Event::Text(e) => {
-                    document.extend_from_slice(&e.into_inner());
+                // Text between commands is ignored.
+                Event::Text(e) if depth > 0 => {
+                    self.document.extend_from_slice(&e);
                 }
                 Event::Eof => return None,
                 _ => {
                     // Handle other event types if needed
                 }
             }
-            buffer.clear();
         }
+        Some(std::str::from_utf8(&self.document).map_err(|e| e.into()))
+    }
+}
+
+/// Whether `tag`, found between commands, starts the next one.  While resynchronizing only
+/// known INDI commands do, so elements left over from malformed xml are skipped.
+fn starts_command(resync: &mut bool, tag: &BytesStart) -> bool {
+    if *resync {
+        *resync = !COMMANDS.contains(&tag.name().as_ref());
+    }
+    !*resync
+}
+
+/// Starts resynchronizing after `error`, wrapping it with the `document` read so far.
+fn skip(resync: &mut bool, document: &[u8], error: crate::DeError) -> crate::DeError {
+    *resync = true;
+    crate::DeError::Skipped {
+        fragment: String::from_utf8_lossy(document).into_owned(),
+        error: Box::new(error),
+    }
+}
+
+/// The top level elements of the INDI protocol.
+const COMMANDS: &[&[u8]] = &[
+    b"defTextVector",
+    b"defNumberVector",
+    b"defSwitchVector",
+    b"defLightVector",
+    b"defBLOBVector",
+    b"setTextVector",
+    b"setNumberVector",
+    b"setSwitchVector",
+    b"setLightVector",
+    b"setBLOBVector",
+    b"newTextVector",
+    b"newNumberVector",
+    b"newSwitchVector",
+    b"newBLOBVector",
+    b"message",
+    b"delProperty",
+    b"getProperties",
+    b"enableBLOB",
+    b"pingRequest",
+    b"pingReply",
+];
+
+/// Writes the opening of `tag`, its name and attributes, leaving it for the caller to close.
+fn write_tag(document: &mut Vec<u8>, tag: &BytesStart) -> Result<(), AttrError> {
+    document.extend_from_slice(b"<");
+    document.extend_from_slice(tag.name().as_ref());
+    for attr in tag.attributes() {
+        let attr = attr?;
+        document.extend_from_slice(b" ");
+        document.extend_from_slice(attr.key.as_ref());
+        document.extend_from_slice(b"=\"");
+        document.extend_from_slice(&attr.value);
+        document.extend_from_slice(b"\"");
     }
+    Ok(())
 }
 
 impl<T: AsyncRead + Unpin + Send> AsyncReadConnection for AsyncIndiReader<T> {
     async fn read(&mut self) -> Option<Result<crate::Command, crate::DeError>> {
-        let doc = match self.read_xml_documents().await? {
+        let doc = match self.read_xml_document().await? {
             Ok(doc) => doc,
-            Err(e) => return Some(Err(e.into())),
+            Err(e) => return Some(Err(e)),
         };
-        let cmd = quick_xml::de::from_str::<crate::Command>(&doc).map_err(|x| x.into());
+        // The document is borrowed, but commands own their names and values, so these are
+        //  still copied out of it.  Borrowing them would need a lifetime on every
+        //  serialization type and is left for a separate change.
+        let cmd =
+            quick_xml::de::from_str::<crate::Command>(doc).map_err(|e| crate::DeError::Skipped {
+                fragment: String::from(doc),
+                error: Box::new(e.into()),
+            });
 
         return Some(cmd);
     }
 }
 
-pub struct AsyncIndiWriter {
-    writer: OwnedWriteHalf,
+pub struct AsyncIndiWriter<W = OwnedWriteHalf> {
+    writer: W,
 }
 
-impl AsyncWriteConnection for AsyncIndiWriter {
+impl<W: AsyncWrite + Unpin + Send> AsyncWriteConnection for AsyncIndiWriter<W> {
     async fn write(&mut self, cmd: Command) -> Result<(), crate::DeError> {
-        let buffer = quick_xml::se::to_string(&cmd)?;
-        self.writer.write(buffer.as_bytes()).await?;
+        match &cmd {
+            // Blobs can be large, so they are encoded as they're written
+            Command::SetBlobVector(set) => set.write_to(&mut self.writer).await?,
+            cmd => {
+                let buffer = quick_xml::se::to_string(cmd)?;
+                self.writer.write_all(buffer.as_bytes()).await?;
+            }
+        }
 
-        self.writer.write(b"\n").await?;
+        self.writer.write_all(b"\n").await?;
         self.writer.flush().await?;
         Ok(())
     }
@@ -119,18 +222,513 @@ impl AsyncWriteConnection for AsyncIndiWriter {
 
 #[cfg(test)]
 mod test {
-    use super::*;
     use crate::client::new;
+    use twinkle_testkit::indi_simulator::IndiSimulator;
 
     #[tokio::test]
     async fn test_threads_stop_on_shutdown() {
-        let connection = TcpStream::connect("indi:7624")
-            .await
-            .expect("connecting to indi");
+        let indi = IndiSimulator::from_env().await.expect("waiting for indi");
+        let connection = indi.connection().await.expect("connecting to indi");
         let mut client = new(connection, None, None).expect("Making client");
         client.shutdown();
-        if let Some((reader, writer)) = client._workers.take() {
-            let _ = tokio::join!(reader, writer);
+        for worker in client._workers.drain(..) {
+            let _ = worker.await;
         }
     }
+
+    #[tokio::test]
+    async fn test_reads_consecutive_commands() {
+        use super::AsyncIndiReader;
+        use crate::client::AsyncReadConnection;
+        use crate::Command;
+        use quick_xml::NsReader;
+        use tokio::io::BufReader;
+
+        let xml: &[u8] = br#"<defSwitchVector device="CCD Simulator" name="CONNECTION" label="Connection" group="Main Control" state="Idle" perm="rw" rule="OneOfMany" timeout="60" timestamp="2022-09-06T01:41:22">
+    <defSwitch name="CONNECT" label="Connect">
+Off
+    </defSwitch>
+</defSwitchVector>
+<pingRequest uid="1"/>
+<message device="CCD Simulator" timestamp="2022-09-06T01:41:23" message="Hello &amp; welcome"/>
+"#;
+        let mut reader = AsyncIndiReader::new(NsReader::from_reader(BufReader::new(xml)));
+
+        assert!(matches!(
+            reader.read().await,
+            Some(Ok(Command::DefSwitchVector(ref v))) if v.name == "CONNECTION"
+        ));
+        assert!(matches!(
+            reader.read().await,
+            Some(Ok(Command::PingRequest(ref p))) if p.uid == "1"
+        ));
+        assert!(matches!(
+            reader.read().await,
+            Some(Ok(Command::Message(ref m))) if m.message.as_deref() == Some("Hello & welcome")
+        ));
+        assert!(reader.read().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resyncs_after_malformed_xml() {
+        use super::AsyncIndiReader;
+        use crate::client::AsyncReadConnection;
+        use crate::{Command, DeError};
+        use quick_xml::NsReader;
+        use tokio::io::BufReader;
+
+        let xml: &[u8] = br#"<defTextVector device="CCD Simulator" name="DRIVER_INFO" label="Driver Info" group="General Info" state="Idle" perm="ro">
+    <defText name="DRIVER_NAME" label="Name">
+CCD Simulator
+    </defTextt>
+    <defText name="DRIVER_EXEC" label="Exec">
+indi_simulator_ccd
+    </defText>
+</defTextVector>
+<pingRequest uid="1"/>
+garbage between commands
+<setNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" state="Bogus"></setNumberVector>
+<pingRequest uid="2"/>
+"#;
+        let mut reader = AsyncIndiReader::new(NsReader::from_reader(BufReader::new(xml)));
+
+        match reader.read().await {
+            Some(Err(DeError::Skipped { fragment, error })) => {
+                assert!(fragment.starts_with("<defTextVector"), "{}", fragment);
+                assert!(matches!(*error, DeError::XmlError(_)), "{:?}", error);
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
+        assert!(matches!(
+            reader.read().await,
+            Some(Ok(Command::PingRequest(ref p))) if p.uid == "1"
+        ));
+        match reader.read().await {
+            Some(Err(DeError::Skipped { fragment, .. })) => {
+                assert!(fragment.starts_with("<setNumberVector"), "{}", fragment);
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
+        assert!(matches!(
+            reader.read().await,
+            Some(Ok(Command::PingRequest(ref p))) if p.uid == "2"
+        ));
+        assert!(reader.read().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_properties() {
+        use tokio::io::{AsyncBufReadExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut client = new(
+            tokio::net::TcpStream::connect(addr).await.unwrap(),
+            Some("CCD Simulator"),
+            None,
+        )
+        .expect("Making client");
+
+        let (server, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(server).lines();
+        let initial = lines.next_line().await.unwrap().unwrap();
+        assert!(initial.starts_with("<getProperties"));
+        assert!(initial.contains("device=\"CCD Simulator\""));
+
+        client
+            .get_properties(Some("CCD Simulator"), Some("CCD_EXPOSURE"))
+            .unwrap();
+        let scoped = lines.next_line().await.unwrap().unwrap();
+        assert!(scoped.contains("device=\"CCD Simulator\""));
+        assert!(scoped.contains("name=\"CCD_EXPOSURE\""));
+
+        client.shutdown();
+        assert!(matches!(
+            client.get_properties(None, None),
+            Err(crate::client::device::SendError::Disconnected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_builder() {
+        use tokio::io::{AsyncBufReadExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = crate::client::ClientBuilder::new()
+            .get_properties(false)
+            .device_timeout(std::time::Duration::from_millis(50))
+            .connect(tokio::net::TcpStream::connect(addr).await.unwrap())
+            .expect("Making client");
+
+        let (server, _) = listener.accept().await.unwrap();
+        let start = std::time::Instant::now();
+        assert!(matches!(
+            client.get_device::<()>("CCD Simulator").await,
+            Err(crate::client::notify::Error::Timeout)
+        ));
+        assert!(start.elapsed() < std::time::Duration::from_millis(500));
+
+        client.get_properties(None, None).unwrap();
+        let mut lines = BufReader::new(server).lines();
+        let first = lines.next_line().await.unwrap().unwrap();
+        assert!(first.starts_with("<getProperties"));
+        assert!(!first.contains("device="));
+    }
+
+    #[tokio::test]
+    async fn test_reconnects() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        const CCD: &str = r#"<defBLOBVector device="CCD Simulator" name="CCD1" state="Idle" perm="ro">
+<defBLOB name="CCD1"/>
+</defBLOBVector>
+<defSwitchVector device="CCD Simulator" name="CONNECTION" state="Idle" perm="rw" rule="OneOfMany" timeout="1">
+<defSwitch name="CONNECT">{}</defSwitch>
+<defSwitch name="DISCONNECT">Off</defSwitch>
+</defSwitchVector>
+"#;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = crate::client::ClientBuilder::new()
+            .reconnect_delay(std::time::Duration::from_millis(10))
+            .connect_with(move || tokio::net::TcpStream::connect(addr));
+
+        let (server, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(server).lines();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<getProperties"));
+        lines
+            .get_mut()
+            .write_all(CCD.replace("{}", "Off").as_bytes())
+            .await
+            .unwrap();
+        let camera = client.get_device::<()>("CCD Simulator").await.unwrap();
+        camera
+            .enable_blob(Some("CCD1"), crate::BlobEnable::Also)
+            .await
+            .unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<enableBLOB"));
+        let connection = camera.get_parameter("CONNECTION").await.unwrap();
+        drop(lines);
+
+        let (server, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(server).lines();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<getProperties"));
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<enableBLOB"), "{}", line);
+        assert!(line.contains("name=\"CCD1\""));
+
+        // The parameter from before the connection dropped is updated by the new definition.
+        let mut changes = connection.changes();
+        lines
+            .get_mut()
+            .write_all(CCD.replace("{}", "On").as_bytes())
+            .await
+            .unwrap();
+        use tokio_stream::StreamExt;
+        let param = changes.next().await.unwrap().unwrap();
+        let values = param
+            .get_values::<std::collections::HashMap<String, crate::Switch>>()
+            .unwrap();
+        assert_eq!(values["CONNECT"].value, crate::SwitchState::On);
+        assert_eq!(
+            client.get_devices().lock().await["CCD Simulator"]
+                .lock()
+                .await
+                .parameter_names()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blob_reenabled_on_redefinition() {
+        use crate::client::device::BlobPolicy;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        const CCD: &[u8] =
+            br#"<defBLOBVector device="CCD Simulator" name="CCD1" state="Idle" perm="ro">
+<defBLOB name="CCD1"/>
+</defBLOBVector>
+<defBLOBVector device="CCD Simulator" name="CCD2" state="Idle" perm="ro">
+<defBLOB name="CCD2"/>
+</defBLOBVector>
+"#;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = new(
+            tokio::net::TcpStream::connect(addr).await.unwrap(),
+            None,
+            None,
+        )
+        .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(server).lines();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<getProperties"));
+        lines.get_mut().write_all(CCD).await.unwrap();
+
+        let camera = client.get_device::<()>("CCD Simulator").await.unwrap();
+        camera
+            .set_blob_policy(BlobPolicy::parameter("CCD1", crate::BlobEnable::Only))
+            .await
+            .unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<enableBLOB"), "{}", line);
+        assert_eq!(
+            client.get_devices().lock().await["CCD Simulator"]
+                .lock()
+                .await
+                .blob_policies(),
+            vec![BlobPolicy::parameter("CCD1", crate::BlobEnable::Only)]
+        );
+
+        // Only CCD1 had a policy of its own to send again.
+        lines
+            .get_mut()
+            .write_all(br#"<delProperty device="CCD Simulator"/>"#)
+            .await
+            .unwrap();
+        lines.get_mut().write_all(CCD).await.unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<enableBLOB"), "{}", line);
+        assert!(line.contains("name=\"CCD1\""), "{}", line);
+        assert!(line.contains(">Only<"), "{}", line);
+        assert!(
+            tokio::time::timeout(std::time::Duration::from_millis(100), lines.next_line())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lenient_parsing() {
+        use crate::client::ClientBuilder;
+        use tokio::io::AsyncWriteExt;
+
+        const FOCUSER: &[u8] = br#"<defNumberVector device="Focuser" name="ABS_FOCUS_POSITION" state="ok">
+<defNumber name="FOCUS_ABSOLUTE_POSITION" format="%6.0f" min="0" max="100000" step="1">1200</defNumber>
+</defNumberVector>
+"#;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let strict = new(
+            tokio::net::TcpStream::connect(addr).await.unwrap(),
+            None,
+            None,
+        )
+        .unwrap();
+        let mut errors = strict.parse_errors();
+        let (mut server, _) = listener.accept().await.unwrap();
+        server.write_all(FOCUSER).await.unwrap();
+        assert!(matches!(
+            *errors.recv().await.unwrap(),
+            crate::DeError::Skipped { .. }
+        ));
+        assert!(strict.get_device::<()>("Focuser").await.is_err());
+
+        let lenient = ClientBuilder::new()
+            .lenient(true)
+            .connect(tokio::net::TcpStream::connect(addr).await.unwrap())
+            .unwrap();
+        let mut warnings = lenient.parse_warnings();
+        let (mut server, _) = listener.accept().await.unwrap();
+        server.write_all(FOCUSER).await.unwrap();
+        let focuser = lenient.get_device::<()>("Focuser").await.unwrap();
+        let position = focuser.get_parameter("ABS_FOCUS_POSITION").await.unwrap();
+        assert_eq!(*position.lock().await.get_state(), crate::PropertyState::Ok);
+        assert_eq!(
+            position
+                .lock()
+                .await
+                .value_of::<f64>("FOCUS_ABSOLUTE_POSITION")
+                .unwrap(),
+            1200.0
+        );
+        assert_eq!(
+            warnings.recv().await.unwrap().to_string(),
+            r#"<defNumberVector> has "ok" instead of "Ok""#
+        );
+        assert_eq!(
+            warnings.recv().await.unwrap().to_string(),
+            r#"<defNumberVector> is missing perm, using "ro""#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drop_stale_updates() {
+        use crate::client::ClientBuilder;
+        use futures::StreamExt;
+        use tokio::io::AsyncWriteExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = ClientBuilder::new()
+            .drop_stale_updates(true)
+            .connect(tokio::net::TcpStream::connect(addr).await.unwrap())
+            .unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+        server
+            .write_all(
+                br#"<defNumberVector device="Focuser" name="ABS_FOCUS_POSITION" state="Ok" perm="rw" timestamp="2022-09-06T01:41:30">
+<defNumber name="FOCUS_ABSOLUTE_POSITION" format="%6.0f" min="0" max="100000" step="1">1200</defNumber>
+</defNumberVector>
+<setNumberVector device="Focuser" name="ABS_FOCUS_POSITION" state="Ok" timestamp="2022-09-06T01:41:20">
+<oneNumber name="FOCUS_ABSOLUTE_POSITION">1000</oneNumber>
+</setNumberVector>
+<setNumberVector device="Focuser" name="ABS_FOCUS_POSITION" state="Busy" timestamp="2022-09-06T01:41:40">
+<oneNumber name="FOCUS_ABSOLUTE_POSITION">1100</oneNumber>
+</setNumberVector>
+"#,
+            )
+            .await
+            .unwrap();
+
+        let focuser = client.get_device::<()>("Focuser").await.unwrap();
+        let position = focuser.get_parameter("ABS_FOCUS_POSITION").await.unwrap();
+        let mut updates = position.subscribe().await;
+        while *updates.next().await.unwrap().unwrap().get_state() != crate::PropertyState::Busy {}
+        // The older update was dropped rather than applied.
+        assert_eq!(position.lock().await.gen(), std::num::Wrapping(1));
+    }
+
+    #[tokio::test]
+    async fn test_devices_with() {
+        use crate::client::device::DeviceInterfaces;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let info = |device: &str, interfaces: u32| {
+            format!(
+                r#"<defTextVector device="{}" name="DRIVER_INFO" label="Driver Info" group="General Info" state="Idle" perm="ro">
+<defText name="DRIVER_NAME">{}</defText>
+<defText name="DRIVER_INTERFACE">{}</defText>
+</defTextVector>
+"#,
+                device, device, interfaces
+            )
+        };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = new(
+            tokio::net::TcpStream::connect(addr).await.unwrap(),
+            None,
+            None,
+        )
+        .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(server).lines();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<getProperties"));
+        for (device, interfaces) in [
+            ("CCD Simulator", 0b110),
+            ("Telescope Simulator", 0b101),
+            ("Focuser Simulator", 0b1000),
+        ] {
+            lines
+                .get_mut()
+                .write_all(info(device, interfaces).as_bytes())
+                .await
+                .unwrap();
+        }
+        client.get_device::<()>("Focuser Simulator").await.unwrap();
+
+        let names = |devices: Vec<crate::client::device::ActiveDevice>| {
+            devices
+                .iter()
+                .map(|d| d.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(client.devices_with(DeviceInterfaces::CCD).await),
+            vec!["CCD Simulator"]
+        );
+        assert_eq!(
+            names(client.devices_with(DeviceInterfaces::GUIDER).await),
+            vec!["CCD Simulator", "Telescope Simulator"]
+        );
+        assert_eq!(
+            names(
+                client
+                    .devices_with(DeviceInterfaces::TELESCOPE | DeviceInterfaces::GUIDER)
+                    .await
+            ),
+            vec!["Telescope Simulator"]
+        );
+        assert!(client.devices_with(DeviceInterfaces::DOME).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connection_status() {
+        use crate::client::{notify, ConnectionStatus};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = new(
+            tokio::net::TcpStream::connect(addr).await.unwrap(),
+            None,
+            None,
+        )
+        .expect("Making client");
+        let status = client.connection_status();
+        assert_eq!(*status.lock().await, ConnectionStatus::Connected);
+
+        let (server, _) = listener.accept().await.unwrap();
+        // Closed once the client has written its getProperties, so that isn't what fails.
+        let mut lines = tokio::io::AsyncBufReadExt::lines(tokio::io::BufReader::new(server));
+        lines.next_line().await.unwrap().unwrap();
+        drop(lines);
+        let reason = notify::wait_fn::<_, (), _, _>(
+            status.subscribe().await,
+            std::time::Duration::from_secs(5),
+            |status| {
+                Ok(match &*status {
+                    ConnectionStatus::Disconnected { reason } => {
+                        notify::Status::Complete(reason.clone())
+                    }
+                    _ => notify::Status::Pending,
+                })
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(reason, "closed by the server");
+    }
+
+    #[tokio::test]
+    async fn test_replies_to_ping() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _client = new(
+            tokio::net::TcpStream::connect(addr).await.unwrap(),
+            None,
+            None,
+        )
+        .expect("Making client");
+
+        let (server, _) = listener.accept().await.unwrap();
+        let (read, mut write) = server.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let get_properties = lines.next_line().await.unwrap().unwrap();
+        assert!(get_properties.starts_with("<getProperties"));
+
+        write
+            .write_all(b"<pingRequest uid=\"7\"/>\n")
+            .await
+            .unwrap();
+        let reply = tokio::time::timeout(std::time::Duration::from_secs(5), lines.next_line())
+            .await
+            .expect("Waiting for ping reply")
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply, "<pingReply uid=\"7\"/>");
+    }
 }
@@ -0,0 +1,186 @@
+//! Encrypted connections to INDI servers, such as an indiserver behind stunnel at a remote
+//! observatory.  Requires the `tls` feature.
+//! # Example
+//! ```no_run
+//! use indi::client::tls::{self, TlsOptions};
+//! async {
+//!     let options = TlsOptions::new()
+//!         .root_certificates("observatory-ca.pem")
+//!         .expect("Reading CA certificate");
+//!     let connection = tls::connect("observatory.example.com:7625", "observatory.example.com", options)
+//!         .await
+//!         .expect("Connecting to server");
+//!     let client = indi::client::new(connection, None, None).expect("Initializing connection");
+//! };
+//! ```
+
+use std::{io, path::Path, sync::Arc};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf},
+    net::{TcpStream, ToSocketAddrs},
+};
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{
+        crypto::ring,
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+        ClientConfig, RootCertStore,
+    },
+    TlsConnector,
+};
+
+use super::{
+    tcpstream::{self, AsyncIndiReader, AsyncIndiWriter},
+    AsyncClientConnection,
+};
+
+/// The certificates a TLS connection trusts and presents.  Nothing is trusted until
+///  [root_certificates](TlsOptions::root_certificates) is called.
+#[derive(Debug)]
+pub struct TlsOptions {
+    roots: RootCertStore,
+    identity: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>,
+}
+
+impl Default for TlsOptions {
+    fn default() -> Self {
+        TlsOptions::new()
+    }
+}
+
+impl TlsOptions {
+    pub fn new() -> Self {
+        TlsOptions {
+            roots: RootCertStore::empty(),
+            identity: None,
+        }
+    }
+
+    /// Trusts servers with certificates signed by any of the certificates in the PEM file at
+    ///  `path`, such as stunnel's self-signed one.
+    pub fn root_certificates(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        for cert in CertificateDer::pem_file_iter(path).map_err(io::Error::other)? {
+            self.roots
+                .add(cert.map_err(io::Error::other)?)
+                .map_err(io::Error::other)?;
+        }
+        Ok(self)
+    }
+
+    /// Presents the certificate chain in the PEM file at `cert`, with the private key in the
+    ///  PEM file at `key`, to servers that verify their clients.
+    pub fn client_certificate(
+        mut self,
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        let certs = CertificateDer::pem_file_iter(cert)
+            .map_err(io::Error::other)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io::Error::other)?;
+        let key = PrivateKeyDer::from_pem_file(key).map_err(io::Error::other)?;
+        self.identity = Some((certs, key));
+        Ok(self)
+    }
+
+    fn config(self) -> io::Result<ClientConfig> {
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_root_certificates(self.roots);
+        match self.identity {
+            Some((certs, key)) => builder
+                .with_client_auth_cert(certs, key)
+                .map_err(io::Error::other),
+            None => Ok(builder.with_no_client_auth()),
+        }
+    }
+}
+
+/// Connects to the INDI server at `addr`, checking that its certificate is for `domain`.
+pub async fn connect(
+    addr: impl ToSocketAddrs,
+    domain: &str,
+    options: TlsOptions,
+) -> io::Result<TlsStream<TcpStream>> {
+    let domain = ServerName::try_from(domain.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let connector = TlsConnector::from(Arc::new(options.config()?));
+    let stream = TcpStream::connect(addr).await?;
+    connector.connect(domain, stream).await
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send + 'static> AsyncClientConnection for TlsStream<S> {
+    type Reader = AsyncIndiReader<ReadHalf<TlsStream<S>>>;
+    type Writer = AsyncIndiWriter<WriteHalf<TlsStream<S>>>;
+
+    fn to_indi(self) -> (Self::Writer, Self::Reader) {
+        let (reader, writer) = tokio::io::split(self);
+        tcpstream::split(reader, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+    use tokio_rustls::{rustls::ServerConfig, TlsAcceptor};
+
+    const CERTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls");
+
+    #[tokio::test]
+    async fn test_tls_connection() {
+        let certs = CertificateDer::pem_file_iter(format!("{}/server.pem", CERTS))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_file(format!("{}/server.key", CERTS)).unwrap();
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            // The first client doesn't trust the server and gives up during the handshake.
+            let stream = loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                if let Ok(stream) = acceptor.accept(stream).await {
+                    break stream;
+                }
+            };
+            let mut lines = BufReader::new(stream).lines();
+            let line = lines.next_line().await.unwrap().unwrap();
+            assert!(line.starts_with("<getProperties"));
+            lines
+                .get_mut()
+                .write_all(
+                    br#"<defSwitchVector device="CCD Simulator" name="CONNECTION" state="Idle" perm="rw" rule="OneOfMany" timeout="1">
+<defSwitch name="CONNECT">Off</defSwitch>
+<defSwitch name="DISCONNECT">On</defSwitch>
+</defSwitchVector>
+"#,
+                )
+                .await
+                .unwrap();
+        });
+
+        let untrusted = connect(addr, "localhost", TlsOptions::new()).await;
+        assert!(untrusted.is_err());
+
+        let options = TlsOptions::new()
+            .root_certificates(format!("{}/ca.pem", CERTS))
+            .unwrap();
+        let connection = connect(addr, "localhost", options).await.unwrap();
+        let client = crate::client::new(connection, None, None).unwrap();
+        client.get_device::<()>("CCD Simulator").await.unwrap();
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+//! Connections to an indiserver on the same machine through its local socket, which it
+//! listens on as well as its TCP port.
+//! # Example
+//! ```no_run
+//! use indi::client::unix;
+//! async {
+//!     let connection = unix::connect(unix::DEFAULT_PATH).await.expect("Connecting to server");
+//!     let client = indi::client::new(connection, None, None).expect("Initializing connection");
+//! };
+//! ```
+
+use std::path::Path;
+
+use tokio::net::{
+    unix::{OwnedReadHalf, OwnedWriteHalf},
+    UnixStream,
+};
+
+use super::{
+    tcpstream::{self, AsyncIndiReader, AsyncIndiWriter},
+    AsyncClientConnection,
+};
+
+/// Where indiserver puts its local socket unless started with `-u`.
+pub const DEFAULT_PATH: &str = "/tmp/indiserver";
+
+/// Connects to the indiserver listening on the socket at `path`.
+pub async fn connect(path: impl AsRef<Path>) -> std::io::Result<UnixStream> {
+    UnixStream::connect(path).await
+}
+
+impl AsyncClientConnection for UnixStream {
+    type Reader = AsyncIndiReader<OwnedReadHalf>;
+    type Writer = AsyncIndiWriter<OwnedWriteHalf>;
+
+    fn to_indi(self) -> (Self::Writer, Self::Reader) {
+        let (reader, writer) = self.into_split();
+        tcpstream::split(reader, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::UnixListener,
+    };
+
+    #[tokio::test]
+    async fn test_unix_connection() {
+        let path = std::env::temp_dir().join(format!("indi-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let client = crate::client::new(connect(&path).await.unwrap(), None, None).unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(server).lines();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<getProperties"));
+        lines
+            .get_mut()
+            .write_all(
+                br#"<defSwitchVector device="CCD Simulator" name="CONNECTION" state="Idle" perm="rw" rule="OneOfMany" timeout="1">
+<defSwitch name="CONNECT">Off</defSwitch>
+<defSwitch name="DISCONNECT">On</defSwitch>
+</defSwitchVector>
+"#,
+            )
+            .await
+            .unwrap();
+        client.get_device::<()>("CCD Simulator").await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
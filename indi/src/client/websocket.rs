@@ -68,12 +68,19 @@ impl<S: Stream<Item = Result<axum::extract::ws::Message, axum::Error>> + Send +
                 axum::extract::ws::Message::Text(cmd) => {
                     let deser = match quick_xml::de::from_str(cmd.as_str()) {
                         Ok(cmd) => cmd,
-                        Err(e) => return Some(Err(e.into())),
+                        Err(e) => {
+                            return Some(Err(crate::DeError::Skipped {
+                                fragment: cmd.to_string(),
+                                error: Box::new(e.into()),
+                            }))
+                        }
                     };
 
                     return Some(Ok(deser));
                 }
-                _ => unimplemented!(),
+                axum::extract::ws::Message::Close(_) => return None,
+                // Pings are answered by axum, and INDI is only sent as text.
+                _ => continue,
             }
         }
     }
@@ -136,12 +143,19 @@ impl<S: Stream<Item = Result<tokio_tungstenite::tungstenite::Message, tokio_tung
                 tokio_tungstenite::tungstenite::Message::Text(cmd) => {
                     let deser = match quick_xml::de::from_str(cmd.as_str()) {
                         Ok(cmd) => cmd,
-                        Err(e) => return Some(Err(e.into())),
+                        Err(e) => {
+                            return Some(Err(crate::DeError::Skipped {
+                                fragment: cmd.to_string(),
+                                error: Box::new(e.into()),
+                            }))
+                        }
                     };
 
                     return Some(Ok(deser));
                 }
-                _ => unimplemented!(),
+                tokio_tungstenite::tungstenite::Message::Close(_) => return None,
+                // Pings are answered by tungstenite, and INDI is only sent as text.
+                _ => continue,
             }
         }
     }
@@ -0,0 +1,167 @@
+//! Finding INDI servers on the local network.
+//!
+//! Servers are found by connecting to the INDI port on each address and sending a
+//!  `getProperties`, timing how long the connection takes.  Only addresses that answer with
+//!  xml are reported, so other services on the port aren't mistaken for INDI servers, but
+//!  neither are INDI servers that have no devices to define.
+//! # Example
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let servers = indi::discovery::discover_local(indi::DEFAULT_PORT, Duration::from_millis(500)).await;
+//!     for server in servers {
+//!         println!("{} in {:?}", server.addr, server.latency);
+//!     }
+//! }
+//! ```
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    task::JoinSet,
+};
+
+use crate::INDI_PROTOCOL_VERSION;
+
+/// An INDI server that answered a `getProperties`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiscoveredServer {
+    pub addr: SocketAddr,
+    /// How long it took to connect.
+    pub latency: Duration,
+}
+
+/// Tries each of `addrs` at once, returning the ones that connected and answered a
+///  `getProperties` within `timeout`, sorted by latency, fastest first.
+pub async fn probe(
+    addrs: impl IntoIterator<Item = SocketAddr>,
+    timeout: Duration,
+) -> Vec<DiscoveredServer> {
+    let mut probes = JoinSet::new();
+    for addr in addrs {
+        probes.spawn(async move {
+            match tokio::time::timeout(timeout, handshake(addr)).await {
+                Ok(Ok(latency)) => Some(DiscoveredServer { addr, latency }),
+                _ => None,
+            }
+        });
+    }
+
+    let mut servers = vec![];
+    while let Some(probe) = probes.join_next().await {
+        if let Ok(Some(server)) = probe {
+            servers.push(server);
+        }
+    }
+    servers.sort_by_key(|s| s.latency);
+    servers
+}
+
+/// Connects to `addr` and checks it answers a `getProperties` with xml, returning how long
+///  connecting took.
+async fn handshake(addr: SocketAddr) -> std::io::Result<Duration> {
+    let start = Instant::now();
+    let mut stream = TcpStream::connect(addr).await?;
+    let latency = start.elapsed();
+    stream
+        .write_all(format!(r#"<getProperties version="{}"/>"#, INDI_PROTOCOL_VERSION).as_bytes())
+        .await?;
+    let mut reply = [0; 1];
+    loop {
+        if stream.read(&mut reply).await? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        if !reply[0].is_ascii_whitespace() {
+            break;
+        }
+    }
+    match reply[0] {
+        b'<' => Ok(latency),
+        _ => Err(std::io::ErrorKind::InvalidData.into()),
+    }
+}
+
+/// Probes `port` on this machine and on every address of the local /24 IPv4 network, see
+///  [probe].  Only this machine is probed if the local network can't be found.  A server
+///  answering on both localhost and this machine's network address is reported once, as
+///  localhost.
+pub async fn discover_local(port: u16, timeout: Duration) -> Vec<DiscoveredServer> {
+    let localhost = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+    let local = local_ipv4().ok();
+    let network = local.into_iter().flat_map(|local| {
+        let [a, b, c, _] = local.octets();
+        (1..255).map(move |d| Ipv4Addr::new(a, b, c, d))
+    });
+    let hosts =
+        std::iter::once(localhost).chain(network.map(|ip| SocketAddr::new(IpAddr::V4(ip), port)));
+    let mut servers = probe(hosts, timeout).await;
+
+    if servers.iter().any(|s| s.addr == localhost) {
+        servers.retain(|s| Some(s.addr.ip()) != local.map(IpAddr::V4));
+    }
+    servers
+}
+
+/// Returns the address of the interface used to reach other networks.  Connecting a UDP
+///  socket only picks the route, nothing is sent.
+fn local_ipv4() -> std::io::Result<Ipv4Addr> {
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9))?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(_) => Err(std::io::Error::other("no local IPv4 address")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Accepts one connection on `listener` and answers it with `reply` once it has sent
+    ///  something.
+    fn answer(listener: TcpListener, reply: &'static [u8]) {
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 64];
+            assert!(stream.read(&mut request).await.unwrap() > 0);
+            stream.write_all(reply).await.unwrap();
+            // Hold the connection open until the probe is done with it.
+            let _ = stream.read(&mut request).await;
+        });
+    }
+
+    #[tokio::test]
+    async fn test_probe() {
+        let indi = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = indi.local_addr().unwrap();
+        answer(
+            indi,
+            br#"<defSwitchVector device="CCD Simulator" name="CONNECTION" state="Idle" perm="rw" rule="OneOfMany">"#,
+        );
+        let http = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let other = http.local_addr().unwrap();
+        answer(http, b"HTTP/1.1 400 Bad Request\r\n\r\n");
+        // Accepts but never answers.
+        let silent = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let quiet = silent.local_addr().unwrap();
+        // Nothing listens on a port that was just released.
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let servers = probe([open, other, quiet, closed], Duration::from_millis(200)).await;
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].addr, open);
+        drop(silent);
+    }
+}
@@ -86,14 +86,16 @@
 //!         .expect("Enabling image retrieval");
 //!
 //!     // Configuring a varienty of the camera's properties at the same time.
-//!     tokio::try_join!(
-//!         camera.change("CCD_CAPTURE_FORMAT", vec![("ASI_IMG_RAW16", true)]),
-//!         camera.change("CCD_TRANSFER_FORMAT", vec![("FORMAT_FITS", true)]),
-//!         camera.change("CCD_CONTROLS", vec![("Offset", 10.0), ("Gain", 240.0)]),
-//!         camera.change("FITS_HEADER", vec![("FITS_OBJECT", "")]),
-//!         camera.change("CCD_BINNING", vec![("HOR_BIN", 2.0), ("VER_BIN", 2.0)]),
-//!         camera.change("CCD_FRAME_TYPE", vec![("FRAME_FLAT", true)]),
-//!         )
+//!     camera
+//!         .change_many([
+//!             ("CCD_CAPTURE_FORMAT", vec![("ASI_IMG_RAW16", true)].into()),
+//!             ("CCD_TRANSFER_FORMAT", vec![("FORMAT_FITS", true)].into()),
+//!             ("CCD_CONTROLS", vec![("Offset", 10.0), ("Gain", 240.0)].into()),
+//!             ("FITS_HEADER", vec![("FITS_OBJECT", "")].into()),
+//!             ("CCD_BINNING", vec![("HOR_BIN", 2.0), ("VER_BIN", 2.0)].into()),
+//!             ("CCD_FRAME_TYPE", vec![("FRAME_FLAT", true)].into()),
+//!         ])
+//!         .await
 //!         .expect("Configuring camera");
 //!
 //!     // Capture a 5 second exposure from the camera
@@ -123,10 +125,20 @@ use std::collections::HashMap;
 
 pub static INDI_PROTOCOL_VERSION: &str = "1.7";
 
+/// The port INDI servers listen on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 7624;
+
 pub mod serialization;
 use serialization::*;
 
 pub mod client;
+pub mod discovery;
+#[cfg(unix)]
+pub mod process;
+pub mod proxy;
+pub mod server;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum PropertyState {
@@ -172,13 +184,13 @@ pub trait FromParamValue {
         Self: Sized;
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Switch {
     pub label: Option<String>,
     pub value: SwitchState,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct SwitchVector {
     pub gen: core::num::Wrapping<usize>,
     pub name: String,
@@ -202,7 +214,7 @@ impl FromParamValue for HashMap<String, Switch> {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Number {
     pub label: Option<String>,
     pub format: String,
@@ -212,7 +224,7 @@ pub struct Number {
     pub value: Sexagesimal,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct NumberVector {
     pub gen: core::num::Wrapping<usize>,
     pub name: String,
@@ -235,13 +247,13 @@ impl FromParamValue for HashMap<String, Number> {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Light {
-    label: Option<String>,
-    value: PropertyState,
+    pub label: Option<String>,
+    pub value: PropertyState,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct LightVector {
     pub gen: core::num::Wrapping<usize>,
     pub name: String,
@@ -262,7 +274,7 @@ impl FromParamValue for HashMap<String, Light> {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Text {
     pub label: Option<String>,
     pub value: String,
@@ -277,7 +289,7 @@ impl FromParamValue for HashMap<String, Text> {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct TextVector {
     pub gen: core::num::Wrapping<usize>,
     pub name: String,
@@ -292,14 +304,16 @@ pub struct TextVector {
     pub values: HashMap<String, Text>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Blob {
     pub label: Option<String>,
     pub format: Option<String>,
+    /// Serialized as base64.
+    #[serde(with = "serialization::blob_vector::base64_data")]
     pub value: Option<Arc<Vec<u8>>>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct BlobVector {
     pub gen: core::num::Wrapping<usize>,
     pub name: String,
@@ -322,7 +336,7 @@ impl FromParamValue for HashMap<String, Blob> {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Parameter {
     TextVector(TextVector),
     NumberVector(NumberVector),
@@ -369,6 +383,15 @@ impl Parameter {
             Parameter::BlobVector(p) => &p.state,
         }
     }
+    pub fn get_timestamp(&self) -> &Option<DateTime<Utc>> {
+        match self {
+            Parameter::TextVector(p) => &p.timestamp,
+            Parameter::NumberVector(p) => &p.timestamp,
+            Parameter::SwitchVector(p) => &p.timestamp,
+            Parameter::LightVector(p) => &p.timestamp,
+            Parameter::BlobVector(p) => &p.timestamp,
+        }
+    }
     pub fn get_timeout(&self) -> &Option<u32> {
         match self {
             Parameter::TextVector(p) => &p.timeout,
@@ -383,6 +406,18 @@ impl Parameter {
         T::values_from(self)
     }
 
+    /// Returns the value named `name` converted to `T`, see [FromValue] for the conversions.
+    /// # Example
+    /// ```
+    /// use indi::*;
+    /// fn value_of_usage_example(exposure: &Parameter) -> Result<f64, TypeError> {
+    ///     exposure.value_of::<f64>("CCD_EXPOSURE_VALUE")
+    /// }
+    /// ```
+    pub fn value_of<T: FromValue>(&self, name: &str) -> Result<T, TypeError> {
+        T::value_from(self, name)
+    }
+
     pub fn gen(&self) -> core::num::Wrapping<usize> {
         match self {
             Parameter::TextVector(p) => p.gen,
@@ -402,11 +437,92 @@ impl Parameter {
             Parameter::BlobVector(p) => &mut p.gen,
         }
     }
+
+    /// Returns the `def*Vector` command defining this parameter, as it currently is, for
+    ///  `device`.
+    pub fn to_def(&self, device: &str) -> Command {
+        match self {
+            Parameter::TextVector(p) => Command::DefTextVector(p.to_def(device)),
+            Parameter::NumberVector(p) => Command::DefNumberVector(p.to_def(device)),
+            Parameter::SwitchVector(p) => Command::DefSwitchVector(p.to_def(device)),
+            Parameter::LightVector(p) => Command::DefLightVector(p.to_def(device)),
+            Parameter::BlobVector(p) => Command::DefBlobVector(p.to_def(device)),
+        }
+    }
 }
 #[derive(Debug)]
 pub enum TypeError {
     TypeMismatch,
+    /// The parameter has no value with this name.
+    MissingValue(String),
+}
+
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeError::TypeMismatch => write!(f, "parameter is a different type"),
+            TypeError::MissingValue(name) => write!(f, "parameter has no value named {}", name),
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
+/// Types a single value of a [Parameter] can be read as, see [Parameter::value_of].
+///  Numbers read as `f64`, [Sexagesimal], a [Duration](std::time::Duration) of that many
+///  seconds, or the whole [Number] with its range, switches as `bool` and texts as `String`.
+pub trait FromValue: Sized {
+    fn value_from(p: &Parameter, name: &str) -> Result<Self, TypeError>;
+}
+
+fn value_named<'a, V>(values: &'a HashMap<String, V>, name: &str) -> Result<&'a V, TypeError> {
+    values
+        .get(name)
+        .ok_or_else(|| TypeError::MissingValue(name.to_string()))
+}
+
+impl FromValue for Sexagesimal {
+    fn value_from(p: &Parameter, name: &str) -> Result<Self, TypeError> {
+        Ok(
+            value_named(p.get_values::<HashMap<String, Number>>()?, name)?
+                .value
+                .clone(),
+        )
+    }
 }
+
+impl FromValue for Number {
+    fn value_from(p: &Parameter, name: &str) -> Result<Self, TypeError> {
+        Ok(value_named(p.get_values::<HashMap<String, Number>>()?, name)?.clone())
+    }
+}
+
+impl FromValue for f64 {
+    fn value_from(p: &Parameter, name: &str) -> Result<Self, TypeError> {
+        Ok(Sexagesimal::value_from(p, name)?.into())
+    }
+}
+
+impl FromValue for std::time::Duration {
+    fn value_from(p: &Parameter, name: &str) -> Result<Self, TypeError> {
+        std::time::Duration::try_from_secs_f64(f64::value_from(p, name)?)
+            .map_err(|_| TypeError::TypeMismatch)
+    }
+}
+
+impl FromValue for bool {
+    fn value_from(p: &Parameter, name: &str) -> Result<Self, TypeError> {
+        Ok(value_named(p.get_values::<HashMap<String, Switch>>()?, name)?.value == SwitchState::On)
+    }
+}
+
+impl FromValue for String {
+    fn value_from(p: &Parameter, name: &str) -> Result<Self, TypeError> {
+        Ok(value_named(p.get_values::<HashMap<String, Text>>()?, name)?
+            .value
+            .clone())
+    }
+}
+
 pub trait TryEq<T> {
     fn try_eq(&self, other: &T) -> Result<bool, TypeError>;
 }
@@ -416,7 +532,7 @@ impl TryEq<Parameter> for Vec<OneSwitch> {
         let current_values = other.get_values::<HashMap<String, Switch>>()?;
 
         Ok(self.iter().all(|other_value| {
-            Some(other_value.value) == current_values.get(&other_value.name).map(|x| x.value)
+            Some(&other_value.value) == current_values.get(&other_value.name).map(|x| &x.value)
         }))
     }
 }
@@ -444,7 +560,10 @@ impl TryEq<Parameter> for Vec<(&str, f64)> {
         let current_values = other.get_values::<HashMap<String, Number>>()?;
 
         Ok(self.iter().all(|other_value| {
-            Some(other_value.1) == current_values.get(other_value.0).map(|x| x.value.into())
+            Some(other_value.1)
+                == current_values
+                    .get(other_value.0)
+                    .map(|x| f64::from(&x.value))
         }))
     }
 }
@@ -454,7 +573,7 @@ impl TryEq<Parameter> for Vec<OneNumber> {
         let current_values = other.get_values::<HashMap<String, Number>>()?;
 
         Ok(self.iter().all(|other_value| {
-            Some(other_value.value) == current_values.get(&other_value.name).map(|x| x.value)
+            Some(&other_value.value) == current_values.get(&other_value.name).map(|x| &x.value)
         }))
     }
 }
@@ -469,6 +588,16 @@ impl TryEq<Parameter> for Vec<(&str, &str)> {
     }
 }
 
+impl TryEq<Parameter> for Vec<(&str, PropertyState)> {
+    fn try_eq(&self, other: &Parameter) -> Result<bool, TypeError> {
+        let current_values = other.get_values::<HashMap<String, Light>>()?;
+
+        Ok(self.iter().all(|other_value| {
+            Some(&other_value.1) == current_values.get(other_value.0).map(|x| &x.value)
+        }))
+    }
+}
+
 impl TryEq<Parameter> for Vec<OneText> {
     fn try_eq(&self, other: &Parameter) -> Result<bool, TypeError> {
         let current_values = other.get_values::<HashMap<String, Text>>()?;
@@ -0,0 +1,464 @@
+//! Running indiserver as a child process.
+//!
+//! [IndiServerProcess] spawns `indiserver` with a list of drivers and waits for it to accept
+//! connections.  When given a FIFO, drivers can be started and stopped while it runs.
+//! [IndiServerSupervisor] keeps it running, spawning it again with the same drivers if it exits
+//! on its own.
+//! # Example
+//! ```no_run
+//! use indi::process::{IndiServerSupervisor, ProcessOptions};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let options = ProcessOptions::new()
+//!         .driver("indi_simulator_ccd")
+//!         .fifo("/tmp/indififo");
+//!     let indiserver = IndiServerSupervisor::spawn(options).await.expect("Starting indiserver");
+//!     indiserver
+//!         .start_driver("indi_simulator_focus")
+//!         .await
+//!         .expect("Starting focuser");
+//!     let client = indiserver.connect().await.expect("Connecting to indiserver");
+//!     drop(client);
+//!     indiserver.shutdown().await.expect("Stopping indiserver");
+//! }
+//! ```
+
+use std::{
+    ffi::{CString, OsString},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::TcpStream,
+    process::{Child, Command},
+    sync::{oneshot, watch},
+    task::JoinHandle,
+};
+
+use crate::{
+    client::{self, Client},
+    serialization::DeError,
+    DEFAULT_PORT,
+};
+
+/// How long to wait for indiserver to accept connections after spawning it.
+pub const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long [shutdown](IndiServerProcess::shutdown) waits for indiserver to exit before
+/// killing it.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum ProcessError {
+    IoError(std::io::Error),
+    /// indiserver didn't start accepting connections, or didn't exit, in time.
+    Timeout,
+    /// indiserver exited while starting up, or a supervised indiserver kept exiting.
+    Exited(ExitStatus),
+    /// Drivers can only be started and stopped when indiserver is given a FIFO.
+    NoFifo,
+    DeError(DeError),
+}
+
+impl std::fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcessError::IoError(e) => write!(f, "io error: {}", e),
+            ProcessError::Timeout => write!(f, "timed out waiting for indiserver"),
+            ProcessError::Exited(status) => write!(f, "indiserver exited: {}", status),
+            ProcessError::NoFifo => write!(f, "indiserver was started without a FIFO"),
+            ProcessError::DeError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProcessError::IoError(e) => Some(e),
+            ProcessError::DeError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ProcessError {
+    fn from(value: std::io::Error) -> Self {
+        ProcessError::IoError(value)
+    }
+}
+
+impl From<DeError> for ProcessError {
+    fn from(value: DeError) -> Self {
+        ProcessError::DeError(value)
+    }
+}
+
+/// How to run indiserver.  Defaults to `indiserver` from the `PATH` on [DEFAULT_PORT] with no
+/// drivers and no FIFO.
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    program: PathBuf,
+    port: u16,
+    drivers: Vec<String>,
+    fifo: Option<PathBuf>,
+    startup_timeout: Duration,
+    max_restarts: Option<u32>,
+    restart_delay: Duration,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        ProcessOptions::new()
+    }
+}
+
+impl ProcessOptions {
+    pub fn new() -> Self {
+        ProcessOptions {
+            program: PathBuf::from("indiserver"),
+            port: DEFAULT_PORT,
+            drivers: vec![],
+            fifo: None,
+            startup_timeout: STARTUP_TIMEOUT,
+            max_restarts: Some(3),
+            restart_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the indiserver executable to run.
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Sets the port indiserver listens on.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Adds a driver to run, such as `indi_simulator_ccd`.
+    pub fn driver(mut self, driver: impl Into<String>) -> Self {
+        self.drivers.push(driver.into());
+        self
+    }
+
+    /// Sets the FIFO indiserver reads `start` and `stop` commands for drivers from.  It's
+    /// created if it doesn't exist.
+    pub fn fifo(mut self, path: impl Into<PathBuf>) -> Self {
+        self.fifo = Some(path.into());
+        self
+    }
+
+    /// Sets how long to wait for indiserver to accept connections, defaults to
+    /// [STARTUP_TIMEOUT].
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    /// Sets how many times in a row an [IndiServerSupervisor] spawns indiserver again after it
+    /// exits, `None` for no limit.  Defaults to 3.
+    pub fn max_restarts(mut self, restarts: Option<u32>) -> Self {
+        self.max_restarts = restarts;
+        self
+    }
+
+    /// Sets how long an [IndiServerSupervisor] waits before spawning indiserver again.
+    pub fn restart_delay(mut self, delay: Duration) -> Self {
+        self.restart_delay = delay;
+        self
+    }
+
+    /// Returns the arguments indiserver is run with.
+    fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["-p".into(), self.port.to_string().into()];
+        if let Some(fifo) = &self.fifo {
+            args.push("-f".into());
+            args.push(fifo.into());
+        }
+        args.extend(self.drivers.iter().map(OsString::from));
+        args
+    }
+}
+
+/// A running indiserver.  It's killed if this is dropped, use
+/// [shutdown](IndiServerProcess::shutdown) to stop it cleanly.
+pub struct IndiServerProcess {
+    port: u16,
+    fifo: Option<PathBuf>,
+    child: Child,
+}
+
+impl IndiServerProcess {
+    /// Spawns indiserver, creating its FIFO if needed, and waits for it to accept connections.
+    pub async fn spawn(options: &ProcessOptions) -> Result<IndiServerProcess, ProcessError> {
+        if let Some(fifo) = &options.fifo {
+            make_fifo(fifo)?;
+        }
+        let child = Command::new(&options.program)
+            .args(options.args())
+            .kill_on_drop(true)
+            .spawn()?;
+        let mut process = IndiServerProcess {
+            port: options.port,
+            fifo: options.fifo.clone(),
+            child,
+        };
+        process.wait_for_server(options.startup_timeout).await?;
+        Ok(process)
+    }
+
+    /// Waits for indiserver to accept a connection, failing early if it exits.
+    async fn wait_for_server(&mut self, timeout: Duration) -> Result<TcpStream, ProcessError> {
+        let port = self.port;
+        let child = &mut self.child;
+        tokio::time::timeout(timeout, async move {
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    return Err(ProcessError::Exited(status));
+                }
+                match TcpStream::connect(("localhost", port)).await {
+                    Ok(stream) => return Ok(stream),
+                    Err(_) => tokio::time::sleep(Duration::from_millis(250)).await,
+                }
+            }
+        })
+        .await
+        .map_err(|_| ProcessError::Timeout)?
+    }
+
+    /// Port indiserver is listening on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Opens a new [Client] connected to indiserver.
+    pub async fn connect(&self) -> Result<Client, ProcessError> {
+        connect(self.port).await
+    }
+
+    /// Starts `driver` through the FIFO.
+    pub async fn start_driver(&self, driver: &str) -> Result<(), ProcessError> {
+        control(self.fifo.as_deref(), "start", driver).await
+    }
+
+    /// Stops `driver` through the FIFO.
+    pub async fn stop_driver(&self, driver: &str) -> Result<(), ProcessError> {
+        control(self.fifo.as_deref(), "stop", driver).await
+    }
+
+    /// Waits for indiserver to exit.
+    pub async fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        self.child.wait().await
+    }
+
+    /// Sends indiserver `SIGTERM` and waits up to [SHUTDOWN_TIMEOUT] for it to exit before
+    /// killing it.
+    pub async fn shutdown(mut self) -> Result<ExitStatus, ProcessError> {
+        if let Some(pid) = self.child.id() {
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGTERM);
+            }
+        }
+
+        match tokio::time::timeout(SHUTDOWN_TIMEOUT, self.child.wait()).await {
+            Ok(status) => Ok(status?),
+            Err(_) => {
+                self.child.kill().await?;
+                Err(ProcessError::Timeout)
+            }
+        }
+    }
+}
+
+async fn connect(port: u16) -> Result<Client, ProcessError> {
+    let stream = TcpStream::connect(("localhost", port)).await?;
+    Ok(client::new(stream, None, None)?)
+}
+
+/// Creates the FIFO at `path` unless something is already there.
+fn make_fifo(path: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Writes the `action` command for `driver` to indiserver's FIFO.
+async fn control(fifo: Option<&Path>, action: &str, driver: &str) -> Result<(), ProcessError> {
+    let fifo = fifo.ok_or(ProcessError::NoFifo)?;
+    let mut file = tokio::fs::OpenOptions::new().write(true).open(fifo).await?;
+    file.write_all(format!("{} {}\n", action, driver).as_bytes())
+        .await?;
+    Ok(())
+}
+
+/// Keeps indiserver running, spawning it again after the restart delay when it exits without
+/// being shut down.  It's restarted with the drivers it was running, including those started
+/// and stopped through the FIFO since.  Gives up after the maximum number of restarts in a row
+/// fail, each restart that starts up successfully resets the count.  indiserver is killed if
+/// this is dropped, use [shutdown](IndiServerSupervisor::shutdown) to stop it cleanly.
+pub struct IndiServerSupervisor {
+    port: u16,
+    fifo: Option<PathBuf>,
+    drivers: Arc<Mutex<Vec<String>>>,
+    restarts: watch::Receiver<u32>,
+    stop: Option<oneshot::Sender<()>>,
+    task: JoinHandle<Result<ExitStatus, ProcessError>>,
+}
+
+impl IndiServerSupervisor {
+    /// Spawns indiserver like [IndiServerProcess::spawn] and starts watching it.
+    pub async fn spawn(options: ProcessOptions) -> Result<IndiServerSupervisor, ProcessError> {
+        let mut process = IndiServerProcess::spawn(&options).await?;
+        let drivers = Arc::new(Mutex::new(options.drivers.clone()));
+        let task_drivers = drivers.clone();
+        let (stop, mut stopped) = oneshot::channel();
+        let (restart_sender, restarts) = watch::channel(0);
+        let supervisor = IndiServerSupervisor {
+            port: options.port,
+            fifo: options.fifo.clone(),
+            drivers,
+            restarts,
+            stop: Some(stop),
+            task: tokio::spawn(async move {
+                let mut failures = 0;
+                loop {
+                    let status = tokio::select! {
+                        status = process.wait() => status?,
+                        _ = &mut stopped => return process.shutdown().await,
+                    };
+                    log::warn!("indiserver exited with {}, restarting", status);
+                    loop {
+                        if options.max_restarts.is_some_and(|max| failures >= max) {
+                            return Err(ProcessError::Exited(status));
+                        }
+                        tokio::time::sleep(options.restart_delay).await;
+                        let mut options = options.clone();
+                        options.drivers = task_drivers
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .clone();
+                        match IndiServerProcess::spawn(&options).await {
+                            Ok(restarted) => {
+                                process = restarted;
+                                failures = 0;
+                                restart_sender.send_modify(|restarts| *restarts += 1);
+                                break;
+                            }
+                            Err(e) => {
+                                log::warn!("restarting indiserver failed: {}", e);
+                                failures += 1;
+                            }
+                        }
+                    }
+                }
+            }),
+        };
+        Ok(supervisor)
+    }
+
+    /// Port indiserver is listening on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Opens a new [Client] connected to indiserver.  Clients don't survive a restart.
+    pub async fn connect(&self) -> Result<Client, ProcessError> {
+        connect(self.port).await
+    }
+
+    /// Starts `driver` through the FIFO, and includes it when indiserver is restarted.
+    pub async fn start_driver(&self, driver: &str) -> Result<(), ProcessError> {
+        control(self.fifo.as_deref(), "start", driver).await?;
+        let mut drivers = self.drivers.lock().unwrap_or_else(PoisonError::into_inner);
+        if !drivers.iter().any(|d| d == driver) {
+            drivers.push(driver.to_string());
+        }
+        Ok(())
+    }
+
+    /// Stops `driver` through the FIFO, and leaves it out when indiserver is restarted.
+    pub async fn stop_driver(&self, driver: &str) -> Result<(), ProcessError> {
+        control(self.fifo.as_deref(), "stop", driver).await?;
+        self.drivers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|d| d != driver);
+        Ok(())
+    }
+
+    /// Returns the drivers indiserver is running.
+    pub fn drivers(&self) -> Vec<String> {
+        self.drivers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns a receiver for the number of times indiserver has been restarted.
+    pub fn restarts(&self) -> watch::Receiver<u32> {
+        self.restarts.clone()
+    }
+
+    /// Stops watching indiserver and shuts it down like [IndiServerProcess::shutdown].
+    /// Returns the error that ended supervision if indiserver couldn't be kept running.
+    pub async fn shutdown(mut self) -> Result<ExitStatus, ProcessError> {
+        if let Some(stop) = self.stop.take() {
+            stop.send(()).ok();
+        }
+        match (&mut self.task).await {
+            Ok(result) => result,
+            Err(e) => Err(ProcessError::IoError(std::io::Error::other(e))),
+        }
+    }
+}
+
+impl Drop for IndiServerSupervisor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    #[tokio::test]
+    async fn test_fifo_commands() {
+        let fifo = std::env::temp_dir().join(format!("indi_test_fifo_{}", std::process::id()));
+        make_fifo(&fifo).unwrap();
+
+        let reader = tokio::fs::File::open(fifo.clone());
+        let writer = async {
+            control(Some(&fifo), "start", "indi_simulator_ccd")
+                .await
+                .unwrap();
+        };
+        let (reader, ()) = tokio::join!(reader, writer);
+        let mut lines = BufReader::new(reader.unwrap()).lines();
+        assert_eq!(
+            lines.next_line().await.unwrap().as_deref(),
+            Some("start indi_simulator_ccd")
+        );
+        std::fs::remove_file(&fifo).unwrap();
+
+        assert!(matches!(
+            control(None, "stop", "indi_simulator_ccd").await,
+            Err(ProcessError::NoFifo)
+        ));
+    }
+}
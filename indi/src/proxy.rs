@@ -0,0 +1,348 @@
+//! Sharing one INDI server with many clients, such as web browsers connecting over
+//! websockets.
+//!
+//! The proxy keeps a single connection to the upstream INDI server and mirrors its devices in
+//! a [Server], which answers the clients' `getProperties` itself and only sends each client
+//! the blobs it has enabled.  Changes from clients are passed on to the upstream server.
+//! # Example
+//! ```no_run
+//! use indi::proxy::Proxy;
+//! use std::time::Duration;
+//! use tokio::net::{TcpListener, TcpStream};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let proxy = Proxy::new();
+//!     let mirror = proxy.clone();
+//!     tokio::spawn(async move {
+//!         mirror
+//!             .mirror_with(|| TcpStream::connect("localhost:7624"), Duration::from_secs(1))
+//!             .await
+//!     });
+//!
+//!     let listener = TcpListener::bind("0.0.0.0:4000").await.expect("Binding to port");
+//!     axum::serve(listener, proxy.router()).await.expect("Serving websockets");
+//! }
+//! ```
+
+use std::{collections::HashSet, future::Future, time::Duration};
+
+use axum::{
+    extract::{ws::WebSocketUpgrade, State},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use log::warn;
+use tokio::sync::mpsc;
+
+use crate::{
+    client::{AsyncClientConnection, AsyncReadConnection, AsyncWriteConnection},
+    serialization::{Command, DeError, DelProperty, EnableBlob, GetProperties},
+    server::Server,
+    BlobEnable, INDI_PROTOCOL_VERSION,
+};
+
+/// Mirrors an upstream INDI server for any number of clients, see the [module](self) docs.
+#[derive(Clone, Default)]
+pub struct Proxy {
+    server: Server,
+}
+
+impl Proxy {
+    pub fn new() -> Proxy {
+        Default::default()
+    }
+
+    /// Returns the [Server] holding the mirrored devices.
+    pub fn server(&self) -> &Server {
+        &self.server
+    }
+
+    /// Mirrors the devices of the INDI server on the other end of `upstream`, passing on
+    ///  the changes clients ask for, until it disconnects.  Every blob is requested from
+    ///  upstream so that any client can enable them.  The devices are deleted once upstream
+    ///  disconnects, so clients aren't left with devices that no longer update.
+    pub async fn mirror<T: AsyncClientConnection>(&self, upstream: T) -> Result<(), DeError> {
+        let mut devices = HashSet::new();
+        let result = self.mirror_devices(upstream, &mut devices).await;
+        for device in devices {
+            self.remove_device(device).await;
+        }
+        result
+    }
+
+    /// Mirrors the INDI server that `connect` connects to, connecting again `delay` after
+    ///  the connection drops or can't be made, like
+    ///  [connect_with](crate::client::ClientBuilder::connect_with).  Never returns.
+    /// # Example
+    /// ```no_run
+    /// use indi::proxy::Proxy;
+    /// use std::time::Duration;
+    /// use tokio::net::TcpStream;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let proxy = Proxy::new();
+    ///     let mirror = proxy.clone();
+    ///     tokio::spawn(async move {
+    ///         mirror
+    ///             .mirror_with(|| TcpStream::connect("localhost:7624"), Duration::from_secs(1))
+    ///             .await
+    ///     });
+    /// }
+    /// ```
+    pub async fn mirror_with<F, Fut, T>(&self, mut connect: F, delay: Duration)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = std::io::Result<T>>,
+        T: AsyncClientConnection,
+    {
+        loop {
+            match connect().await {
+                Ok(upstream) => match self.mirror(upstream).await {
+                    Ok(()) => warn!("Upstream INDI server disconnected"),
+                    Err(e) => warn!("Mirroring upstream INDI server failed: {}", e),
+                },
+                Err(e) => warn!("Connecting to upstream INDI server: {}", e),
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Mirrors `upstream` until it disconnects, adding the names of the devices it defines
+    ///  to `devices`.
+    async fn mirror_devices<T: AsyncClientConnection>(
+        &self,
+        upstream: T,
+        devices: &mut HashSet<String>,
+    ) -> Result<(), DeError> {
+        let (mut writer, mut reader) = upstream.to_indi();
+        let (requests, mut outgoing) = mpsc::unbounded_channel();
+        requests
+            .send(Command::GetProperties(GetProperties {
+                version: INDI_PROTOCOL_VERSION.to_string(),
+                device: None,
+                name: None,
+            }))
+            .ok();
+
+        let read = async move {
+            while let Some(command) = reader.read().await {
+                let command = match command {
+                    Ok(command) => command,
+                    Err(e) => {
+                        warn!("Skipping command from upstream: {}", e);
+                        continue;
+                    }
+                };
+                if let Command::PingRequest(request) = command {
+                    requests.send(Command::PingReply(request.reply())).ok();
+                    continue;
+                }
+                if let Some(device) = command.device_name() {
+                    if devices.insert(device.clone()) {
+                        self.add_device(device, &requests);
+                    }
+                }
+                if let Err(e) = self.server.publish(command).await {
+                    warn!("Skipping update from upstream: {:?}", e);
+                }
+            }
+        };
+        let write = async move {
+            while let Some(command) = outgoing.recv().await {
+                writer.write(command).await?;
+            }
+            writer.shutdown().await
+        };
+        tokio::select! {
+            () = read => Ok(()),
+            written = write => written,
+        }
+    }
+
+    /// Deletes `device` from the server and from its clients.
+    async fn remove_device(&self, device: String) {
+        let delete = Command::DelProperty(DelProperty {
+            device: device.clone(),
+            name: None,
+            timestamp: None,
+            message: Some(String::from("Upstream INDI server disconnected")),
+        });
+        if let Err(e) = self.server.publish(delete).await {
+            warn!("Deleting {:?}: {:?}", device, e);
+        }
+        self.server.get_devices().lock().await.remove(&device);
+    }
+
+    /// Adds `device` to the server, passing the changes clients ask for on to `upstream`.
+    fn add_device(&self, device: &str, upstream: &mpsc::UnboundedSender<Command>) {
+        upstream
+            .send(Command::EnableBlob(EnableBlob {
+                device: device.to_string(),
+                name: None,
+                enabled: BlobEnable::Also,
+            }))
+            .ok();
+        let mut handle = self.server.add_device(device);
+        let upstream = upstream.clone();
+        tokio::spawn(async move {
+            while let Some(command) = handle.recv().await {
+                if upstream.send(command).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Serves the client on the other end of `connection` until it disconnects.
+    pub async fn serve<T: AsyncClientConnection>(&self, connection: T) -> Result<(), DeError> {
+        self.server.serve(connection).await
+    }
+
+    /// Returns a router serving websocket clients at `/`.
+    pub fn router(self) -> Router {
+        Router::new().route("/", get(upgrade)).with_state(self)
+    }
+}
+
+async fn upgrade(ws: WebSocketUpgrade, State(proxy): State<Proxy>) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| async move {
+        if let Err(e) = proxy.serve(socket).await {
+            warn!("Websocket client failed: {}", e);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client, Blob};
+    use std::{collections::HashMap, time::Duration};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_stream::StreamExt;
+
+    const CCD: &str = r#"<defBLOBVector device="CCD Simulator" name="CCD1" state="Idle" perm="ro">
+<defBLOB name="CCD1"/>
+</defBLOBVector>
+<defSwitchVector device="CCD Simulator" name="CONNECTION" state="Idle" perm="rw" rule="OneOfMany" timeout="1">
+<defSwitch name="CONNECT">Off</defSwitch>
+<defSwitch name="DISCONNECT">On</defSwitch>
+</defSwitchVector>
+"#;
+
+    const IMAGE: &str = r#"<setBLOBVector device="CCD Simulator" name="CCD1" state="Ok">
+<oneBLOB name="CCD1" size="5" format=".fits">aGVsbG8=</oneBLOB>
+</setBLOBVector>
+<setSwitchVector device="CCD Simulator" name="CONNECTION" state="Ok">
+<oneSwitch name="CONNECT">On</oneSwitch>
+<oneSwitch name="DISCONNECT">Off</oneSwitch>
+</setSwitchVector>
+"#;
+
+    #[tokio::test]
+    async fn test_blob_policies() {
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = Proxy::new();
+        let mirror = proxy.clone();
+        let stream = TcpStream::connect(upstream.local_addr().unwrap())
+            .await
+            .unwrap();
+        tokio::spawn(async move { mirror.mirror(stream).await });
+        let (stream, _) = upstream.accept().await.unwrap();
+        let mut upstream = BufReader::new(stream).lines();
+        let line = upstream.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<getProperties"));
+        upstream.get_mut().write_all(CCD.as_bytes()).await.unwrap();
+        let line = upstream.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<enableBLOB"), "{}", line);
+
+        let clients = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut cameras = vec![];
+        for _ in 0..2 {
+            let connection = TcpStream::connect(clients.local_addr().unwrap())
+                .await
+                .unwrap();
+            let (stream, _) = clients.accept().await.unwrap();
+            let proxy = proxy.clone();
+            tokio::spawn(async move { proxy.serve(stream).await });
+            let client = client::new(connection, None, None).unwrap();
+            let camera = client.get_device::<()>("CCD Simulator").await.unwrap();
+            cameras.push((client, camera));
+        }
+
+        let with_blobs = &cameras[0].1;
+        with_blobs
+            .enable_blob(Some("CCD1"), BlobEnable::Also)
+            .await
+            .unwrap();
+        // Only sent upstream once the proxy has seen the enableBLOB before it.
+        let change = with_blobs.clone();
+        tokio::spawn(async move { change.change("CONNECTION", vec![("CONNECT", true)]).await });
+        let line = upstream.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with("<newSwitchVector"), "{}", line);
+
+        upstream
+            .get_mut()
+            .write_all(IMAGE.as_bytes())
+            .await
+            .unwrap();
+        for (i, (_, camera)) in cameras.iter().enumerate() {
+            let connection = camera.get_parameter("CONNECTION").await.unwrap();
+            let mut changes = connection.subscribe().await;
+            while let Some(Ok(param)) = tokio::time::timeout(Duration::from_secs(5), changes.next())
+                .await
+                .unwrap()
+            {
+                if *param.get_state() == crate::PropertyState::Ok {
+                    break;
+                }
+            }
+            // Anything sent before the switch update has arrived by now.
+            let ccd = camera.get_parameter("CCD1").await.unwrap();
+            let ccd = ccd.lock().await;
+            let blobs = ccd.get_values::<HashMap<String, Blob>>().unwrap();
+            assert_eq!(blobs["CCD1"].value.is_some(), i == 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = upstream.local_addr().unwrap();
+        let proxy = Proxy::new();
+        let mirror = proxy.clone();
+        tokio::spawn(async move {
+            mirror
+                .mirror_with(move || TcpStream::connect(addr), Duration::from_millis(10))
+                .await
+        });
+        let devices = proxy.server().get_devices();
+
+        for _ in 0..2 {
+            let (stream, _) = upstream.accept().await.unwrap();
+            let mut stream = BufReader::new(stream).lines();
+            let line = stream.next_line().await.unwrap().unwrap();
+            assert!(line.starts_with("<getProperties"));
+            stream.get_mut().write_all(CCD.as_bytes()).await.unwrap();
+            let mut changes = devices.subscribe().await;
+            while let Some(Ok(devices)) = changes.next().await {
+                if devices.contains_key("CCD Simulator") {
+                    break;
+                }
+            }
+
+            // The mirrored devices are deleted once upstream disconnects.
+            drop(stream);
+            while let Some(Ok(devices)) = changes.next().await {
+                if devices.is_empty() {
+                    break;
+                }
+            }
+        }
+    }
+}
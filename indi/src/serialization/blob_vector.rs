@@ -1,4 +1,6 @@
+use quick_xml::events::BytesStart;
 use serde::{Deserialize, Deserializer};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 use std::{num::Wrapping, sync::Arc};
 
@@ -6,7 +8,8 @@ use crate::{BlobVector, Parameter};
 
 use super::super::*;
 use super::{
-    CommandToUpdate, CommandtoParam, DefBlobVector, SetBlobVector, Timestamp, UpdateError,
+    CommandToUpdate, CommandtoParam, DefBlob, DefBlobVector, OneBlob, SetBlobVector, Timestamp,
+    UpdateError,
 };
 
 impl CommandtoParam for DefBlobVector {
@@ -44,11 +47,43 @@ impl CommandtoParam for DefBlobVector {
     }
 }
 
+impl BlobVector {
+    /// Returns the `defBLOBVector` defining this parameter for `device`, with its blobs
+    /// sorted by name.  Like any `defBLOBVector` it doesn't carry the blobs' data.
+    pub fn to_def(&self, device: &str) -> DefBlobVector {
+        let mut blobs: Vec<DefBlob> = self
+            .values
+            .iter()
+            .map(|(name, blob)| DefBlob {
+                name: name.clone(),
+                label: blob.label.clone(),
+            })
+            .collect();
+        blobs.sort_by(|a, b| a.name.cmp(&b.name));
+        DefBlobVector {
+            device: device.to_string(),
+            name: self.name.clone(),
+            label: self.label.clone(),
+            group: self.group.clone(),
+            state: self.state,
+            perm: self.perm,
+            timeout: self.timeout,
+            timestamp: self.timestamp.map(Timestamp::from),
+            message: None,
+            blobs,
+        }
+    }
+}
+
 impl CommandToUpdate for SetBlobVector {
     fn get_name(&self) -> &String {
         &self.name
     }
 
+    fn get_timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
     fn update_param(self, param: &mut Parameter) -> Result<String, UpdateError> {
         match param {
             Parameter::BlobVector(blob_vector) => {
@@ -57,8 +92,9 @@ impl CommandToUpdate for SetBlobVector {
                 blob_vector.timestamp = self.timestamp.map(Timestamp::into_inner);
                 for blob in self.blobs {
                     if let Some(existing) = blob_vector.values.get_mut(&blob.name) {
-                        existing.format = Some(blob.format);
-                        existing.value = Some(Arc::new(blob.value.into()));
+                        let (format, value) = stored(blob)?;
+                        existing.format = Some(format);
+                        existing.value = Some(Arc::new(value));
                     }
                 }
                 Ok(self.name)
@@ -68,6 +104,117 @@ impl CommandToUpdate for SetBlobVector {
     }
 }
 
+/// Format and data to keep for `blob`, decompressed when the `zlib` feature is enabled.
+fn stored(blob: OneBlob) -> Result<(String, Vec<u8>), UpdateError> {
+    #[cfg(feature = "zlib")]
+    if blob.is_compressed() {
+        let data = blob
+            .decompress()
+            .map_err(|_| UpdateError::DecompressError(blob.name.clone()))?;
+        return Ok((blob.uncompressed_format().to_string(), data));
+    }
+    Ok((blob.format, blob.value.into()))
+}
+
+/// Most space [decompress](OneBlob::decompress) reserves up front, as a multiple of the
+/// compressed length.
+#[cfg(feature = "zlib")]
+const MAX_RESERVED_RATIO: u64 = 16;
+
+impl OneBlob {
+    /// Whether the data is zlib compressed, which INDI marks by appending `.z` to the format.
+    pub fn is_compressed(&self) -> bool {
+        self.format.ends_with(".z")
+    }
+
+    /// Format of the data once decompressed, such as `.fits` for a `.fits.z` blob.
+    pub fn uncompressed_format(&self) -> &str {
+        self.format.strip_suffix(".z").unwrap_or(&self.format)
+    }
+
+    /// Returns the decompressed data of a compressed blob.  The raw data is still
+    /// available from `value`.
+    #[cfg(feature = "zlib")]
+    pub fn decompress(&self) -> Result<Vec<u8>, std::io::Error> {
+        use std::io::Read;
+
+        // `size` comes from the driver, so don't let it reserve more than the data could
+        // plausibly decompress to; the Vec grows if it really is larger.
+        let capacity = self
+            .size
+            .min(self.value.0.len() as u64 * MAX_RESERVED_RATIO) as usize;
+        let mut data = Vec::with_capacity(capacity);
+        flate2::read::ZlibDecoder::new(self.value.0.as_slice()).read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+// A multiple of 3 so that only the final chunk of a blob is padded.
+const ENCODE_CHUNK: usize = 48 * 1024;
+
+impl SetBlobVector {
+    /// Writes the command as XML to `writer`, base64 encoding each blob a chunk at a time
+    /// rather than building the whole document in memory first.
+    ///
+    /// # Arguments
+    /// * `writer` - Where to write the command, usually the connection to an INDI server.
+    pub async fn write_to<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut tag = BytesStart::new("setBLOBVector");
+        tag.push_attribute(("device", self.device.as_str()));
+        tag.push_attribute(("name", self.name.as_str()));
+        tag.push_attribute(("state", state_str(self.state)));
+        if let Some(timeout) = self.timeout {
+            tag.push_attribute(("timeout", timeout.to_string().as_str()));
+        }
+        if let Some(timestamp) = &self.timestamp {
+            tag.push_attribute(("timestamp", timestamp.to_indi_string().as_str()));
+        }
+        if let Some(message) = &self.message {
+            tag.push_attribute(("message", message.as_str()));
+        }
+        write_open(writer, &tag).await?;
+
+        let mut encoded = String::new();
+        for blob in &self.blobs {
+            let mut tag = BytesStart::new("oneBLOB");
+            tag.push_attribute(("name", blob.name.as_str()));
+            tag.push_attribute(("size", blob.size.to_string().as_str()));
+            if let Some(enclen) = blob.enclen {
+                tag.push_attribute(("enclen", enclen.to_string().as_str()));
+            }
+            tag.push_attribute(("format", blob.format.as_str()));
+            write_open(writer, &tag).await?;
+
+            for chunk in blob.value.0.chunks(ENCODE_CHUNK) {
+                encoded.clear();
+                base64::encode_config_buf(chunk, base64::STANDARD, &mut encoded);
+                encoded.push('\n');
+                writer.write_all(encoded.as_bytes()).await?;
+            }
+            writer.write_all(b"</oneBLOB>").await?;
+        }
+        writer.write_all(b"</setBLOBVector>").await
+    }
+}
+
+async fn write_open<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    tag: &BytesStart<'_>,
+) -> std::io::Result<()> {
+    writer.write_all(b"<").await?;
+    writer.write_all(tag).await?;
+    writer.write_all(b">\n").await
+}
+
+fn state_str(state: PropertyState) -> &'static str {
+    match state {
+        PropertyState::Idle => "Idle",
+        PropertyState::Ok => "Ok",
+        PropertyState::Busy => "Busy",
+        PropertyState::Alert => "Alert",
+    }
+}
+
 impl From<Vec<u8>> for super::Blob {
     fn from(value: Vec<u8>) -> Self {
         super::Blob(value)
@@ -104,6 +251,36 @@ impl Serialize for super::Blob {
     }
 }
 
+/// Serializes a [Blob](crate::Blob)'s data as base64, for use with `#[serde(with)]`.
+pub(crate) mod base64_data {
+    use std::sync::Arc;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Arc<Vec<u8>>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(data) => serializer.serialize_some(&base64::encode(data.as_slice())),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Arc<Vec<u8>>>, D::Error> {
+        let value: Option<String> = Deserialize::deserialize(deserializer)?;
+        value
+            .map(|s| {
+                base64::decode(s)
+                    .map(Arc::new)
+                    .map_err(serde::de::Error::custom)
+            })
+            .transpose()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
@@ -189,6 +366,81 @@ mod tests {
         assert_eq!(param.blobs.len(), 2)
     }
 
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn test_compressed_blob() {
+        use std::io::Write;
+
+        let data = b"SIMPLE  =                    T".repeat(100);
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let xml = format!(
+            r#"<setBLOBVector device="CCD Simulator" name="CCD1" state="Ok" timeout="60" timestamp="2022-09-06T01:41:22">
+    <oneBLOB name="CCD1" size="{}" enclen="{}" format=".fits.z">
+{}
+    </oneBLOB>
+</setBLOBVector>"#,
+            data.len(),
+            compressed.len(),
+            base64::encode(&compressed)
+        );
+        let set: SetBlobVector = quick_xml::de::from_str(&xml).unwrap();
+        assert!(set.blobs[0].is_compressed());
+        assert_eq!(set.blobs[0].uncompressed_format(), ".fits");
+        assert_eq!(set.blobs[0].value.0, compressed);
+        assert_eq!(set.blobs[0].decompress().unwrap(), data);
+
+        // A bogus size doesn't reserve that much memory.
+        let mut bogus = set.blobs[0].clone();
+        bogus.size = u64::MAX;
+        let decompressed = bogus.decompress().unwrap();
+        assert_eq!(decompressed, data);
+        assert!(decompressed.capacity() < 1024 * 1024);
+
+        let def: DefBlobVector = quick_xml::de::from_str(
+            r#"<defBLOBVector device="CCD Simulator" name="CCD1" label="Image Data" group="Image Info" state="Idle" perm="ro">
+    <defBLOB name="CCD1" label="Image"/>
+</defBLOBVector>"#,
+        )
+        .unwrap();
+        let mut param = def.to_param(Wrapping(0));
+        set.update_param(&mut param).unwrap();
+
+        let blob = &param.get_values::<HashMap<String, crate::Blob>>().unwrap()["CCD1"];
+        assert_eq!(blob.format, Some(String::from(".fits")));
+        assert_eq!(blob.value.as_deref(), Some(&data));
+    }
+
+    #[tokio::test]
+    async fn test_write_to() {
+        let data: Vec<u8> = (0..3 * super::ENCODE_CHUNK + 1)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let set = SetBlobVector {
+            device: String::from("CCD Simulator"),
+            name: String::from("CCD1"),
+            state: PropertyState::Ok,
+            timeout: Some(60),
+            timestamp: None,
+            message: Some(String::from("<saved & sent>")),
+            blobs: vec![OneBlob {
+                name: String::from("CCD1"),
+                size: data.len() as u64,
+                enclen: None,
+                format: String::from(".fits"),
+                value: super::super::Blob(data),
+            }],
+        };
+
+        let mut xml = vec![];
+        set.write_to(&mut xml).await.unwrap();
+        let parsed: SetBlobVector =
+            quick_xml::de::from_str(std::str::from_utf8(&xml).unwrap()).unwrap();
+        assert_eq!(parsed, set);
+    }
+
     #[test]
     fn test_set_blob_vector() {
         let xml = include_str!("../../tests/image_capture_blob_vector.log");
@@ -0,0 +1,228 @@
+use super::{Command, DeError};
+
+/// Incrementally parses INDI commands from bytes pushed into it, for transports that aren't
+/// [AsyncRead](tokio::io::AsyncRead), such as serial links or shared memory.
+///
+/// Bytes can be fed in chunks of any size, a command is returned once all of it has arrived.
+/// Like the TCP client, malformed xml is returned as a [DeError::Skipped] and parsing carries
+/// on with the next command.
+///
+/// # Example
+/// ```
+/// use indi::serialization::{decoder::Decoder, Command};
+///
+/// let mut decoder = Decoder::new();
+/// assert!(decoder.feed(b"<pingRequest ui").is_empty());
+/// let commands = decoder.feed(b"d=\"1\"/>\n<pingRequest uid=\"2\"/>");
+/// assert_eq!(commands.len(), 2);
+/// assert!(matches!(commands[0], Ok(Command::PingRequest(_))));
+/// ```
+#[derive(Debug, Default)]
+pub struct Decoder {
+    pending: Vec<u8>,
+    // Where scanning `pending` stopped, and the state it stopped in, so that a command
+    // arriving in many small chunks isn't rescanned from its start for each one.
+    position: usize,
+    state: State,
+    depth: usize,
+    command_start: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum State {
+    /// Between tags.
+    #[default]
+    Text,
+    /// In the tag starting at `start`, inside quotes when `quote` is set.
+    Tag { start: usize, quote: Option<u8> },
+    /// In a comment, processing instruction or declaration, until `end`.
+    Markup { end: &'static [u8] },
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Default::default()
+    }
+
+    /// Adds `bytes` to the input, returning the commands completed by them in the order
+    /// they arrived.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Result<Command, DeError>> {
+        self.pending.extend_from_slice(bytes);
+        let mut commands = vec![];
+        while let Some(result) = self.next_command() {
+            commands.push(result);
+        }
+        self.compact();
+        commands
+    }
+
+    /// Scans for the end of the next top level element and parses it.
+    fn next_command(&mut self) -> Option<Result<Command, DeError>> {
+        while self.position < self.pending.len() {
+            let pending = &self.pending;
+            match self.state {
+                State::Text => {
+                    // Text between commands is dropped.
+                    let Some(i) = pending[self.position..].iter().position(|&b| b == b'<') else {
+                        self.position = pending.len();
+                        return None;
+                    };
+                    let start = self.position + i;
+                    let rest = &pending[start..];
+                    self.state = if rest.starts_with(b"<?") {
+                        State::Markup { end: b"?>" }
+                    } else if rest.starts_with(b"<!--") {
+                        State::Markup { end: b"-->" }
+                    } else if b"<!--".starts_with(rest) {
+                        // Not enough has arrived to tell.
+                        self.position = start;
+                        return None;
+                    } else if rest.starts_with(b"<!") {
+                        State::Markup { end: b">" }
+                    } else {
+                        State::Tag { start, quote: None }
+                    };
+                    self.position = start + 1;
+                }
+                State::Markup { end } => {
+                    match pending[self.position..]
+                        .windows(end.len())
+                        .position(|w| w == end)
+                    {
+                        Some(i) => {
+                            self.position += i + end.len();
+                            self.state = State::Text;
+                        }
+                        None => {
+                            self.position = pending.len().saturating_sub(end.len() - 1);
+                            return None;
+                        }
+                    }
+                }
+                State::Tag { start, quote } => {
+                    let b = pending[self.position];
+                    self.position += 1;
+                    match (quote, b) {
+                        (Some(q), b) if q == b => {
+                            self.state = State::Tag { start, quote: None };
+                        }
+                        (Some(_), _) => {}
+                        (None, b'"' | b'\'') => {
+                            self.state = State::Tag {
+                                start,
+                                quote: Some(b),
+                            };
+                        }
+                        (None, b'>') => {
+                            self.state = State::Text;
+                            if let Some(command) = self.end_tag(start) {
+                                return Some(command);
+                            }
+                        }
+                        (None, _) => {}
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Tracks the depth after the tag from `start` to `self.position`, parsing the command
+    /// if it was the end of one.
+    fn end_tag(&mut self, start: usize) -> Option<Result<Command, DeError>> {
+        let tag = &self.pending[start..self.position];
+        if tag.starts_with(b"</") {
+            // An end tag between commands is left over from malformed xml.
+            if self.depth == 0 {
+                return None;
+            }
+            self.depth -= 1;
+        } else {
+            if self.depth == 0 {
+                self.command_start = start;
+            }
+            if !tag.ends_with(b"/>") {
+                self.depth += 1;
+            }
+        }
+        if self.depth > 0 {
+            return None;
+        }
+        let xml = &self.pending[self.command_start..self.position];
+        let result = match std::str::from_utf8(xml) {
+            Ok(xml) => quick_xml::de::from_str(xml).map_err(DeError::from),
+            Err(e) => Err(e.into()),
+        };
+        Some(result.map_err(|error| DeError::Skipped {
+            fragment: String::from_utf8_lossy(xml).into_owned(),
+            error: Box::new(error),
+        }))
+    }
+
+    /// Drops the input that has been dealt with.
+    fn compact(&mut self) {
+        let keep = match self.state {
+            _ if self.depth > 0 => self.command_start,
+            State::Tag { start, .. } => start,
+            _ => self.position,
+        };
+        self.pending.drain(..keep);
+        self.position -= keep;
+        self.command_start = self.command_start.saturating_sub(keep);
+        if let State::Tag { start, quote } = self.state {
+            self.state = State::Tag {
+                start: start - keep,
+                quote,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::serialization::CommandIter;
+
+    #[test]
+    fn test_feed_in_chunks() {
+        let xml = include_bytes!("../../tests/image_capture.log");
+        let expected: Vec<Command> = CommandIter::new(Cursor::new(&xml[..]))
+            .map(Result::unwrap)
+            .collect();
+
+        for chunk_size in [1, 7, 4096, xml.len()] {
+            let mut decoder = Decoder::new();
+            let mut commands = vec![];
+            for chunk in xml.chunks(chunk_size) {
+                commands.extend(decoder.feed(chunk).into_iter().map(Result::unwrap));
+            }
+            assert_eq!(commands, expected, "chunk size {}", chunk_size);
+            assert!(
+                decoder.pending.is_empty() || decoder.pending.iter().all(u8::is_ascii_whitespace)
+            );
+        }
+    }
+
+    #[test]
+    fn test_feed_malformed() {
+        let mut decoder = Decoder::new();
+        let results = decoder.feed(
+            br#"<?xml version="1.0"?>
+<!-- <message message="commented out"/> -->
+</stray>
+<message message="a > b"/>
+<setNumberVector device="CCD Simulator" name="CCD_TEMPERATURE" state="Bogus"></setNumberVector>
+<pingRequest uid="1"/>"#,
+        );
+        assert_eq!(results.len(), 3);
+        assert!(
+            matches!(&results[0], Ok(Command::Message(m)) if m.message.as_deref() == Some("a > b"))
+        );
+        assert!(
+            matches!(&results[1], Err(DeError::Skipped { fragment, .. }) if fragment.starts_with("<setNumberVector"))
+        );
+        assert!(matches!(&results[2], Ok(Command::PingRequest(_))));
+    }
+}
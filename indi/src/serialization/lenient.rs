@@ -0,0 +1,307 @@
+//! Best-effort parsing of the slightly invalid xml some third-party drivers send, used by
+//! clients built with [lenient](crate::client::ClientBuilder::lenient) parsing.
+//!
+//! Values of `state`, `perm` and `rule` attributes, and of switches, lights and
+//! `enableBLOB`s, are recognized regardless of case.  Missing required attributes get these
+//! defaults:
+//!
+//! | Element | Attribute | Default |
+//! |---------|-----------|---------|
+//! | `def*Vector` | `state` | `Idle` |
+//! | `defTextVector`, `defNumberVector`, `defSwitchVector`, `defBLOBVector` | `perm` | `ro` |
+//! | `defSwitchVector` | `rule` | `AnyOfMany` |
+//! | `set*Vector` | `state` | `Ok` |
+//! | `defNumber` | `format` | `%g` |
+//! | `defNumber` | `min`, `max`, `step` | `0`, leaving the number without a range |
+//!
+//! Each fix is reported as a [ParseWarning].
+
+use std::str;
+
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+
+use super::{Command, DeError};
+
+const STATES: &[&str] = &["Idle", "Ok", "Busy", "Alert"];
+const PERMS: &[&str] = &["ro", "wo", "rw"];
+const RULES: &[&str] = &["OneOfMany", "AtMostOne", "AnyOfMany"];
+const SWITCHES: &[&str] = &["On", "Off"];
+const BLOB_ENABLES: &[&str] = &["Never", "Also", "Only"];
+
+/// Something wrong with a command that lenient parsing worked around.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// A required attribute was missing, and `default` was used instead.
+    MissingAttribute {
+        element: String,
+        attribute: String,
+        default: String,
+    },
+    /// A value was recognized despite its case, such as `ok` for `Ok`.
+    Miscased {
+        element: String,
+        found: String,
+        corrected: String,
+    },
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseWarning::MissingAttribute {
+                element,
+                attribute,
+                default,
+            } => write!(
+                f,
+                "<{}> is missing {}, using {:?}",
+                element, attribute, default
+            ),
+            ParseWarning::Miscased {
+                element,
+                found,
+                corrected,
+            } => write!(
+                f,
+                "<{}> has {:?} instead of {:?}",
+                element, found, corrected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseWarning {}
+
+/// Parses the single command in `xml`, working around the problems described in the
+/// [module](self) docs, and returns it along with the warnings for what was fixed.
+pub fn parse(xml: &str) -> Result<(Command, Vec<ParseWarning>), DeError> {
+    let mut warnings = vec![];
+    let repaired = repair(xml, &mut warnings)?;
+    let command = quick_xml::de::from_str(&repaired)?;
+    Ok((command, warnings))
+}
+
+/// Returns `xml` with its problems fixed, adding a warning for each to `warnings`.
+fn repair(xml: &str, warnings: &mut Vec<ParseWarning>) -> Result<String, DeError> {
+    let mut reader = Reader::from_str(xml);
+    let mut document = String::with_capacity(xml.len());
+    let mut elements = vec![];
+    loop {
+        match reader.read_event()? {
+            Event::Start(tag) => {
+                elements.push(write_tag(&mut document, &tag, warnings)?);
+                document.push('>');
+            }
+            Event::Empty(tag) => {
+                write_tag(&mut document, &tag, warnings)?;
+                document.push_str("/>");
+            }
+            Event::End(tag) => {
+                elements.pop();
+                document.push_str("</");
+                document.push_str(str::from_utf8(tag.name().as_ref())?);
+                document.push('>');
+            }
+            Event::Text(text) => {
+                let text = str::from_utf8(&text)?;
+                let element = elements.last().map(String::as_str).unwrap_or_default();
+                match canonical(text, text_values(element)) {
+                    Some(corrected) if corrected != text.trim() => {
+                        warnings.push(ParseWarning::Miscased {
+                            element: element.to_string(),
+                            found: text.trim().to_string(),
+                            corrected: corrected.to_string(),
+                        });
+                        document.push_str(corrected);
+                    }
+                    _ => document.push_str(text),
+                }
+            }
+            Event::CData(data) => {
+                document.push_str("<![CDATA[");
+                document.push_str(str::from_utf8(&data)?);
+                document.push_str("]]>");
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(document)
+}
+
+/// Writes the opening of `tag` with its attributes fixed, leaving it for the caller to close,
+/// and returns its name.
+fn write_tag(
+    document: &mut String,
+    tag: &BytesStart,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<String, DeError> {
+    let element = str::from_utf8(tag.name().as_ref())?.to_string();
+    document.push('<');
+    document.push_str(&element);
+    let mut seen = vec![];
+    for attr in tag.attributes() {
+        let attr = attr?;
+        let key = str::from_utf8(attr.key.as_ref())?;
+        let mut value = str::from_utf8(&attr.value)?;
+        if let Some(corrected) = canonical(value, attribute_values(key)) {
+            if corrected != value {
+                warnings.push(ParseWarning::Miscased {
+                    element: element.clone(),
+                    found: value.to_string(),
+                    corrected: corrected.to_string(),
+                });
+                value = corrected;
+            }
+        }
+        push_attribute(document, key, value);
+        seen.push(key.to_string());
+    }
+    for (attribute, default) in defaults(&element) {
+        if !seen.iter().any(|key| key == attribute) {
+            warnings.push(ParseWarning::MissingAttribute {
+                element: element.clone(),
+                attribute: attribute.to_string(),
+                default: default.to_string(),
+            });
+            push_attribute(document, attribute, default);
+        }
+    }
+    Ok(element)
+}
+
+fn push_attribute(document: &mut String, key: &str, value: &str) {
+    document.push(' ');
+    document.push_str(key);
+    document.push_str("=\"");
+    document.push_str(value);
+    document.push('"');
+}
+
+/// Returns the value in `allowed` matching `value` regardless of case and surrounding
+/// whitespace.
+fn canonical(value: &str, allowed: &[&'static str]) -> Option<&'static str> {
+    let value = value.trim();
+    allowed
+        .iter()
+        .find(|allowed| allowed.eq_ignore_ascii_case(value))
+        .copied()
+}
+
+/// The values allowed for the attribute named `key`, if it's an enum.
+fn attribute_values(key: &str) -> &'static [&'static str] {
+    match key {
+        "state" => STATES,
+        "perm" => PERMS,
+        "rule" => RULES,
+        _ => &[],
+    }
+}
+
+/// The values allowed for the text of `element`, if it's an enum.
+fn text_values(element: &str) -> &'static [&'static str] {
+    match element {
+        "defSwitch" | "oneSwitch" => SWITCHES,
+        "defLight" | "oneLight" => STATES,
+        "enableBLOB" => BLOB_ENABLES,
+        _ => &[],
+    }
+}
+
+/// The required attributes of `element` that have a default.
+fn defaults(element: &str) -> &'static [(&'static str, &'static str)] {
+    match element {
+        "defTextVector" | "defNumberVector" | "defBLOBVector" => {
+            &[("state", "Idle"), ("perm", "ro")]
+        }
+        "defSwitchVector" => &[("state", "Idle"), ("perm", "ro"), ("rule", "AnyOfMany")],
+        "defLightVector" => &[("state", "Idle")],
+        "setTextVector" | "setNumberVector" | "setSwitchVector" | "setLightVector"
+        | "setBLOBVector" => &[("state", "Ok")],
+        "defNumber" => &[("format", "%g"), ("min", "0"), ("max", "0"), ("step", "0")],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PropertyPerm, PropertyState, SwitchRule, SwitchState};
+
+    #[test]
+    fn test_fixes_case() {
+        let xml = r#"<defSwitchVector device="CCD Simulator" name="CONNECTION" state="OK" perm="RW" rule="oneofmany">
+    <defSwitch name="CONNECT">on</defSwitch>
+    <defSwitch name="DISCONNECT">Off</defSwitch>
+</defSwitchVector>"#;
+        assert!(quick_xml::de::from_str::<Command>(xml).is_err());
+
+        let (command, warnings) = parse(xml).unwrap();
+        let Command::DefSwitchVector(def) = command else {
+            panic!("Unexpected command: {:?}", command);
+        };
+        assert_eq!(def.state, PropertyState::Ok);
+        assert_eq!(def.perm, PropertyPerm::RW);
+        assert_eq!(def.rule, SwitchRule::OneOfMany);
+        assert_eq!(def.switches[0].value, SwitchState::On);
+        assert_eq!(
+            warnings,
+            vec![
+                ParseWarning::Miscased {
+                    element: String::from("defSwitchVector"),
+                    found: String::from("OK"),
+                    corrected: String::from("Ok"),
+                },
+                ParseWarning::Miscased {
+                    element: String::from("defSwitchVector"),
+                    found: String::from("RW"),
+                    corrected: String::from("rw"),
+                },
+                ParseWarning::Miscased {
+                    element: String::from("defSwitchVector"),
+                    found: String::from("oneofmany"),
+                    corrected: String::from("OneOfMany"),
+                },
+                ParseWarning::Miscased {
+                    element: String::from("defSwitch"),
+                    found: String::from("on"),
+                    corrected: String::from("On"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fills_missing_attributes() {
+        let xml = r#"<defNumberVector device="Focuser" name="ABS_FOCUS_POSITION" label="Absolute &amp; position">
+    <defNumber name="FOCUS_ABSOLUTE_POSITION">1200</defNumber>
+</defNumberVector>"#;
+        let (command, warnings) = parse(xml).unwrap();
+        let Command::DefNumberVector(def) = command else {
+            panic!("Unexpected command: {:?}", command);
+        };
+        assert_eq!(def.label.as_deref(), Some("Absolute & position"));
+        assert_eq!(def.state, PropertyState::Idle);
+        assert_eq!(def.perm, PropertyPerm::RO);
+        assert_eq!(def.numbers[0].format, "%g");
+        assert_eq!(f64::from(&def.numbers[0].value), 1200.0);
+        assert_eq!(warnings.len(), 6);
+        assert_eq!(
+            warnings[0].to_string(),
+            r#"<defNumberVector> is missing state, using "Idle""#
+        );
+    }
+
+    #[test]
+    fn test_valid_xml_has_no_warnings() {
+        let xml = r#"<setSwitchVector device="CCD Simulator" name="CONNECTION" state="Ok">
+    <oneSwitch name="CONNECT">On</oneSwitch>
+</setSwitchVector>"#;
+        let (command, warnings) = parse(xml).unwrap();
+        assert_eq!(command, quick_xml::de::from_str::<Command>(xml).unwrap());
+        assert!(warnings.is_empty());
+    }
+}
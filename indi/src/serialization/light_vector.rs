@@ -33,11 +33,42 @@ impl CommandtoParam for DefLightVector {
     }
 }
 
+impl LightVector {
+    /// Returns the `defLightVector` defining this parameter for `device`, with its lights
+    /// sorted by name.
+    pub fn to_def(&self, device: &str) -> DefLightVector {
+        let mut lights: Vec<DefLight> = self
+            .values
+            .iter()
+            .map(|(name, light)| DefLight {
+                name: name.clone(),
+                label: light.label.clone(),
+                value: light.value,
+            })
+            .collect();
+        lights.sort_by(|a, b| a.name.cmp(&b.name));
+        DefLightVector {
+            device: device.to_string(),
+            name: self.name.clone(),
+            label: self.label.clone(),
+            group: self.group.clone(),
+            state: self.state,
+            timestamp: self.timestamp.map(Timestamp::from),
+            message: None,
+            lights,
+        }
+    }
+}
+
 impl CommandToUpdate for SetLightVector {
     fn get_name(&self) -> &String {
         &self.name
     }
 
+    fn get_timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
     fn update_param(self, param: &mut Parameter) -> Result<String, UpdateError> {
         match param {
             Parameter::LightVector(light_vector) => {
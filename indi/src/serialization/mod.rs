@@ -8,10 +8,14 @@ use quick_xml::de::{IoReader, XmlRead};
 use serde::Serialize;
 
 pub mod blob_vector;
+pub mod decoder;
 pub mod del_property;
 pub mod get_properties;
+pub mod lenient;
 pub mod light_vector;
 pub mod message;
+pub mod number_format;
+pub mod ping;
 pub mod switch_vector;
 use super::*;
 
@@ -23,13 +27,21 @@ mod tests;
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Timestamp(pub DateTime<Utc>);
 
+impl Timestamp {
+    /// Formats the timestamp as INDI expects it, to the millisecond and without a zone.
+    pub(crate) fn to_indi_string(self) -> String {
+        let mut ts = self.to_rfc3339_opts(SecondsFormat::Millis, true);
+        ts.pop();
+        ts
+    }
+}
+
 impl Serialize for Timestamp {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let ts = self.to_rfc3339_opts(SecondsFormat::Millis, true);
-        serializer.serialize_str(&ts.as_str()[..ts.len() - 1])
+        serializer.serialize_str(&self.to_indi_string())
     }
 }
 
@@ -39,12 +51,44 @@ impl<'de> Deserialize<'de> for Timestamp {
         D: serde::Deserializer<'de>,
     {
         let s: String = Deserialize::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|e| serde::de::Error::custom(format!("invalid timestamp {:?}: {}", s, e)))
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = ParseError;
 
-        let datetime = DateTime::from_str(&format!("{}Z", s)).unwrap();
-        Ok(Timestamp(datetime))
+    /// Parses the timestamps drivers send: INDI's `YYYY-MM-DDTHH:MM:SS` in UTC, optionally
+    /// with fractional seconds, a space instead of the `T`, or a `Z` or UTC offset suffix.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Timestamp(datetime.with_timezone(&Utc)));
+        }
+        let datetime = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+            .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f"))?;
+        Ok(Timestamp(datetime.and_utc()))
     }
 }
 
+/// Deserializes an optional `timestamp` attribute, treating an empty or malformed value as
+/// missing rather than failing the whole command.
+fn optional_timestamp<'de, D>(deserializer: D) -> Result<Option<Timestamp>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
+    Ok(s.filter(|s| !s.trim().is_empty())
+        .and_then(|s| match s.parse() {
+            Ok(timestamp) => Some(timestamp),
+            Err(e) => {
+                log::warn!("Ignoring invalid timestamp {:?}: {}", s, e);
+                None
+            }
+        }))
+}
+
 impl Deref for Timestamp {
     type Target = DateTime<Utc>;
 
@@ -65,7 +109,7 @@ impl Timestamp {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 
 pub enum Command {
     // Commands from Device to Connections
@@ -105,6 +149,12 @@ pub enum Command {
     // Commands from Connection to Device
     #[serde(rename = "getProperties")]
     GetProperties(GetProperties),
+
+    // Keepalive commands, sent in either direction by INDI 2.x servers and clients
+    #[serde(rename = "pingRequest")]
+    PingRequest(PingRequest),
+    #[serde(rename = "pingReply")]
+    PingReply(PingReply),
 }
 
 impl Command {
@@ -133,6 +183,33 @@ impl Command {
                 None => None,
             },
             Command::EnableBlob(c) => Some(&c.device),
+            Command::PingRequest(_) => None,
+            Command::PingReply(_) => None,
+        }
+    }
+
+    /// Returns the name of the parameter the command is for, if it is for a single one.
+    pub fn param_name(&self) -> Option<&String> {
+        match self {
+            Command::DefTextVector(c) => Some(&c.name),
+            Command::SetTextVector(c) => Some(&c.name),
+            Command::NewTextVector(c) => Some(&c.name),
+            Command::DefNumberVector(c) => Some(&c.name),
+            Command::SetNumberVector(c) => Some(&c.name),
+            Command::NewNumberVector(c) => Some(&c.name),
+            Command::DefSwitchVector(c) => Some(&c.name),
+            Command::SetSwitchVector(c) => Some(&c.name),
+            Command::NewSwitchVector(c) => Some(&c.name),
+            Command::DefLightVector(c) => Some(&c.name),
+            Command::SetLightVector(c) => Some(&c.name),
+            Command::DefBlobVector(c) => Some(&c.name),
+            Command::SetBlobVector(c) => Some(&c.name),
+            Command::Message(_) => None,
+            Command::DelProperty(c) => c.name.as_ref(),
+            Command::GetProperties(c) => c.name.as_ref(),
+            Command::EnableBlob(c) => c.name.as_ref(),
+            Command::PingRequest(_) => None,
+            Command::PingReply(_) => None,
         }
     }
 }
@@ -228,6 +305,8 @@ pub enum UpdateError {
     ParameterMissing(String),
     ParameterTypeMismatch(String),
     PoisonError,
+    /// The named compressed BLOB couldn't be decompressed.
+    DecompressError(String),
 }
 
 impl<T> From<PoisonError<T>> for UpdateError {
@@ -236,6 +315,23 @@ impl<T> From<PoisonError<T>> for UpdateError {
     }
 }
 
+impl std::fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateError::ParameterMissing(name) => write!(f, "parameter {:?} is not defined", name),
+            UpdateError::ParameterTypeMismatch(name) => {
+                write!(f, "parameter {:?} is a different type", name)
+            }
+            UpdateError::PoisonError => write!(f, "lock poisoned"),
+            UpdateError::DecompressError(name) => {
+                write!(f, "failed to decompress blob {:?}", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {}
+
 pub enum Action {
     Define,
     Update,
@@ -250,14 +346,34 @@ pub trait CommandtoParam {
 
 pub trait CommandToUpdate {
     fn get_name(&self) -> &String;
+    fn get_timestamp(&self) -> Option<Timestamp>;
     fn update_param(self, param: &mut Parameter) -> Result<String, UpdateError>;
 }
 
+#[derive(Debug)]
 pub enum ClientErrors {
     DeError(DeError),
     UpdateError(UpdateError),
 }
 
+impl std::fmt::Display for ClientErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientErrors::DeError(e) => write!(f, "{}", e),
+            ClientErrors::UpdateError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientErrors {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientErrors::DeError(e) => Some(e),
+            ClientErrors::UpdateError(e) => Some(e),
+        }
+    }
+}
+
 impl From<DeError> for ClientErrors {
     fn from(err: DeError) -> Self {
         ClientErrors::DeError(err)
@@ -269,44 +385,49 @@ impl From<UpdateError> for ClientErrors {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "defTextVector")]
 pub struct DefTextVector {
     #[serde(rename = "@device")]
     pub device: String,
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@label")]
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
-    #[serde(rename = "@group")]
+    #[serde(rename = "@group", skip_serializing_if = "Option::is_none")]
     pub group: Option<String>,
     #[serde(rename = "@state")]
     pub state: PropertyState,
     #[serde(rename = "@perm")]
     pub perm: PropertyPerm,
-    #[serde(rename = "@timeout")]
+    #[serde(rename = "@timeout", skip_serializing_if = "Option::is_none")]
     pub timeout: Option<u32>,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
     #[serde(rename = "defText")]
     pub texts: Vec<DefText>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "defText")]
 pub struct DefText {
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@label")]
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
     #[serde(rename = "$text", default = "String::new")]
     pub value: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "setTextVector")]
 pub struct SetTextVector {
     #[serde(rename = "@device")]
@@ -315,25 +436,35 @@ pub struct SetTextVector {
     pub name: String,
     #[serde(rename = "@state")]
     pub state: PropertyState,
-    #[serde(rename = "@timeout")]
+    #[serde(rename = "@timeout", skip_serializing_if = "Option::is_none")]
     pub timeout: Option<u32>,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
     #[serde(rename = "oneText")]
     pub texts: Vec<OneText>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "newTextVector")]
 pub struct NewTextVector {
     #[serde(rename = "@device")]
     pub device: String,
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
 
     #[serde(rename = "oneText")]
@@ -349,45 +480,64 @@ pub struct OneText {
     pub value: String,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+/// A number, written either as a decimal or as sexagesimal such as `19:50:47`.  Values parsed
+///  from text are written back the way they were written, as long as they haven't changed,
+///  so echoing a driver's values doesn't change their formatting.  Comparisons ignore the
+///  text.
+#[derive(Debug, Clone)]
 pub struct Sexagesimal {
     pub hour: f64,
     pub minute: Option<f64>,
     pub second: Option<f64>,
+    original: Option<Arc<Original>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// The text a [Sexagesimal] was parsed from, and the components it was parsed into.
+#[derive(Debug)]
+struct Original {
+    text: String,
+    hour: f64,
+    minute: Option<f64>,
+    second: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "defNumberVector")]
 pub struct DefNumberVector {
     #[serde(rename = "@device")]
     pub device: String,
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@label")]
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
-    #[serde(rename = "@group")]
+    #[serde(rename = "@group", skip_serializing_if = "Option::is_none")]
     pub group: Option<String>,
     #[serde(rename = "@state")]
     pub state: PropertyState,
     #[serde(rename = "@perm")]
     pub perm: PropertyPerm,
-    #[serde(rename = "@timeout")]
+    #[serde(rename = "@timeout", skip_serializing_if = "Option::is_none")]
     pub timeout: Option<u32>,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
     #[serde(rename = "defNumber")]
     pub numbers: Vec<DefNumber>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "defNumber")]
 pub struct DefNumber {
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@label")]
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
     #[serde(rename = "@format")]
     pub format: String,
@@ -401,7 +551,7 @@ pub struct DefNumber {
     pub value: Sexagesimal,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "setNumberVector")]
 pub struct SetNumberVector {
     #[serde(rename = "@device")]
@@ -410,40 +560,50 @@ pub struct SetNumberVector {
     pub name: String,
     #[serde(rename = "@state")]
     pub state: PropertyState,
-    #[serde(rename = "@timeout")]
+    #[serde(rename = "@timeout", skip_serializing_if = "Option::is_none")]
     pub timeout: Option<u32>,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
     #[serde(rename = "oneNumber")]
     pub numbers: Vec<SetOneNumber>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "oneNumber")]
 pub struct SetOneNumber {
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@min")]
+    #[serde(rename = "@min", skip_serializing_if = "Option::is_none")]
     pub min: Option<f64>,
-    #[serde(rename = "@max")]
+    #[serde(rename = "@max", skip_serializing_if = "Option::is_none")]
     pub max: Option<f64>,
-    #[serde(rename = "@step")]
+    #[serde(rename = "@step", skip_serializing_if = "Option::is_none")]
     pub step: Option<f64>,
     #[serde(rename = "$value")]
     pub value: Sexagesimal,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "newNumberVector")]
 pub struct NewNumberVector {
     #[serde(rename = "@device")]
     pub device: String,
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
 
     #[serde(rename = "oneNumber")]
@@ -459,16 +619,16 @@ pub struct OneNumber {
     pub value: Sexagesimal,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "defSwitchVector")]
 pub struct DefSwitchVector {
     #[serde(rename = "@device")]
     pub device: String,
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@label")]
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
-    #[serde(rename = "@group")]
+    #[serde(rename = "@group", skip_serializing_if = "Option::is_none")]
     pub group: Option<String>,
     #[serde(rename = "@state")]
     pub state: PropertyState,
@@ -476,29 +636,34 @@ pub struct DefSwitchVector {
     pub perm: PropertyPerm,
     #[serde(rename = "@rule")]
     pub rule: SwitchRule,
-    #[serde(rename = "@timeout")]
+    #[serde(rename = "@timeout", skip_serializing_if = "Option::is_none")]
     pub timeout: Option<u32>,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
     #[serde(rename = "defSwitch")]
     pub switches: Vec<DefSwitch>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "defSwitch")]
 pub struct DefSwitch {
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@label")]
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
     #[serde(rename = "$text")]
     pub value: SwitchState,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "setSwitchVector")]
 pub struct SetSwitchVector {
     #[serde(rename = "@device")]
@@ -507,24 +672,34 @@ pub struct SetSwitchVector {
     pub name: String,
     #[serde(rename = "@state")]
     pub state: PropertyState,
-    #[serde(rename = "@timeout")]
+    #[serde(rename = "@timeout", skip_serializing_if = "Option::is_none")]
     pub timeout: Option<u32>,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
     #[serde(rename = "oneSwitch")]
     pub switches: Vec<OneSwitch>,
 }
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "newSwitchVector")]
 pub struct NewSwitchVector {
     #[serde(rename = "@device")]
     pub device: String,
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
 
     #[serde(rename = "oneSwitch")]
@@ -540,40 +715,45 @@ pub struct OneSwitch {
     pub value: SwitchState,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "defLightVector")]
 pub struct DefLightVector {
     #[serde(rename = "@device")]
     pub device: String,
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@label")]
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
-    #[serde(rename = "@group")]
+    #[serde(rename = "@group", skip_serializing_if = "Option::is_none")]
     pub group: Option<String>,
     #[serde(rename = "@state")]
     pub state: PropertyState,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
     #[serde(rename = "defLight")]
     pub lights: Vec<DefLight>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "defLight")]
 pub struct DefLight {
     #[serde(rename = "@name")]
-    name: String,
-    #[serde(rename = "@label")]
-    label: Option<String>,
+    pub name: String,
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
     #[serde(rename = "$text")]
-    value: PropertyState,
+    pub value: PropertyState,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "setLightVector")]
 pub struct SetLightVector {
     #[serde(rename = "@device")]
@@ -582,60 +762,70 @@ pub struct SetLightVector {
     pub name: String,
     #[serde(rename = "@state")]
     pub state: PropertyState,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
     #[serde(rename = "oneLight")]
     pub lights: Vec<OneLight>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "oneLight")]
 pub struct OneLight {
     #[serde(rename = "@name")]
-    name: String,
+    pub name: String,
     #[serde(rename = "$text")]
-    value: PropertyState,
+    pub value: PropertyState,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "defBLOBVector")]
 pub struct DefBlobVector {
     #[serde(rename = "@device")]
     pub device: String,
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@label")]
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
-    #[serde(rename = "@group")]
+    #[serde(rename = "@group", skip_serializing_if = "Option::is_none")]
     pub group: Option<String>,
     #[serde(rename = "@state")]
     pub state: PropertyState,
     #[serde(rename = "@perm")]
     pub perm: PropertyPerm,
-    #[serde(rename = "@timeout")]
+    #[serde(rename = "@timeout", skip_serializing_if = "Option::is_none")]
     pub timeout: Option<u32>,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
     #[serde(rename = "defBLOB")]
     pub blobs: Vec<DefBlob>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "defBLOB")]
 pub struct DefBlob {
     #[serde(rename = "@name")]
-    name: String,
-    #[serde(rename = "@label")]
-    label: Option<String>,
+    pub name: String,
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename = "setBLOBVector")]
 pub struct SetBlobVector {
     #[serde(rename = "@device")]
@@ -644,11 +834,16 @@ pub struct SetBlobVector {
     pub name: String,
     #[serde(rename = "@state")]
     pub state: PropertyState,
-    #[serde(rename = "@timeout")]
+    #[serde(rename = "@timeout", skip_serializing_if = "Option::is_none")]
     pub timeout: Option<u32>,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
     #[serde(rename = "oneBLOB")]
@@ -658,14 +853,14 @@ pub struct SetBlobVector {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Blob(pub Vec<u8>);
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "oneBLOB")]
 pub struct OneBlob {
     #[serde(rename = "@name")]
     pub name: String,
     #[serde(rename = "@size")]
     pub size: u64,
-    #[serde(rename = "@enclen")]
+    #[serde(rename = "@enclen", skip_serializing_if = "Option::is_none")]
     pub enclen: Option<u64>,
     #[serde(rename = "@format")]
     pub format: String,
@@ -673,7 +868,7 @@ pub struct OneBlob {
     pub value: Blob,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "enableBLOB")]
 pub struct EnableBlob {
     #[serde(rename = "@device")]
@@ -687,28 +882,38 @@ pub struct EnableBlob {
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "message")]
 pub struct Message {
-    #[serde(rename = "@device")]
+    #[serde(rename = "@device", skip_serializing_if = "Option::is_none")]
     pub device: Option<String>,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "delProperty")]
 pub struct DelProperty {
     #[serde(rename = "@device")]
     pub device: String,
-    #[serde(rename = "@name")]
+    #[serde(rename = "@name", skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
-    #[serde(rename = "@timestamp")]
+    #[serde(
+        rename = "@timestamp",
+        skip_serializing_if = "Option::is_none",
+        default,
+        deserialize_with = "optional_timestamp"
+    )]
     pub timestamp: Option<Timestamp>,
-    #[serde(rename = "@message")]
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename = "getProperties")]
 pub struct GetProperties {
     #[serde(rename = "@version")]
@@ -721,6 +926,31 @@ pub struct GetProperties {
     pub name: Option<String>,
 }
 
+/// Sent by INDI 2.x peers to check the connection is alive.  The other side must answer
+/// with a [PingReply] carrying the same `uid`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename = "pingRequest")]
+pub struct PingRequest {
+    #[serde(rename = "@uid")]
+    pub uid: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename = "pingReply")]
+pub struct PingReply {
+    #[serde(rename = "@uid")]
+    pub uid: String,
+}
+
+impl PingRequest {
+    /// Returns the reply answering this request.
+    pub fn reply(&self) -> PingReply {
+        PingReply {
+            uid: self.uid.clone(),
+        }
+    }
+}
+
 // pub trait XmlSerialization {
 //     fn write<'a, T: std::io::Write>(
 //         &self,
@@ -747,7 +977,13 @@ pub enum DeError {
     UnexpectedEvent(String),
     UnexpectedTag(String),
     AxumError(axum::Error),
-    Tungstenite(tokio_tungstenite::tungstenite::Error),
+    /// Boxed since it's much larger than the other errors.
+    Tungstenite(Box<tokio_tungstenite::tungstenite::Error>),
+    /// The xml in `fragment` couldn't be parsed and was skipped.
+    Skipped {
+        fragment: String,
+        error: Box<DeError>,
+    },
 }
 
 impl From<quick_xml::Error> for DeError {
@@ -770,7 +1006,7 @@ impl From<std::string::FromUtf8Error> for DeError {
 
 impl From<tokio_tungstenite::tungstenite::Error> for DeError {
     fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
-        DeError::Tungstenite(err)
+        DeError::Tungstenite(Box::new(err))
     }
 }
 
@@ -817,6 +1053,57 @@ impl From<AttrError> for DeError {
     }
 }
 
+impl std::fmt::Display for DeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeError::SerializationError(e) => write!(f, "serialization error: {}", e),
+            DeError::XmlError(e) => write!(f, "xml error: {}", e),
+            DeError::XmlDeError(e) => write!(f, "xml deserialization error: {}", e),
+            DeError::IoError(e) => write!(f, "io error: {}", e),
+            DeError::DecodeUtf8(e) => write!(f, "invalid utf-8: {}", e),
+            DeError::FromUtf8Error(e) => write!(f, "invalid utf-8: {}", e),
+            DeError::DecodeLatin(e) => write!(f, "invalid latin-1: {}", e),
+            DeError::ParseIntError(e) => write!(f, "invalid integer: {}", e),
+            DeError::ParseFloatError(e) => write!(f, "invalid number: {}", e),
+            DeError::ParseSexagesimalError(value) => {
+                write!(f, "invalid sexagesimal number {:?}", value)
+            }
+            DeError::ParseDateTimeError(e) => write!(f, "invalid timestamp: {}", e),
+            DeError::MissingAttr(name) => write!(f, "missing attribute {:?}", name),
+            DeError::BadAttr(e) => write!(f, "bad attribute: {}", e),
+            DeError::UnexpectedAttr(name) => write!(f, "unexpected attribute {:?}", name),
+            DeError::UnexpectedEvent(event) => write!(f, "unexpected xml event {}", event),
+            DeError::UnexpectedTag(tag) => write!(f, "unexpected tag {:?}", tag),
+            DeError::AxumError(e) => write!(f, "websocket error: {}", e),
+            DeError::Tungstenite(e) => write!(f, "websocket error: {}", e),
+            DeError::Skipped { fragment, error } => {
+                write!(f, "skipped malformed xml ({}): {}", error, fragment)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeError::SerializationError(e) => Some(e),
+            DeError::XmlError(e) => Some(e),
+            DeError::XmlDeError(e) => Some(e),
+            DeError::IoError(e) => Some(e),
+            DeError::DecodeUtf8(e) => Some(e),
+            DeError::FromUtf8Error(e) => Some(e),
+            DeError::ParseIntError(e) => Some(e),
+            DeError::ParseFloatError(e) => Some(e),
+            DeError::ParseDateTimeError(e) => Some(e),
+            DeError::BadAttr(e) => Some(e),
+            DeError::AxumError(e) => Some(e),
+            DeError::Tungstenite(e) => Some(e.as_ref()),
+            DeError::Skipped { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
 pub struct CommandIter<'a, T: XmlRead<'a>> {
     xml_reader: quick_xml::de::Deserializer<'a, T>,
 }
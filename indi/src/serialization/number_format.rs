@@ -0,0 +1,382 @@
+//! Formatting numbers the way INDI drivers ask for them.
+//!
+//! Every `defNumber` carries a printf style `format`, such as `%.3f`, or INDI's sexagesimal
+//! extension `%<w>.<f>m` where `<w>` is the total width and `<f>` picks the precision of the
+//! fractional part:
+//!
+//! | `<f>` | Output        |
+//! |-------|---------------|
+//! | 9     | `-d:mm:ss.ss` |
+//! | 8     | `-d:mm:ss.s`  |
+//! | 6     | `-d:mm:ss`    |
+//! | 5     | `-d:mm.m`     |
+//! | 3     | `-d:mm`       |
+
+use std::{str::FromStr, sync::Arc};
+
+use super::Original;
+use crate::{DeError, NewNumberVector, Number, NumberVector, Sexagesimal};
+
+/// Formats `value` according to the INDI number `format`.  Formats that can't be understood
+/// fall back to the shortest decimal representation of `value`.
+pub fn format_number(format: &str, value: f64) -> String {
+    let Some((prefix, spec, suffix)) = Spec::parse(format) else {
+        return value.to_string();
+    };
+    let number = match spec.conversion {
+        'm' => return format!("{}{}{}", prefix, sexagesimal(&spec, value), suffix),
+        'f' | 'F' => format!("{:.*}", spec.precision.unwrap_or(6), value.abs()),
+        'e' | 'E' => exponential(spec.precision.unwrap_or(6), value.abs()),
+        'g' | 'G' => general(spec.precision.unwrap_or(6), value.abs()),
+        _ => format!("{}", value.abs().round()),
+    };
+    let number = if spec.conversion.is_ascii_uppercase() {
+        number.to_uppercase()
+    } else {
+        number
+    };
+    format!("{}{}{}", prefix, spec.pad(value, number), suffix)
+}
+
+/// A single printf conversion, `%[flags][width][.precision]conversion`.
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+impl Spec {
+    /// Splits `format` into the text before the conversion, the conversion and the text
+    /// after it.
+    fn parse(format: &str) -> Option<(&str, Spec, &str)> {
+        let start = format.find('%')?;
+        let mut chars = format[start + 1..].char_indices().peekable();
+        let mut spec = Spec {
+            left: false,
+            plus: false,
+            space: false,
+            zero: false,
+            width: 0,
+            precision: None,
+            conversion: 'f',
+        };
+        while let Some((_, c)) = chars.peek() {
+            match c {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '0' => spec.zero = true,
+                '#' => {}
+                _ => break,
+            }
+            chars.next();
+        }
+        spec.width = digits(&mut chars);
+        if chars.next_if(|(_, c)| *c == '.').is_some() {
+            spec.precision = Some(digits(&mut chars));
+        }
+        // Length modifiers such as `l` in `%lf` don't change anything for an f64.
+        while chars.next_if(|(_, c)| *c == 'l' || *c == 'h').is_some() {}
+        let (i, conversion) = chars.next()?;
+        if !"fFeEgGdim".contains(conversion) {
+            return None;
+        }
+        spec.conversion = conversion;
+        let end = start + 1 + i + conversion.len_utf8();
+        Some((&format[..start], spec, &format[end..]))
+    }
+
+    /// Adds the sign of `value` to `number` and pads it to the width.
+    fn pad(&self, value: f64, number: String) -> String {
+        let sign = if value.is_sign_negative() {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        };
+        let fill = self.width.saturating_sub(sign.len() + number.len());
+        if self.left {
+            format!("{}{}{}", sign, number, " ".repeat(fill))
+        } else if self.zero {
+            format!("{}{}{}", sign, "0".repeat(fill), number)
+        } else {
+            format!("{}{}{}", " ".repeat(fill), sign, number)
+        }
+    }
+}
+
+fn digits(chars: &mut std::iter::Peekable<std::str::CharIndices>) -> usize {
+    let mut n = 0;
+    while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+        n = n * 10 + c.to_digit(10).unwrap_or_default() as usize;
+    }
+    n
+}
+
+/// `%e`, with at least two exponent digits like C.
+fn exponential(precision: usize, value: f64) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or_default();
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+/// `%g`, which picks `%e` or `%f` by the exponent and drops trailing zeros.
+fn general(precision: usize, value: f64) -> String {
+    let precision = precision.max(1);
+    let formatted = format!("{:.*e}", precision - 1, value);
+    let exponent: i32 = formatted
+        .split_once('e')
+        .and_then(|(_, e)| e.parse().ok())
+        .unwrap_or_default();
+    if exponent < -4 || exponent >= precision as i32 {
+        let formatted = exponential(precision - 1, value);
+        let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, ""));
+        format!("{}e{}", trim_zeros(mantissa), exponent)
+    } else {
+        let decimals = (precision as i32 - 1 - exponent) as usize;
+        trim_zeros(&format!("{:.*}", decimals, value)).to_string()
+    }
+}
+
+fn trim_zeros(number: &str) -> &str {
+    if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    }
+}
+
+/// INDI's `%<w>.<f>m`, following `fs_sexa` from the INDI library.
+fn sexagesimal(spec: &Spec, value: f64) -> String {
+    let fraction_base: u64 = match spec.precision.unwrap_or_default() {
+        9 => 360000,
+        8 => 36000,
+        6 => 3600,
+        5 => 600,
+        _ => 60,
+    };
+    let width = spec
+        .width
+        .saturating_sub(spec.precision.unwrap_or_default());
+
+    let negative = value < 0.0;
+    let n = (value.abs() * fraction_base as f64 + 0.5) as u64;
+    let whole = n / fraction_base;
+    let fraction = n % fraction_base;
+
+    let mut out = if negative && whole == 0 {
+        format!("{:>width$}", "-0", width = width)
+    } else if negative {
+        format!("{:>width$}", -(whole as i64), width = width)
+    } else {
+        format!("{:>width$}", whole, width = width)
+    };
+    let per_minute = fraction_base / 60;
+    let (minutes, rest) = (fraction / per_minute.max(1), fraction % per_minute.max(1));
+    match fraction_base {
+        600 => out.push_str(&format!(":{:02}.{}", fraction / 10, fraction % 10)),
+        3600 => out.push_str(&format!(":{:02}:{:02}", minutes, rest)),
+        36000 => out.push_str(&format!(":{:02}:{:02}.{}", minutes, rest / 10, rest % 10)),
+        360000 => out.push_str(&format!(
+            ":{:02}:{:02}.{:02}",
+            minutes,
+            rest / 100,
+            rest % 100
+        )),
+        _ => out.push_str(&format!(":{:02}", fraction)),
+    }
+    out
+}
+
+/// Whether `format` is INDI's sexagesimal `%<w>.<f>m`.
+pub fn is_sexagesimal(format: &str) -> bool {
+    matches!(Spec::parse(format), Some((_, spec, _)) if spec.conversion == 'm')
+}
+
+impl NewNumberVector {
+    /// Rewrites the values `param` formats as sexagesimal, such as RA and Dec, into that form
+    /// since some drivers mis-parse plain decimals for them.  Other values are left as is.
+    pub fn apply_formats(&mut self, param: &NumberVector) {
+        for number in &mut self.numbers {
+            let Some(format) = param.values.get(&number.name).map(|n| &n.format) else {
+                continue;
+            };
+            if !is_sexagesimal(format) {
+                continue;
+            }
+            if let Ok(value) = number.value.format(format).parse() {
+                number.value = value;
+            }
+        }
+    }
+}
+
+impl Sexagesimal {
+    /// Formats the value according to an INDI number `format`, see [format_number].
+    pub fn format(&self, format: &str) -> String {
+        format_number(format, self.into())
+    }
+}
+
+impl Number {
+    /// Formats the value the way the driver defined it.
+    pub fn formatted(&self) -> String {
+        self.value.format(&self.format)
+    }
+}
+
+impl FromStr for Sexagesimal {
+    type Err = DeError;
+
+    /// Parses decimal numbers and sexagesimal ones separated by `:` or spaces, such as
+    /// `-12:30:15.5`, `12 30` or `5:07.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let components: Option<Vec<f64>> = s
+            .trim()
+            .split([' ', ':'])
+            .filter(|c| !c.is_empty())
+            .map(|c| c.parse().ok())
+            .collect();
+        let (hour, minute, second) = match components.as_deref() {
+            Some(&[hour]) => (hour, None, None),
+            Some(&[hour, minute]) => (hour, Some(minute), None),
+            Some(&[hour, minute, second]) => (hour, Some(minute), Some(second)),
+            _ => return Err(DeError::ParseSexagesimalError(s.to_string())),
+        };
+        Ok(Sexagesimal {
+            hour,
+            minute,
+            second,
+            original: Some(Arc::new(Original {
+                text: s.trim().to_string(),
+                hour,
+                minute,
+                second,
+            })),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_sexagesimal() {
+        assert_eq!(format_number("%10.6m", 12.5), "  12:30:00");
+        assert_eq!(format_number("%9.6m", -0.5), " -0:30:00");
+        assert_eq!(format_number("%9.6m", -10.5), "-10:30:00");
+        assert_eq!(format_number("%11.8m", 5.25125), "  5:15:04.5");
+        assert_eq!(format_number("%12.9m", 5.25125), "  5:15:04.50");
+        assert_eq!(format_number("%7.5m", 5.125), " 5:07.5");
+        assert_eq!(format_number("%5.3m", 5.999999), " 6:00");
+    }
+
+    #[test]
+    fn test_format_printf() {
+        assert_eq!(format_number("%4.0f", 1280.0), "1280");
+        assert_eq!(format_number("%6.2f", 1.23456), "  1.23");
+        assert_eq!(format_number("%-6.2f|", -1.23456), "-1.23 |");
+        assert_eq!(format_number("%+07.2f", 1.23456), "+001.23");
+        assert_eq!(format_number("%.3f", -0.0001), "-0.000");
+        assert_eq!(format_number("%e", 1500.0), "1.500000e+03");
+        assert_eq!(format_number("%.2E", 0.00015), "1.50E-04");
+        assert_eq!(format_number("%g", 0.0001), "0.0001");
+        assert_eq!(format_number("%g", 100.0), "100");
+        assert_eq!(format_number("%g", 1234567.0), "1.23457e+06");
+        assert_eq!(format_number("%d", 42.4), "42");
+        assert_eq!(format_number("%.2lf C", 21.456), "21.46 C");
+        assert_eq!(format_number("unknown", 1.5), "1.5");
+    }
+
+    #[test]
+    fn test_number_formatted() {
+        let number = Number {
+            label: None,
+            format: String::from("%010.6m"),
+            min: 0.0,
+            max: 24.0,
+            step: 0.0,
+            value: "19:50:47".parse().unwrap(),
+        };
+        assert_eq!(number.formatted(), "  19:50:47");
+    }
+
+    #[test]
+    fn test_apply_formats() {
+        let number = |format: &str| Number {
+            label: None,
+            format: String::from(format),
+            min: 0.0,
+            max: 360.0,
+            step: 0.0,
+            value: 0.0.into(),
+        };
+        let param = NumberVector {
+            gen: std::num::Wrapping(0),
+            name: String::from("EQUATORIAL_EOD_COORD"),
+            group: None,
+            label: None,
+            state: crate::PropertyState::Idle,
+            perm: crate::PropertyPerm::RW,
+            timeout: None,
+            timestamp: None,
+            values: [
+                (String::from("RA"), number("%010.6m")),
+                (String::from("DEC"), number("%.2f")),
+            ]
+            .into(),
+        };
+        let mut command = NewNumberVector {
+            device: String::from("Telescope Simulator"),
+            name: String::from("EQUATORIAL_EOD_COORD"),
+            timestamp: None,
+            numbers: vec![
+                crate::OneNumber {
+                    name: String::from("RA"),
+                    value: 19.846388.into(),
+                },
+                crate::OneNumber {
+                    name: String::from("DEC"),
+                    value: 40.7339.into(),
+                },
+            ],
+        };
+        command.apply_formats(&param);
+
+        let xml = quick_xml::se::to_string(&command).unwrap();
+        assert!(
+            xml.contains("<oneNumber name=\"RA\">19:50:47</oneNumber>"),
+            "{}",
+            xml
+        );
+        assert!(
+            xml.contains("<oneNumber name=\"DEC\">40.7339</oneNumber>"),
+            "{}",
+            xml
+        );
+    }
+
+    #[test]
+    fn test_parse_sexagesimal() {
+        assert_eq!(
+            "12:30:15.5".parse::<Sexagesimal>().unwrap(),
+            Sexagesimal::new(12.0, Some(30.0), Some(15.5))
+        );
+        assert_eq!(f64::from(" -0:30 ".parse::<Sexagesimal>().unwrap()), -0.5);
+        assert_eq!(f64::from("12 30".parse::<Sexagesimal>().unwrap()), 12.5);
+        assert_eq!(f64::from("+5:07.5".parse::<Sexagesimal>().unwrap()), 5.125);
+        assert!("".parse::<Sexagesimal>().is_err());
+        assert!("12:xx".parse::<Sexagesimal>().is_err());
+        assert!("1:2:3:4".parse::<Sexagesimal>().is_err());
+    }
+}
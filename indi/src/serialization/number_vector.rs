@@ -1,5 +1,3 @@
-use std::str;
-
 use super::super::*;
 use super::*;
 
@@ -9,22 +7,8 @@ impl<'de> Deserialize<'de> for Sexagesimal {
         D: serde::Deserializer<'de>,
     {
         let s: String = Deserialize::deserialize(deserializer)?;
-        let mut components = s.split([' ', ':']);
-
-        let hour = components
-            .next()
-            .map(str::parse)
-            .transpose()
-            .unwrap()
-            .unwrap();
-        let minute = components.next().map(str::parse).transpose().unwrap();
-        let second = components.next().map(str::parse).transpose().unwrap();
-
-        Ok(Sexagesimal {
-            hour,
-            minute,
-            second,
-        })
+        s.parse()
+            .map_err(|_| serde::de::Error::custom(format!("invalid number {:?}", s)))
     }
 }
 
@@ -37,8 +21,36 @@ impl Serialize for Sexagesimal {
     }
 }
 
+impl Sexagesimal {
+    pub fn new(hour: f64, minute: Option<f64>, second: Option<f64>) -> Sexagesimal {
+        Sexagesimal {
+            hour,
+            minute,
+            second,
+            original: None,
+        }
+    }
+
+    /// Returns the text the value was parsed from, unless it has changed since.
+    pub fn original(&self) -> Option<&str> {
+        self.original
+            .as_ref()
+            .filter(|o| (o.hour, o.minute, o.second) == (self.hour, self.minute, self.second))
+            .map(|o| o.text.as_str())
+    }
+}
+
+impl PartialEq for Sexagesimal {
+    fn eq(&self, other: &Self) -> bool {
+        (self.hour, self.minute, self.second) == (other.hour, other.minute, other.second)
+    }
+}
+
 impl std::fmt::Display for Sexagesimal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(original) = self.original() {
+            return write!(f, "{}", original);
+        }
         write!(f, "{}", self.hour)?;
         if let Some(minute) = self.minute {
             write!(f, ":{}", minute)?;
@@ -55,16 +67,18 @@ impl From<f64> for Sexagesimal {
     fn from(value: f64) -> Self {
         // TODO: try splitting minute and second out of value instead of putting
         //  it all in hour.
-        Self {
-            hour: value.into(),
-            minute: None,
-            second: None,
-        }
+        Sexagesimal::new(value, None, None)
     }
 }
 
 impl From<Sexagesimal> for f64 {
     fn from(value: Sexagesimal) -> Self {
+        f64::from(&value)
+    }
+}
+
+impl From<&Sexagesimal> for f64 {
+    fn from(value: &Sexagesimal) -> Self {
         let mut val = value.hour;
 
         let sign = value.hour.signum();
@@ -119,11 +133,48 @@ impl CommandtoParam for DefNumberVector {
     }
 }
 
+impl NumberVector {
+    /// Returns the `defNumberVector` defining this parameter for `device`, with its numbers
+    /// sorted by name.
+    pub fn to_def(&self, device: &str) -> DefNumberVector {
+        let mut numbers: Vec<DefNumber> = self
+            .values
+            .iter()
+            .map(|(name, number)| DefNumber {
+                name: name.clone(),
+                label: number.label.clone(),
+                format: number.format.clone(),
+                min: number.min,
+                max: number.max,
+                step: number.step,
+                value: number.value.clone(),
+            })
+            .collect();
+        numbers.sort_by(|a, b| a.name.cmp(&b.name));
+        DefNumberVector {
+            device: device.to_string(),
+            name: self.name.clone(),
+            label: self.label.clone(),
+            group: self.group.clone(),
+            state: self.state,
+            perm: self.perm,
+            timeout: self.timeout,
+            timestamp: self.timestamp.map(Timestamp::from),
+            message: None,
+            numbers,
+        }
+    }
+}
+
 impl CommandToUpdate for SetNumberVector {
     fn get_name(&self) -> &String {
         &self.name
     }
 
+    fn get_timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
     fn update_param(self, param: &mut Parameter) -> Result<String, UpdateError> {
         match param {
             Parameter::NumberVector(number_vector) => {
@@ -145,6 +196,67 @@ impl CommandToUpdate for SetNumberVector {
     }
 }
 
+/// A value in a [NewNumberVector] that is outside of its parameter's `min`/`max`, or isn't a
+/// whole number of `step`s from `min`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct InvalidNumber {
+    pub name: String,
+    pub value: f64,
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+}
+
+impl std::fmt::Display for InvalidNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.value < self.min || self.value > self.max {
+            write!(
+                f,
+                "{} of {} is outside of {} to {}",
+                self.name, self.value, self.min, self.max
+            )
+        } else {
+            write!(
+                f,
+                "{} of {} is not a multiple of {} from {}",
+                self.name, self.value, self.step, self.min
+            )
+        }
+    }
+}
+
+impl std::error::Error for InvalidNumber {}
+
+impl NewNumberVector {
+    /// Checks each value against the `min`, `max` and `step` of the matching number in `param`.
+    /// Numbers with `min` not below `max` have no range, and a `step` of 0 allows any value,
+    /// following INDI's conventions.  Values `param` doesn't define aren't checked.
+    pub fn validate(&self, param: &NumberVector) -> Result<(), InvalidNumber> {
+        for number in &self.numbers {
+            let Some(def) = param.values.get(&number.name) else {
+                continue;
+            };
+            if def.min >= def.max {
+                continue;
+            }
+            let value = f64::from(&number.value);
+            let steps = (value - def.min) / def.step;
+            let in_range = value >= def.min && value <= def.max;
+            let on_step = def.step <= 0.0 || (steps - steps.round()).abs() < 1e-6;
+            if !in_range || !on_step {
+                return Err(InvalidNumber {
+                    name: number.name.clone(),
+                    value,
+                    min: def.min,
+                    max: def.max,
+                    step: def.step,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -280,19 +392,36 @@ mod tests {
         let event: Result<Sexagesimal, _> = quick_xml::de::from_str(xml);
 
         if let Ok(e) = event {
-            assert_eq!(
-                Sexagesimal {
-                    hour: -10.,
-                    minute: Some(30.3),
-                    second: None
-                },
-                e.into()
-            );
+            assert_eq!(Sexagesimal::new(-10., Some(30.3), None), e);
         } else {
             panic!("Unexpected");
         }
     }
 
+    #[test]
+    fn test_sexagesimal_round_trip() {
+        let xml = r#"<defNumberVector device="Telescope Simulator" name="EQUATORIAL_EOD_COORD" state="Idle" perm="rw">
+    <defNumber name="RA" format="%010.6m" min="0" max="24" step="0">19:50:47</defNumber>
+    <defNumber name="DEC" format="%010.6m" min="-90" max="90" step="0">+08:52:06.0</defNumber>
+</defNumberVector>"#;
+        let def: DefNumberVector = quick_xml::de::from_str(xml).unwrap();
+        let Parameter::NumberVector(param) = def.to_param(Wrapping(0)) else {
+            panic!("Not a number vector");
+        };
+        let echoed = quick_xml::se::to_string(&param.to_def("Telescope Simulator")).unwrap();
+        assert!(echoed.contains(">19:50:47</defNumber>"), "{}", echoed);
+        assert!(echoed.contains(">+08:52:06.0</defNumber>"), "{}", echoed);
+
+        let mut ra = param.values["RA"].value.clone();
+        assert_eq!(ra.original(), Some("19:50:47"));
+        assert_eq!(ra, Sexagesimal::new(19.0, Some(50.0), Some(47.0)));
+        // A changed value no longer matches its text.
+        ra.second = Some(48.0);
+        assert_eq!(ra.original(), None);
+        assert_eq!(ra.to_string(), "19:50:48");
+        assert_eq!(Sexagesimal::from(1.5).to_string(), "1.5");
+    }
+
     #[test]
     fn test_parse_number_sexagesimal_2() {
         let xml = r#"-10:30:18"#;
@@ -300,14 +429,7 @@ mod tests {
         let event: Result<Sexagesimal, _> = quick_xml::de::from_str(xml);
 
         if let Ok(e) = event {
-            assert_eq!(
-                Sexagesimal {
-                    hour: -10.0,
-                    minute: Some(30.),
-                    second: Some(18.)
-                },
-                e.into()
-            );
+            assert_eq!(Sexagesimal::new(-10.0, Some(30.), Some(18.)), e);
         } else {
             panic!("Unexpected");
         }
@@ -338,4 +460,60 @@ mod tests {
             String::from_str("<newNumberVector device=\"CCD Simulator\" name=\"Exposure\" timestamp=\"2022-10-13T07:41:56.301\"><oneNumber name=\"seconds\">3</oneNumber></newNumberVector>").unwrap()
         );
     }
+
+    #[test]
+    fn test_validate() {
+        let number = |min: f64, max: f64, step: f64| Number {
+            label: None,
+            format: String::from("%g"),
+            min,
+            max,
+            step,
+            value: 0.0.into(),
+        };
+        let param = NumberVector {
+            gen: Wrapping(0),
+            name: String::from("CCD_BINNING"),
+            group: None,
+            label: None,
+            state: PropertyState::Idle,
+            perm: PropertyPerm::RW,
+            timeout: None,
+            timestamp: None,
+            values: HashMap::from([
+                (String::from("HOR_BIN"), number(1.0, 4.0, 1.0)),
+                (String::from("OFFSET"), number(0.0, 0.0, 0.0)),
+                (String::from("DEC"), number(-90.0, 90.0, 0.0)),
+            ]),
+        };
+        let command = |name: &str, value: Sexagesimal| NewNumberVector {
+            device: String::from("CCD Simulator"),
+            name: String::from("CCD_BINNING"),
+            timestamp: None,
+            numbers: vec![OneNumber {
+                name: String::from(name),
+                value,
+            }],
+        };
+
+        assert_eq!(command("HOR_BIN", 3.0.into()).validate(&param), Ok(()));
+        assert_eq!(command("OFFSET", 1000.0.into()).validate(&param), Ok(()));
+        assert_eq!(command("UNKNOWN", 1000.0.into()).validate(&param), Ok(()));
+        assert_eq!(
+            command("DEC", "-45:30:00".parse().unwrap()).validate(&param),
+            Ok(())
+        );
+
+        let err = command("HOR_BIN", 5.0.into()).validate(&param).unwrap_err();
+        assert_eq!(err.to_string(), "HOR_BIN of 5 is outside of 1 to 4");
+        let err = command("HOR_BIN", 2.5.into()).validate(&param).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "HOR_BIN of 2.5 is not a multiple of 1 from 1"
+        );
+        let err = command("DEC", "-95:00:00".parse().unwrap())
+            .validate(&param)
+            .unwrap_err();
+        assert_eq!(err.value, -95.0);
+    }
 }
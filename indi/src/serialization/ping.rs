@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::serialization::{Command, PingReply, PingRequest};
+
+    #[test]
+    fn test_ping_request() {
+        let xml = r#"
+    <pingRequest uid="1715870412301"/>
+                    "#;
+        let command: Command = quick_xml::de::from_str(xml).unwrap();
+
+        assert_eq!(command.device_name(), None);
+        match command {
+            Command::PingRequest(request) => {
+                assert_eq!(request.uid, "1715870412301");
+                assert_eq!(
+                    request.reply(),
+                    PingReply {
+                        uid: String::from("1715870412301")
+                    }
+                );
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_send_ping_reply() {
+        let command = Command::PingReply(PingRequest { uid: "42".into() }.reply());
+
+        let result = quick_xml::se::to_string(&command).unwrap();
+        assert_eq!(result, String::from_str("<pingReply uid=\"42\"/>").unwrap());
+    }
+
+    #[test]
+    fn test_get_properties_v2() {
+        let xml = r#"
+    <getProperties version="2.0" device="Telescope Simulator"/>
+                    "#;
+        let command: Command = quick_xml::de::from_str(xml).unwrap();
+
+        match command {
+            Command::GetProperties(get) => {
+                assert_eq!(get.version, "2.0");
+                assert_eq!(get.device, Some(String::from("Telescope Simulator")));
+                assert_eq!(get.name, None);
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
+    }
+}
@@ -36,14 +36,50 @@ impl CommandtoParam for DefSwitchVector {
     }
 }
 
+impl SwitchVector {
+    /// Returns the `defSwitchVector` defining this parameter for `device`, with its switches
+    /// sorted by name.
+    pub fn to_def(&self, device: &str) -> DefSwitchVector {
+        let mut switches: Vec<DefSwitch> = self
+            .values
+            .iter()
+            .map(|(name, switch)| DefSwitch {
+                name: name.clone(),
+                label: switch.label.clone(),
+                value: switch.value,
+            })
+            .collect();
+        switches.sort_by(|a, b| a.name.cmp(&b.name));
+        DefSwitchVector {
+            device: device.to_string(),
+            name: self.name.clone(),
+            label: self.label.clone(),
+            group: self.group.clone(),
+            state: self.state,
+            perm: self.perm,
+            rule: self.rule,
+            timeout: self.timeout,
+            timestamp: self.timestamp.map(Timestamp::from),
+            message: None,
+            switches,
+        }
+    }
+}
+
 impl CommandToUpdate for SetSwitchVector {
     fn get_name(&self) -> &String {
         &self.name
     }
 
+    fn get_timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
     fn update_param(self, param: &mut Parameter) -> Result<String, UpdateError> {
         match param {
             Parameter::SwitchVector(switch_vector) => {
+                switch_vector.state = self.state;
+                switch_vector.timeout = self.timeout;
                 switch_vector.timestamp = self.timestamp.map(Timestamp::into_inner);
                 for switch in self.switches {
                     if let Some(existing) = switch_vector.values.get_mut(&switch.name) {
@@ -57,6 +93,87 @@ impl CommandToUpdate for SetSwitchVector {
     }
 }
 
+/// A [NewSwitchVector] that would leave its parameter breaking the parameter's [SwitchRule].
+#[derive(Debug, PartialEq, Clone)]
+pub struct InvalidSwitches {
+    pub rule: SwitchRule,
+    /// The switches that would be on.
+    pub on: Vec<String>,
+}
+
+impl std::fmt::Display for InvalidSwitches {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let allowed = match self.rule {
+            SwitchRule::OneOfMany => "exactly one switch",
+            SwitchRule::AtMostOne => "at most one switch",
+            SwitchRule::AnyOfMany => "any switches",
+        };
+        write!(
+            f,
+            "{:?} allows {} on, not {:?}",
+            self.rule, allowed, self.on
+        )
+    }
+}
+
+impl std::error::Error for InvalidSwitches {}
+
+impl NewSwitchVector {
+    /// Checks the switches against `param`'s [SwitchRule].  When one switch of a `OneOfMany` or
+    /// `AtMostOne` parameter is turned on the rest are added turned off, so drivers that don't
+    /// turn them off themselves end up in a valid state.
+    pub fn apply_rule(&mut self, param: &SwitchVector) -> Result<(), InvalidSwitches> {
+        if param.rule == SwitchRule::AnyOfMany {
+            return Ok(());
+        }
+        let on: Vec<String> = self
+            .switches
+            .iter()
+            .filter(|s| s.value == SwitchState::On)
+            .map(|s| s.name.clone())
+            .collect();
+        match on.len() {
+            0 => {
+                // Switches not in the command keep their current value.
+                let still_on: Vec<String> = param
+                    .values
+                    .iter()
+                    .filter(|(name, s)| {
+                        s.value == SwitchState::On
+                            && !self.switches.iter().any(|o| o.name == **name)
+                    })
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                if param.rule == SwitchRule::OneOfMany && still_on.is_empty() {
+                    return Err(InvalidSwitches {
+                        rule: param.rule,
+                        on: still_on,
+                    });
+                }
+            }
+            1 => {
+                let mut off: Vec<&String> = param
+                    .values
+                    .keys()
+                    .filter(|name| !self.switches.iter().any(|o| o.name == **name))
+                    .collect();
+                off.sort();
+                self.switches.extend(off.into_iter().map(|name| OneSwitch {
+                    name: name.clone(),
+                    value: SwitchState::Off,
+                }));
+            }
+            _ => {
+                return Err(InvalidSwitches {
+                    rule: param.rule,
+                    on,
+                })
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -190,4 +307,73 @@ On
         assert_eq!(param.name, "DEBUG");
         assert_eq!(param.switches.len(), 2)
     }
+
+    #[test]
+    fn test_apply_rule() {
+        let switch = |value| Switch { label: None, value };
+        let mut param = SwitchVector {
+            gen: Wrapping(0),
+            name: String::from("CONNECTION"),
+            group: None,
+            label: None,
+            state: PropertyState::Idle,
+            perm: PropertyPerm::RW,
+            rule: SwitchRule::OneOfMany,
+            timeout: None,
+            timestamp: None,
+            values: HashMap::from([
+                (String::from("CONNECT"), switch(SwitchState::Off)),
+                (String::from("DISCONNECT"), switch(SwitchState::On)),
+                (String::from("RECONNECT"), switch(SwitchState::Off)),
+            ]),
+        };
+        let command = |switches: Vec<(&str, SwitchState)>| NewSwitchVector {
+            device: String::from("CCD Simulator"),
+            name: String::from("CONNECTION"),
+            timestamp: None,
+            switches: switches
+                .into_iter()
+                .map(|(name, value)| OneSwitch {
+                    name: String::from(name),
+                    value,
+                })
+                .collect(),
+        };
+
+        let mut connect = command(vec![("CONNECT", SwitchState::On)]);
+        connect.apply_rule(&param).unwrap();
+        assert_eq!(
+            connect,
+            command(vec![
+                ("CONNECT", SwitchState::On),
+                ("DISCONNECT", SwitchState::Off),
+                ("RECONNECT", SwitchState::Off),
+            ])
+        );
+
+        let mut both = command(vec![
+            ("CONNECT", SwitchState::On),
+            ("RECONNECT", SwitchState::On),
+        ]);
+        assert_eq!(
+            both.apply_rule(&param),
+            Err(InvalidSwitches {
+                rule: SwitchRule::OneOfMany,
+                on: vec![String::from("CONNECT"), String::from("RECONNECT")]
+            })
+        );
+
+        let mut none = command(vec![("DISCONNECT", SwitchState::Off)]);
+        assert!(none.apply_rule(&param).is_err());
+        let mut other_off = command(vec![("CONNECT", SwitchState::Off)]);
+        assert_eq!(other_off.apply_rule(&param), Ok(()));
+
+        param.rule = SwitchRule::AtMostOne;
+        let mut none = command(vec![("DISCONNECT", SwitchState::Off)]);
+        assert_eq!(none.apply_rule(&param), Ok(()));
+        assert_eq!(none.switches.len(), 1);
+
+        param.rule = SwitchRule::AnyOfMany;
+        assert_eq!(both.apply_rule(&param), Ok(()));
+    }
 }
@@ -19,3 +19,49 @@ fn test_set_simulator_log() {
         }
     }
 }
+
+#[test]
+fn test_error_source() {
+    use std::error::Error;
+
+    let err: DeError = "1.5".parse::<i32>().unwrap_err().into();
+    assert_eq!(
+        err.to_string(),
+        "invalid integer: invalid digit found in string"
+    );
+    assert!(err.source().unwrap().is::<std::num::ParseIntError>());
+
+    let err: Box<dyn Error> = Box::new(UpdateError::ParameterMissing(String::from("CCD1")));
+    assert_eq!(err.to_string(), "parameter \"CCD1\" is not defined");
+}
+
+#[test]
+fn test_parse_timestamp() {
+    let expected = Timestamp(DateTime::from_str("2022-09-06T01:41:22Z").unwrap());
+    for s in [
+        "2022-09-06T01:41:22",
+        "2022-09-06T01:41:22Z",
+        "2022-09-06 01:41:22",
+        "2022-09-06T03:41:22+02:00",
+        " 2022-09-06T01:41:22\n",
+    ] {
+        assert_eq!(s.parse::<Timestamp>(), Ok(expected), "{:?}", s);
+    }
+    assert_eq!(
+        "2022-09-06T01:41:22.301".parse::<Timestamp>().unwrap(),
+        Timestamp(DateTime::from_str("2022-09-06T01:41:22.301Z").unwrap())
+    );
+    assert!("2022-09-06".parse::<Timestamp>().is_err());
+    assert!("yesterday".parse::<Timestamp>().is_err());
+    assert!(quick_xml::de::from_str::<Timestamp>("<t>yesterday</t>").is_err());
+
+    for timestamp in ["", "yesterday"] {
+        let xml = format!(
+            r#"<message device="CCD Simulator" timestamp="{}" message="hello"/>"#,
+            timestamp
+        );
+        let message: Message = quick_xml::de::from_str(&xml).unwrap();
+        assert_eq!(message.timestamp, None);
+        assert_eq!(message.message.as_deref(), Some("hello"));
+    }
+}
@@ -35,11 +35,44 @@ impl CommandtoParam for DefTextVector {
     }
 }
 
+impl TextVector {
+    /// Returns the `defTextVector` defining this parameter for `device`, with its texts
+    /// sorted by name.
+    pub fn to_def(&self, device: &str) -> DefTextVector {
+        let mut texts: Vec<DefText> = self
+            .values
+            .iter()
+            .map(|(name, text)| DefText {
+                name: name.clone(),
+                label: text.label.clone(),
+                value: text.value.clone(),
+            })
+            .collect();
+        texts.sort_by(|a, b| a.name.cmp(&b.name));
+        DefTextVector {
+            device: device.to_string(),
+            name: self.name.clone(),
+            label: self.label.clone(),
+            group: self.group.clone(),
+            state: self.state,
+            perm: self.perm,
+            timeout: self.timeout,
+            timestamp: self.timestamp.map(Timestamp::from),
+            message: None,
+            texts,
+        }
+    }
+}
+
 impl CommandToUpdate for SetTextVector {
     fn get_name(&self) -> &String {
         &self.name
     }
 
+    fn get_timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
     fn update_param(self, param: &mut Parameter) -> Result<String, UpdateError> {
         match param {
             Parameter::TextVector(text_vector) => {
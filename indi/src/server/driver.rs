@@ -0,0 +1,255 @@
+use std::future::Future;
+
+use tokio::io::{Stdin, Stdout};
+
+use super::Server;
+use crate::{
+    client::{
+        tcpstream::{self, AsyncIndiReader, AsyncIndiWriter},
+        AsyncClientConnection,
+    },
+    serialization::{ClientErrors, Command},
+    BlobEnable, UpdateError,
+};
+
+/// A device driver written in rust, run in a [Server] with [add_driver](Server::add_driver)
+///  or under a stock indiserver with [run].
+///
+/// # Example
+/// ```no_run
+/// use indi::{serialization::*, server::driver::{self, Driver, Updates}};
+///
+/// struct Focuser {
+///     position: f64,
+/// }
+///
+/// impl Focuser {
+///     fn position(&self) -> SetNumberVector {
+///         SetNumberVector {
+///             device: self.name(),
+///             name: String::from("ABS_FOCUS_POSITION"),
+///             state: indi::PropertyState::Ok,
+///             timeout: None,
+///             timestamp: Some(chrono::Utc::now().into()),
+///             message: None,
+///             numbers: vec![SetOneNumber {
+///                 name: String::from("FOCUS_ABSOLUTE_POSITION"),
+///                 min: None,
+///                 max: None,
+///                 step: None,
+///                 value: self.position.into(),
+///             }],
+///         }
+///     }
+/// }
+///
+/// impl Driver for Focuser {
+///     fn name(&self) -> String {
+///         String::from("Rust Focuser")
+///     }
+///
+///     fn properties(&self) -> Vec<Command> {
+///         vec![Command::DefNumberVector(DefNumberVector {
+///             device: self.name(),
+///             name: String::from("ABS_FOCUS_POSITION"),
+///             label: None,
+///             group: Some(String::from("Main Control")),
+///             state: indi::PropertyState::Idle,
+///             perm: indi::PropertyPerm::RW,
+///             timeout: Some(60),
+///             timestamp: None,
+///             message: None,
+///             numbers: vec![DefNumber {
+///                 name: String::from("FOCUS_ABSOLUTE_POSITION"),
+///                 label: None,
+///                 format: String::from("%6.0f"),
+///                 min: 0.0,
+///                 max: 100000.0,
+///                 step: 1.0,
+///                 value: self.position.into(),
+///             }],
+///         })]
+///     }
+///
+///     async fn change(&mut self, command: Command, updates: &Updates) {
+///         if let Command::NewNumberVector(new) = command {
+///             self.position = f64::from(&new.numbers[0].value);
+///             updates
+///                 .send(Command::SetNumberVector(self.position()))
+///                 .await
+///                 .expect("Updating position");
+///         }
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     driver::run(Focuser { position: 0.0 }).await.expect("Running driver");
+/// }
+/// ```
+pub trait Driver: Send + 'static {
+    /// Returns the name of the driver's device.
+    fn name(&self) -> String;
+
+    /// Returns the `def*Vector`s defining the device's parameters.  Their current values are
+    ///  kept by the server from then on.
+    fn properties(&self) -> Vec<Command>;
+
+    /// Called once the parameters are defined, with the [Updates] to keep for sending changes
+    ///  nobody asked for, such as a new temperature reading.
+    fn start(&mut self, _updates: Updates) {}
+
+    /// Handles a `new*Vector` from a client asking to change one of the device's parameters,
+    ///  which should be answered with a `set*Vector` sent through `updates`.
+    fn change(&mut self, command: Command, updates: &Updates) -> impl Future<Output = ()> + Send;
+}
+
+/// Sends a driver's commands to the clients, see [Driver].
+#[derive(Clone)]
+pub struct Updates {
+    server: Server,
+}
+
+impl Updates {
+    /// Sends a `def*Vector`, `set*Vector`, `delProperty` or `message` from the driver.  Fails
+    ///  without sending anything if it updates a parameter that isn't defined.
+    pub async fn send(&self, command: Command) -> Result<(), UpdateError> {
+        self.server.publish(command).await
+    }
+}
+
+impl Server {
+    /// Adds `driver`'s device and defines its parameters, returning the task handling changes
+    ///  to them.  The task runs until a device with the same name is added.
+    pub async fn add_driver<D: Driver>(
+        &self,
+        mut driver: D,
+    ) -> Result<tokio::task::JoinHandle<()>, UpdateError> {
+        let mut device = self.add_device(&driver.name());
+        for def in driver.properties() {
+            device.send(def).await?;
+        }
+        let updates = Updates {
+            server: self.clone(),
+        };
+        driver.start(updates.clone());
+        Ok(tokio::spawn(async move {
+            while let Some(command) = device.recv().await {
+                driver.change(command, &updates).await;
+            }
+        }))
+    }
+}
+
+/// The stdin and stdout indiserver uses to talk to its drivers.
+pub struct Stdio;
+
+impl AsyncClientConnection for Stdio {
+    type Reader = AsyncIndiReader<Stdin>;
+    type Writer = AsyncIndiWriter<Stdout>;
+
+    fn to_indi(self) -> (Self::Writer, Self::Reader) {
+        tcpstream::split(tokio::io::stdin(), tokio::io::stdout())
+    }
+}
+
+/// Runs `driver` under indiserver, which talks to it over stdin and stdout, until stdin is
+///  closed.  Nothing else may be written to stdout, so logs need to go to stderr.
+pub async fn run<D: Driver>(driver: D) -> Result<(), ClientErrors> {
+    serve(driver, Stdio).await
+}
+
+/// Runs `driver` for the indiserver on the other end of `connection` until it disconnects.
+///  Unlike a client, indiserver is sent every blob and decides which of its clients get them.
+pub async fn serve<D: Driver, T: AsyncClientConnection>(
+    driver: D,
+    connection: T,
+) -> Result<(), ClientErrors> {
+    let server = Server::new();
+    let task = server.add_driver(driver).await?;
+    let served = server.serve_with_blobs(connection, BlobEnable::Also).await;
+    task.abort();
+    Ok(served?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client, serialization::*, PropertyPerm, PropertyState};
+    use tokio::net::{TcpListener, TcpStream};
+
+    struct Focuser {
+        position: f64,
+    }
+
+    impl Driver for Focuser {
+        fn name(&self) -> String {
+            String::from("Fake Focuser")
+        }
+
+        fn properties(&self) -> Vec<Command> {
+            vec![Command::DefNumberVector(DefNumberVector {
+                device: self.name(),
+                name: String::from("ABS_FOCUS_POSITION"),
+                label: None,
+                group: None,
+                state: PropertyState::Idle,
+                perm: PropertyPerm::RW,
+                timeout: Some(5),
+                timestamp: None,
+                message: None,
+                numbers: vec![DefNumber {
+                    name: String::from("FOCUS_ABSOLUTE_POSITION"),
+                    label: None,
+                    format: String::from("%6.0f"),
+                    min: 0.0,
+                    max: 1000.0,
+                    step: 1.0,
+                    value: self.position.into(),
+                }],
+            })]
+        }
+
+        async fn change(&mut self, command: Command, updates: &Updates) {
+            let Command::NewNumberVector(new) = command else {
+                return;
+            };
+            self.position = f64::from(&new.numbers[0].value);
+            let set = SetNumberVector {
+                device: new.device,
+                name: new.name,
+                state: PropertyState::Ok,
+                timeout: None,
+                timestamp: None,
+                message: None,
+                numbers: vec![SetOneNumber {
+                    name: String::from("FOCUS_ABSOLUTE_POSITION"),
+                    min: None,
+                    max: None,
+                    step: None,
+                    value: self.position.into(),
+                }],
+            };
+            updates.send(Command::SetNumberVector(set)).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serve_driver() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // The client stands in for indiserver.
+        let indiserver = client::new(TcpStream::connect(addr).await.unwrap(), None, None).unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio::spawn(serve(Focuser { position: 10.0 }, stream));
+
+        let focuser = indiserver.get_device::<()>("Fake Focuser").await.unwrap();
+        focuser
+            .change(
+                "ABS_FOCUS_POSITION",
+                vec![("FOCUS_ABSOLUTE_POSITION", 500.0)],
+            )
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,452 @@
+pub mod driver;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+};
+
+use log::warn;
+use tokio::{net::TcpListener, sync::mpsc};
+
+use crate::{
+    client::{
+        notify::Notify, AsyncClientConnection, AsyncReadConnection, AsyncWriteConnection,
+        DeviceStore, MemoryDeviceStore,
+    },
+    serialization::{Command, DeError, GetProperties},
+    BlobEnable, UpdateError,
+};
+
+/// An INDI server for devices implemented in rust.  Devices are added with
+///  [add_device](Server::add_device), and clients connect over TCP with
+///  [listen](Server::listen) or over any other [AsyncClientConnection], such as a websocket,
+///  with [serve](Server::serve).
+///
+/// The server keeps the current state of every device's parameters, answering `getProperties`
+///  itself, and only sends clients the devices and parameters they've asked for and the blobs
+///  they've enabled.
+///
+/// # Example
+/// ```no_run
+/// use indi::server::Server;
+/// async fn server_usage_example() {
+///     let server = Server::new();
+///     let device = server.add_device("Fake Focuser");
+///     let listener = tokio::net::TcpListener::bind("0.0.0.0:7624")
+///         .await
+///         .expect("Binding to port");
+///     tokio::spawn(async move { server.listen(listener).await });
+///     // Define the device's parameters with `device.send` and handle changes
+///     //  from clients with `device.recv`.
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Server {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    devices: Arc<Notify<MemoryDeviceStore>>,
+    // Each device's handle id, and the sender for changes to it.
+    backends: Mutex<HashMap<String, (usize, mpsc::UnboundedSender<Command>)>>,
+    connections: Mutex<Vec<Connection>>,
+    next_id: AtomicUsize,
+}
+
+/// A client connected to the server.
+struct Connection {
+    id: usize,
+    sender: mpsc::UnboundedSender<Command>,
+    // The devices and parameters asked for with `getProperties`, `None` meaning all of them.
+    properties: Vec<(Option<String>, Option<String>)>,
+    blobs: HashMap<String, HashMap<Option<String>, BlobEnable>>,
+    // Used for devices the client hasn't sent `enableBLOB` for.
+    default_blobs: BlobEnable,
+}
+
+impl Connection {
+    /// Whether the client should be sent `command`.
+    fn wants(&self, command: &Command) -> bool {
+        let device = command.device_name();
+        let name = command.param_name();
+        let asked = self.properties.iter().any(|(d, n)| {
+            (d.is_none() || d.as_ref() == device)
+                && (n.is_none() || name.is_none() || n.as_ref() == name)
+        });
+        if !asked {
+            return false;
+        }
+        let Some(device) = device else {
+            return true;
+        };
+        let enabled = self
+            .blobs
+            .get(device)
+            .and_then(|blobs| blobs.get(&name.cloned()).or_else(|| blobs.get(&None)))
+            .copied()
+            .unwrap_or(self.default_blobs);
+        match command {
+            Command::SetBlobVector(_) => enabled != BlobEnable::Never,
+            _ => enabled != BlobEnable::Only,
+        }
+    }
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            shared: Arc::new(Shared {
+                devices: Arc::new(Notify::new(HashMap::new())),
+                backends: Default::default(),
+                connections: Default::default(),
+                next_id: Default::default(),
+            }),
+        }
+    }
+
+    /// Returns the server's devices and their current parameters.
+    pub fn get_devices(&self) -> Arc<Notify<MemoryDeviceStore>> {
+        self.shared.devices.clone()
+    }
+
+    /// Adds the device named `name`, returning the [DeviceHandle] used to define its parameters
+    ///  and receive the changes clients ask for.  Adding a device with the same name again
+    ///  replaces the previous handle.
+    pub fn add_device(&self, name: &str) -> DeviceHandle {
+        let (sender, requests) = mpsc::unbounded_channel();
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        self.shared
+            .backends
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.to_string(), (id, sender));
+        DeviceHandle {
+            name: name.to_string(),
+            id,
+            server: self.clone(),
+            requests,
+        }
+    }
+
+    /// Accepts clients from `listener`, serving each of them in its own task.  Only returns if
+    ///  accepting a connection fails.
+    pub async fn listen(&self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.serve(stream).await {
+                    warn!("Connection to {} failed: {}", addr, e);
+                }
+            });
+        }
+    }
+
+    /// Serves the client on the other end of `connection` until it disconnects.
+    pub async fn serve<T: AsyncClientConnection>(&self, connection: T) -> Result<(), DeError> {
+        self.serve_with_blobs(connection, BlobEnable::Never).await
+    }
+
+    /// Serves the client on the other end of `connection`, sending it blobs as `default_blobs`
+    ///  until it enables them itself.
+    async fn serve_with_blobs<T: AsyncClientConnection>(
+        &self,
+        connection: T,
+        default_blobs: BlobEnable,
+    ) -> Result<(), DeError> {
+        let (mut writer, mut reader) = connection.to_indi();
+        let (sender, mut outgoing) = mpsc::unbounded_channel();
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        self.shared
+            .connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Connection {
+                id,
+                sender: sender.clone(),
+                properties: vec![],
+                blobs: HashMap::new(),
+                default_blobs,
+            });
+
+        let read = async move {
+            while let Some(command) = reader.read().await {
+                match command {
+                    Ok(command) => self.handle(id, &sender, command).await,
+                    Err(e) => warn!("Skipping command from client: {}", e),
+                }
+            }
+            self.shared
+                .connections
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .retain(|c| c.id != id);
+        };
+        let write = async move {
+            while let Some(command) = outgoing.recv().await {
+                writer.write(command).await?;
+            }
+            writer.shutdown().await
+        };
+        let ((), written) = tokio::join!(read, write);
+        written
+    }
+
+    /// Handles a command sent by the client `id`.
+    async fn handle(&self, id: usize, sender: &mpsc::UnboundedSender<Command>, command: Command) {
+        match command {
+            Command::GetProperties(get) => self.get_properties(id, get).await,
+            Command::EnableBlob(enable) => {
+                self.with_connection(id, |c| {
+                    let blobs = c.blobs.entry(enable.device).or_default();
+                    // A device wide setting replaces any per parameter ones.
+                    if enable.name.is_none() {
+                        blobs.clear();
+                    }
+                    blobs.insert(enable.name, enable.enabled);
+                });
+            }
+            Command::PingRequest(request) => {
+                sender.send(Command::PingReply(request.reply())).ok();
+            }
+            Command::PingReply(_) => {}
+            command @ (Command::NewTextVector(_)
+            | Command::NewNumberVector(_)
+            | Command::NewSwitchVector(_)) => {
+                let device = command.device_name().cloned().unwrap_or_default();
+                let backends = self
+                    .shared
+                    .backends
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                match backends.get(&device) {
+                    Some((_, backend)) => {
+                        backend.send(command).ok();
+                    }
+                    None => warn!("Client changed unknown device {:?}", device),
+                }
+            }
+            command => warn!("Ignoring command only sent by devices: {:?}", command),
+        }
+    }
+
+    /// Sends the client `id` the parameters matching `get`, and everything about them from now on.
+    async fn get_properties(&self, id: usize, get: GetProperties) {
+        // Holding the devices keeps updates from being sent before the parameters are defined.
+        let devices = self.shared.devices.lock().await;
+        let mut names: Vec<&String> = devices
+            .keys()
+            .filter(|name| get.device.is_none() || get.device.as_ref() == Some(name))
+            .collect();
+        names.sort();
+
+        let mut defs = vec![];
+        for name in names {
+            let device = devices[name].lock().await;
+            for param_name in device.parameter_names() {
+                if get.device.is_some()
+                    && get.name.is_some()
+                    && get.name.as_ref() != Some(param_name)
+                {
+                    continue;
+                }
+                if let Some(param) = device.get_parameters().get(param_name) {
+                    defs.push(param.lock().await.to_def(name));
+                }
+            }
+        }
+        // A parameter name is only used along with a device.
+        let name = get.device.as_ref().and(get.name);
+        self.with_connection(id, |c| {
+            for def in defs {
+                c.sender.send(def).ok();
+            }
+            c.properties.push((get.device, name));
+        });
+    }
+
+    fn with_connection(&self, id: usize, f: impl FnOnce(&mut Connection)) {
+        let mut connections = self
+            .shared
+            .connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(c) = connections.iter_mut().find(|c| c.id == id) {
+            f(c);
+        }
+    }
+
+    /// Applies `command` to the devices and sends it to the clients that want it.
+    pub(crate) async fn publish(&self, command: Command) -> Result<(), UpdateError> {
+        let mut devices = self.shared.devices.lock().await;
+        devices.update(command.clone(), |_| ()).await?;
+        let connections = self
+            .shared
+            .connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for connection in connections.iter().filter(|c| c.wants(&command)) {
+            connection.sender.send(command.clone()).ok();
+        }
+        Ok(())
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server::new()
+    }
+}
+
+/// A device's connection to a [Server], see [Server::add_device].
+pub struct DeviceHandle {
+    name: String,
+    id: usize,
+    server: Server,
+    requests: mpsc::UnboundedReceiver<Command>,
+}
+
+impl DeviceHandle {
+    /// Returns the name the device was added with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sends a `def*Vector`, `set*Vector`, `delProperty` or `message` from the device,
+    ///  updating the server's copy of its parameters and passing it on to the interested
+    ///  clients.  Fails without sending anything if it updates a parameter that isn't defined.
+    pub async fn send(&self, command: Command) -> Result<(), UpdateError> {
+        self.server.publish(command).await
+    }
+
+    /// Returns the next `new*Vector` sent by a client to change one of the device's
+    ///  parameters, or `None` once the device has been replaced by another with the same name.
+    pub async fn recv(&mut self) -> Option<Command> {
+        self.requests.recv().await
+    }
+}
+
+impl Drop for DeviceHandle {
+    fn drop(&mut self) {
+        let mut backends = self
+            .server
+            .shared
+            .backends
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if backends
+            .get(&self.name)
+            .is_some_and(|(id, _)| *id == self.id)
+        {
+            backends.remove(&self.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client, serialization::SetSwitchVector, PropertyPerm, PropertyState, SwitchRule,
+        SwitchState, TryEq,
+    };
+    use tokio::net::TcpStream;
+
+    fn def_connection(state: SwitchState) -> Command {
+        Command::DefSwitchVector(crate::serialization::DefSwitchVector {
+            device: String::from("Fake Device"),
+            name: String::from("CONNECTION"),
+            label: None,
+            group: Some(String::from("Main Control")),
+            state: PropertyState::Idle,
+            perm: PropertyPerm::RW,
+            rule: SwitchRule::OneOfMany,
+            timeout: Some(5),
+            timestamp: None,
+            message: None,
+            switches: vec![
+                crate::serialization::DefSwitch {
+                    name: String::from("CONNECT"),
+                    label: None,
+                    value: state,
+                },
+                crate::serialization::DefSwitch {
+                    name: String::from("DISCONNECT"),
+                    label: None,
+                    value: SwitchState::On,
+                },
+            ],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_serves_devices() {
+        let server = Server::new();
+        let mut device = server.add_device("Fake Device");
+        device.send(def_connection(SwitchState::Off)).await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listening = server.clone();
+        tokio::spawn(async move { listening.listen(listener).await });
+
+        // Answers each change by applying it.
+        tokio::spawn(async move {
+            while let Some(Command::NewSwitchVector(new)) = device.recv().await {
+                let set = SetSwitchVector {
+                    device: new.device,
+                    name: new.name,
+                    state: PropertyState::Ok,
+                    timeout: None,
+                    timestamp: None,
+                    message: None,
+                    switches: new.switches,
+                };
+                device.send(Command::SetSwitchVector(set)).await.unwrap();
+            }
+        });
+
+        let client = client::new(TcpStream::connect(addr).await.unwrap(), None, None).unwrap();
+        let fake = client.get_device::<()>("Fake Device").await.unwrap();
+        let param = fake
+            .change("CONNECTION", vec![("CONNECT", true)])
+            .await
+            .unwrap();
+        assert_eq!(*param.get_state(), PropertyState::Ok);
+
+        let devices = server.get_devices();
+        let devices = devices.lock().await;
+        let device = devices["Fake Device"].lock().await;
+        let param = device.get_parameters()["CONNECTION"].lock().await;
+        assert!(vec![("CONNECT", true), ("DISCONNECT", false)]
+            .try_eq(&param)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_wants() {
+        let mut connection = Connection {
+            id: 0,
+            sender: mpsc::unbounded_channel().0,
+            properties: vec![],
+            blobs: HashMap::new(),
+            default_blobs: BlobEnable::Never,
+        };
+        let def = def_connection(SwitchState::Off);
+        assert!(!connection.wants(&def));
+
+        connection
+            .properties
+            .push((Some(String::from("Fake Device")), None));
+        assert!(connection.wants(&def));
+
+        connection.blobs.insert(
+            String::from("Fake Device"),
+            HashMap::from([(None, BlobEnable::Only)]),
+        );
+        assert!(!connection.wants(&def));
+    }
+}
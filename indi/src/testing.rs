@@ -0,0 +1,394 @@
+//! A mock INDI server for testing clients without a running indiserver or simulators.
+//! Enabled with the `testing` feature.
+//!
+//! [MockServer] serves its devices over in-memory [DuplexStream]s.  Tests define the
+//!  devices' parameters with the same xml a driver would send, and look at the `new*Vector`s
+//!  clients send to change them.  Changes are confirmed with an `Ok` `set*Vector` unless told
+//!  otherwise with [accept_changes](MockServer::accept_changes).
+//! # Example
+//! ```no_run
+//! use indi::{testing::MockServer, serialization::Command};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let server = MockServer::new();
+//!     server
+//!         .send(
+//!             r#"<defSwitchVector device="Telescope Simulator" name="CONNECTION" state="Idle" perm="rw" rule="OneOfMany" timeout="60">
+//!     <defSwitch name="CONNECT">Off</defSwitch>
+//!     <defSwitch name="DISCONNECT">On</defSwitch>
+//! </defSwitchVector>"#,
+//!         )
+//!         .await;
+//!
+//!     let client = server.connect().expect("Connecting to mock server");
+//!     let mount = client
+//!         .get_device::<()>("Telescope Simulator")
+//!         .await
+//!         .expect("Getting mount");
+//!     mount
+//!         .change("CONNECTION", vec![("CONNECT", true)])
+//!         .await
+//!         .expect("Connecting mount");
+//!
+//!     let change = server.next_change(Duration::from_secs(1)).await;
+//!     assert!(matches!(change, Some(Command::NewSwitchVector(_))));
+//! }
+//! ```
+
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    io::{DuplexStream, ReadHalf, WriteHalf},
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+use crate::{
+    client::{
+        self,
+        tcpstream::{self, AsyncIndiReader, AsyncIndiWriter},
+        AsyncClientConnection, Client,
+    },
+    serialization::{
+        Command, CommandIter, DeError, SetNumberVector, SetOneNumber, SetSwitchVector,
+        SetTextVector,
+    },
+    server::{DeviceHandle, Server},
+    Parameter, PropertyState,
+};
+
+/// Buffer size of each side of the connections to a [MockServer].
+const BUFFER_SIZE: usize = 64 * 1024;
+
+impl AsyncClientConnection for DuplexStream {
+    type Reader = AsyncIndiReader<ReadHalf<DuplexStream>>;
+    type Writer = AsyncIndiWriter<WriteHalf<DuplexStream>>;
+
+    fn to_indi(self) -> (Self::Writer, Self::Reader) {
+        let (reader, writer) = tokio::io::split(self);
+        tcpstream::split(reader, writer)
+    }
+}
+
+struct Shared {
+    server: Server,
+    accept: AtomicBool,
+    received: Mutex<Vec<Command>>,
+    changes: mpsc::UnboundedSender<Command>,
+}
+
+/// A mock INDI server, see the [module](self) docs.  The server stops when dropped.
+pub struct MockServer {
+    shared: Arc<Shared>,
+    devices: Mutex<HashSet<String>>,
+    changes: tokio::sync::Mutex<mpsc::UnboundedReceiver<Command>>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl MockServer {
+    pub fn new() -> MockServer {
+        let (changes, received) = mpsc::unbounded_channel();
+        MockServer {
+            shared: Arc::new(Shared {
+                server: Server::new(),
+                accept: AtomicBool::new(true),
+                received: Default::default(),
+                changes,
+            }),
+            devices: Default::default(),
+            changes: tokio::sync::Mutex::new(received),
+            tasks: Default::default(),
+        }
+    }
+
+    /// Returns the [Server] holding the mock devices.
+    pub fn server(&self) -> &Server {
+        &self.shared.server
+    }
+
+    /// Sends the `def*Vector`s, `set*Vector`s, `delProperty`s and `message`s in `xml` from
+    ///  the devices they name, adding any devices not seen before.
+    /// # Panics
+    /// If `xml` isn't valid INDI, or updates a parameter that isn't defined.
+    pub async fn send(&self, xml: &str) {
+        for command in CommandIter::new(std::io::Cursor::new(xml)) {
+            let command = command.expect("Parsing mock server xml");
+            if let Some(device) = command.device_name() {
+                let added = self
+                    .devices
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(device.clone());
+                if added {
+                    let handle = self.shared.server.add_device(device);
+                    let task = tokio::spawn(self.shared.clone().handle(handle));
+                    self.tasks
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push(task);
+                }
+            }
+            self.shared
+                .server
+                .publish(command)
+                .await
+                .expect("Updating mock server");
+        }
+    }
+
+    /// Sets whether changes sent by clients are confirmed with an `Ok` `set*Vector`.  When
+    ///  they aren't, tests answer them with [send](MockServer::send).
+    pub fn accept_changes(&self, accept: bool) {
+        self.shared.accept.store(accept, Ordering::Relaxed);
+    }
+
+    /// Returns a new connection to the server.
+    pub fn connection(&self) -> DuplexStream {
+        let (client, server) = tokio::io::duplex(BUFFER_SIZE);
+        let mock = self.shared.server.clone();
+        let task = tokio::spawn(async move {
+            mock.serve(server).await.ok();
+        });
+        self.tasks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(task);
+        client
+    }
+
+    /// Returns a new [Client] tracking every device on the server.
+    pub fn connect(&self) -> Result<Client, DeError> {
+        client::new(self.connection(), None, None)
+    }
+
+    /// Returns every `new*Vector` clients have sent, oldest first.
+    pub fn received(&self) -> Vec<Command> {
+        self.shared
+            .received
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Waits up to `timeout` for the next `new*Vector` sent by a client, returning `None` if
+    ///  there wasn't one.  Each one is only returned once.
+    pub async fn next_change(&self, timeout: Duration) -> Option<Command> {
+        let mut changes = self.changes.lock().await;
+        tokio::time::timeout(timeout, changes.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+}
+
+impl Default for MockServer {
+    fn default() -> Self {
+        MockServer::new()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        for task in self
+            .tasks
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .drain(..)
+        {
+            task.abort();
+        }
+    }
+}
+
+impl Shared {
+    /// Records the changes clients send to the device of `handle`, confirming them if
+    ///  changes are being accepted.
+    async fn handle(self: Arc<Self>, mut handle: DeviceHandle) {
+        while let Some(command) = handle.recv().await {
+            self.received
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(command.clone());
+            self.changes.send(command.clone()).ok();
+            if self.accept.load(Ordering::Relaxed) {
+                if let Some(confirmation) = self.confirmation(command).await {
+                    if let Err(e) = handle.send(confirmation).await {
+                        log::warn!("Confirming change failed: {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the `set*Vector` confirming `command`, or `None` if it isn't a change this
+    ///  server knows how to confirm.  Those are only recorded, never echoed back to clients.
+    async fn confirmation(&self, command: Command) -> Option<Command> {
+        let timestamp = Some(chrono::Utc::now().into());
+        Some(match command {
+            Command::NewNumberVector(new) => Command::SetNumberVector(SetNumberVector {
+                device: new.device,
+                name: new.name,
+                state: PropertyState::Ok,
+                timeout: None,
+                timestamp,
+                message: None,
+                numbers: new
+                    .numbers
+                    .into_iter()
+                    .map(|number| SetOneNumber {
+                        name: number.name,
+                        min: None,
+                        max: None,
+                        step: None,
+                        value: number.value,
+                    })
+                    .collect(),
+            }),
+            Command::NewTextVector(new) => Command::SetTextVector(SetTextVector {
+                device: new.device,
+                name: new.name,
+                state: PropertyState::Ok,
+                timeout: None,
+                timestamp,
+                message: None,
+                texts: new.texts,
+            }),
+            Command::NewSwitchVector(mut new) => {
+                // Like a driver, turns the other switches off when the rule calls for it.
+                let mut state = PropertyState::Ok;
+                let devices = self.server.get_devices();
+                let devices = devices.lock().await;
+                if let Some(device) = devices.get(&new.device) {
+                    let device = device.lock().await;
+                    if let Some(param) = device.get_parameters().get(&new.name) {
+                        if let Parameter::SwitchVector(switches) = &*param.lock().await {
+                            if new.apply_rule(switches).is_err() {
+                                state = PropertyState::Alert;
+                            }
+                        }
+                    }
+                }
+                Command::SetSwitchVector(SetSwitchVector {
+                    device: new.device,
+                    name: new.name,
+                    state,
+                    timeout: None,
+                    timestamp,
+                    message: None,
+                    switches: new.switches,
+                })
+            }
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SwitchState;
+
+    const MOUNT: &str = r#"<defSwitchVector device="Telescope Simulator" name="CONNECTION" state="Idle" perm="rw" rule="OneOfMany" timeout="60">
+    <defSwitch name="CONNECT">Off</defSwitch>
+    <defSwitch name="DISCONNECT">On</defSwitch>
+</defSwitchVector>
+<defNumberVector device="Telescope Simulator" name="TELESCOPE_TIMED_GUIDE_NS" state="Idle" perm="rw" timeout="60">
+    <defNumber name="TIMED_GUIDE_N" format="%.f" min="0" max="60000" step="100">0</defNumber>
+    <defNumber name="TIMED_GUIDE_S" format="%.f" min="0" max="60000" step="100">0</defNumber>
+</defNumberVector>
+"#;
+
+    #[tokio::test]
+    async fn test_accepted_changes() {
+        let server = MockServer::new();
+        server.send(MOUNT).await;
+        let client = server.connect().unwrap();
+        let mount = client
+            .get_device::<()>("Telescope Simulator")
+            .await
+            .unwrap();
+
+        let connection = mount
+            .change("CONNECTION", vec![("CONNECT", true)])
+            .await
+            .unwrap();
+        let switches = connection
+            .get_values::<std::collections::HashMap<String, crate::Switch>>()
+            .unwrap();
+        assert_eq!(switches["DISCONNECT"].value, SwitchState::Off);
+        match server.next_change(Duration::from_secs(1)).await {
+            Some(Command::NewSwitchVector(c)) => assert_eq!(c.name, "CONNECTION"),
+            c => panic!("Unexpected change: {:?}", c),
+        }
+
+        mount
+            .change("TELESCOPE_TIMED_GUIDE_NS", vec![("TIMED_GUIDE_N", 500.0)])
+            .await
+            .unwrap();
+        match server.next_change(Duration::from_secs(1)).await {
+            Some(Command::NewNumberVector(c)) => {
+                assert_eq!(c.numbers[0].name, "TIMED_GUIDE_N");
+                assert_eq!(f64::from(&c.numbers[0].value), 500.0);
+            }
+            c => panic!("Unexpected change: {:?}", c),
+        }
+        assert_eq!(server.received().len(), 2);
+        assert!(server
+            .next_change(Duration::from_millis(50))
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_answered_changes() {
+        let server = MockServer::new();
+        server.send(MOUNT).await;
+        server.accept_changes(false);
+        let client = server.connect().unwrap();
+        let mount = client
+            .get_device::<()>("Telescope Simulator")
+            .await
+            .unwrap();
+
+        let connect = {
+            let mount = mount.clone();
+            tokio::spawn(async move {
+                mount
+                    .change("CONNECTION", vec![("CONNECT", true)])
+                    .await
+                    .map(|_| ())
+            })
+        };
+        assert!(server.next_change(Duration::from_secs(1)).await.is_some());
+        server
+            .send(
+                r#"<setSwitchVector device="Telescope Simulator" name="CONNECTION" state="Alert" message="No mount found">
+    <oneSwitch name="CONNECT">Off</oneSwitch>
+    <oneSwitch name="DISCONNECT">On</oneSwitch>
+</setSwitchVector>
+"#,
+            )
+            .await;
+        assert!(connect.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_changes_not_echoed() {
+        let server = MockServer::new();
+        let enable = Command::EnableBlob(crate::serialization::EnableBlob {
+            device: String::from("Telescope Simulator"),
+            name: None,
+            enabled: crate::BlobEnable::Also,
+        });
+        assert!(server.shared.confirmation(enable).await.is_none());
+    }
+}
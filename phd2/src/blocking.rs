@@ -0,0 +1,230 @@
+//! A synchronous client for phd2, for scripts and GUIs that don't use async.
+//!
+//! [Client] owns a private tokio runtime and blocks on each call to the
+//! [Phd2Connection] it wraps.  It must not be used from within an async runtime, blocking
+//! calls there panic.
+//! # Example
+//! ```no_run
+//! use phd2::{blocking::Client, serialization::Settle};
+//! use std::time::Duration;
+//!
+//! fn main() {
+//!     let mut phd2 = Client::connect("localhost:4400").expect("Connecting to phd2");
+//!     phd2.loop_().expect("Looping");
+//!     let settle = Settle::new(1.5, Duration::from_secs(10), Duration::from_secs(60));
+//!     phd2.start_guiding_auto(settle).expect("Guiding");
+//!
+//!     while let Some(event) = phd2.recv_event() {
+//!         println!("{:?}", event.event);
+//!     }
+//! }
+//! ```
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use serde::Serialize;
+use tokio::{
+    net::{TcpStream, ToSocketAddrs},
+    runtime::Runtime,
+    sync::{broadcast, mpsc},
+};
+
+use crate::{
+    equipment::{EquipmentMismatch, ExpectedEquipment},
+    serialization::{
+        Axis, Calibration, ClearCalibrationParam, CoolerStatus, DecGuideMode, Equipment,
+        ExportedConfigSettings, LockShiftParams, LockShiftUnits, Phd2Status, Profile,
+        PulseDirection, SavedImage, ServerEvent, Settle, SettleDone, StarImage, State,
+        VariableDelaySettings, WhichDevice,
+    },
+    ClientError, Phd2Connection, Phd2ConnectionBuilder, SettleHandle,
+};
+
+/// Defines a method that blocks on the [Phd2Connection] method of the same name.
+macro_rules! blocking {
+    ($($name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        $(
+            #[doc = concat!("See [Phd2Connection::", stringify!($name), "].")]
+            pub fn $name(&self, $($arg: $ty),*) -> $ret {
+                self.runtime.block_on(self.connection.$name($($arg),*))
+            }
+        )*
+    };
+}
+
+/// A blocking connection to phd2.
+pub struct Client {
+    // Dropped before the runtime so the reader task is stopped first.
+    connection: Phd2Connection<TcpStream>,
+    events: mpsc::Receiver<ServerEvent>,
+    runtime: Runtime,
+}
+
+impl Client {
+    /// Connects to phd2 at `addr`, usually port 4400 for the first instance.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> std::io::Result<Client> {
+        let runtime = new_runtime()?;
+        let stream = runtime.block_on(TcpStream::connect(addr))?;
+        let (connection, events) = runtime.block_on(async { Phd2Connection::from(stream) });
+        Ok(Client {
+            connection,
+            events,
+            runtime,
+        })
+    }
+
+    /// Connects to phd2 with the settings of `builder`.
+    pub fn connect_with(builder: Phd2ConnectionBuilder) -> std::io::Result<Client> {
+        let runtime = new_runtime()?;
+        let (connection, events) = runtime.block_on(builder.connect())?;
+        Ok(Client {
+            connection,
+            events,
+            runtime,
+        })
+    }
+
+    /// Returns the wrapped async connection.
+    pub fn connection(&self) -> &Phd2Connection<TcpStream> {
+        &self.connection
+    }
+
+    /// Waits for the next event from phd2, returns `None` once the connection is closed.
+    pub fn recv_event(&mut self) -> Option<ServerEvent> {
+        self.events.blocking_recv()
+    }
+
+    /// Returns the next event if one has been received, without waiting.
+    pub fn try_recv_event(&mut self) -> Option<ServerEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// See [Phd2Connection::subscribe].  Use
+    /// [blocking_recv](broadcast::Receiver::blocking_recv) to wait for events.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<ServerEvent>> {
+        self.connection.subscribe()
+    }
+
+    /// Waits for phd2 to settle after [dither](Client::dither) or [guide](Client::guide).
+    pub fn wait_for_settle(&self, settle: SettleHandle) -> Result<SettleDone, ClientError> {
+        self.runtime.block_on(settle)
+    }
+
+    /// See [Phd2Connection::disconnect].
+    pub fn disconnect(self) -> std::io::Result<()> {
+        let Client {
+            connection,
+            runtime,
+            ..
+        } = self;
+        runtime.block_on(connection.disconnect())
+    }
+
+    /// See [Phd2Connection::get_algo_param].
+    pub fn get_algo_param<S: Into<String> + Serialize>(
+        &self,
+        axis: Axis,
+        name: S,
+    ) -> Result<f64, ClientError> {
+        self.runtime
+            .block_on(self.connection.get_algo_param(axis, name))
+    }
+
+    /// See [Phd2Connection::set_algo_param].
+    pub fn set_algo_param(
+        &self,
+        axis: Axis,
+        name: impl Into<String>,
+        value: f64,
+    ) -> Result<isize, ClientError> {
+        self.runtime
+            .block_on(self.connection.set_algo_param(axis, name, value))
+    }
+
+    /// See [Phd2Connection::set_lock_shift_params].
+    pub fn set_lock_shift_params(
+        &self,
+        rate: [f64; 2],
+        units: impl Into<String>,
+        axes: impl Into<String>,
+    ) -> Result<isize, ClientError> {
+        self.runtime
+            .block_on(self.connection.set_lock_shift_params(rate, units, axes))
+    }
+
+    /// See [Phd2Connection::call_raw].
+    pub fn call_raw(
+        &self,
+        method: impl Into<String>,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, ClientError> {
+        self.runtime
+            .block_on(self.connection.call_raw(method, params))
+    }
+
+    blocking! {
+        heartbeat(interval: Duration) -> ClientError;
+        close() -> std::io::Result<()>;
+        capture_single_frame(exposure: Duration, subframe: Option<[u32; 4]>) -> Result<isize, ClientError>;
+        check_equipment(expected: &ExpectedEquipment) -> Result<Vec<EquipmentMismatch>, ClientError>;
+        clear_calibration(target: ClearCalibrationParam) -> Result<isize, ClientError>;
+        dither(amount: f64, ra_only: bool, settle: Settle) -> Result<SettleHandle, ClientError>;
+        dither_and_settle(amount: f64, ra_only: bool, settle: Settle) -> Result<SettleDone, ClientError>;
+        ensure_profile_connected(name: &str) -> Result<Profile, ClientError>;
+        export_config_settings() -> Result<ExportedConfigSettings, ClientError>;
+        find_star(roi: Option<[usize; 4]>) -> Result<[f64; 2], ClientError>;
+        flip_calibration() -> Result<isize, ClientError>;
+        get_algo_param_names(axis: Axis) -> Result<Vec<String>, ClientError>;
+        get_app_state() -> Result<State, ClientError>;
+        get_camera_frame_size() -> Result<[usize; 2], ClientError>;
+        get_calibrated() -> Result<bool, ClientError>;
+        get_calibration_data(which: WhichDevice) -> Result<Calibration, ClientError>;
+        get_connected() -> Result<bool, ClientError>;
+        get_cooler_status() -> Result<CoolerStatus, ClientError>;
+        get_current_equipment() -> Result<HashMap<String, Equipment>, ClientError>;
+        get_dec_guide_mode() -> Result<DecGuideMode, ClientError>;
+        get_exposure() -> Result<Duration, ClientError>;
+        get_exposure_durations() -> Result<Vec<Duration>, ClientError>;
+        get_guide_output_enabled() -> Result<bool, ClientError>;
+        get_lock_position() -> Result<Option<[f64; 2]>, ClientError>;
+        get_lock_shift_enabled() -> Result<bool, ClientError>;
+        get_lock_shift_params() -> Result<LockShiftParams, ClientError>;
+        get_paused() -> Result<bool, ClientError>;
+        get_pixel_scale() -> Result<f64, ClientError>;
+        get_profile() -> Result<Profile, ClientError>;
+        get_profiles() -> Result<Vec<Profile>, ClientError>;
+        get_search_region() -> Result<isize, ClientError>;
+        get_settling() -> Result<bool, ClientError>;
+        get_ccd_temperature() -> Result<HashMap<String, f64>, ClientError>;
+        get_star_image(size: Option<u32>) -> Result<StarImage, ClientError>;
+        get_status_snapshot() -> Result<Phd2Status, ClientError>;
+        get_use_subframes() -> Result<bool, ClientError>;
+        get_variable_delay_settings() -> Result<VariableDelaySettings, ClientError>;
+        guide(settle: Settle, recalibrate: Option<bool>, roi: Option<[usize; 4]>) -> Result<SettleHandle, ClientError>;
+        guide_and_wait(settle: Settle, recalibrate: Option<bool>, roi: Option<[usize; 4]>) -> Result<SettleDone, ClientError>;
+        guide_pulse(amount: isize, direction: PulseDirection, which: Option<WhichDevice>) -> Result<isize, ClientError>;
+        loop_() -> Result<isize, ClientError>;
+        save_image() -> Result<SavedImage, ClientError>;
+        set_connected(connected: bool) -> Result<isize, ClientError>;
+        set_dec_guide_mode(mode: DecGuideMode) -> Result<isize, ClientError>;
+        set_exposure(exposure: Duration) -> Result<isize, ClientError>;
+        set_guide_output_enabled(enabled: bool) -> Result<isize, ClientError>;
+        set_lock_position(x: f64, y: f64, exact: Option<bool>) -> Result<isize, ClientError>;
+        set_lock_shift_enabled(enabled: bool) -> Result<isize, ClientError>;
+        set_paused(paused: bool, full: bool) -> Result<isize, ClientError>;
+        set_profile(profile_id: isize) -> Result<isize, ClientError>;
+        set_variable_delay_settings(settings: VariableDelaySettings) -> Result<isize, ClientError>;
+        shutdown() -> Result<isize, ClientError>;
+        start_guiding_auto(settle: Settle) -> Result<SettleDone, ClientError>;
+        stop_capture() -> Result<isize, ClientError>;
+        track_moving_target(rate_ra: f64, rate_dec: f64, units: LockShiftUnits) -> Result<LockShiftParams, ClientError>;
+    }
+}
+
+/// The reader task needs a worker thread to keep receiving events between calls.
+fn new_runtime() -> std::io::Result<Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+}
@@ -0,0 +1,245 @@
+//! Configuring a [Phd2Connection] before connecting.
+//!
+//! [Phd2ConnectionBuilder] connects to phd2 by host and port, or wraps an existing stream,
+//! with the timeouts, event buffer size, reconnect policy and inspection hooks set up front.
+//! # Example
+//! ```no_run
+//! use phd2::{Phd2ConnectionBuilder, ReconnectPolicy};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let (phd2, mut events) = Phd2ConnectionBuilder::new()
+//!         .host("astro.local")
+//!         .timeout(Duration::from_secs(2))
+//!         .method_timeout("find_star", Duration::from_secs(30))
+//!         .reconnect(ReconnectPolicy::Retry {
+//!             delay: Duration::from_secs(5),
+//!             max_attempts: None,
+//!         })
+//!         .inspect_write(|request| println!("-> {}", request))
+//!         .connect()
+//!         .await
+//!         .expect("Connecting to phd2");
+//!     println!("{:?}", phd2.get_app_state().await);
+//!     while let Some(event) = events.recv().await {
+//!         println!("{:?}", event.event);
+//!     }
+//! }
+//! ```
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use tokio::{net::TcpStream, sync::mpsc::Receiver};
+
+use crate::{serialization::ServerEvent, Phd2Connection, DEFAULT_RPC_TIMEOUT, EVENT_BUFFER_SIZE};
+
+/// Port phd2 listens on for its first instance.
+pub const DEFAULT_PORT: u16 = 4400;
+
+/// Called with each line sent to or received from phd2.
+pub type Inspect = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Opens a new stream to phd2 when reconnecting.
+pub(crate) type Connect<T> =
+    Box<dyn FnMut() -> Pin<Box<dyn Future<Output = std::io::Result<T>> + Send>> + Send>;
+
+/// What a connection opened with [connect](Phd2ConnectionBuilder::connect) does when phd2
+/// closes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReconnectPolicy {
+    /// The connection ends when phd2 closes it.
+    #[default]
+    Never,
+    /// Try to reconnect every `delay`, giving up after `max_attempts` failed attempts in a
+    /// row.  `None` keeps trying until the connection is dropped.
+    Retry {
+        delay: Duration,
+        max_attempts: Option<u32>,
+    },
+}
+
+/// What the event receiver returned with a connection does with events that arrive while
+/// it's full.  Dropped events are counted by
+/// [dropped_events](Phd2Connection::dropped_events).  Subscriptions always drop their
+/// oldest events, reporting how many were missed as a lag.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Drop each event that arrives while the receiver is full.
+    #[default]
+    DropNewest,
+    /// Drop events with these [names](crate::serialization::Event::name) while the receiver
+    /// is full, waiting for room for any other event.
+    DropEventTypes(Vec<String>),
+    /// Wait for room in the receiver.  Responses to rpc calls are read in the same task, so
+    /// calls stall while the receiver is full.
+    Wait,
+    /// Stop sending to the receiver the first time it's full, ending it early.  Every later
+    /// event is counted as dropped.
+    Close,
+}
+
+/// Settings shared by every way of starting a connection.
+pub(crate) struct Options {
+    pub(crate) timeout: Duration,
+    pub(crate) method_timeouts: HashMap<String, Duration>,
+    pub(crate) event_buffer_size: usize,
+    pub(crate) overflow: OverflowPolicy,
+    pub(crate) reconnect: ReconnectPolicy,
+    pub(crate) inspect_read: Option<Inspect>,
+    pub(crate) inspect_write: Option<Inspect>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            timeout: DEFAULT_RPC_TIMEOUT,
+            method_timeouts: HashMap::new(),
+            event_buffer_size: EVENT_BUFFER_SIZE,
+            overflow: OverflowPolicy::DropNewest,
+            reconnect: ReconnectPolicy::Never,
+            inspect_read: None,
+            inspect_write: None,
+        }
+    }
+}
+
+/// Builds a [Phd2Connection].  Defaults to phd2's first instance on localhost with the same
+/// settings as [Phd2Connection::from].
+pub struct Phd2ConnectionBuilder {
+    host: String,
+    port: u16,
+    options: Options,
+}
+
+impl Default for Phd2ConnectionBuilder {
+    fn default() -> Self {
+        Phd2ConnectionBuilder::new()
+    }
+}
+
+impl Phd2ConnectionBuilder {
+    pub fn new() -> Self {
+        Phd2ConnectionBuilder {
+            host: String::from("localhost"),
+            port: DEFAULT_PORT,
+            options: Options::default(),
+        }
+    }
+
+    /// Sets the host [connect](Phd2ConnectionBuilder::connect) connects to.
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    /// Sets the port [connect](Phd2ConnectionBuilder::connect) connects to.  Each phd2
+    /// instance listens on [DEFAULT_PORT] plus its instance number minus one.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Sets how long to wait for phd2 to respond to an rpc call, see
+    /// [with_timeout](Phd2Connection::with_timeout).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+
+    /// Sets the timeout for a single rpc `method`, see
+    /// [with_method_timeout](Phd2Connection::with_method_timeout).
+    pub fn method_timeout(mut self, method: impl Into<String>, timeout: Duration) -> Self {
+        self.options.method_timeouts.insert(method.into(), timeout);
+        self
+    }
+
+    /// Sets how many events are buffered for the event receiver and each subscription.
+    /// Defaults to [EVENT_BUFFER_SIZE].
+    ///
+    /// # Panics
+    /// Panics if `size` is zero.
+    pub fn event_buffer_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "event buffer size must be greater than zero");
+        self.options.event_buffer_size = size;
+        self
+    }
+
+    /// Sets what happens to events that arrive while the event receiver is full.
+    pub fn overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.options.overflow = policy;
+        self
+    }
+
+    /// Sets what happens when phd2 closes a connection opened with
+    /// [connect](Phd2ConnectionBuilder::connect).  Connections made with
+    /// [build](Phd2ConnectionBuilder::build) can't be reopened and always end.
+    ///
+    /// Each time the connection closes the event receiver and subscriptions get an
+    /// [Event::Disconnected](crate::serialization::Event::Disconnected), and an
+    /// [Event::Connected](crate::serialization::Event::Connected) once reconnected.  Rpc
+    /// calls fail with [ClientError::Disconnected](crate::ClientError::Disconnected) while
+    /// disconnected.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.options.reconnect = policy;
+        self
+    }
+
+    /// Calls `inspect` with each line received from phd2, before it's parsed.
+    pub fn inspect_read<F: Fn(&str) + Send + Sync + 'static>(mut self, inspect: F) -> Self {
+        self.options.inspect_read = Some(Arc::new(inspect));
+        self
+    }
+
+    /// Calls `inspect` with each rpc request before it's sent to phd2.
+    pub fn inspect_write<F: Fn(&str) + Send + Sync + 'static>(mut self, inspect: F) -> Self {
+        self.options.inspect_write = Some(Arc::new(inspect));
+        self
+    }
+
+    /// Connects to phd2 at the configured host and port.
+    pub async fn connect(
+        self,
+    ) -> std::io::Result<(Phd2Connection<TcpStream>, Receiver<ServerEvent>)> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        let reconnect = match self.options.reconnect {
+            ReconnectPolicy::Never => None,
+            ReconnectPolicy::Retry { .. } => {
+                let (host, port) = (self.host, self.port);
+                let connect: Connect<TcpStream> =
+                    Box::new(move || Box::pin(TcpStream::connect((host.clone(), port))));
+                Some(connect)
+            }
+        };
+        Ok(Phd2Connection::start(stream, self.options, reconnect))
+    }
+
+    /// Starts a connection over an existing `stream`, ignoring the host, port and reconnect
+    /// policy.
+    pub fn build<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite + 'static>(
+        self,
+        stream: T,
+    ) -> (Phd2Connection<T>, Receiver<ServerEvent>) {
+        Phd2Connection::start(stream, self.options, None)
+    }
+}
+
+/// Opens a new stream with `connect` following `policy`, returns `None` after giving up.
+pub(crate) async fn reconnect<T>(policy: ReconnectPolicy, connect: &mut Connect<T>) -> Option<T> {
+    let (delay, max_attempts) = match policy {
+        ReconnectPolicy::Never => return None,
+        ReconnectPolicy::Retry {
+            delay,
+            max_attempts,
+        } => (delay, max_attempts),
+    };
+    let mut attempts = 0;
+    while max_attempts.is_none_or(|max_attempts| attempts < max_attempts) {
+        attempts += 1;
+        tokio::time::sleep(delay).await;
+        if let Ok(stream) = connect().await {
+            return Some(stream);
+        }
+    }
+    None
+}
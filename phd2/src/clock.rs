@@ -0,0 +1,108 @@
+//! Estimating the skew between phd2's clock and the local one, and how long events take to
+//! arrive.
+//!
+//! The [offset](ServerEvent::offset) of each event is its latency plus the skew between the
+//! clocks.  [ClockEstimator] separates the two by taking the smallest recent offset as the
+//! least delayed event, which took about half the round trip of an rpc call to arrive.
+//! # Example
+//! ```no_run
+//! use phd2::{clock::ClockEstimator, serialization::Event, Phd2Connection};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let (phd2, mut events): (Phd2Connection<_>, _) = Phd2Connection::from(
+//!         tokio::net::TcpStream::connect("localhost:4400")
+//!             .await
+//!             .expect("Connecting to phd2"),
+//!     );
+//!     let mut clock = ClockEstimator::new(100);
+//!     while let Some(event) = events.recv().await {
+//!         clock.add(&event);
+//!         let Some(estimate) = clock.estimate(phd2.stats().average_round_trip) else {
+//!             continue;
+//!         };
+//!         if let Event::GuideStep(_) = &event.event {
+//!             println!(
+//!                 "guide step taken at {:?}, {:.3}s latency",
+//!                 estimate.to_local(event.timestamp),
+//!                 estimate.latency
+//!             );
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::serialization::{Event, ServerEvent};
+
+/// Skew and latency from a [ClockEstimator], in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockEstimate {
+    /// How far the local clock is ahead of phd2's.
+    pub skew: f64,
+    /// Average time from phd2 sending an event to it being received.
+    pub latency: f64,
+}
+
+impl ClockEstimate {
+    /// Converts a phd2 `timestamp`, in seconds since the unix epoch, to the local clock.
+    pub fn to_local(&self, timestamp: f64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs_f64((timestamp + self.skew).max(0.0))
+    }
+}
+
+/// Estimates clock skew and latency from the offsets of recent events.
+#[derive(Debug, Clone)]
+pub struct ClockEstimator {
+    offsets: VecDeque<f64>,
+    window: usize,
+}
+
+impl ClockEstimator {
+    /// Estimates from the last `window` events.
+    ///
+    /// # Panics
+    /// Panics if `window` is zero.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "window must hold at least one event");
+        ClockEstimator {
+            offsets: VecDeque::with_capacity(window),
+            window,
+        }
+    }
+
+    /// Adds the offset of `event`.  Events the connection makes itself, such as
+    /// [Event::Connected], are ignored since they weren't sent by phd2.
+    pub fn add(&mut self, event: &ServerEvent) {
+        if matches!(event.event, Event::Connected(_) | Event::Disconnected(_)) {
+            return;
+        }
+        if self.offsets.len() == self.window {
+            self.offsets.pop_front();
+        }
+        self.offsets.push_back(event.offset());
+    }
+
+    /// Estimates the skew and latency, or `None` before any events have been added.
+    /// `round_trip` is the time rpc calls take, such as
+    /// [average_round_trip](crate::ConnectionStats::average_round_trip).  Without it the
+    /// least delayed event is assumed to have arrived instantly, which counts all of its
+    /// latency as skew.
+    pub fn estimate(&self, round_trip: Option<Duration>) -> Option<ClockEstimate> {
+        if self.offsets.is_empty() {
+            return None;
+        }
+        let min = self.offsets.iter().copied().fold(f64::INFINITY, f64::min);
+        let mean = self.offsets.iter().sum::<f64>() / self.offsets.len() as f64;
+        let one_way = round_trip.unwrap_or_default().as_secs_f64() / 2.0;
+        let skew = min - one_way;
+        Some(ClockEstimate {
+            skew,
+            latency: mean - skew,
+        })
+    }
+}
@@ -0,0 +1,116 @@
+//! Dithering between the frames of a capture loop.
+//!
+//! [DitherCoordinator] runs each capture through [capture](DitherCoordinator::capture),
+//! dithering and waiting for phd2 to settle before a capture whenever a dither is due, so
+//! no frame is taken while the guide star is moving.
+//! # Example
+//! ```no_run
+//! use phd2::{dither::DitherCoordinator, serialization::Settle, Phd2Connection};
+//! use std::{sync::Arc, time::Duration};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+//!         tokio::net::TcpStream::connect("localhost:4400")
+//!             .await
+//!             .expect("Connecting to phd2"),
+//!     );
+//!     let settle = Settle::new(1.5, Duration::from_secs(10), Duration::from_secs(60));
+//!     let mut dither = DitherCoordinator::new(Arc::new(phd2), 5.0, settle).every(3);
+//!     for frame in 0..30 {
+//!         dither
+//!             .capture(|| async move {
+//!                 println!("exposing frame {}", frame);
+//!                 tokio::time::sleep(Duration::from_secs(120)).await;
+//!             })
+//!             .await
+//!             .expect("Dithering");
+//!     }
+//! }
+//! ```
+
+use std::{future::Future, sync::Arc};
+
+use crate::{
+    serialization::{Settle, SettleDone},
+    ClientError, Phd2Connection,
+};
+
+/// Dithers every few frames of a capture loop.
+pub struct DitherCoordinator<T> {
+    phd2: Arc<Phd2Connection<T>>,
+    amount: f64,
+    ra_only: bool,
+    settle: Settle,
+    every: u32,
+    frames: u32,
+}
+
+impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite + 'static> DitherCoordinator<T> {
+    /// Dithers by up to `amount` pixels after every frame, waiting for phd2 to settle with
+    /// `settle`.
+    pub fn new(phd2: Arc<Phd2Connection<T>>, amount: f64, settle: Settle) -> Self {
+        DitherCoordinator {
+            phd2,
+            amount,
+            ra_only: false,
+            settle,
+            every: 1,
+            frames: 0,
+        }
+    }
+
+    /// Dithers after every `frames` frames instead of after each one.
+    ///
+    /// # Panics
+    /// Panics if `frames` is zero.
+    pub fn every(mut self, frames: u32) -> Self {
+        assert!(frames > 0, "dither interval must be at least one frame");
+        self.every = frames;
+        self
+    }
+
+    /// Only dithers in right ascension.
+    pub fn ra_only(mut self, ra_only: bool) -> Self {
+        self.ra_only = ra_only;
+        self
+    }
+
+    /// Number of frames that can be captured before the next dither, zero when the next
+    /// [capture](DitherCoordinator::capture) dithers first.
+    pub fn frames_until_dither(&self) -> u32 {
+        self.every.saturating_sub(self.frames)
+    }
+
+    /// Restarts the count of frames since the last dither, such as after slewing to a new
+    /// target.
+    pub fn reset(&mut self) {
+        self.frames = 0;
+    }
+
+    /// Dithers now and waits for phd2 to settle, restarting the count of frames.
+    pub async fn dither(&mut self) -> Result<SettleDone, ClientError> {
+        let done = self
+            .phd2
+            .dither_and_settle(self.amount, self.ra_only, self.settle)
+            .await?;
+        self.frames = 0;
+        Ok(done)
+    }
+
+    /// Runs `capture`, dithering first if a dither is due.  If dithering or settling fails
+    /// the frame isn't captured and the error is returned, the dither is tried again on the
+    /// next call.
+    pub async fn capture<F, Fut, R>(&mut self, capture: F) -> Result<R, ClientError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = R>,
+    {
+        if self.frames >= self.every {
+            self.dither().await?;
+        }
+        let result = capture().await;
+        self.frames += 1;
+        Ok(result)
+    }
+}
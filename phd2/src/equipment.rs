@@ -0,0 +1,152 @@
+//! Checking phd2's equipment against what a session expects.
+//!
+//! [ExpectedEquipment] describes the devices a session needs by the names phd2 reports
+//! from [get_current_equipment](crate::Phd2Connection::get_current_equipment), and
+//! [check_equipment](crate::Phd2Connection::check_equipment) lists how phd2's equipment
+//! differs, so automation can refuse to start with the wrong profile selected.
+//! # Example
+//! ```no_run
+//! use phd2::{equipment::ExpectedEquipment, Phd2Connection};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+//!         tokio::net::TcpStream::connect("localhost:4400")
+//!             .await
+//!             .expect("Connecting to phd2"),
+//!     );
+//!     let expected = ExpectedEquipment::new()
+//!         .camera("ZWO ASI120MM Mini")
+//!         .mount("On-camera");
+//!     let mismatches = phd2.check_equipment(&expected).await.expect("Getting equipment");
+//!     for mismatch in &mismatches {
+//!         println!("{}", mismatch);
+//!     }
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::serialization::Equipment;
+
+/// The devices phd2 should have, by the keys phd2 uses such as `"camera"` and `"mount"`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExpectedEquipment {
+    devices: BTreeMap<String, String>,
+    allow_others: bool,
+}
+
+impl ExpectedEquipment {
+    pub fn new() -> Self {
+        ExpectedEquipment::default()
+    }
+
+    /// Expects the device phd2 calls `kind` to be named `name` and connected.
+    pub fn device(mut self, kind: impl Into<String>, name: impl Into<String>) -> Self {
+        self.devices.insert(kind.into(), name.into());
+        self
+    }
+
+    /// Expects the guide camera to be named `name`.
+    pub fn camera(self, name: impl Into<String>) -> Self {
+        self.device("camera", name)
+    }
+
+    /// Expects the mount to be named `name`.
+    pub fn mount(self, name: impl Into<String>) -> Self {
+        self.device("mount", name)
+    }
+
+    /// Allows phd2 to have devices that aren't expected, instead of reporting them as
+    /// [EquipmentMismatch::Unexpected].
+    pub fn allow_others(mut self, allow: bool) -> Self {
+        self.allow_others = allow;
+        self
+    }
+
+    /// Lists how `actual` differs from the expected devices, ordered by device kind.  An
+    /// empty list means the equipment matches.
+    pub fn diff(&self, actual: &HashMap<String, Equipment>) -> Vec<EquipmentMismatch> {
+        let mut mismatches = vec![];
+        for (kind, expected) in &self.devices {
+            match actual.get(kind) {
+                None => mismatches.push(EquipmentMismatch::Missing {
+                    kind: kind.clone(),
+                    expected: expected.clone(),
+                }),
+                Some(equipment) if equipment.name != *expected => {
+                    mismatches.push(EquipmentMismatch::WrongDevice {
+                        kind: kind.clone(),
+                        expected: expected.clone(),
+                        actual: equipment.name.clone(),
+                    })
+                }
+                Some(equipment) if !equipment.connected => {
+                    mismatches.push(EquipmentMismatch::Disconnected { kind: kind.clone() })
+                }
+                Some(_) => {}
+            }
+        }
+        if !self.allow_others {
+            let mut others: Vec<_> = actual
+                .iter()
+                .filter(|(kind, _)| !self.devices.contains_key(*kind))
+                .map(|(kind, equipment)| EquipmentMismatch::Unexpected {
+                    kind: kind.clone(),
+                    actual: equipment.name.clone(),
+                })
+                .collect();
+            others.sort_by(|a, b| a.kind().cmp(b.kind()));
+            mismatches.extend(others);
+        }
+        mismatches
+    }
+}
+
+/// A way phd2's equipment differs from an [ExpectedEquipment].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EquipmentMismatch {
+    /// phd2's profile has no device of this kind.
+    Missing { kind: String, expected: String },
+    /// phd2's profile has a different device.
+    WrongDevice {
+        kind: String,
+        expected: String,
+        actual: String,
+    },
+    /// The device is right but isn't connected.
+    Disconnected { kind: String },
+    /// phd2's profile has a device that wasn't expected.
+    Unexpected { kind: String, actual: String },
+}
+
+impl EquipmentMismatch {
+    /// The kind of device, such as `"camera"`.
+    pub fn kind(&self) -> &str {
+        match self {
+            EquipmentMismatch::Missing { kind, .. }
+            | EquipmentMismatch::WrongDevice { kind, .. }
+            | EquipmentMismatch::Disconnected { kind }
+            | EquipmentMismatch::Unexpected { kind, .. } => kind,
+        }
+    }
+}
+
+impl std::fmt::Display for EquipmentMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EquipmentMismatch::Missing { kind, expected } => {
+                write!(f, "missing {} {:?}", kind, expected)
+            }
+            EquipmentMismatch::WrongDevice {
+                kind,
+                expected,
+                actual,
+            } => write!(f, "expected {} {:?}, found {:?}", kind, expected, actual),
+            EquipmentMismatch::Disconnected { kind } => write!(f, "{} isn't connected", kind),
+            EquipmentMismatch::Unexpected { kind, actual } => {
+                write!(f, "unexpected {} {:?}", kind, actual)
+            }
+        }
+    }
+}
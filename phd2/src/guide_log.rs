@@ -0,0 +1,368 @@
+//! Parses the guide logs phd2 writes to disk (`PHD2_GuideLog_*.txt`) into the same
+//! [GuideStep] and [Calibration] structures used by the live protocol, so past sessions
+//! can be analyzed with the same tooling.
+//! # Example
+//! ```no_run
+//! use phd2::{guide_log::GuideLog, stats::GuideStats};
+//!
+//! let log = GuideLog::open("PHD2_GuideLog_2023-05-21_210234.txt").expect("Reading guide log");
+//! for guiding in log.guiding() {
+//!     let mut stats = GuideStats::new(guiding.steps.len());
+//!     guiding.steps.iter().for_each(|step| stats.add(step));
+//!     println!("{}: rms {:.2}px", guiding.started_at, stats.rms_total().unwrap_or(0.0));
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::serialization::{Calibration, CalibrationData, EastWest, GuideStep, NorthSouth, Parity};
+
+#[derive(Debug)]
+pub enum GuideLogError {
+    IoError(std::io::Error),
+    /// A line couldn't be parsed, `line` counts from 1.
+    Parse {
+        line: usize,
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for GuideLogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GuideLogError::IoError(e) => write!(f, "io error: {}", e),
+            GuideLogError::Parse { line, reason } => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for GuideLogError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GuideLogError::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for GuideLogError {
+    fn from(value: std::io::Error) -> Self {
+        GuideLogError::IoError(value)
+    }
+}
+
+/// A calibration run from a guide log.
+#[derive(Debug, PartialEq)]
+pub struct CalibrationSection {
+    /// Local time the calibration started, as written by phd2.
+    pub started_at: String,
+    /// Name of the device that was calibrated, if the calibration completed.
+    pub mount: Option<String>,
+    pub calibration: Calibration,
+}
+
+/// A guiding run from a guide log.
+#[derive(Debug)]
+pub struct GuidingSection {
+    /// Local time guiding started, as written by phd2.
+    pub started_at: String,
+    /// Each guided frame.  Frames where the star was lost are left out.  Guide logs don't
+    /// record `HFD` or `AvgDist`, so `hfd` and `avg_dist` are `NaN`.
+    pub steps: Vec<GuideStep>,
+}
+
+#[derive(Debug)]
+pub enum Section {
+    Calibration(CalibrationSection),
+    Guiding(GuidingSection),
+}
+
+/// The calibration and guiding runs recorded in a phd2 guide log, in the order they
+/// happened.
+#[derive(Debug)]
+pub struct GuideLog {
+    pub sections: Vec<Section>,
+}
+
+#[derive(Default)]
+struct AxisCalibration {
+    angle: f64,
+    rate: f64,
+    parity: Option<Parity>,
+}
+
+impl GuideLog {
+    /// Reads the guide log at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<GuideLog, GuideLogError> {
+        GuideLog::read(BufReader::new(std::fs::File::open(path)?))
+    }
+
+    /// Reads a guide log from `reader`.
+    pub fn read<R: BufRead>(reader: R) -> Result<GuideLog, GuideLogError> {
+        let mut parser = Parser::default();
+        for (index, line) in reader.lines().enumerate() {
+            parser
+                .line(line?.trim_end())
+                .map_err(|reason| GuideLogError::Parse {
+                    line: index + 1,
+                    reason,
+                })?;
+        }
+        parser.finish();
+        Ok(GuideLog {
+            sections: parser.sections,
+        })
+    }
+
+    /// Returns the calibration runs in the log.
+    pub fn calibrations(&self) -> impl Iterator<Item = &CalibrationSection> {
+        self.sections.iter().filter_map(|section| match section {
+            Section::Calibration(calibration) => Some(calibration),
+            _ => None,
+        })
+    }
+
+    /// Returns the guiding runs in the log.
+    pub fn guiding(&self) -> impl Iterator<Item = &GuidingSection> {
+        self.sections.iter().filter_map(|section| match section {
+            Section::Guiding(guiding) => Some(guiding),
+            _ => None,
+        })
+    }
+}
+
+struct CalibrationState {
+    started_at: String,
+    mount: Option<String>,
+    x: Option<AxisCalibration>,
+    y: Option<AxisCalibration>,
+}
+
+struct GuidingState {
+    started_at: String,
+    columns: HashMap<String, usize>,
+    steps: Vec<GuideStep>,
+}
+
+#[derive(Default)]
+struct Parser {
+    sections: Vec<Section>,
+    calibration: Option<CalibrationState>,
+    guiding: Option<GuidingState>,
+}
+
+impl Parser {
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        if let Some(started_at) = line.strip_prefix("Calibration Begins at ") {
+            self.finish();
+            self.calibration = Some(CalibrationState {
+                started_at: String::from(started_at),
+                mount: None,
+                x: None,
+                y: None,
+            });
+        } else if let Some(started_at) = line.strip_prefix("Guiding Begins at ") {
+            self.finish();
+            self.guiding = Some(GuidingState {
+                started_at: String::from(started_at),
+                columns: HashMap::new(),
+                steps: vec![],
+            });
+        } else if line.starts_with("Calibration Ends") || line.starts_with("Guiding Ends") {
+            self.finish();
+        } else if let Some(calibration) = &mut self.calibration {
+            calibration.line(line)?;
+        } else if let Some(guiding) = &mut self.guiding {
+            guiding.line(line)?;
+        }
+        Ok(())
+    }
+
+    /// Closes the section being read, if any.
+    fn finish(&mut self) {
+        if let Some(calibration) = self.calibration.take() {
+            self.sections
+                .push(Section::Calibration(calibration.into_section()));
+        }
+        if let Some(guiding) = self.guiding.take() {
+            self.sections.push(Section::Guiding(GuidingSection {
+                started_at: guiding.started_at,
+                steps: guiding.steps,
+            }));
+        }
+    }
+}
+
+impl CalibrationState {
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        if let Some(mount) = line.strip_prefix("Calibration complete, mount = ") {
+            self.mount = Some(String::from(mount.trim_end_matches('.')));
+        } else if let Some((direction, result)) = line.split_once(" calibration complete. ") {
+            let axis = parse_axis_calibration(result)?;
+            match direction {
+                "West" | "East" | "Left" | "Right" => self.x = Some(axis),
+                "North" | "South" | "Up" | "Down" => self.y = Some(axis),
+                other => return Err(format!("unknown calibration direction {}", other)),
+            }
+        }
+        Ok(())
+    }
+
+    fn into_section(self) -> CalibrationSection {
+        let data = match (self.mount.is_some(), self.x, self.y) {
+            (true, Some(x), Some(y)) => Some(CalibrationData {
+                x_angle: x.angle,
+                x_rate: x.rate,
+                x_parity: x.parity.unwrap_or(Parity::Unknown),
+                y_angle: y.angle,
+                y_rate: y.rate,
+                y_parity: y.parity.unwrap_or(Parity::Unknown),
+            }),
+            _ => None,
+        };
+        CalibrationSection {
+            started_at: self.started_at,
+            mount: self.mount,
+            calibration: Calibration {
+                calibrated: data.is_some(),
+                data,
+            },
+        }
+    }
+}
+
+/// Parses `Angle = 1.1 deg, Rate = 7.017 px/sec, Parity = Normal`.
+fn parse_axis_calibration(result: &str) -> Result<AxisCalibration, String> {
+    let mut axis = AxisCalibration::default();
+    for field in result.split(", ") {
+        let (name, value) = field
+            .split_once(" = ")
+            .ok_or_else(|| format!("invalid calibration result {}", result))?;
+        let number = || {
+            value
+                .split(' ')
+                .next()
+                .and_then(|number| number.parse::<f64>().ok())
+                .ok_or_else(|| format!("invalid {} {}", name, value))
+        };
+        match name {
+            "Angle" => axis.angle = number()?,
+            "Rate" => axis.rate = number()?,
+            "Parity" => {
+                axis.parity = Some(match value {
+                    "Normal" => Parity::Pos,
+                    "Reversed" => Parity::Neg,
+                    _ => Parity::Unknown,
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok(axis)
+}
+
+impl GuidingState {
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        if line.starts_with("Frame,") {
+            self.columns = split_csv(line)
+                .into_iter()
+                .enumerate()
+                .map(|(index, name)| (String::from(name), index))
+                .collect();
+        } else if line.starts_with(|c: char| c.is_ascii_digit()) {
+            if self.columns.is_empty() {
+                return Err(String::from("guide step before the column header"));
+            }
+            let fields = split_csv(line);
+            if let Some(step) = self.guide_step(&fields)? {
+                self.steps.push(step);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `None` for frames where the star was lost.
+    fn guide_step(&self, fields: &[&str]) -> Result<Option<GuideStep>, String> {
+        let field = |name: &str| -> Option<&str> {
+            self.columns
+                .get(name)
+                .and_then(|index| fields.get(*index))
+                .copied()
+                .filter(|value| !value.is_empty())
+        };
+        let required = |name: &str| -> Result<&str, String> {
+            field(name).ok_or_else(|| format!("missing {}", name))
+        };
+        let number = |name: &str| -> Result<f64, String> {
+            required(name)?
+                .parse()
+                .map_err(|_| format!("invalid {}", name))
+        };
+        let optional = |name: &str| -> Result<Option<f64>, String> {
+            field(name)
+                .map(|value| value.parse().map_err(|_| format!("invalid {}", name)))
+                .transpose()
+        };
+
+        let mount = required("mount")?;
+        if mount == "DROP" {
+            return Ok(None);
+        }
+
+        Ok(Some(GuideStep {
+            frame: required("Frame")?
+                .parse()
+                .map_err(|_| String::from("invalid Frame"))?,
+            time: number("Time")?,
+            mount: String::from(mount),
+            dx: number("dx")?,
+            dy: number("dy")?,
+            ra_distance_raw: number("RARawDistance")?,
+            de_distance_raw: number("DECRawDistance")?,
+            ra_distance_guide: number("RAGuideDistance")?,
+            de_distance_guide: number("DECGuideDistance")?,
+            ra_duration: optional("RADuration")?.filter(|duration| *duration != 0.0),
+            ra_direction: match field("RADirection") {
+                Some("E") => Some(EastWest::East),
+                Some("W") => Some(EastWest::West),
+                _ => None,
+            },
+            dec_duration: optional("DECDuration")?.filter(|duration| *duration != 0.0),
+            dec_direction: match field("DECDirection") {
+                Some("N") => Some(NorthSouth::North),
+                Some("S") => Some(NorthSouth::South),
+                _ => None,
+            },
+            star_mass: number("StarMass")?,
+            snr: number("SNR")?,
+            hfd: optional("HFD")?.unwrap_or(f64::NAN),
+            avg_dist: f64::NAN,
+            ra_limited: None,
+            dec_limited: None,
+            error_code: optional("ErrorCode")?.map(|code| code as i32),
+        }))
+    }
+}
+
+/// Splits a line of comma separated values, removing the quotes around quoted values.
+fn split_csv(line: &str) -> Vec<&str> {
+    let mut fields = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                fields.push(line[start..index].trim_matches('"'));
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(line[start..].trim_matches('"'));
+    fields
+}
@@ -0,0 +1,140 @@
+use tokio::{sync::watch, task::JoinHandle};
+use tokio_stream::StreamExt;
+
+use crate::{
+    serialization::{Event, State},
+    subscription::EventStream,
+    ClientError, Phd2Connection,
+};
+
+/// What phd2 is doing, as tracked by a [Guider].  Mirrors phd2's [State], with settling
+/// after guiding starts or a dither tracked separately from guiding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuiderState {
+    Stopped,
+    Selected,
+    Looping,
+    Calibrating,
+    Guiding,
+    Settling,
+    LostLock,
+    Paused,
+}
+
+impl From<State> for GuiderState {
+    fn from(value: State) -> Self {
+        match value {
+            State::Stopped => GuiderState::Stopped,
+            State::Selected => GuiderState::Selected,
+            State::Calibrating => GuiderState::Calibrating,
+            State::Guiding => GuiderState::Guiding,
+            State::LostLock => GuiderState::LostLock,
+            State::Paused => GuiderState::Paused,
+            State::Looping => GuiderState::Looping,
+        }
+    }
+}
+
+impl GuiderState {
+    /// Returns the state phd2 is in after sending `event`.
+    pub fn next(self, event: &Event) -> GuiderState {
+        match event {
+            Event::AppState(app_state) => app_state.state.into(),
+            Event::StarSelected(_) => GuiderState::Selected,
+            Event::LoopingExposures(_) => GuiderState::Looping,
+            Event::StartCalibration(_) | Event::Calibrating(_) => GuiderState::Calibrating,
+            Event::StartGuiding(_) | Event::Resumed(_) => GuiderState::Guiding,
+            Event::SettleBegin(_) | Event::Settling(_) => GuiderState::Settling,
+            Event::SettleDone(_) => match self {
+                GuiderState::Settling => GuiderState::Guiding,
+                other => other,
+            },
+            Event::GuideStep(_) => match self {
+                GuiderState::Settling => GuiderState::Settling,
+                _ => GuiderState::Guiding,
+            },
+            Event::StarLost(_) => GuiderState::LostLock,
+            Event::Paused(_) => GuiderState::Paused,
+            Event::LoopingExposuresStopped(_)
+            | Event::GuidingStopped(_)
+            | Event::CalibrationFailed(_) => GuiderState::Stopped,
+            _ => self,
+        }
+    }
+}
+
+/// Tracks phd2's [GuiderState] from its events, so it doesn't need to be polled with
+/// [get_app_state](Phd2Connection::get_app_state).  Tracking stops when the connection to
+/// phd2 closes or the guider is dropped.  If events are missed because the guider fell
+/// behind, the state is corrected by the next event that determines it.
+/// # Example
+/// ```no_run
+/// use phd2::{Guider, GuiderState, Phd2Connection};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+///         tokio::net::TcpStream::connect("localhost:4400")
+///             .await
+///             .expect("Connecting to phd2"),
+///     );
+///     let guider = Guider::new(&phd2).await.expect("Getting app state");
+///     let mut states = guider.subscribe();
+///     while states.changed().await.is_ok() {
+///         if *states.borrow() == GuiderState::LostLock {
+///             println!("lost the guide star");
+///         }
+///     }
+/// }
+/// ```
+pub struct Guider {
+    state: watch::Receiver<GuiderState>,
+    task: JoinHandle<()>,
+}
+
+impl Guider {
+    /// Starts tracking the state of phd2, starting from the state returned by
+    /// [get_app_state](Phd2Connection::get_app_state).
+    pub async fn new<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite>(
+        phd2: &Phd2Connection<T>,
+    ) -> Result<Guider, ClientError> {
+        // Subscribe first so events sent while asking for the state are applied after it.
+        let events = phd2.event_stream();
+        let state = phd2.get_app_state().await?;
+        Ok(Guider::from_events(events, state.into()))
+    }
+
+    fn from_events(mut events: EventStream, state: GuiderState) -> Guider {
+        let (sender, receiver) = watch::channel(state);
+        let task = tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                sender.send_if_modified(|state| {
+                    let next = state.next(&event.event);
+                    let modified = *state != next;
+                    *state = next;
+                    modified
+                });
+            }
+        });
+        Guider {
+            state: receiver,
+            task,
+        }
+    }
+
+    /// Returns the current state.
+    pub fn state(&self) -> GuiderState {
+        *self.state.borrow()
+    }
+
+    /// Returns a receiver that is notified each time the state changes.
+    pub fn subscribe(&self) -> watch::Receiver<GuiderState> {
+        self.state.clone()
+    }
+}
+
+impl Drop for Guider {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -9,19 +9,22 @@
 //! ### Simple usage.
 //!
 //! The simpliest way to use this crate is to convert a [TcpStream](tokio::net::TcpStream) to a [Phd2Connection](Phd2Connection) to send commands and receive events.
+//! Use a [Phd2ConnectionBuilder] to set timeouts, reconnect automatically or inspect the traffic.
+//! Any number of subscribers can receive events at the same time, see [subscribe](Phd2Connection::subscribe).
 //! #### Example
 //! ```no_run
 //! use phd2::{serialization::Event, Phd2Connection};
 //!
 //! #[tokio::main]
 //! async fn main() {
-//!     let mut phd2: Phd2Connection<_>= tokio::net::TcpStream::connect("localhost:4400")
-//!         .await
-//!         .expect("Connecting to phd2")
-//!         .into();
+//!     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+//!         tokio::net::TcpStream::connect("localhost:4400")
+//!             .await
+//!             .expect("Connecting to phd2"),
+//!     );
 //!     let mut pixel_scale = phd2.get_pixel_scale().await.expect("Getting pixel scale.");
-//!     
-//!     let mut sub = phd2.subscribe().await;
+//!
+//!     let mut sub = phd2.subscribe();
 //!
 //!     while let Ok(event) = sub.recv().await {
 //!         if let Event::GuideStep(guide) = &event.event {
@@ -34,8 +37,33 @@
 //!     }
 //! }
 //! ```
-
+//!
+//! [PixelScale](pixel_scale::PixelScale) does the conversion to arcseconds and keeps the
+//! pixel scale up to date.  Code that isn't async can use a [blocking::Client].
+
+pub mod blocking;
+pub mod builder;
+pub mod clock;
+pub mod dither;
+pub mod equipment;
+pub mod guide_log;
+pub mod guider;
+pub mod pixel_scale;
+pub mod pool;
+pub mod process;
+pub mod recording;
+pub mod recovery;
 pub mod serialization;
+pub mod settle;
+pub mod stats;
+pub mod subscription;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+pub use builder::{OverflowPolicy, Phd2ConnectionBuilder, ReconnectPolicy};
+pub use guider::{Guider, GuiderState};
+pub use settle::SettleHandle;
 use std::{
     collections::HashMap,
     sync::{atomic::Ordering, Arc},
@@ -45,10 +73,13 @@ use std::{
 use serde::Serialize;
 use serde_json::json;
 use serialization::{
-    Axis, Calibration, ClearCalibrationParam, CoolerStatus, DecGuideMode, DurationMillis,
-    Equipment, InvalidState, JsonRpcRequest, JsonRpcResponse, LockShiftParams, Profile,
-    PulseDirection, ServerEvent, ServerMessage, Settle, StarImage, State, WhichDevice,
+    Axis, Calibration, ClearCalibrationParam, Connected, CoolerStatus, DecGuideMode, Disconnected,
+    DurationMillis, Equipment, Event, ExportedConfigSettings, InvalidState, JsonRpcRequest,
+    JsonRpcResponse, LockShiftAxes, LockShiftParams, LockShiftUnits, Phd2Status, Profile,
+    PulseDirection, RpcError, SavedImage, ServerEvent, ServerMessage, Settle, SettleDone,
+    StarImage, State, VariableDelaySettings, Version, WhichDevice,
 };
+use subscription::{EventStream, FilteredSubscription, FromEvent, Lagged, TypedSubscription};
 
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
@@ -58,22 +89,142 @@ use tokio::{
 #[cfg(test)]
 mod tests;
 
+/// Default amount of time to wait for phd2 to respond to an rpc call.
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Number of events buffered for each event receiver and subscription.  A subscription
+/// that falls further behind than this returns [Lagged](subscription::Lagged).
+pub const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// Number of times [ensure_profile_connected](Phd2Connection::ensure_profile_connected)
+/// tries to connect the equipment.
+pub const CONNECT_ATTEMPTS: u32 = 3;
+
+/// Time to wait between attempts to connect the equipment.
+const CONNECT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Number of times [start_guiding_auto](Phd2Connection::start_guiding_auto) tries to
+/// select a guide star.
+pub const FIND_STAR_ATTEMPTS: u32 = 5;
+
+/// Time to wait after the first failed attempt to select a guide star, doubled after each
+/// failure after that.
+const FIND_STAR_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Rpc methods that older versions of phd2 don't have, with the first version that does.
+/// Calls to them fail with [ClientError::UnsupportedByServer] when phd2 is older.
+pub const METHOD_MIN_VERSIONS: &[(&str, &str)] = &[
+    ("get_variable_delay_settings", "2.6.10"),
+    ("set_variable_delay_settings", "2.6.10"),
+];
+
+/// Smallest star image phd2 will return from [get_star_image](Phd2Connection::get_star_image).
+pub const MIN_STAR_IMAGE_SIZE: u32 = 15;
+
 #[derive(Debug)]
 pub enum ClientError {
     IoError(std::io::Error),
     SerdeJsonError(serde_json::Error),
-    RpcError(serde_json::Value),
+    /// phd2 responded to the call with an error.
+    RpcError(RpcError),
     RpcUnexpectedResponse(serde_json::Value),
     RpcMissingResult,
     InvalidState(InvalidState),
+    /// An argument was rejected before it was sent to phd2.
+    InvalidArgument(String),
+    /// phd2 reported that guiding didn't settle.
+    SettleFailed(SettleDone),
+    /// phd2 couldn't calibrate before guiding, holds the reason it gave.
+    CalibrationFailed(String),
+    /// phd2 has no equipment profile with the given name.
+    ProfileNotFound(String),
+    /// phd2 reported different lock shift settings than were set, holds what it reported.
+    LockShiftNotApplied(LockShiftParams),
+    /// The version of phd2 connected to is older than the first with the rpc method, see
+    /// [METHOD_MIN_VERSIONS].
+    UnsupportedByServer {
+        method: String,
+        required: &'static str,
+        version: String,
+    },
+    /// The event subscription fell behind and may have missed the event being waited for.
+    Lagged(Lagged),
+    /// The connection to phd2 closed while waiting for an event.
+    ConnectionClosed,
+    /// The connection to phd2 closed before it responded to an rpc call.
+    Disconnected,
+    /// phd2 stopped answering the [heartbeat](Phd2Connection::heartbeat), holds the error
+    /// from the failed check.
+    ConnectionLost(Box<ClientError>),
     Timeout(Elapsed),
 }
 
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::IoError(e) => write!(f, "io error: {}", e),
+            ClientError::SerdeJsonError(e) => write!(f, "json error: {}", e),
+            ClientError::RpcError(e) => write!(f, "phd2 returned an error: {}", e),
+            ClientError::RpcUnexpectedResponse(value) => {
+                write!(f, "unexpected response from phd2: {}", value)
+            }
+            ClientError::RpcMissingResult => write!(f, "phd2 response is missing a result"),
+            ClientError::InvalidState(e) => write!(f, "{}", e),
+            ClientError::InvalidArgument(reason) => write!(f, "invalid argument: {}", reason),
+            ClientError::ProfileNotFound(name) => write!(f, "phd2 has no profile named {:?}", name),
+            ClientError::LockShiftNotApplied(params) => write!(
+                f,
+                "phd2 didn't apply the lock shift, it reports {:?} {} in {}, enabled: {}",
+                params.rate, params.units, params.axes, params.enabled
+            ),
+            ClientError::UnsupportedByServer {
+                method,
+                required,
+                version,
+            } => write!(
+                f,
+                "phd2 {} doesn't support {}, it needs phd2 {} or newer",
+                version, method, required
+            ),
+            ClientError::SettleFailed(done) => match &done.error {
+                Some(error) => write!(f, "failed to settle: {}", error),
+                None => write!(f, "failed to settle, status {}", done.status),
+            },
+            ClientError::CalibrationFailed(reason) => {
+                write!(f, "failed to calibrate: {}", reason)
+            }
+            ClientError::Lagged(e) => write!(f, "{}", e),
+            ClientError::ConnectionClosed => write!(f, "connection to phd2 closed"),
+            ClientError::Disconnected => write!(f, "disconnected from phd2"),
+            ClientError::ConnectionLost(e) => write!(f, "lost connection to phd2: {}", e),
+            ClientError::Timeout(_) => write!(f, "timed out waiting for phd2 to respond"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::IoError(e) => Some(e),
+            ClientError::SerdeJsonError(e) => Some(e),
+            ClientError::Lagged(e) => Some(e),
+            ClientError::ConnectionLost(e) => Some(e.as_ref()),
+            ClientError::Timeout(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl From<Elapsed> for ClientError {
     fn from(value: Elapsed) -> Self {
         ClientError::Timeout(value)
     }
 }
+impl From<Lagged> for ClientError {
+    fn from(value: Lagged) -> Self {
+        ClientError::Lagged(value)
+    }
+}
 impl From<InvalidState> for ClientError {
     fn from(value: InvalidState) -> Self {
         ClientError::InvalidState(value)
@@ -92,65 +243,282 @@ impl From<serde_json::Error> for ClientError {
 }
 
 impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite + 'static> Phd2Connection<T> {
+    /// Starts reading from `value`, returning the connection and a receiver for every event
+    /// phd2 sends.  Events are dropped instead of queued once [EVENT_BUFFER_SIZE] events are
+    /// waiting in the receiver, so it should be read promptly or dropped.  Use a
+    /// [Phd2ConnectionBuilder] to choose another [OverflowPolicy].
+    ///
+    /// The receiver starts with an [Event::Connected] and ends with an
+    /// [Event::Disconnected] when the connection closes.  Subscriptions are created after
+    /// the connection so only receive the [Event::Disconnected].
     pub fn from(value: T) -> (Phd2Connection<T>, tokio::sync::mpsc::Receiver<ServerEvent>) {
-        let (read, write) = tokio::io::split(value);
-        let (events, recv) = tokio::sync::mpsc::channel(1024);
+        Phd2Connection::start(value, builder::Options::default(), None)
+    }
 
-        let client = Phd2Connection {
-            connection: Arc::new(tokio::sync::Mutex::new(Connection {
-                pending_requests: Default::default(),
-                write,
-            })),
-            last_id: std::sync::atomic::AtomicU64::new(0),
+    /// Starts the reader task.  When the stream closes it's reopened with `connect` if set,
+    /// following the reconnect policy in `options`.
+    fn start(
+        value: T,
+        options: builder::Options,
+        mut connect: Option<builder::Connect<T>>,
+    ) -> (Phd2Connection<T>, tokio::sync::mpsc::Receiver<ServerEvent>) {
+        let (read, write) = tokio::io::split(value);
+        let (events, recv) = tokio::sync::mpsc::channel(options.event_buffer_size);
+        events
+            .try_send(connection_event(Event::Connected(Connected {})))
+            .ok();
+        let (broadcast, subscriptions) =
+            tokio::sync::broadcast::channel(options.event_buffer_size);
+
+        let connection = Arc::new(tokio::sync::Mutex::new(Connection {
+            pending_requests: Default::default(),
+            closed: false,
+            write,
+        }));
+        let subscriptions = Arc::new(subscriptions);
+        let held_subscriptions = Arc::downgrade(&subscriptions);
+        let client_connection = connection.clone();
+        let inspect_read = options.inspect_read.clone();
+        let reconnect_policy = options.reconnect;
+        let dropped_events = Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let server_version = Arc::new(std::sync::Mutex::new(None));
+        let read_version = server_version.clone();
+        let metrics = Arc::new(Metrics::new());
+        let read_metrics = metrics.clone();
+        let mut publisher = Publisher {
+            events: Some(events),
+            broadcast,
+            held_subscriptions,
+            overflow: options.overflow,
+            dropped: dropped_events.clone(),
         };
 
-        let connection = client.connection.clone();
-
-        tokio::spawn(async move {
+        let reader = tokio::spawn(async move {
             let mut read = BufReader::new(read);
 
             let mut buf = String::new();
             loop {
-                buf.clear();
-                match read.read_line(&mut buf).await {
-                    Ok(0) => break,
-                    Err(e) => {
-                        dbg!(e);
-                        break
-                    },
-                    _ => {}
-                }
-                let obj = serde_json::from_str::<ServerMessage>(&buf);
-
-                match obj {
-                    Ok(obj) => match obj {
-                        ServerMessage::ServerEvent(event) => {
-                            events
-                                .send(event)
-                                .await
-                                .expect("Sending ServerEvent to channel");
-                        }
-                        ServerMessage::JsonRpcResponse(rpc) => {
-                            let mut lock = connection.lock().await;
-                            if let Some(pr) = lock.pending_requests.remove(&rpc.id) {
-                                pr.send(rpc).ok();
+                loop {
+                    buf.clear();
+                    match read.read_line(&mut buf).await {
+                        Ok(0) => break,
+                        Err(e) => {
+                            tracing::warn!("error reading from phd2: {}", e);
+                            break
+                        },
+                        _ => {}
+                    }
+                    if let Some(inspect) = &inspect_read {
+                        inspect(buf.trim_end());
+                    }
+                    let obj = serde_json::from_str::<ServerMessage>(&buf);
+
+                    match obj {
+                        Ok(obj) => match obj {
+                            ServerMessage::ServerEvent(event) => {
+                                read_metrics.events.fetch_add(1, Ordering::Relaxed);
+                                if let Event::Version(version) = &event.event {
+                                    *read_version.lock().unwrap() = Some(version.clone());
+                                }
+                                publisher.publish(event).await
                             }
+                            ServerMessage::JsonRpcResponse(rpc) => {
+                                let mut lock = connection.lock().await;
+                                if let Some(pr) = lock.pending_requests.remove(&rpc.id) {
+                                    pr.send(rpc).ok();
+                                }
+                            }
+                        },
+                        Err(e) => {
+                            tracing::warn!(
+                                "couldn't parse message from phd2 {:?}: {}",
+                                buf.trim_end(),
+                                e
+                            );
                         }
-                    },
-                    Err(e) => {
-                        dbg!(&buf);
-                        dbg!(e);
                     }
                 }
+                {
+                    // Dropping the senders fails the calls waiting on them.
+                    let mut lock = connection.lock().await;
+                    lock.closed = true;
+                    lock.pending_requests.clear();
+                }
+                publisher
+                    .publish(connection_event(Event::Disconnected(Disconnected {})))
+                    .await;
+
+                let stream = match &mut connect {
+                    Some(connect) => builder::reconnect(reconnect_policy, connect).await,
+                    None => None,
+                };
+                let Some(stream) = stream else { break };
+                let (stream_read, write) = tokio::io::split(stream);
+                read = BufReader::new(stream_read);
+                {
+                    let mut lock = connection.lock().await;
+                    lock.write = write;
+                    lock.closed = false;
+                }
+                publisher
+                    .publish(connection_event(Event::Connected(Connected {})))
+                    .await;
             }
         });
 
+        let client = Phd2Connection {
+            connection: client_connection,
+            last_id: std::sync::atomic::AtomicU64::new(0),
+            timeout: options.timeout,
+            method_timeouts: options.method_timeouts,
+            inspect_write: options.inspect_write,
+            subscriptions,
+            dropped_events,
+            metrics,
+            server_version,
+            reader,
+        };
         (client, recv)
     }
 }
 
+/// Wraps an event generated by the client rather than phd2.  These have no host or instance.
+fn connection_event(event: Event) -> ServerEvent {
+    let now = std::time::SystemTime::now();
+    ServerEvent {
+        timestamp: now
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64(),
+        host: String::new(),
+        inst: 0,
+        received: now,
+        event,
+    }
+}
+
+/// A snapshot of the traffic on a connection, from [stats](Phd2Connection::stats).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionStats {
+    /// Rpc calls sent that haven't been answered or given up on yet.
+    pub requests_in_flight: u64,
+    /// Rpc calls phd2 has answered, including with an error.
+    pub requests_completed: u64,
+    /// Average time phd2 took to answer a call, `None` until the first answer.
+    pub average_round_trip: Option<Duration>,
+    /// Events received from phd2.
+    pub events_received: u64,
+    /// Average number of events received per second since the connection was opened.
+    /// Compare [events_received](ConnectionStats::events_received) between snapshots for
+    /// a recent rate.
+    pub events_per_second: f64,
+}
+
+/// Counters behind [ConnectionStats], shared with the reader task.
+struct Metrics {
+    opened: std::time::Instant,
+    in_flight: std::sync::atomic::AtomicU64,
+    completed: std::sync::atomic::AtomicU64,
+    round_trip_micros: std::sync::atomic::AtomicU64,
+    events: std::sync::atomic::AtomicU64,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Metrics {
+            opened: std::time::Instant::now(),
+            in_flight: Default::default(),
+            completed: Default::default(),
+            round_trip_micros: Default::default(),
+            events: Default::default(),
+        }
+    }
+
+    fn responded(&self, round_trip: Duration) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        self.round_trip_micros
+            .fetch_add(round_trip.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> ConnectionStats {
+        let completed = self.completed.load(Ordering::Relaxed);
+        let events = self.events.load(Ordering::Relaxed);
+        ConnectionStats {
+            requests_in_flight: self.in_flight.load(Ordering::Relaxed),
+            requests_completed: completed,
+            average_round_trip: (completed > 0).then(|| {
+                Duration::from_micros(self.round_trip_micros.load(Ordering::Relaxed) / completed)
+            }),
+            events_received: events,
+            events_per_second: events as f64 / self.opened.elapsed().as_secs_f64(),
+        }
+    }
+}
+
+/// Counts an rpc call as in flight until dropped, so calls that time out or are cancelled
+/// stop being counted too.
+struct InFlight<'a>(&'a std::sync::atomic::AtomicU64);
+
+impl<'a> InFlight<'a> {
+    fn new(in_flight: &'a std::sync::atomic::AtomicU64) -> Self {
+        in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(in_flight)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Sends events read from phd2 to the event receiver and subscriptions.
+struct Publisher {
+    /// Taken when [OverflowPolicy::Close] ends the receiver.
+    events: Option<tokio::sync::mpsc::Sender<ServerEvent>>,
+    broadcast: tokio::sync::broadcast::Sender<Arc<ServerEvent>>,
+    held_subscriptions: std::sync::Weak<tokio::sync::broadcast::Receiver<Arc<ServerEvent>>>,
+    overflow: OverflowPolicy,
+    dropped: Arc<std::sync::atomic::AtomicU64>,
+}
+
+impl Publisher {
+    async fn publish(&mut self, event: ServerEvent) {
+        // The connection holds a receiver while it's alive, only copy the
+        // event when someone has subscribed.
+        if self.broadcast.receiver_count() > self.held_subscriptions.strong_count() {
+            self.broadcast.send(Arc::new(event.clone())).ok();
+        }
+        let Some(events) = &self.events else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        let event = match events.try_send(event) {
+            Err(tokio::sync::mpsc::error::TrySendError::Full(event)) => event,
+            _ => return,
+        };
+        match &self.overflow {
+            OverflowPolicy::DropNewest => {}
+            OverflowPolicy::DropEventTypes(names) => {
+                if !names.iter().any(|name| name == event.event.name()) {
+                    events.send(event).await.ok();
+                    return;
+                }
+            }
+            OverflowPolicy::Wait => {
+                events.send(event).await.ok();
+                return;
+            }
+            OverflowPolicy::Close => self.events = None,
+        }
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 struct Connection<T> {
     pending_requests: HashMap<u64, tokio::sync::oneshot::Sender<JsonRpcResponse>>,
+    /// Set once the reader stops, no more responses will arrive.
+    closed: bool,
     write: tokio::io::WriteHalf<T>,
 }
 
@@ -158,22 +526,299 @@ pub struct Phd2Connection<T> {
     connection: Arc<tokio::sync::Mutex<Connection<T>>>,
 
     last_id: std::sync::atomic::AtomicU64,
+
+    timeout: Duration,
+    method_timeouts: HashMap<String, Duration>,
+
+    inspect_write: Option<builder::Inspect>,
+
+    // Kept so new subscriptions can be created, the channel closes when the reader task ends.
+    subscriptions: Arc<tokio::sync::broadcast::Receiver<Arc<ServerEvent>>>,
+
+    dropped_events: Arc<std::sync::atomic::AtomicU64>,
+
+    metrics: Arc<Metrics>,
+
+    /// From the [Version] event phd2 sends when a connection opens.
+    server_version: Arc<std::sync::Mutex<Option<Version>>>,
+
+    reader: tokio::task::JoinHandle<()>,
+}
+
+impl<T> Drop for Phd2Connection<T> {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
 }
 
 impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
+    /// Sets how long to wait for phd2 to respond to an rpc call before returning
+    /// [ClientError::Timeout].  Defaults to [DEFAULT_RPC_TIMEOUT].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the timeout for a single rpc `method` (such as `"guide"` or `"find_star"`),
+    /// overriding the connection's default timeout for calls to that method.
+    pub fn with_method_timeout(mut self, method: impl Into<String>, timeout: Duration) -> Self {
+        self.method_timeouts.insert(method.into(), timeout);
+        self
+    }
+
+    /// Returns the timeout that will be used for calls to the rpc `method`.
+    pub fn timeout_for(&self, method: &str) -> Duration {
+        self.method_timeouts
+            .get(method)
+            .copied()
+            .unwrap_or(self.timeout)
+    }
+
+    /// Returns the version of phd2 from the [Version] event it sends when the connection
+    /// opens, or `None` if it hasn't been received yet.
+    pub fn server_version(&self) -> Option<Version> {
+        self.server_version.lock().unwrap().clone()
+    }
+
+    /// Fails with [ClientError::UnsupportedByServer] if phd2 is known to be too old for
+    /// `method`.  Calls are allowed until phd2 has sent its version.
+    fn check_supported(&self, method: &str) -> Result<(), ClientError> {
+        let Some((_, required)) = METHOD_MIN_VERSIONS.iter().find(|(name, _)| *name == method)
+        else {
+            return Ok(());
+        };
+        match &*self.server_version.lock().unwrap() {
+            Some(version) if !version.is_at_least(required) => {
+                Err(ClientError::UnsupportedByServer {
+                    method: String::from(method),
+                    required,
+                    version: version.phd_version.clone(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns counts of the rpc calls and events on this connection, for reporting how
+    /// well it's doing.
+    pub fn stats(&self) -> ConnectionStats {
+        self.metrics.snapshot()
+    }
+
+    /// Returns how many events the event receiver returned with this connection has missed
+    /// because it was full, see [OverflowPolicy].
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events.load(Ordering::Relaxed)
+    }
+
+    /// Returns a receiver for every event sent after it is created.  Each call returns a new
+    /// receiver, so events can be read by several tasks at once.  A receiver that falls more
+    /// than [EVENT_BUFFER_SIZE] events behind returns
+    /// [Lagged](tokio::sync::broadcast::error::RecvError::Lagged) and skips the oldest events.
+    /// # Example
+    /// ```no_run
+    /// use phd2::{serialization::Event, Phd2Connection};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+    ///         tokio::net::TcpStream::connect("localhost:4400")
+    ///             .await
+    ///             .expect("Connecting to phd2"),
+    ///     );
+    ///     let mut alerts = phd2.subscribe();
+    ///     tokio::spawn(async move {
+    ///         while let Ok(event) = alerts.recv().await {
+    ///             if let Event::Alert(alert) = &event.event {
+    ///                 println!("phd2: {}", alert.msg);
+    ///             }
+    ///         }
+    ///     });
+    ///
+    ///     let mut events = phd2.subscribe();
+    ///     while let Ok(event) = events.recv().await {
+    ///         println!("{:?}", event.event);
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<Arc<ServerEvent>> {
+        self.subscriptions.resubscribe()
+    }
+
+    /// Returns a subscription that only receives events of type `E`, such as
+    /// [GuideStep](serialization::GuideStep) or [SettleDone](serialization::SettleDone).
+    /// Only events sent after the subscription is created are received.
+    /// # Example
+    /// ```no_run
+    /// use phd2::{serialization::GuideStep, Phd2Connection};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+    ///         tokio::net::TcpStream::connect("localhost:4400")
+    ///             .await
+    ///             .expect("Connecting to phd2"),
+    ///     );
+    ///     let mut guide_steps = phd2.subscribe_to::<GuideStep>();
+    ///     while let Ok(Some(step)) = guide_steps.recv().await {
+    ///         println!("guide error: {:2.2}", (step.dx.powi(2) + step.dy.powi(2)).sqrt());
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe_to<E: FromEvent>(&self) -> TypedSubscription<E> {
+        TypedSubscription::new(self.subscriptions.resubscribe())
+    }
+
+    /// Returns a subscription that only receives the events `filter` returns `true` for.
+    /// Only events sent after the subscription is created are received.
+    pub fn subscribe_filtered<F: FnMut(&Event) -> bool>(
+        &self,
+        filter: F,
+    ) -> FilteredSubscription<F> {
+        FilteredSubscription::new(self.subscriptions.resubscribe(), filter)
+    }
+
+    /// Returns a [Stream](tokio_stream::Stream) of every event sent after it is created.
+    /// # Example
+    /// ```no_run
+    /// use phd2::{serialization::Event, Phd2Connection};
+    /// use std::time::Duration;
+    /// use tokio_stream::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+    ///         tokio::net::TcpStream::connect("localhost:4400")
+    ///             .await
+    ///             .expect("Connecting to phd2"),
+    ///     );
+    ///     let guide_steps = phd2
+    ///         .event_stream()
+    ///         .filter_map(|event| match &event.event {
+    ///             Event::GuideStep(step) => Some(step.frame),
+    ///             _ => None,
+    ///         })
+    ///         .timeout(Duration::from_secs(10));
+    ///     tokio::pin!(guide_steps);
+    ///     while let Some(Ok(frame)) = guide_steps.next().await {
+    ///         println!("guided frame {}", frame);
+    ///     }
+    /// }
+    /// ```
+    pub fn event_stream(&self) -> EventStream {
+        EventStream::new(self.subscriptions.resubscribe())
+    }
+
+    /// Calls the rpc `method` with `params` and returns the `result` of the response, for
+    /// methods this crate doesn't have a wrapper for yet.  Uses the timeout set for `method`,
+    /// see [timeout_for](Phd2Connection::timeout_for).
+    /// # Example
+    /// ```no_run
+    /// use phd2::Phd2Connection;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+    ///         tokio::net::TcpStream::connect("localhost:4400")
+    ///             .await
+    ///             .expect("Connecting to phd2"),
+    ///     );
+    ///     let algo = phd2
+    ///         .call_raw("get_algo_param", json!({"axis": "ra", "name": "MinMove"}))
+    ///         .await
+    ///         .expect("Getting min move");
+    ///     println!("ra min move: {}", algo);
+    /// }
+    /// ```
+    pub async fn call_raw(
+        &self,
+        method: impl Into<String>,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, ClientError> {
+        self.call(JsonRpcRequest {
+            id: self.next_id(),
+            method: method.into(),
+            params,
+        })
+        .await
+    }
+
+    /// Calls the rpc `method` with `params`, waiting up to `timeout` for phd2 to respond
+    /// instead of the connection's configured timeout.  Returns the `result` of the response,
+    /// or [ClientError::UnsupportedByServer] like other calls if phd2 is too old for `method`.
+    /// # Example
+    /// ```no_run
+    /// use phd2::Phd2Connection;
+    /// use serde_json::json;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+    ///         tokio::net::TcpStream::connect("localhost:4400")
+    ///             .await
+    ///             .expect("Connecting to phd2"),
+    ///     );
+    ///     // Searching for a star can take a while on slow machines.
+    ///     let star = phd2
+    ///         .call_with_timeout("find_star", json!({}), Duration::from_secs(30))
+    ///         .await
+    ///         .expect("Finding star");
+    /// }
+    /// ```
+    pub async fn call_with_timeout(
+        &self,
+        method: impl Into<String>,
+        params: serde_json::Value,
+        timeout: Duration,
+    ) -> Result<serde_json::Value, ClientError> {
+        let request = JsonRpcRequest {
+            id: self.next_id(),
+            method: method.into(),
+            params,
+        };
+        self.check_supported(&request.method)?;
+        self.send_request(request, timeout).await
+    }
+
     async fn call(&self, request: JsonRpcRequest) -> Result<serde_json::Value, ClientError> {
-        Ok(tokio::time::timeout(Duration::from_secs(1), async move {
+        self.check_supported(&request.method)?;
+        let timeout = self.timeout_for(&request.method);
+        self.send_request(request, timeout).await
+    }
+
+    async fn send_request(
+        &self,
+        request: JsonRpcRequest,
+        timeout: Duration,
+    ) -> Result<serde_json::Value, ClientError> {
+        let _in_flight = InFlight::new(&self.metrics.in_flight);
+        Ok(tokio::time::timeout(timeout, async move {
             let (tx, rx) = tokio::sync::oneshot::channel();
+            let sent;
             {
                 let mut sender = self.connection.lock().await;
+                if sender.closed {
+                    return Err(ClientError::Disconnected);
+                }
+                let line = serde_json::to_string(&request)?;
+                if let Some(inspect) = &self.inspect_write {
+                    inspect(&line);
+                }
                 sender.pending_requests.insert(request.id, tx);
-                sender.write.write(&serde_json::to_vec(&request)?).await?;
+                sender.write.write(line.as_bytes()).await?;
                 sender.write.write(b"\n").await?;
+                sent = std::time::Instant::now();
             }
-            let resp = rx.await.unwrap();
+            let resp = rx.await.map_err(|_| ClientError::Disconnected)?;
+            self.metrics.responded(sent.elapsed());
 
             if let Some(e) = resp.error {
-                return Err(ClientError::RpcError(e));
+                return Err(match serde_json::from_value::<RpcError>(e.clone()) {
+                    Ok(e) => ClientError::RpcError(e),
+                    Err(_) => ClientError::RpcUnexpectedResponse(e),
+                });
             }
             match resp.result {
                 Some(result) => Ok(result),
@@ -183,10 +828,70 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
         .await??)
     }
 
+    /// How long to wait for the result of `settle`.
+    fn settle_timeout(&self, settle: Settle) -> Duration {
+        // phd2 gives up on settling after settle.timeout, leave time for it to say so.
+        Duration::from(settle.timeout) + self.timeout
+    }
+
     fn next_id(&self) -> u64 {
         self.last_id.fetch_add(1, Ordering::SeqCst)
     }
 
+    /// Checks that phd2 is still responding by calling
+    /// [get_app_state](Phd2Connection::get_app_state) every `interval`.  Runs until a check
+    /// times out or the connection fails, then returns [ClientError::ConnectionLost].  phd2
+    /// can stop responding without closing the socket, which otherwise goes unnoticed until
+    /// the next call.
+    /// # Example
+    /// ```no_run
+    /// use phd2::Phd2Connection;
+    /// use std::{sync::Arc, time::Duration};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+    ///         tokio::net::TcpStream::connect("localhost:4400")
+    ///             .await
+    ///             .expect("Connecting to phd2"),
+    ///     );
+    ///     let phd2 = Arc::new(phd2);
+    ///     let heartbeat = tokio::spawn({
+    ///         let phd2 = phd2.clone();
+    ///         async move { phd2.heartbeat(Duration::from_secs(5)).await }
+    ///     });
+    ///     let lost = heartbeat.await.unwrap();
+    ///     println!("{}", lost);
+    /// }
+    /// ```
+    pub async fn heartbeat(&self, interval: Duration) -> ClientError {
+        let mut checks = tokio::time::interval(interval);
+        checks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            checks.tick().await;
+            // Any answer from phd2, even an error, means it's still there.
+            if let Err(
+                e @ (ClientError::Timeout(_) | ClientError::IoError(_) | ClientError::Disconnected),
+            ) =
+                self.get_app_state().await
+            {
+                return ClientError::ConnectionLost(Box::new(e));
+            }
+        }
+    }
+
+    /// Stops reading from phd2 and closes the connection.  Pending rpc calls fail with
+    /// [ClientError::Disconnected], as do calls made afterwards.  The event receiver and
+    /// subscriptions end without an [Event::Disconnected].
+    pub async fn close(&self) -> std::io::Result<()> {
+        self.reader.abort();
+        let mut lock = self.connection.lock().await;
+        lock.closed = true;
+        lock.pending_requests.clear();
+        lock.write.flush().await?;
+        lock.write.shutdown().await
+    }
+
     pub async fn disconnect(self) -> std::io::Result<()> {
         let mut lock = self.connection.lock().await;
         lock.write.shutdown().await
@@ -212,6 +917,15 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
         Ok(serde_json::from_value(result)?)
     }
 
+    /// Compares phd2's current equipment with `expected`, returning how it differs.  An
+    /// empty list means phd2 has the expected equipment connected.
+    pub async fn check_equipment(
+        &self,
+        expected: &equipment::ExpectedEquipment,
+    ) -> Result<Vec<equipment::EquipmentMismatch>, ClientError> {
+        Ok(expected.diff(&self.get_current_equipment().await?))
+    }
+
     pub async fn clear_calibration(
         &self,
         target: ClearCalibrationParam,
@@ -229,19 +943,86 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
         Ok(serde_json::from_value(result)?)
     }
 
+    /// Dithers the lock position by up to `amount` pixels.  Returns once phd2 has started,
+    /// await the [SettleHandle] to wait for it to settle.
     pub async fn dither(
         &self,
         amount: f64,
         ra_only: bool,
         settle: Settle,
-    ) -> Result<isize, ClientError> {
+    ) -> Result<SettleHandle, ClientError> {
+        // Subscribe first so a quick settle isn't missed.
+        let settle_done = self.subscribe_to::<SettleDone>();
+        let progress = self.event_stream();
+        let id = self.next_id();
+
+        self.call(JsonRpcRequest {
+            id,
+            method: String::from("dither"),
+            params: json!({"amount": amount, "raOnly": ra_only, "settle": settle}),
+        })
+        .await?;
+
+        Ok(SettleHandle::dither(
+            settle_done,
+            progress,
+            self.settle_timeout(settle),
+        ))
+    }
+
+    /// Dithers like [dither](Phd2Connection::dither), then waits for phd2 to settle.  Returns
+    /// [ClientError::SettleFailed] if phd2 reports it didn't settle within `settle.timeout`,
+    /// or [ClientError::Timeout] if phd2 hasn't reported either way within `settle.timeout`
+    /// plus the rpc timeout.
+    pub async fn dither_and_settle(
+        &self,
+        amount: f64,
+        ra_only: bool,
+        settle: Settle,
+    ) -> Result<SettleDone, ClientError> {
+        self.dither(amount, ra_only, settle).await?.await
+    }
+
+    /// Selects the equipment profile called `name` and connects its equipment, unless it's
+    /// already selected and connected.  Capture is stopped first since phd2 can't change
+    /// profiles while capturing.  Connecting is tried up to [CONNECT_ATTEMPTS] times, since
+    /// equipment that was just disconnected isn't always ready straight away.  Returns
+    /// [ClientError::ProfileNotFound] if there's no profile called `name`.
+    pub async fn ensure_profile_connected(&self, name: &str) -> Result<Profile, ClientError> {
+        let profile = self
+            .get_profiles()
+            .await?
+            .into_iter()
+            .find(|profile| profile.name == name)
+            .ok_or_else(|| ClientError::ProfileNotFound(String::from(name)))?;
+        if self.get_profile().await?.id == profile.id && self.get_connected().await? {
+            return Ok(profile);
+        }
+
+        self.stop_capture().await?;
+        self.set_connected(false).await?;
+        self.set_profile(profile.id).await?;
+
+        let mut attempt = 1;
+        while let Err(e) = self.set_connected(true).await {
+            if attempt == CONNECT_ATTEMPTS {
+                return Err(e);
+            }
+            attempt += 1;
+            tokio::time::sleep(CONNECT_RETRY_DELAY).await;
+        }
+        Ok(profile)
+    }
+
+    /// Has phd2 export all of its settings to a file on the machine phd2 is running on.
+    pub async fn export_config_settings(&self) -> Result<ExportedConfigSettings, ClientError> {
         let id = self.next_id();
 
         let result = self
             .call(JsonRpcRequest {
                 id,
-                method: String::from("dither"),
-                params: json!({"amount": amount, "raOnly": ra_only, "settle": settle}),
+                method: String::from("export_config_settings"),
+                params: json!([]),
             })
             .await?;
 
@@ -568,6 +1349,20 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
         Ok(serde_json::from_value(result)?)
     }
 
+    /// Returns true if phd2 is currently settling after a `guide` or `dither` command.
+    pub async fn get_settling(&self) -> Result<bool, ClientError> {
+        let id = self.next_id();
+        let result = self
+            .call(JsonRpcRequest {
+                id,
+                method: String::from("get_settling"),
+                params: json!([]),
+            })
+            .await?;
+
+        Ok(serde_json::from_value(result)?)
+    }
+
     pub async fn get_ccd_temperature(&self) -> Result<HashMap<String, f64>, ClientError> {
         let id = self.next_id();
         let result = self
@@ -581,21 +1376,53 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
         Ok(serde_json::from_value(result)?)
     }
 
-    /// Phd2 simulator is giving me an invalid string for the pixels causing a parse error for the response.
-    /// PR to resolve this issue: https://github.com/OpenPHDGuiding/phd2/pull/1076
-    pub async fn get_star_image(&self) -> Result<StarImage, ClientError> {
+    /// Returns an image of the guide star.  `size` is the width and height of the image
+    /// in pixels and must be at least [MIN_STAR_IMAGE_SIZE], if it is `None` phd2 picks the size.
+    pub async fn get_star_image(&self, size: Option<u32>) -> Result<StarImage, ClientError> {
+        let mut params = json!({});
+        if let Some(size) = size {
+            if size < MIN_STAR_IMAGE_SIZE {
+                return Err(ClientError::InvalidArgument(format!(
+                    "star image size must be at least {}, got {}",
+                    MIN_STAR_IMAGE_SIZE, size
+                )));
+            }
+            params["size"] = json!(size);
+        }
         let id = self.next_id();
         let result = self
             .call(JsonRpcRequest {
                 id,
                 method: String::from("get_star_image"),
-                params: json!([]),
+                params,
             })
             .await?;
 
         Ok(serde_json::from_value(result)?)
     }
 
+    /// Gets the app state, equipment and calibration together for dashboards that show them
+    /// side by side.  The calls are made concurrently, so the results describe the same
+    /// moment as closely as possible.
+    pub async fn get_status_snapshot(&self) -> Result<Phd2Status, ClientError> {
+        let (app_state, connected, calibrated, paused, pixel_scale, equipment) = tokio::try_join!(
+            self.get_app_state(),
+            self.get_connected(),
+            self.get_calibrated(),
+            self.get_paused(),
+            self.get_pixel_scale(),
+            self.get_current_equipment(),
+        )?;
+        Ok(Phd2Status {
+            app_state,
+            connected,
+            calibrated,
+            paused,
+            pixel_scale,
+            equipment,
+        })
+    }
+
     pub async fn get_use_subframes(&self) -> Result<bool, ClientError> {
         let id = self.next_id();
         let result = self
@@ -609,12 +1436,36 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
         Ok(serde_json::from_value(result)?)
     }
 
+    pub async fn get_variable_delay_settings(&self) -> Result<VariableDelaySettings, ClientError> {
+        let id = self.next_id();
+        let result = self
+            .call(JsonRpcRequest {
+                id,
+                method: String::from("get_variable_delay_settings"),
+                params: json!([]),
+            })
+            .await?;
+
+        Ok(serde_json::from_value(result)?)
+    }
+
+    /// Starts guiding, calibrating first if needed.  Returns once phd2 has started, await the
+    /// [SettleHandle] to wait for it to settle.
     pub async fn guide(
         &self,
         settle: Settle,
         recalibrate: Option<bool>,
         roi: Option<[usize; 4]>,
-    ) -> Result<isize, ClientError> {
+    ) -> Result<SettleHandle, ClientError> {
+        // Subscribe first so a quick settle isn't missed.
+        let settle_done = self.subscribe_to::<SettleDone>();
+        let started = self.subscribe_filtered(|event| {
+            matches!(
+                event,
+                Event::SettleBegin(_) | Event::SettleDone(_) | Event::CalibrationFailed(_)
+            )
+        });
+        let progress = self.event_stream();
         let id = self.next_id();
         let mut params = json!({ "settle": settle });
         if let Some(recalibrate) = recalibrate {
@@ -623,15 +1474,34 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
         if let Some(roi) = roi {
             params["roi"] = serde_json::to_value(roi).unwrap();
         }
-        let result = self
-            .call(JsonRpcRequest {
-                id,
-                method: String::from("guide"),
-                params,
-            })
-            .await?;
+        self.call(JsonRpcRequest {
+            id,
+            method: String::from("guide"),
+            params,
+        })
+        .await?;
+
+        Ok(SettleHandle::guide(
+            started,
+            settle_done,
+            progress,
+            self.settle_timeout(settle),
+        ))
+    }
 
-        Ok(serde_json::from_value(result)?)
+    /// Starts guiding like [guide](Phd2Connection::guide), then waits for phd2 to settle.
+    /// Returns [ClientError::CalibrationFailed] if phd2 needed to calibrate and couldn't,
+    /// [ClientError::SettleFailed] if it reports it didn't settle within `settle.timeout`, or
+    /// [ClientError::Timeout] if it hasn't reported either way within `settle.timeout` plus
+    /// the rpc timeout.  Calibrating can take several minutes, only the settle itself is
+    /// limited by `settle.timeout`.
+    pub async fn guide_and_wait(
+        &self,
+        settle: Settle,
+        recalibrate: Option<bool>,
+        roi: Option<[usize; 4]>,
+    ) -> Result<SettleDone, ClientError> {
+        self.guide(settle, recalibrate, roi).await?.await
     }
 
     pub async fn guide_pulse(
@@ -672,7 +1542,9 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
         Ok(serde_json::from_value(result)?)
     }
 
-    pub async fn save_image(&self) -> Result<HashMap<String, String>, ClientError> {
+    /// Saves the current image to a FITS file on the machine phd2 is running on, use
+    /// [SavedImage::take] to read it.
+    pub async fn save_image(&self) -> Result<SavedImage, ClientError> {
         let id = self.next_id();
 
         let result = self
@@ -845,6 +1717,22 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
 
         Ok(serde_json::from_value(result)?)
     }
+    pub async fn set_variable_delay_settings(
+        &self,
+        settings: VariableDelaySettings,
+    ) -> Result<isize, ClientError> {
+        let id = self.next_id();
+
+        let result = self
+            .call(JsonRpcRequest {
+                id,
+                method: String::from("set_variable_delay_settings"),
+                params: json!(settings),
+            })
+            .await?;
+
+        Ok(serde_json::from_value(result)?)
+    }
     pub async fn shutdown(&self) -> Result<isize, ClientError> {
         let id = self.next_id();
 
@@ -858,6 +1746,33 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
 
         Ok(serde_json::from_value(result)?)
     }
+
+    /// Gets phd2 guiding from a stopped state: starts looping exposures, selects a guide star,
+    /// then guides like [guide_and_wait](Phd2Connection::guide_and_wait).  Selecting a star
+    /// is tried up to [FIND_STAR_ATTEMPTS] times while phd2 can't find one, waiting twice as
+    /// long after each failure, since the first exposures may not be ready or may not show a
+    /// star.
+    pub async fn start_guiding_auto(&self, settle: Settle) -> Result<SettleDone, ClientError> {
+        self.loop_().await?;
+
+        let mut delay = FIND_STAR_RETRY_DELAY;
+        let mut attempt = 1;
+        loop {
+            match self.find_star(None).await {
+                Ok(_) => break,
+                // phd2 returns an error when it can't find a star.
+                Err(ClientError::RpcError(_)) if attempt < FIND_STAR_ATTEMPTS => {
+                    attempt += 1;
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.guide_and_wait(settle, None, None).await
+    }
+
     pub async fn stop_capture(&self) -> Result<isize, ClientError> {
         let id = self.next_id();
 
@@ -871,4 +1786,37 @@ impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite> Phd2Connection<T> {
 
         Ok(serde_json::from_value(result)?)
     }
+
+    /// Shifts the lock position to follow a target moving relative to the stars, such as a
+    /// comet, at `rate_ra` and `rate_dec` in `units`.  Lock shift is enabled and read back
+    /// from phd2, returning [ClientError::LockShiftNotApplied] if phd2 reports other
+    /// settings.
+    pub async fn track_moving_target(
+        &self,
+        rate_ra: f64,
+        rate_dec: f64,
+        units: LockShiftUnits,
+    ) -> Result<LockShiftParams, ClientError> {
+        let axes = LockShiftAxes::RaDec;
+        self.set_lock_shift_params([rate_ra, rate_dec], units, axes)
+            .await?;
+        self.set_lock_shift_enabled(true).await?;
+
+        let params = self.get_lock_shift_params().await?;
+        // phd2 stores the rates as floats, allow for rounding.
+        let rate_matches = params
+            .rate
+            .iter()
+            .zip([rate_ra, rate_dec])
+            .all(|(reported, set)| (reported - set).abs() < 1e-6);
+        if params.enabled
+            && rate_matches
+            && params.units == units.as_str()
+            && params.axes == axes.as_str()
+        {
+            Ok(params)
+        } else {
+            Err(ClientError::LockShiftNotApplied(params))
+        }
+    }
 }
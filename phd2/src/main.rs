@@ -28,7 +28,9 @@ async fn main() {
         None,
     )
     .await
-    .expect("guiding");
+    .expect("guiding")
+    .await
+    .expect("settling");
 
     phd2.disconnect().await.expect("disconnecting");
 
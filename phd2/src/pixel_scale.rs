@@ -0,0 +1,120 @@
+//! Converting guide errors from pixels to arcseconds.
+//!
+//! phd2 reports guide errors in pixels of the guide camera.  [PixelScale] keeps the pixel
+//! scale from [get_pixel_scale](Phd2Connection::get_pixel_scale) up to date as the
+//! equipment profile changes, and converts [GuideStep]s to arcseconds with it.
+//! # Example
+//! ```no_run
+//! use phd2::{pixel_scale::PixelScale, serialization::GuideStep, Phd2Connection};
+//! use std::sync::Arc;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+//!         tokio::net::TcpStream::connect("localhost:4400")
+//!             .await
+//!             .expect("Connecting to phd2"),
+//!     );
+//!     let phd2 = Arc::new(phd2);
+//!     let mut guide_steps = phd2.subscribe_to::<GuideStep>();
+//!     let pixel_scale = PixelScale::new(phd2.clone())
+//!         .await
+//!         .expect("Getting pixel scale");
+//!     while let Ok(Some(step)) = guide_steps.recv().await {
+//!         println!("guide event: {:.2} arcsec.", pixel_scale.guide_step(&step).total());
+//!     }
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{
+    serialization::{ConfigurationChange, GuideStep},
+    ClientError, Phd2Connection,
+};
+
+/// The distances of a [GuideStep] in arcseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GuideStepArcsec {
+    pub dx: f64,
+    pub dy: f64,
+    pub ra_distance_raw: f64,
+    pub de_distance_raw: f64,
+    pub ra_distance_guide: f64,
+    pub de_distance_guide: f64,
+}
+
+impl GuideStepArcsec {
+    /// Converts the distances of `step` using `pixel_scale` in arcseconds per pixel.
+    pub fn new(step: &GuideStep, pixel_scale: f64) -> GuideStepArcsec {
+        GuideStepArcsec {
+            dx: step.dx * pixel_scale,
+            dy: step.dy * pixel_scale,
+            ra_distance_raw: step.ra_distance_raw * pixel_scale,
+            de_distance_raw: step.de_distance_raw * pixel_scale,
+            ra_distance_guide: step.ra_distance_guide * pixel_scale,
+            de_distance_guide: step.de_distance_guide * pixel_scale,
+        }
+    }
+
+    /// Distance of the star from the lock position.
+    pub fn total(&self) -> f64 {
+        self.dx.hypot(self.dy)
+    }
+}
+
+/// phd2's pixel scale in arcseconds per pixel, fetched again each time phd2 sends a
+/// [ConfigurationChange].  If fetching it fails the last value is kept.  Updates stop when
+/// the connection to phd2 closes or this is dropped.
+pub struct PixelScale {
+    scale: watch::Receiver<f64>,
+    task: JoinHandle<()>,
+}
+
+impl PixelScale {
+    /// Gets the current pixel scale and starts watching for changes.
+    pub async fn new<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite + 'static>(
+        phd2: Arc<Phd2Connection<T>>,
+    ) -> Result<PixelScale, ClientError> {
+        // Subscribe first so a change while getting the scale isn't missed.
+        let mut changes = phd2.subscribe_to::<ConfigurationChange>();
+        let (sender, scale) = watch::channel(phd2.get_pixel_scale().await?);
+        let task = tokio::spawn(async move {
+            // Missed events may have been changes too, so lagging also refreshes.
+            while !matches!(changes.recv().await, Ok(None)) {
+                if let Ok(scale) = phd2.get_pixel_scale().await {
+                    sender.send_replace(scale);
+                }
+            }
+        });
+        Ok(PixelScale { scale, task })
+    }
+
+    /// Returns the pixel scale in arcseconds per pixel.
+    pub fn get(&self) -> f64 {
+        *self.scale.borrow()
+    }
+
+    /// Returns a receiver that is notified each time the pixel scale is fetched.
+    pub fn subscribe(&self) -> watch::Receiver<f64> {
+        self.scale.clone()
+    }
+
+    /// Converts `pixels` to arcseconds.
+    pub fn arcsec(&self, pixels: f64) -> f64 {
+        pixels * self.get()
+    }
+
+    /// Converts the distances of `step` to arcseconds.
+    pub fn guide_step(&self, step: &GuideStep) -> GuideStepArcsec {
+        GuideStepArcsec::new(step, self.get())
+    }
+}
+
+impl Drop for PixelScale {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,135 @@
+//! Managing connections to several phd2 instances, such as one per rig in a multi-rig setup.
+//!
+//! A [Phd2Pool] holds a [Phd2Connection] for each rig by name.  Rpc calls are made on the
+//! rig's connection from [rig](Phd2Pool::rig), and events from every rig are received from
+//! [subscribe](Phd2Pool::subscribe) tagged with the rig they came from.
+//! # Example
+//! ```no_run
+//! use phd2::{pool::Phd2Pool, Phd2ConnectionBuilder};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut pool = Phd2Pool::new();
+//!     let mut events = pool.subscribe();
+//!     for (rig, port) in [("east", 4400), ("west", 4401)] {
+//!         let (phd2, _events) = Phd2ConnectionBuilder::new()
+//!             .port(port)
+//!             .connect()
+//!             .await
+//!             .expect("Connecting to phd2");
+//!         pool.add(rig, phd2);
+//!     }
+//!
+//!     pool.rig("east").unwrap().loop_().await.expect("Looping");
+//!     while let Ok(event) = events.recv().await {
+//!         println!("{}: {:?}", event.rig, event.event.event);
+//!     }
+//! }
+//! ```
+
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::{sync::broadcast, task::JoinHandle};
+
+use crate::{serialization::ServerEvent, Phd2Connection, EVENT_BUFFER_SIZE};
+
+/// An event from one of the rigs in a [Phd2Pool].
+#[derive(Debug, Clone)]
+pub struct RigEvent {
+    /// Name the rig was added to the pool with.
+    pub rig: Arc<str>,
+    pub event: Arc<ServerEvent>,
+}
+
+/// Forwarding ends on its own when the connection is dropped, so it only needs to be
+/// stopped when the connection is taken out of the pool.
+struct Rig<T> {
+    connection: Phd2Connection<T>,
+    forward: JoinHandle<()>,
+}
+
+/// Connections to several phd2 instances, by rig name.
+pub struct Phd2Pool<T> {
+    rigs: HashMap<String, Rig<T>>,
+    events: broadcast::Sender<RigEvent>,
+}
+
+impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite + 'static> Default for Phd2Pool<T> {
+    fn default() -> Self {
+        Phd2Pool::new()
+    }
+}
+
+impl<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite + 'static> Phd2Pool<T> {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        Phd2Pool {
+            rigs: HashMap::new(),
+            events,
+        }
+    }
+
+    /// Adds `connection` as `rig`, returning the connection it replaces if there was one.
+    pub fn add(
+        &mut self,
+        rig: impl Into<String>,
+        connection: Phd2Connection<T>,
+    ) -> Option<Phd2Connection<T>> {
+        let rig = rig.into();
+        let name: Arc<str> = Arc::from(rig.as_str());
+        let mut events = connection.subscribe();
+        let sender = self.events.clone();
+        let forward = tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        sender
+                            .send(RigEvent {
+                                rig: name.clone(),
+                                event,
+                            })
+                            .ok();
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        self.rigs
+            .insert(
+                rig,
+                Rig {
+                    connection,
+                    forward,
+                },
+            )
+            .map(Rig::into_connection)
+    }
+
+    /// Removes `rig` from the pool, returning its connection.
+    pub fn remove(&mut self, rig: &str) -> Option<Phd2Connection<T>> {
+        self.rigs.remove(rig).map(Rig::into_connection)
+    }
+
+    /// Returns the connection to `rig`.
+    pub fn rig(&self, rig: &str) -> Option<&Phd2Connection<T>> {
+        self.rigs.get(rig).map(|rig| &rig.connection)
+    }
+
+    /// Returns the names of the rigs in the pool.
+    pub fn rigs(&self) -> impl Iterator<Item = &str> {
+        self.rigs.keys().map(String::as_str)
+    }
+
+    /// Returns a receiver for the events sent by every rig after it is created.
+    pub fn subscribe(&self) -> broadcast::Receiver<RigEvent> {
+        self.events.subscribe()
+    }
+}
+
+impl<T> Rig<T> {
+    fn into_connection(self) -> Phd2Connection<T> {
+        self.forward.abort();
+        self.connection
+    }
+}
@@ -0,0 +1,336 @@
+//! Running phd2 as a child process.
+//!
+//! [Phd2Process] spawns phd2, waits for its EventMonitoring server to accept connections and
+//! optionally selects an equipment profile.  [Phd2Supervisor] keeps it running, spawning it
+//! again if it exits on its own.
+//! # Example
+//! ```no_run
+//! use phd2::process::{Phd2Supervisor, ProcessOptions};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let options = ProcessOptions::new().instance(2).profile("Simulator");
+//!     let phd2 = Phd2Supervisor::spawn(options).await.expect("Starting phd2");
+//!     let (connection, _events) = phd2.connect().await.expect("Connecting to phd2");
+//!     println!("{:?}", connection.get_app_state().await);
+//!     drop(connection);
+//!     phd2.shutdown().await.expect("Stopping phd2");
+//! }
+//! ```
+
+use std::{path::PathBuf, process::ExitStatus, time::Duration};
+
+use tokio::{
+    net::TcpStream,
+    process::{Child, Command},
+    sync::{mpsc::Receiver, oneshot, watch},
+    task::JoinHandle,
+};
+
+use crate::{builder::DEFAULT_PORT, serialization::ServerEvent, ClientError, Phd2Connection};
+
+/// How long to wait for phd2 to accept connections after spawning it.
+pub const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long [shutdown](Phd2Process::shutdown) waits for phd2 to exit before killing it.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum ProcessError {
+    IoError(std::io::Error),
+    /// phd2 didn't start accepting connections, or didn't exit, in time.
+    Timeout,
+    /// phd2 exited while starting up, or a supervised phd2 kept exiting.
+    Exited(ExitStatus),
+    /// Selecting the equipment profile failed.
+    ClientError(ClientError),
+}
+
+impl std::fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcessError::IoError(e) => write!(f, "io error: {}", e),
+            ProcessError::Timeout => write!(f, "timed out waiting for phd2"),
+            ProcessError::Exited(status) => write!(f, "phd2 exited: {}", status),
+            ProcessError::ClientError(e) => write!(f, "phd2 error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProcessError::IoError(e) => Some(e),
+            ProcessError::ClientError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ProcessError {
+    fn from(value: std::io::Error) -> Self {
+        ProcessError::IoError(value)
+    }
+}
+impl From<ClientError> for ProcessError {
+    fn from(value: ClientError) -> Self {
+        ProcessError::ClientError(value)
+    }
+}
+
+/// How to run phd2.  Defaults to instance 1 of `phd2` from the `PATH`, leaving the profile
+/// as it was.
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    program: PathBuf,
+    instance: u16,
+    profile: Option<String>,
+    startup_timeout: Duration,
+    max_restarts: Option<u32>,
+    restart_delay: Duration,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        ProcessOptions::new()
+    }
+}
+
+impl ProcessOptions {
+    pub fn new() -> Self {
+        ProcessOptions {
+            program: PathBuf::from("phd2"),
+            instance: 1,
+            profile: None,
+            startup_timeout: STARTUP_TIMEOUT,
+            max_restarts: Some(3),
+            restart_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the phd2 executable to run.
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Sets the instance number phd2 runs as.  phd2 listens on port `4400 + instance - 1`,
+    /// so distinct instances can run side by side.
+    ///
+    /// # Panics
+    /// Panics if `instance` is zero, instance numbers start at 1.
+    pub fn instance(mut self, instance: u16) -> Self {
+        assert!(instance > 0, "phd2 instances start at 1");
+        self.instance = instance;
+        self
+    }
+
+    /// Selects the equipment profile called `name` and connects its equipment once phd2 is
+    /// running, see [ensure_profile_connected](Phd2Connection::ensure_profile_connected).
+    pub fn profile(mut self, name: impl Into<String>) -> Self {
+        self.profile = Some(name.into());
+        self
+    }
+
+    /// Sets how long to wait for phd2 to accept connections, defaults to [STARTUP_TIMEOUT].
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    /// Sets how many times in a row a [Phd2Supervisor] spawns phd2 again after it exits,
+    /// `None` for no limit.  Defaults to 3.
+    pub fn max_restarts(mut self, restarts: Option<u32>) -> Self {
+        self.max_restarts = restarts;
+        self
+    }
+
+    /// Sets how long a [Phd2Supervisor] waits before spawning phd2 again.
+    pub fn restart_delay(mut self, delay: Duration) -> Self {
+        self.restart_delay = delay;
+        self
+    }
+
+    /// Port the instance listens on.
+    pub fn port(&self) -> u16 {
+        DEFAULT_PORT + self.instance - 1
+    }
+}
+
+/// A running phd2.  It's killed if this is dropped, use [shutdown](Phd2Process::shutdown)
+/// to stop it cleanly.
+pub struct Phd2Process {
+    port: u16,
+    child: Child,
+}
+
+impl Phd2Process {
+    /// Spawns phd2 and waits for it to accept connections, then selects the profile if one
+    /// is set.
+    pub async fn spawn(options: &ProcessOptions) -> Result<Phd2Process, ProcessError> {
+        let child = Command::new(&options.program)
+            .arg("-i")
+            .arg(options.instance.to_string())
+            .kill_on_drop(true)
+            .spawn()?;
+        let mut process = Phd2Process {
+            port: options.port(),
+            child,
+        };
+        let stream = process.wait_for_server(options.startup_timeout).await?;
+        if let Some(profile) = &options.profile {
+            let (phd2, _events) = Phd2Connection::from(stream);
+            phd2.ensure_profile_connected(profile).await?;
+        }
+        Ok(process)
+    }
+
+    /// Waits for phd2 to accept a connection, failing early if it exits.
+    async fn wait_for_server(&mut self, timeout: Duration) -> Result<TcpStream, ProcessError> {
+        let port = self.port;
+        let child = &mut self.child;
+        tokio::time::timeout(timeout, async move {
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    return Err(ProcessError::Exited(status));
+                }
+                match TcpStream::connect(("localhost", port)).await {
+                    Ok(stream) => return Ok(stream),
+                    Err(_) => tokio::time::sleep(Duration::from_millis(250)).await,
+                }
+            }
+        })
+        .await
+        .map_err(|_| ProcessError::Timeout)?
+    }
+
+    /// Port phd2 is listening on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Opens a new connection to phd2.
+    pub async fn connect(
+        &self,
+    ) -> std::io::Result<(Phd2Connection<TcpStream>, Receiver<ServerEvent>)> {
+        connect(self.port).await
+    }
+
+    /// Waits for phd2 to exit.
+    pub async fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        self.child.wait().await
+    }
+
+    /// Asks phd2 to shut down and waits up to [SHUTDOWN_TIMEOUT] for it to exit before
+    /// killing it.
+    pub async fn shutdown(mut self) -> Result<ExitStatus, ProcessError> {
+        if let Ok((phd2, _events)) = self.connect().await {
+            phd2.shutdown().await.ok();
+        }
+
+        match tokio::time::timeout(SHUTDOWN_TIMEOUT, self.child.wait()).await {
+            Ok(status) => Ok(status?),
+            Err(_) => {
+                self.child.kill().await?;
+                Err(ProcessError::Timeout)
+            }
+        }
+    }
+}
+
+async fn connect(port: u16) -> std::io::Result<(Phd2Connection<TcpStream>, Receiver<ServerEvent>)> {
+    let stream = TcpStream::connect(("localhost", port)).await?;
+    Ok(Phd2Connection::from(stream))
+}
+
+/// Keeps phd2 running, spawning it again after the restart delay when it exits without
+/// being shut down.  Gives up after the maximum number of restarts in a row fail, each
+/// restart that starts up successfully resets the count.  phd2 is killed if this is
+/// dropped, use [shutdown](Phd2Supervisor::shutdown) to stop it cleanly.
+pub struct Phd2Supervisor {
+    port: u16,
+    restarts: watch::Receiver<u32>,
+    stop: Option<oneshot::Sender<()>>,
+    task: JoinHandle<Result<ExitStatus, ProcessError>>,
+}
+
+impl Phd2Supervisor {
+    /// Spawns phd2 like [Phd2Process::spawn] and starts watching it.
+    pub async fn spawn(options: ProcessOptions) -> Result<Phd2Supervisor, ProcessError> {
+        let mut process = Phd2Process::spawn(&options).await?;
+        let port = process.port();
+        let (stop, mut stopped) = oneshot::channel();
+        let (restart_sender, restarts) = watch::channel(0);
+        let task = tokio::spawn(async move {
+            let mut failures = 0;
+            loop {
+                let status = tokio::select! {
+                    status = process.wait() => status?,
+                    _ = &mut stopped => return process.shutdown().await,
+                };
+                tracing::warn!("phd2 exited with {}, restarting", status);
+                loop {
+                    if options.max_restarts.is_some_and(|max| failures >= max) {
+                        return Err(ProcessError::Exited(status));
+                    }
+                    tokio::time::sleep(options.restart_delay).await;
+                    match Phd2Process::spawn(&options).await {
+                        Ok(restarted) => {
+                            process = restarted;
+                            failures = 0;
+                            restart_sender.send_modify(|restarts| *restarts += 1);
+                            break;
+                        }
+                        Err(e) => {
+                            tracing::warn!("restarting phd2 failed: {}", e);
+                            failures += 1;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(Phd2Supervisor {
+            port,
+            restarts,
+            stop: Some(stop),
+            task,
+        })
+    }
+
+    /// Port phd2 is listening on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Opens a new connection to phd2.  Connections don't survive a restart, use a
+    /// [Phd2ConnectionBuilder](crate::Phd2ConnectionBuilder) with a
+    /// [ReconnectPolicy](crate::ReconnectPolicy) to follow phd2 across restarts.
+    pub async fn connect(
+        &self,
+    ) -> std::io::Result<(Phd2Connection<TcpStream>, Receiver<ServerEvent>)> {
+        connect(self.port).await
+    }
+
+    /// Returns a receiver for the number of times phd2 has been restarted.
+    pub fn restarts(&self) -> watch::Receiver<u32> {
+        self.restarts.clone()
+    }
+
+    /// Stops watching phd2 and shuts it down like [Phd2Process::shutdown].  Returns the
+    /// error that ended supervision if phd2 couldn't be kept running.
+    pub async fn shutdown(mut self) -> Result<ExitStatus, ProcessError> {
+        if let Some(stop) = self.stop.take() {
+            stop.send(()).ok();
+        }
+        match (&mut self.task).await {
+            Ok(result) => result,
+            Err(e) => Err(ProcessError::IoError(std::io::Error::other(e))),
+        }
+    }
+}
+
+impl Drop for Phd2Supervisor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,200 @@
+//! Recording and replaying sessions with phd2.
+//!
+//! [Recorder] wraps the transport given to [Phd2Connection](crate::Phd2Connection::from)
+//! and writes every line phd2 sends to a recording, along with the time it was received.
+//! [replay] feeds a recording back through a [Phd2Connection](crate::Phd2Connection) at
+//! real or accelerated speed.  Logs of raw phd2 messages without receive times, such as the
+//! ones written by phd2's event server, can be replayed too, paced by each event's
+//! `Timestamp`.
+//! # Example
+//! ```no_run
+//! use phd2::{recording, Phd2Connection};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let recording = tokio::io::BufReader::new(
+//!         tokio::fs::File::open("session.log")
+//!             .await
+//!             .expect("Opening recording"),
+//!     );
+//!     // Replay ten times faster than it was recorded.
+//!     let (phd2, mut events): (Phd2Connection<_>, _) =
+//!         Phd2Connection::from(recording::replay(recording, 10.0));
+//!     while let Some(event) = events.recv().await {
+//!         println!("{:?}", event.event);
+//!     }
+//! }
+//! ```
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use pin_project::pin_project;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use tokio::{
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf,
+    },
+    sync::mpsc,
+};
+
+/// A line of a recording.
+#[derive(Serialize, Deserialize, Debug)]
+struct Recorded<'a> {
+    /// Seconds since the unix epoch when the message was received.
+    received: f64,
+    #[serde(borrow)]
+    message: &'a RawValue,
+}
+
+/// Wraps a transport to phd2, recording everything read from it.  Lines are written to the
+/// recording by a background task, which stops when the recorder is dropped.
+#[pin_project]
+pub struct Recorder<T> {
+    #[pin]
+    inner: T,
+    partial: Vec<u8>,
+    lines: mpsc::UnboundedSender<(SystemTime, Vec<u8>)>,
+}
+
+impl<T> Recorder<T> {
+    /// Wraps `inner`, writing what is read from it to `recording`.
+    pub fn new<W: AsyncWrite + Send + Unpin + 'static>(inner: T, mut recording: W) -> Self {
+        let (lines, mut recv) = mpsc::unbounded_channel::<(SystemTime, Vec<u8>)>();
+        tokio::spawn(async move {
+            while let Some((received, line)) = recv.recv().await {
+                // Partial lines from a connection closing mid message aren't recorded.
+                let message = match serde_json::from_slice::<&RawValue>(&line) {
+                    Ok(message) => message,
+                    Err(_) => continue,
+                };
+                let received = received
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                let mut recorded = serde_json::to_vec(&Recorded { received, message })
+                    .expect("Serializing recorded message");
+                recorded.push(b'\n');
+                if recording.write_all(&recorded).await.is_err() {
+                    break;
+                }
+            }
+            recording.flush().await.ok();
+        });
+        Recorder {
+            inner,
+            partial: vec![],
+            lines,
+        }
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for Recorder<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.project();
+        let before = buf.filled().len();
+        let result = this.inner.poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            let received = SystemTime::now();
+            for byte in &buf.filled()[before..] {
+                if *byte == b'\n' {
+                    let line = std::mem::take(this.partial);
+                    this.lines.send((received, line)).ok();
+                } else {
+                    this.partial.push(*byte);
+                }
+            }
+        }
+        result
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for Recorder<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
+/// Returns when `line` was received along with the message it holds.  Lines written by a
+/// [Recorder] use the time they were received, raw phd2 events use their `Timestamp`, and
+/// anything else has no time.
+fn line_time(line: &str) -> Option<(f64, &str)> {
+    #[derive(Deserialize)]
+    struct Timestamp {
+        #[serde(rename = "Timestamp")]
+        timestamp: f64,
+    }
+
+    if let Ok(recorded) = serde_json::from_str::<Recorded>(line) {
+        return Some((recorded.received, recorded.message.get()));
+    }
+    serde_json::from_str::<Timestamp>(line)
+        .ok()
+        .map(|timestamp| (timestamp.timestamp, line))
+}
+
+/// Replays `recording`, returning a transport for [Phd2Connection](crate::Phd2Connection::from)
+/// that yields the recorded messages.  `speed` is how many times faster than real time to
+/// replay, use `1.0` for real time and [f64::INFINITY] to replay without waiting.  Anything
+/// written to the transport, such as rpc calls, is discarded.
+///
+/// # Panics
+/// Panics if `speed` isn't greater than zero.
+pub fn replay<R: AsyncBufRead + Send + Unpin + 'static>(recording: R, speed: f64) -> DuplexStream {
+    assert!(speed > 0.0, "replay speed must be greater than zero");
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut discard, mut write) = tokio::io::split(server);
+    tokio::spawn(async move {
+        tokio::io::copy(&mut discard, &mut tokio::io::sink())
+            .await
+            .ok();
+    });
+    tokio::spawn(async move {
+        let mut lines = recording.lines();
+        let mut last_time: Option<f64> = None;
+        while let Ok(Some(line)) = lines.next_line().await {
+            let message = match line_time(&line) {
+                Some((time, message)) => {
+                    if let Some(last_time) = last_time {
+                        let delay = Duration::from_secs_f64((time - last_time).max(0.0) / speed);
+                        tokio::time::sleep(delay).await;
+                    }
+                    last_time = Some(time);
+                    message
+                }
+                None => &line,
+            };
+            if write.write_all(message.as_bytes()).await.is_err()
+                || write.write_all(b"\n").await.is_err()
+            {
+                break;
+            }
+        }
+        write.shutdown().await.ok();
+    });
+    client
+}
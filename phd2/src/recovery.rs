@@ -0,0 +1,238 @@
+//! Recovering from a lost guide star.
+//!
+//! phd2 keeps trying to find the star it was guiding on after sending
+//! [StarLost](crate::serialization::StarLost), which never succeeds if the star drifted out
+//! of its search region or a cloud passed for too long.  [StarLostRecovery] watches for
+//! the star being lost while guiding and, if phd2 hasn't found it again after a delay,
+//! selects a star again and resumes guiding.
+//! # Example
+//! ```no_run
+//! use phd2::{
+//!     recovery::{RecoveryEvent, RecoveryPolicy, StarLostRecovery},
+//!     serialization::Settle,
+//!     Phd2Connection,
+//! };
+//! use std::{sync::Arc, time::Duration};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+//!         tokio::net::TcpStream::connect("localhost:4400")
+//!             .await
+//!             .expect("Connecting to phd2"),
+//!     );
+//!     let settle = Settle::new(1.5, Duration::from_secs(10), Duration::from_secs(60));
+//!     let policy = RecoveryPolicy::new(settle)
+//!         .max_attempts(5)
+//!         .within_roi(200)
+//!         .on_event(|event| {
+//!             if let RecoveryEvent::Failed(e) = event {
+//!                 println!("couldn't resume guiding: {}", e);
+//!             }
+//!         });
+//!     let _recovery = StarLostRecovery::new(Arc::new(phd2), policy);
+//!     tokio::signal::ctrl_c().await.expect("Waiting for ctrl-c");
+//! }
+//! ```
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+use crate::{
+    serialization::{Event, Settle, State},
+    ClientError, GuiderState, Phd2Connection,
+};
+
+/// Default for [max_attempts](RecoveryPolicy::max_attempts).
+pub const RECOVERY_ATTEMPTS: u32 = 3;
+
+/// Default for [retry_delay](RecoveryPolicy::retry_delay).
+pub const RECOVERY_DELAY: Duration = Duration::from_secs(10);
+
+/// Progress of a recovery, passed to the [on_event](RecoveryPolicy::on_event) callback.
+#[derive(Debug)]
+pub enum RecoveryEvent {
+    /// The star was lost while guiding.
+    StarLost,
+    /// Selecting a star and guiding again, starting from attempt 1.
+    Attempt(u32),
+    /// Guiding resumed, either on its own or after `attempts` attempts.
+    Recovered { attempts: u32 },
+    /// Giving up after the last attempt failed with this error.
+    Failed(ClientError),
+}
+
+type Callback = Arc<dyn Fn(&RecoveryEvent) + Send + Sync>;
+
+/// How [StarLostRecovery] resumes guiding.
+#[derive(Clone)]
+pub struct RecoveryPolicy {
+    settle: Settle,
+    max_attempts: u32,
+    retry_delay: Duration,
+    roi_size: Option<usize>,
+    callback: Option<Callback>,
+}
+
+impl RecoveryPolicy {
+    /// Resumes guiding with `settle`, making up to [RECOVERY_ATTEMPTS] attempts
+    /// [RECOVERY_DELAY] apart anywhere in the frame.
+    pub fn new(settle: Settle) -> Self {
+        RecoveryPolicy {
+            settle,
+            max_attempts: RECOVERY_ATTEMPTS,
+            retry_delay: RECOVERY_DELAY,
+            roi_size: None,
+            callback: None,
+        }
+    }
+
+    /// Sets how many times to try to resume guiding before giving up, at least once.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Sets how long to wait before each attempt.  phd2 may find the star again on its own
+    /// during the wait, in which case no attempt is made.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Only looks for a star in a `size` pixel square around the last lock position,
+    /// keeping the target framed the same way.  The whole frame is searched if phd2 hasn't
+    /// sent a lock position yet.
+    pub fn within_roi(mut self, size: usize) -> Self {
+        self.roi_size = Some(size);
+        self
+    }
+
+    /// Calls `callback` as recovery progresses.
+    pub fn on_event<F: Fn(&RecoveryEvent) + Send + Sync + 'static>(mut self, callback: F) -> Self {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    fn notify(&self, event: RecoveryEvent) {
+        if let Some(callback) = &self.callback {
+            callback(&event);
+        }
+    }
+
+    /// The region around `lock` to search for a star in, as phd2's `[x, y, width, height]`.
+    fn roi(&self, lock: Option<[f64; 2]>) -> Option<[usize; 4]> {
+        let (size, [x, y]) = self.roi_size.zip(lock)?;
+        let half = size as f64 / 2.0;
+        Some([
+            (x - half).max(0.0) as usize,
+            (y - half).max(0.0) as usize,
+            size,
+            size,
+        ])
+    }
+}
+
+impl std::fmt::Debug for RecoveryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecoveryPolicy")
+            .field("settle", &self.settle)
+            .field("max_attempts", &self.max_attempts)
+            .field("retry_delay", &self.retry_delay)
+            .field("roi_size", &self.roi_size)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Resumes guiding when phd2 loses the guide star, following a [RecoveryPolicy].  Only
+/// stars lost while guiding are recovered, guiding is noticed from the events phd2 sends
+/// after this is created.  Stops when the connection to phd2 closes or this is dropped.
+pub struct StarLostRecovery {
+    task: JoinHandle<()>,
+}
+
+impl StarLostRecovery {
+    pub fn new<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite + 'static>(
+        phd2: Arc<Phd2Connection<T>>,
+        policy: RecoveryPolicy,
+    ) -> StarLostRecovery {
+        let mut events = phd2.subscribe();
+        let task = tokio::spawn(async move {
+            let mut state = GuiderState::Stopped;
+            let mut lock = None;
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                match &event.event {
+                    Event::LockPositionSet(position) => lock = Some([position.x, position.y]),
+                    Event::StarLost(_)
+                        if matches!(state, GuiderState::Guiding | GuiderState::Settling) =>
+                    {
+                        policy.notify(RecoveryEvent::StarLost);
+                        state = match recover(&phd2, &policy, lock).await {
+                            Ok(attempts) => {
+                                policy.notify(RecoveryEvent::Recovered { attempts });
+                                GuiderState::Guiding
+                            }
+                            Err(e) => {
+                                policy.notify(RecoveryEvent::Failed(e));
+                                GuiderState::Stopped
+                            }
+                        };
+                        // Events sent while recovering are out of date.
+                        events = events.resubscribe();
+                        continue;
+                    }
+                    _ => {}
+                }
+                state = state.next(&event.event);
+            }
+        });
+        StarLostRecovery { task }
+    }
+}
+
+impl Drop for StarLostRecovery {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Tries to resume guiding, returning the number of attempts made.
+async fn recover<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite>(
+    phd2: &Phd2Connection<T>,
+    policy: &RecoveryPolicy,
+    lock: Option<[f64; 2]>,
+) -> Result<u32, ClientError> {
+    let mut attempt = 0;
+    loop {
+        tokio::time::sleep(policy.retry_delay).await;
+        if phd2.get_app_state().await? == State::Guiding {
+            return Ok(attempt);
+        }
+        attempt += 1;
+        policy.notify(RecoveryEvent::Attempt(attempt));
+        match resume(phd2, policy, lock).await {
+            Ok(()) => return Ok(attempt),
+            Err(e) if attempt >= policy.max_attempts => return Err(e),
+            Err(_) => {}
+        }
+    }
+}
+
+/// Selects a star again and guides on it.
+async fn resume<T: Send + tokio::io::AsyncRead + tokio::io::AsyncWrite>(
+    phd2: &Phd2Connection<T>,
+    policy: &RecoveryPolicy,
+    lock: Option<[f64; 2]>,
+) -> Result<(), ClientError> {
+    phd2.stop_capture().await?;
+    phd2.loop_().await?;
+    phd2.find_star(policy.roi(lock)).await?;
+    phd2.guide_and_wait(policy.settle, None, None).await?;
+    Ok(())
+}
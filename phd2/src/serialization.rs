@@ -1,10 +1,15 @@
 use std::time::Duration;
 
-use base64::Engine;
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
 use itertools::Itertools;
+use ndarray::{Array2, ShapeError};
 use serde::{de::Visitor, Deserialize, Serialize, Serializer};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Version {
     // #[serde(flatten)]
     // pub common: Common,
@@ -18,7 +23,27 @@ pub struct Version {
     pub msg_version: u32,
 }
 
-#[derive(Deserialize, Debug)]
+impl Version {
+    /// Whether phd2 is at least `version`, given as dot separated numbers such as `"2.6.10"`.
+    /// The sub version, which marks development builds, is ignored.
+    pub fn is_at_least(&self, version: &str) -> bool {
+        version_numbers(&self.phd_version) >= version_numbers(version)
+    }
+}
+
+/// Parses the numbers of a version such as `"2.6.11"`, ignoring anything after the digits
+/// of each part.
+fn version_numbers(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .map(|part| {
+            let digits = part.len() - part.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            part[..digits].parse().unwrap_or(0)
+        })
+        .collect()
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct LockPositionSet {
     #[serde(alias = "X")]
     pub x: f64,
@@ -26,7 +51,7 @@ pub struct LockPositionSet {
     pub y: f64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Calibrating {
     #[serde(alias = "Mount")]
     pub mount: String,
@@ -39,13 +64,13 @@ pub struct Calibrating {
     pub state: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct CalibrationComplete {
     #[serde(alias = "Mount")]
     pub mount: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct StarSelected {
     #[serde(alias = "X")]
     pub x: f64,
@@ -53,19 +78,19 @@ pub struct StarSelected {
     pub y: f64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct StartGuiding {}
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Paused {}
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct StartCalibration {
     #[serde(alias = "Mount")]
     pub mount: String,
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
 pub enum State {
     Stopped,
     Selected,
@@ -78,6 +103,14 @@ pub enum State {
 #[derive(Debug)]
 pub struct InvalidState(pub String);
 
+impl std::fmt::Display for InvalidState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid app state: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidState {}
+
 impl TryFrom<&str> for State {
     type Error = InvalidState;
 
@@ -94,40 +127,40 @@ impl TryFrom<&str> for State {
         }
     }
 }
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct AppState {
     #[serde(alias = "State")]
     pub state: State,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct CalibrationFailed {
     #[serde(alias = "Reason")]
     pub reason: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct CalibrationDataFlipped {
     #[serde(alias = "Mount")]
     pub mount: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct LockPositionShiftLimitReached {}
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct LoopingExposures {
     #[serde(alias = "Frame")]
     pub frame: u32,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct LoopingExposuresStopped {}
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct SettleBegin {}
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Settling {
     #[serde(alias = "Distance")]
     pub distance: f64,
@@ -139,7 +172,7 @@ pub struct Settling {
     pub star_locked: bool,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct SettleDone {
     #[serde(alias = "Status")]
     pub status: u32,
@@ -151,7 +184,7 @@ pub struct SettleDone {
     pub dropped_frames: u32,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct StarLost {
     #[serde(alias = "Frame")]
     pub frame: u32,
@@ -169,24 +202,24 @@ pub struct StarLost {
     pub status: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct GuidingStopped {}
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Resumed {}
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub enum NorthSouth {
     North,
     South,
 }
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub enum EastWest {
     East,
     West,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct GuideStep {
     #[serde(alias = "Frame")]
     pub frame: u32,
@@ -228,16 +261,16 @@ pub struct GuideStep {
     pub error_code: Option<i32>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct GuidingDithered {
     pub dx: f64,
     pub dy: f64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct LockPositionLost {}
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Alert {
     #[serde(alias = "Msg")]
     pub msg: String,
@@ -245,7 +278,7 @@ pub struct Alert {
     pub msg_type: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct GuideParamChange {
     #[serde(alias = "Name")]
     pub name: String,
@@ -253,10 +286,20 @@ pub struct GuideParamChange {
     pub value: serde_json::Value,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ConfigurationChange {}
 
-#[derive(Deserialize, Debug)]
+/// Not sent by phd2.  Sent by [Phd2Connection](crate::Phd2Connection) when it starts reading
+/// from phd2.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Connected {}
+
+/// Not sent by phd2.  Sent by [Phd2Connection](crate::Phd2Connection) when the connection to
+/// phd2 closes, it's the last event received.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Disconnected {}
+
+#[derive(Deserialize, Debug, Clone)]
 #[serde(tag = "Event")]
 pub enum Event {
     Version(Version),
@@ -285,10 +328,74 @@ pub enum Event {
     Alert(Alert),
     GuideParamChange(GuideParamChange),
     ConfigurationChange(ConfigurationChange),
+    Connected(Connected),
+    Disconnected(Disconnected),
+    /// An event this crate doesn't recognize, or couldn't parse, such as one added in a newer
+    /// version of phd2.  `event` is the name of the event and `raw` has its fields.
+    #[serde(skip_deserializing)]
+    Unknown {
+        event: String,
+        raw: serde_json::Value,
+    },
+}
+
+impl Event {
+    /// The name phd2 gives the event, such as `"GuideStep"`.
+    pub fn name(&self) -> &str {
+        match self {
+            Event::Version(_) => "Version",
+            Event::LockPositionSet(_) => "LockPositionSet",
+            Event::Calibrating(_) => "Calibrating",
+            Event::CalibrationComplete(_) => "CalibrationComplete",
+            Event::StarSelected(_) => "StarSelected",
+            Event::StartGuiding(_) => "StartGuiding",
+            Event::Paused(_) => "Paused",
+            Event::StartCalibration(_) => "StartCalibration",
+            Event::AppState(_) => "AppState",
+            Event::CalibrationFailed(_) => "CalibrationFailed",
+            Event::CalibrationDataFlipped(_) => "CalibrationDataFlipped",
+            Event::LockPositionShiftLimitReached(_) => "LockPositionShiftLimitReached",
+            Event::LoopingExposures(_) => "LoopingExposures",
+            Event::LoopingExposuresStopped(_) => "LoopingExposuresStopped",
+            Event::SettleBegin(_) => "SettleBegin",
+            Event::Settling(_) => "Settling",
+            Event::SettleDone(_) => "SettleDone",
+            Event::StarLost(_) => "StarLost",
+            Event::GuidingStopped(_) => "GuidingStopped",
+            Event::Resumed(_) => "Resumed",
+            Event::GuideStep(_) => "GuideStep",
+            Event::GuidingDithered(_) => "GuidingDithered",
+            Event::LockPositionLost(_) => "LockPositionLost",
+            Event::Alert(_) => "Alert",
+            Event::GuideParamChange(_) => "GuideParamChange",
+            Event::ConfigurationChange(_) => "ConfigurationChange",
+            Event::Connected(_) => "Connected",
+            Event::Disconnected(_) => "Disconnected",
+            Event::Unknown { event, .. } => event,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
+/// Deserializes an [Event], falling back to [Event::Unknown] for events that can't be
+/// parsed so they aren't lost.
+fn deserialize_event<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Event, D::Error> {
+    let raw = serde_json::Value::deserialize(deserializer)?;
+    let event = match raw.get("Event").and_then(|event| event.as_str()) {
+        Some(event) => String::from(event),
+        None => return Err(serde::de::Error::missing_field("Event")),
+    };
+    match Event::deserialize(&raw) {
+        Ok(event) => Ok(event),
+        Err(e) => {
+            tracing::debug!("unrecognized phd2 event {}: {}", event, e);
+            Ok(Event::Unknown { event, raw })
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct ServerEvent {
+    /// When phd2 sent the event by its own clock, in seconds since the unix epoch.
     #[serde(alias = "Timestamp")]
     pub timestamp: f64,
     #[serde(alias = "Host")]
@@ -296,10 +403,28 @@ pub struct ServerEvent {
     #[serde(alias = "Inst")]
     pub inst: u32,
 
-    #[serde(flatten)]
+    /// When the event was received, by the local clock.
+    #[serde(skip_deserializing, default = "std::time::SystemTime::now")]
+    pub received: std::time::SystemTime,
+
+    #[serde(flatten, deserialize_with = "deserialize_event")]
     pub event: Event,
 }
 
+impl ServerEvent {
+    /// Seconds from when phd2 sent the event to when it was received, the sum of the
+    /// latency and how far the local clock is ahead of phd2's.  See
+    /// [ClockEstimator](crate::clock::ClockEstimator) to separate the two.
+    pub fn offset(&self) -> f64 {
+        let received = self
+            .received
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        received - self.timestamp
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct JsonRpcResponse {
     pub jsonrpc: String,
@@ -308,6 +433,21 @@ pub struct JsonRpcResponse {
     pub result: Option<serde_json::Value>,
     pub error: Option<serde_json::Value>,
 }
+/// The `error` member of a [JsonRpcResponse].
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
 pub enum ServerMessage {
@@ -331,6 +471,11 @@ impl From<Duration> for DurationSeconds {
         DurationSeconds(value)
     }
 }
+impl From<DurationSeconds> for Duration {
+    fn from(value: DurationSeconds) -> Self {
+        value.0
+    }
+}
 impl Serialize for DurationSeconds {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -457,6 +602,48 @@ pub struct CalibrationData {
     pub y_parity: Parity,
 }
 
+impl Calibration {
+    /// Rate the guide star moves in RA while calibrating, in arcseconds per second.
+    /// `pixel_scale` is in arcseconds per pixel, see
+    /// [get_pixel_scale](crate::Phd2Connection::get_pixel_scale).
+    pub fn ra_rate_arcsec(&self, pixel_scale: f64) -> Option<f64> {
+        self.data.as_ref().map(|data| data.x_rate * pixel_scale)
+    }
+
+    /// Rate the guide star moves in Dec while calibrating, in arcseconds per second.
+    /// `pixel_scale` is in arcseconds per pixel.
+    pub fn dec_rate_arcsec(&self, pixel_scale: f64) -> Option<f64> {
+        self.data.as_ref().map(|data| data.y_rate * pixel_scale)
+    }
+
+    /// How far from perpendicular the RA and Dec axes are, in degrees.  phd2 warns when this
+    /// is more than about 10 degrees, usually from backlash or a poor polar alignment.
+    pub fn orthogonality_error(&self) -> Option<f64> {
+        self.data.as_ref().map(|data| {
+            let between = (data.y_angle - data.x_angle).rem_euclid(180.0);
+            (between - 90.0).abs()
+        })
+    }
+
+    /// Returns true if a calibration made with the mount on `calibrated_on` needs to be
+    /// flipped to guide with the mount on `current`, such as after a meridian flip.  Returns
+    /// false if there is no calibration or either side isn't known.
+    pub fn flip_expected(&self, calibrated_on: PierSide, current: PierSide) -> bool {
+        self.calibrated
+            && calibrated_on != PierSide::Unknown
+            && current != PierSide::Unknown
+            && calibrated_on != current
+    }
+}
+
+/// Side of the pier a german equatorial mount's counterweights point away from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PierSide {
+    East,
+    West,
+    Unknown,
+}
+
 #[derive(Deserialize, Debug, PartialEq)]
 pub enum Parity {
     #[serde(rename = "+")]
@@ -490,6 +677,53 @@ pub enum DecGuideMode {
     South,
 }
 
+/// Units of a lock shift rate, converts to the names phd2 uses for
+/// [set_lock_shift_params](crate::Phd2Connection::set_lock_shift_params).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockShiftUnits {
+    ArcsecPerHour,
+    PixelsPerHour,
+}
+
+impl LockShiftUnits {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockShiftUnits::ArcsecPerHour => "arcsec/hr",
+            LockShiftUnits::PixelsPerHour => "pixels/hr",
+        }
+    }
+}
+
+impl From<LockShiftUnits> for String {
+    fn from(value: LockShiftUnits) -> Self {
+        String::from(value.as_str())
+    }
+}
+
+/// Axes of a lock shift rate, converts to the names phd2 uses for
+/// [set_lock_shift_params](crate::Phd2Connection::set_lock_shift_params).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockShiftAxes {
+    RaDec,
+    /// The camera's x and y axes.
+    XY,
+}
+
+impl LockShiftAxes {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockShiftAxes::RaDec => "RA/Dec",
+            LockShiftAxes::XY => "X/Y",
+        }
+    }
+}
+
+impl From<LockShiftAxes> for String {
+    fn from(value: LockShiftAxes) -> Self {
+        String::from(value.as_str())
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct LockShiftParams {
     pub axes: String,
@@ -497,15 +731,74 @@ pub struct LockShiftParams {
     pub units: String,
     pub rate: [f64; 2],
 }
+
+/// Result of [export_config_settings](crate::Phd2Connection::export_config_settings),
+/// identifying where phd2 wrote its settings.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct ExportedConfigSettings {
+    /// Path of the exported settings file on the machine phd2 is running on.
+    pub filename: String,
+}
+
+/// Result of [save_image](crate::Phd2Connection::save_image), identifying where phd2 saved
+/// the current image.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct SavedImage {
+    /// Path of the FITS file on the machine phd2 is running on.  phd2 saves it to a temporary
+    /// directory and doesn't delete it.
+    pub filename: std::path::PathBuf,
+}
+
+impl SavedImage {
+    /// Reads the saved FITS file and deletes it.  Only works when phd2 is running on the same
+    /// machine, or the file is otherwise reachable at the same path.
+    pub async fn take(self) -> std::io::Result<Vec<u8>> {
+        let image = tokio::fs::read(&self.filename).await?;
+        tokio::fs::remove_file(&self.filename).await?;
+        Ok(image)
+    }
+}
+
+/// Result of [get_status_snapshot](crate::Phd2Connection::get_status_snapshot), phd2's
+/// state as answered by calls made at the same time.
+#[derive(Debug, PartialEq)]
+pub struct Phd2Status {
+    pub app_state: State,
+    pub connected: bool,
+    pub calibrated: bool,
+    pub paused: bool,
+    /// Arcseconds per pixel.
+    pub pixel_scale: f64,
+    pub equipment: std::collections::HashMap<String, Equipment>,
+}
+
 #[derive(Deserialize, Debug, PartialEq)]
 pub struct Profile {
     pub id: isize,
     pub name: String,
 }
 
+/// Settings for phd2's variable exposure delay, which uses a short delay between
+/// exposures while settling and a long delay once guiding has stabilized.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub struct VariableDelaySettings {
+    #[serde(rename = "Enabled")]
+    pub enabled: bool,
+    #[serde(rename = "ShortDelaySeconds")]
+    pub short_delay_seconds: u32,
+    #[serde(rename = "LongDelaySeconds")]
+    pub long_delay_seconds: u32,
+}
+
 #[derive(Debug)]
 pub struct Base64Image(pub Vec<u16>);
 
+/// phd2 pads the encoded pixels, but older versions are known to send unpadded strings.
+const BASE64_IMAGE_ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
 struct Base64ImageVisitor;
 impl<'de> Visitor<'de> for Base64ImageVisitor {
     type Value = Vec<u16>;
@@ -514,26 +807,27 @@ impl<'de> Visitor<'de> for Base64ImageVisitor {
         formatter.write_str("a base64 encoded string")
     }
 
-    fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
     where
         E: serde::de::Error,
     {
-        match base64::engine::general_purpose::STANDARD_NO_PAD.decode(v) {
-            Ok(bytes) => {
-                let bytes = bytes
-                    .iter()
-                    .tuples()
-                    .map(|(high, low)| {
-                        let high = (*high as u16) << 8;
-                        let low = *low as u16;
-                        high + low
-                    })
-                    .collect();
-                Ok(bytes)
-            }
+        match BASE64_IMAGE_ENGINE.decode(v) {
+            // Pixels are sent as 16 bit little-endian values.
+            Ok(bytes) => Ok(bytes
+                .iter()
+                .tuples()
+                .map(|(low, high)| u16::from_le_bytes([*low, *high]))
+                .collect()),
             Err(e) => Err(serde::de::Error::custom(e.to_string())),
         }
     }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        self.visit_str(&v)
+    }
 }
 impl<'de> Deserialize<'de> for Base64Image {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -554,6 +848,28 @@ pub struct StarImage {
     pub pixels: Base64Image,
 }
 
+impl StarImage {
+    /// Returns the decoded pixel values in row-major order.
+    pub fn pixel_values(&self) -> &[u16] {
+        &self.pixels.0
+    }
+
+    /// Returns the value of the pixel at column `x` and row `y`, or `None` if it is
+    /// outside of the image.
+    pub fn get(&self, x: usize, y: usize) -> Option<u16> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.pixels.0.get(y * self.width + x).copied()
+    }
+
+    /// Returns the image as an array indexed by `[row, column]`.  Fails if phd2 sent a
+    /// different number of pixels than `width * height`.
+    pub fn to_array(&self) -> Result<Array2<u16>, ShapeError> {
+        Array2::from_shape_vec((self.height, self.width), self.pixels.0.clone())
+    }
+}
+
 #[derive(Serialize, Debug, PartialEq)]
 pub enum PulseDirection {
     N,
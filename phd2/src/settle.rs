@@ -0,0 +1,132 @@
+//! Waiting for phd2 to settle after [guide](crate::Phd2Connection::guide) or
+//! [dither](crate::Phd2Connection::dither).
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{
+    serialization::{Event, SettleDone, Settling},
+    subscription::{EventStream, FilteredSubscription, TypedSubscription},
+    ClientError,
+};
+
+type SettleFuture = Pin<Box<dyn Future<Output = Result<SettleDone, ClientError>> + Send>>;
+
+/// Returned by [guide](crate::Phd2Connection::guide) and
+/// [dither](crate::Phd2Connection::dither) once phd2 has accepted the command.  Awaiting it
+/// waits for phd2 to settle, returning [ClientError::SettleFailed] if phd2 reports it didn't
+/// settle within the settle timeout, [ClientError::Timeout] if phd2 hasn't reported either
+/// way by the settle timeout plus the rpc timeout, or [ClientError::CalibrationFailed] if
+/// guiding needed a calibration that failed.  Calibrating can take several minutes, only the
+/// settle itself is limited by the timeout.
+/// # Example
+/// ```no_run
+/// use phd2::{serialization::Settle, Phd2Connection};
+/// use std::time::Duration;
+/// use tokio_stream::StreamExt;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+///         tokio::net::TcpStream::connect("localhost:4400")
+///             .await
+///             .expect("Connecting to phd2"),
+///     );
+///     let settle = Settle::new(1.5, Duration::from_secs(10), Duration::from_secs(60));
+///     let mut settling = phd2.dither(5.0, false, settle).await.expect("Dithering");
+///     let mut progress = settling.progress().expect("Getting progress");
+///     tokio::spawn(async move {
+///         while let Some(progress) = progress.next().await {
+///             println!("{:.2}px, {:.0}s", progress.distance, progress.time);
+///         }
+///     });
+///     settling.await.expect("Settling");
+/// }
+/// ```
+#[must_use = "phd2 settles whether or not the handle is awaited"]
+pub struct SettleHandle {
+    result: SettleFuture,
+    progress: Option<EventStream>,
+}
+
+impl SettleHandle {
+    /// Waits for the [SettleDone] that follows a dither.
+    pub(crate) fn dither(
+        settle_done: TypedSubscription<SettleDone>,
+        progress: EventStream,
+        timeout: Duration,
+    ) -> SettleHandle {
+        SettleHandle {
+            result: Box::pin(wait_for_settle(settle_done, timeout)),
+            progress: Some(progress),
+        }
+    }
+
+    /// Waits for guiding to start, then for the [SettleDone] that follows.
+    pub(crate) fn guide<F: FnMut(&Event) -> bool + Send + 'static>(
+        mut started: FilteredSubscription<F>,
+        settle_done: TypedSubscription<SettleDone>,
+        progress: EventStream,
+        timeout: Duration,
+    ) -> SettleHandle {
+        SettleHandle {
+            result: Box::pin(async move {
+                let event = started.recv().await?.ok_or(ClientError::ConnectionClosed)?;
+                if let Event::CalibrationFailed(failed) = &event.event {
+                    return Err(ClientError::CalibrationFailed(failed.reason.clone()));
+                }
+                wait_for_settle(settle_done, timeout).await
+            }),
+            progress: Some(progress),
+        }
+    }
+
+    /// Returns a stream of the [Settling] events phd2 sends until it is done settling.  The
+    /// stream can only be taken once, later calls return `None`.
+    pub fn progress(&mut self) -> Option<impl Stream<Item = Settling> + Send + Unpin> {
+        self.progress.take().map(|events| {
+            events
+                .map_while(|event| match &event.event {
+                    Event::SettleDone(_) => None,
+                    Event::Settling(settling) => Some(Some(settling.clone())),
+                    _ => Some(None),
+                })
+                .filter_map(|settling| settling)
+        })
+    }
+}
+
+impl Future for SettleHandle {
+    type Output = Result<SettleDone, ClientError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.result.as_mut().poll(cx)
+    }
+}
+
+impl std::fmt::Debug for SettleHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SettleHandle").finish_non_exhaustive()
+    }
+}
+
+/// Waits up to `timeout` for `settle_done`, failing if phd2 didn't settle.
+async fn wait_for_settle(
+    mut settle_done: TypedSubscription<SettleDone>,
+    timeout: Duration,
+) -> Result<SettleDone, ClientError> {
+    let done = tokio::time::timeout(timeout, settle_done.recv())
+        .await??
+        .ok_or(ClientError::ConnectionClosed)?;
+    if done.status == 0 {
+        Ok(done)
+    } else {
+        Err(ClientError::SettleFailed(done))
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+
+use crate::serialization::GuideStep;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    frame: u32,
+    ra: f64,
+    dec: f64,
+    snr: f64,
+}
+
+impl Sample {
+    fn total(&self) -> f64 {
+        (self.ra.powi(2) + self.dec.powi(2)).sqrt()
+    }
+}
+
+/// Guiding statistics over the last `window` [GuideStep] events.  Errors are the raw
+/// RA/Dec distances phd2 reports, in pixels.
+/// # Example
+/// ```no_run
+/// use phd2::{serialization::GuideStep, stats::GuideStats, Phd2Connection};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (phd2, _events): (Phd2Connection<_>, _) = Phd2Connection::from(
+///         tokio::net::TcpStream::connect("localhost:4400")
+///             .await
+///             .expect("Connecting to phd2"),
+///     );
+///     let mut stats = GuideStats::new(30);
+///     let mut guide_steps = phd2.subscribe_to::<GuideStep>();
+///     while let Ok(Some(step)) = guide_steps.recv().await {
+///         stats.add(&step);
+///         println!("rms error: {:.2}px", stats.rms_total().unwrap());
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct GuideStats {
+    window: usize,
+    samples: VecDeque<Sample>,
+}
+
+impl GuideStats {
+    /// Creates an accumulator that keeps the last `window` guide steps.
+    pub fn new(window: usize) -> GuideStats {
+        GuideStats {
+            window: window.max(1),
+            samples: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Adds a guide step, dropping the oldest one if the window is full.  A step with a
+    /// lower frame number than the previous one starts a new guiding session and clears
+    /// the window.
+    pub fn add(&mut self, step: &GuideStep) {
+        if matches!(self.samples.back(), Some(last) if step.frame <= last.frame) {
+            self.samples.clear();
+        }
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            frame: step.frame,
+            ra: step.ra_distance_raw,
+            dec: step.de_distance_raw,
+            snr: step.snr,
+        });
+    }
+
+    /// Removes all guide steps.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Number of guide steps in the window.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// RMS of the RA error.
+    pub fn rms_ra(&self) -> Option<f64> {
+        self.rms(|sample| sample.ra)
+    }
+
+    /// RMS of the Dec error.
+    pub fn rms_dec(&self) -> Option<f64> {
+        self.rms(|sample| sample.dec)
+    }
+
+    /// RMS of the combined RA and Dec error.
+    pub fn rms_total(&self) -> Option<f64> {
+        self.rms(Sample::total)
+    }
+
+    /// Largest combined RA and Dec error.
+    pub fn peak(&self) -> Option<f64> {
+        self.samples.iter().map(Sample::total).reduce(f64::max)
+    }
+
+    /// Change in the guide star's SNR per frame, from a least squares fit over the window.
+    /// A negative trend usually means clouds or dew.  Needs at least two guide steps.
+    pub fn snr_trend(&self) -> Option<f64> {
+        if self.samples.len() < 2 {
+            return None;
+        }
+        let n = self.samples.len() as f64;
+        let mean_frame = self.samples.iter().map(|s| s.frame as f64).sum::<f64>() / n;
+        let mean_snr = self.samples.iter().map(|s| s.snr).sum::<f64>() / n;
+
+        let (covariance, variance) =
+            self.samples
+                .iter()
+                .fold((0.0, 0.0), |(covariance, variance), sample| {
+                    let frame = sample.frame as f64 - mean_frame;
+                    (
+                        covariance + frame * (sample.snr - mean_snr),
+                        variance + frame.powi(2),
+                    )
+                });
+        Some(covariance / variance)
+    }
+
+    /// Number of frames within the window that didn't produce a guide step, usually
+    /// because the star was lost.
+    pub fn dropped_frames(&self) -> usize {
+        match (self.samples.front(), self.samples.back()) {
+            (Some(first), Some(last)) => {
+                (last.frame - first.frame) as usize + 1 - self.samples.len()
+            }
+            _ => 0,
+        }
+    }
+
+    fn rms(&self, value: impl Fn(&Sample) -> f64) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let sum: f64 = self
+            .samples
+            .iter()
+            .map(|sample| value(sample).powi(2))
+            .sum();
+        Some((sum / self.samples.len() as f64).sqrt())
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use pin_project::pin_project;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream,
+};
+
+use crate::serialization::*;
+
+/// Implemented by the type carried by each [Event] variant, allowing it to be
+/// pulled out of an [Event] by type.
+pub trait FromEvent: Sized {
+    fn from_event(event: &Event) -> Option<Self>;
+}
+
+macro_rules! impl_from_event {
+    ($($variant:ident),* $(,)?) => {
+        $(
+            impl FromEvent for $variant {
+                fn from_event(event: &Event) -> Option<Self> {
+                    match event {
+                        Event::$variant(e) => Some(e.clone()),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_event!(
+    Version,
+    LockPositionSet,
+    Calibrating,
+    CalibrationComplete,
+    StarSelected,
+    StartGuiding,
+    Paused,
+    StartCalibration,
+    AppState,
+    CalibrationFailed,
+    CalibrationDataFlipped,
+    LockPositionShiftLimitReached,
+    LoopingExposures,
+    LoopingExposuresStopped,
+    SettleBegin,
+    Settling,
+    SettleDone,
+    StarLost,
+    GuidingStopped,
+    Resumed,
+    GuideStep,
+    GuidingDithered,
+    LockPositionLost,
+    Alert,
+    GuideParamChange,
+    ConfigurationChange,
+    Connected,
+    Disconnected,
+);
+
+/// Returned by a subscription that fell more than [EVENT_BUFFER_SIZE](crate::EVENT_BUFFER_SIZE)
+/// events behind.  Holds the number of events that were missed, the next call to `recv`
+/// continues with the oldest event still buffered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lagged(pub u64);
+
+impl std::fmt::Display for Lagged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "subscription lagged behind, {} events missed", self.0)
+    }
+}
+
+impl std::error::Error for Lagged {}
+
+/// Receives a single type of event from phd2.  Created with
+/// [subscribe_to](crate::Phd2Connection::subscribe_to).
+pub struct TypedSubscription<T> {
+    receiver: broadcast::Receiver<Arc<ServerEvent>>,
+    _event: PhantomData<T>,
+}
+
+impl<T: FromEvent> TypedSubscription<T> {
+    pub(crate) fn new(receiver: broadcast::Receiver<Arc<ServerEvent>>) -> Self {
+        TypedSubscription {
+            receiver,
+            _event: PhantomData,
+        }
+    }
+
+    /// Waits for the next event of type `T`.  Returns `None` once the connection to phd2
+    /// has closed, or [Lagged] if events were missed because the subscription fell behind.
+    pub async fn recv(&mut self) -> Result<Option<T>, Lagged> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => {
+                    if let Some(event) = T::from_event(&event.event) {
+                        return Ok(Some(event));
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => return Err(Lagged(missed)),
+                Err(RecvError::Closed) => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Receives the events from phd2 matching a filter.  Created with
+/// [subscribe_filtered](crate::Phd2Connection::subscribe_filtered).
+pub struct FilteredSubscription<F> {
+    receiver: broadcast::Receiver<Arc<ServerEvent>>,
+    filter: F,
+}
+
+impl<F: FnMut(&Event) -> bool> FilteredSubscription<F> {
+    pub(crate) fn new(receiver: broadcast::Receiver<Arc<ServerEvent>>, filter: F) -> Self {
+        FilteredSubscription { receiver, filter }
+    }
+
+    /// Waits for the next event the filter returns `true` for.  Returns `None` once the
+    /// connection to phd2 has closed, or [Lagged] if events were missed because the
+    /// subscription fell behind.
+    pub async fn recv(&mut self) -> Result<Option<Arc<ServerEvent>>, Lagged> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => {
+                    if (self.filter)(&event.event) {
+                        return Ok(Some(event));
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => return Err(Lagged(missed)),
+                Err(RecvError::Closed) => return Ok(None),
+            }
+        }
+    }
+}
+
+/// A [Stream] of every event from phd2, for use with [StreamExt](tokio_stream::StreamExt)
+/// combinators.  Created with [event_stream](crate::Phd2Connection::event_stream).  Events
+/// missed because the stream fell behind are counted by [missed](EventStream::missed).
+#[pin_project]
+#[must_use = "streams do nothing unless polled"]
+pub struct EventStream {
+    #[pin]
+    stream: BroadcastStream<Arc<ServerEvent>>,
+    missed: u64,
+}
+
+impl EventStream {
+    pub(crate) fn new(receiver: broadcast::Receiver<Arc<ServerEvent>>) -> Self {
+        EventStream {
+            stream: BroadcastStream::new(receiver),
+            missed: 0,
+        }
+    }
+
+    /// Number of events skipped so far because the stream fell more than
+    /// [EVENT_BUFFER_SIZE](crate::EVENT_BUFFER_SIZE) events behind.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Arc<ServerEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(event)) => return Poll::Ready(Some(event)),
+                Some(Err(BroadcastStreamRecvError::Lagged(missed))) => *this.missed += missed,
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
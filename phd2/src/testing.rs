@@ -0,0 +1,270 @@
+//! A mock phd2 server for testing code that talks to phd2 without a phd2 install.
+//! Enabled with the `testing` feature.
+//!
+//! [MockServer] listens on a local TCP port like phd2's EventMonitoring server.  It answers
+//! rpc calls from a table of responses and sends events when told to.
+//! # Example
+//! ```no_run
+//! use phd2::testing::MockServer;
+//! use serde_json::json;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let server = MockServer::bind().await.expect("Binding mock server");
+//!     server.respond("get_pixel_scale", json!(1.5));
+//!
+//!     let (phd2, mut events) = server.connect().await.expect("Connecting to mock server");
+//!     assert_eq!(phd2.get_pixel_scale().await.unwrap(), 1.5);
+//!
+//!     server.emit(json!({"Event": "StartGuiding"}));
+//!     let event = events.recv().await.unwrap();
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc, watch},
+    task::JoinHandle,
+};
+
+use crate::{
+    serialization::{RpcError, ServerEvent},
+    Phd2Connection,
+};
+
+/// JSON-RPC error code phd2 returns for methods it doesn't know.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+type Handler = Arc<dyn Fn(&Value) -> Result<Value, RpcError> + Send + Sync>;
+
+#[derive(Default)]
+struct State {
+    handlers: HashMap<String, Handler>,
+    on_connect: Vec<Value>,
+    requests: Vec<Value>,
+}
+
+/// A mock phd2 EventMonitoring server.  Calls to methods without a response return a
+/// [METHOD_NOT_FOUND] error.  The server stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    events: broadcast::Sender<String>,
+    clients: watch::Receiver<usize>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Starts a server on a free port on localhost.
+    pub async fn bind() -> std::io::Result<MockServer> {
+        MockServer::bind_to("127.0.0.1:0").await
+    }
+
+    /// Starts a server on `addr`.
+    pub async fn bind_to(addr: impl tokio::net::ToSocketAddrs) -> std::io::Result<MockServer> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let (events, _) = broadcast::channel(1024);
+        let (connected, clients) = watch::channel(0);
+
+        let task = {
+            let state = state.clone();
+            let events = events.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let client = Client {
+                        state: state.clone(),
+                        events: events.subscribe(),
+                    };
+                    connected.send_modify(|count| *count += 1);
+                    tokio::spawn(client.run(stream));
+                }
+            })
+        };
+
+        Ok(MockServer {
+            addr,
+            state,
+            events,
+            clients,
+            task,
+        })
+    }
+
+    /// Address the server is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Connects a [Phd2Connection] to the server.  Returns once the server has accepted the
+    /// connection, so events emitted afterwards are received.
+    pub async fn connect(
+        &self,
+    ) -> std::io::Result<(Phd2Connection<TcpStream>, mpsc::Receiver<ServerEvent>)> {
+        let mut clients = self.clients.clone();
+        let connected = *clients.borrow_and_update();
+        let stream = TcpStream::connect(self.addr).await?;
+        while *clients.borrow_and_update() == connected {
+            clients
+                .changed()
+                .await
+                .map_err(|_| std::io::Error::from(std::io::ErrorKind::ConnectionAborted))?;
+        }
+        Ok(Phd2Connection::from(stream))
+    }
+
+    /// Answers calls to `method` with `result`.
+    pub fn respond(&self, method: impl Into<String>, result: Value) {
+        self.respond_with(method, move |_| Ok(result.clone()));
+    }
+
+    /// Answers calls to `method` with an rpc error.
+    pub fn respond_error(&self, method: impl Into<String>, code: i64, message: impl Into<String>) {
+        let error = RpcError {
+            code,
+            message: message.into(),
+        };
+        self.respond_with(method, move |_| Err(error.clone()));
+    }
+
+    /// Answers calls to `method` by calling `handler` with the call's params.
+    pub fn respond_with<F>(&self, method: impl Into<String>, handler: F)
+    where
+        F: Fn(&Value) -> Result<Value, RpcError> + Send + Sync + 'static,
+    {
+        self.state
+            .lock()
+            .unwrap()
+            .handlers
+            .insert(method.into(), Arc::new(handler));
+    }
+
+    /// Sends `events` to each client as soon as it connects, like the `Version` and state
+    /// events phd2 sends to new connections.
+    pub fn on_connect(&self, events: Vec<Value>) {
+        self.state.lock().unwrap().on_connect = events.into_iter().map(with_header).collect();
+    }
+
+    /// Sends `event` to every connected client.  `Timestamp`, `Host` and `Inst` are filled in
+    /// if missing.
+    pub fn emit(&self, event: Value) {
+        self.events.send(with_header(event).to_string()).ok();
+    }
+
+    /// Returns the requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Fills in the fields phd2 sends with every event.
+fn with_header(mut event: Value) -> Value {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    for (key, value) in [
+        ("Timestamp", json!(timestamp)),
+        ("Host", json!("localhost")),
+        ("Inst", json!(1)),
+    ] {
+        if event.get(key).is_none() {
+            event[key] = value;
+        }
+    }
+    event
+}
+
+struct Client {
+    state: Arc<Mutex<State>>,
+    events: broadcast::Receiver<String>,
+}
+
+impl Client {
+    async fn run(mut self, stream: TcpStream) {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+
+        let on_connect = self.state.lock().unwrap().on_connect.clone();
+        for event in on_connect {
+            if write_line(&mut write, &event.to_string()).await.is_err() {
+                return;
+            }
+        }
+
+        loop {
+            let line = tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => self.response(&line).to_string(),
+                    _ => break,
+                },
+                event = self.events.recv() => match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            };
+            if write_line(&mut write, &line).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    fn response(&self, line: &str) -> Value {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                return json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": e.to_string()}})
+            }
+        };
+
+        let handler = {
+            let mut state = self.state.lock().unwrap();
+            state.requests.push(request.clone());
+            request["method"]
+                .as_str()
+                .and_then(|method| state.handlers.get(method))
+                .cloned()
+        };
+        let result = match handler {
+            Some(handler) => handler(&request["params"]),
+            None => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: String::from("method not found"),
+            }),
+        };
+
+        match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+            Err(error) => json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": {"code": error.code, "message": error.message},
+            }),
+        }
+    }
+}
+
+async fn write_line(
+    write: &mut tokio::net::tcp::OwnedWriteHalf,
+    line: &str,
+) -> std::io::Result<()> {
+    write.write_all(line.as_bytes()).await?;
+    write.write_all(b"\r\n").await
+}
@@ -1,6 +1,7 @@
 use super::*;
 
 use tokio::fs::File;
+use tokio_stream::StreamExt;
 
 #[tokio::test]
 async fn test_read_session() {
@@ -12,167 +13,1819 @@ async fn test_read_session() {
     }
 }
 
-// #[cfg(feature = "test_phd2_simulator")]
-mod integration {
-    use crate::serialization::Event;
-    use crate::*;
-    use std::time::Duration;
-    use tokio::net::TcpStream;
+/// Answers the next request sent to `server` with `reply`, returning the request.
+fn respond_once(
+    server: tokio::io::DuplexStream,
+    reply: serde_json::Value,
+) -> tokio::task::JoinHandle<serde_json::Value> {
+    respond_with_events(server, reply, vec![])
+}
 
-    #[tokio::test]
-    async fn test_integration_phd2_simulator() -> Result<(), ClientError> {
-        println!("Starting phd2");
+/// Answers the next request sent to `server` with `reply`, then sends `events`.  Returns
+/// the request.
+fn respond_with_events(
+    server: tokio::io::DuplexStream,
+    reply: serde_json::Value,
+    events: Vec<serde_json::Value>,
+) -> tokio::task::JoinHandle<serde_json::Value> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 
-        let mut phd2_instance = tokio::process::Command::new("phd2").spawn().unwrap();
+    tokio::spawn(async move {
+        let (read, mut write) = tokio::io::split(server);
+        let mut lines = BufReader::new(read).lines();
+        let request: serde_json::Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        let mut response = reply;
+        response["jsonrpc"] = json!("2.0");
+        response["id"] = request["id"].clone();
+        write
+            .write_all(format!("{}\n", response).as_bytes())
+            .await
+            .unwrap();
+        for mut event in events {
+            event["Timestamp"] = json!(1684470047.430);
+            event["Host"] = json!("astro");
+            event["Inst"] = json!(1);
+            write
+                .write_all(format!("{}\n", event).as_bytes())
+                .await
+                .unwrap();
+        }
+        request
+    })
+}
 
-        let (phd2, mut events): (Phd2Connection<_>, _) = loop {
-            println!("Connecting to phd2");
-            let connection = TcpStream::connect("localhost:4400").await;
-            match connection {
-                Ok(connection) => break Phd2Connection::from(connection),
-                Err(e) => {
-                    dbg!(e);
-                    println!("Waiting 1s before trying again");
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                }
-            }
-        };
+#[tokio::test]
+async fn test_get_variable_delay_settings() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    let request = respond_once(
+        server,
+        json!({"result": {"Enabled": true, "ShortDelaySeconds": 1, "LongDelaySeconds": 30}}),
+    );
 
-        phd2.stop_capture().await?;
-        phd2.set_connected(false).await?;
+    let settings = phd2.get_variable_delay_settings().await.unwrap();
+    assert_eq!(
+        settings,
+        VariableDelaySettings {
+            enabled: true,
+            short_delay_seconds: 1,
+            long_delay_seconds: 30,
+        }
+    );
+    assert_eq!(
+        request.await.unwrap()["method"],
+        json!("get_variable_delay_settings")
+    );
+}
 
-        let profiles = phd2.get_profiles().await?;
-        let simulator_profile = profiles
-            .iter()
-            .find(|item| item.name == "Simulator")
-            .expect("Finding 'Simulator' profile.");
-        phd2.set_profile(simulator_profile.id).await?;
+#[tokio::test]
+async fn test_set_variable_delay_settings() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    let request = respond_once(server, json!({"result": 0}));
 
-        assert_eq!(phd2.get_profile().await?.name, String::from("Simulator"));
+    phd2.set_variable_delay_settings(VariableDelaySettings {
+        enabled: false,
+        short_delay_seconds: 2,
+        long_delay_seconds: 15,
+    })
+    .await
+    .unwrap();
 
-        phd2.set_connected(true).await?;
-        assert!(phd2.get_connected().await?);
+    let request = request.await.unwrap();
+    assert_eq!(request["method"], json!("set_variable_delay_settings"));
+    assert_eq!(
+        request["params"],
+        json!({"Enabled": false, "ShortDelaySeconds": 2, "LongDelaySeconds": 15})
+    );
+}
 
-        assert_eq!(phd2.get_app_state().await?, State::Stopped);
+#[tokio::test]
+async fn test_get_settling() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    let request = respond_once(server, json!({"result": true}));
 
-        phd2.clear_calibration(ClearCalibrationParam::Both).await?;
-        assert!(!phd2.get_calibrated().await?);
+    assert!(phd2.get_settling().await.unwrap());
+    assert_eq!(request.await.unwrap()["method"], json!("get_settling"));
+}
 
-        phd2.get_camera_frame_size().await?;
-        phd2.get_current_equipment().await?;
+#[tokio::test]
+async fn test_export_config_settings() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    let request = respond_once(
+        server,
+        json!({"result": {"filename": "/home/astro/PHD2/PHD2_settings.txt"}}),
+    );
+
+    let exported = phd2.export_config_settings().await.unwrap();
+    assert_eq!(exported.filename, "/home/astro/PHD2/PHD2_settings.txt");
+    assert_eq!(
+        request.await.unwrap()["method"],
+        json!("export_config_settings")
+    );
+}
 
-        phd2.get_cooler_status().await?;
+#[tokio::test]
+async fn test_get_star_image_size() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
 
-        phd2.set_dec_guide_mode(DecGuideMode::Auto).await?;
-        assert_eq!(phd2.get_dec_guide_mode().await?, DecGuideMode::Auto);
+    let result = phd2.get_star_image(Some(MIN_STAR_IMAGE_SIZE - 1)).await;
+    assert!(matches!(result, Err(ClientError::InvalidArgument(_))));
 
-        let exp = phd2.get_exposure_durations().await?[0];
-        phd2.set_exposure(exp).await?;
-        assert_eq!(phd2.get_exposure().await?, exp);
+    let request = respond_once(
+        server,
+        json!({"result": {
+            "frame": 3,
+            "width": 2,
+            "height": 2,
+            "star_pos": [1.0, 1.0],
+            "pixels": "AQACAAAB//8="
+        }}),
+    );
+    phd2.get_star_image(Some(32)).await.unwrap();
+    assert_eq!(request.await.unwrap()["params"], json!({"size": 32}));
+}
 
-        phd2.set_guide_output_enabled(true).await?;
-        assert!(phd2.get_guide_output_enabled().await?);
+#[tokio::test]
+async fn test_typed_subscriptions() {
+    let path = "./src/test_data/session.log";
+    let log = std::fs::read_to_string(path).unwrap();
+    let count = |event: &str| {
+        log.lines()
+            .filter(|line| line.contains(&format!("\"Event\":\"{}\"", event)))
+            .count()
+    };
 
-        phd2.get_lock_shift_params().await?;
-        phd2.set_lock_shift_params([1.0, 1.0], "arcsec/hr", "RA/Dec")
-            .await?;
-        phd2.set_lock_shift_enabled(false).await?;
-        assert!(!phd2.get_lock_shift_enabled().await?);
+    // The legacy receiver is held but never read, it must not stall the subscriptions.
+    let (phd2, _events): (Phd2Connection<File>, _) =
+        Phd2Connection::from(File::open(path).await.unwrap());
+    let mut guide_steps = phd2.subscribe_to::<serialization::GuideStep>();
+    let mut star_lost = phd2.subscribe_filtered(|event| {
+        matches!(event, Event::StarLost(_) | Event::LockPositionLost(_))
+    });
 
-        let param = &phd2.get_algo_param_names(Axis::Dec).await?[1];
-        let value = phd2.get_algo_param(Axis::Dec, param).await?;
-        phd2.set_algo_param(Axis::Dec, param, value).await?;
+    let guide_steps = tokio::spawn(async move {
+        let mut frames = vec![];
+        while let Some(step) = guide_steps.recv().await.unwrap() {
+            frames.push(step.frame);
+        }
+        frames
+    });
+    let star_lost = tokio::spawn(async move {
+        let mut count = 0;
+        while star_lost.recv().await.unwrap().is_some() {
+            count += 1;
+        }
+        count
+    });
 
-        phd2.get_pixel_scale().await?;
-        phd2.get_search_region().await?;
-        phd2.get_ccd_temperature().await?;
-        phd2.get_use_subframes().await?;
+    let frames = guide_steps.await.unwrap();
+    assert_eq!(frames.len(), count("GuideStep"));
+    assert_eq!(
+        star_lost.await.unwrap(),
+        count("StarLost") + count("LockPositionLost")
+    );
+}
 
-        // Start doing frame-things
-        phd2.capture_single_frame(Duration::from_secs(1), None)
-            .await?;
+#[tokio::test]
+async fn test_subscription_lagged() {
+    use tokio::io::AsyncWriteExt;
 
-        phd2.set_exposure(Duration::from_secs(1)).await?;
-        {
-            println!("Starting looping");
-            phd2.loop_().await?;
+    let (client, mut server) = tokio::io::duplex(1024);
+    let (phd2, mut events) = Phd2Connection::from(client);
+    let mut looping = phd2.subscribe_to::<serialization::LoopingExposures>();
+    let mut stream = phd2.event_stream();
 
-            let mut frame_count = 0;
-            loop {
-                dbg!(frame_count);
-                let event = events.recv().await.unwrap();
-                if let Event::LoopingExposures(_) = &event.event {
-                    frame_count += 1;
-                    if frame_count > 5 {
-                        break;
-                    }
-                }
-            }
-            phd2.guide_pulse(10, PulseDirection::E, None).await?;
-            phd2.find_star(Some([621, 356, 50, 50])).await?;
+    let total = EVENT_BUFFER_SIZE as u32 + 10;
+    tokio::spawn(async move {
+        for frame in 1..=total {
+            let event = json!({
+                "Event": "LoopingExposures",
+                "Timestamp": 1684470047.430,
+                "Host": "astro",
+                "Inst": 1,
+                "Frame": frame,
+            });
+            server
+                .write_all(format!("{}\n", event).as_bytes())
+                .await
+                .unwrap();
         }
-        {
-            println!("Starting guiding");
-            let settle = Settle::new(1.5, Duration::from_secs(1), Duration::from_secs(60));
-            phd2.guide(settle, Some(true), None).await?;
+    });
 
-            loop {
-                let event = events.recv().await.unwrap();
-                if let Event::SettleDone(event) = &event.event {
-                    assert_eq!(event.status, 0);
-                    break;
-                }
+    // The reader task has finished once the legacy receiver closes.
+    while events.recv().await.is_some() {}
+
+    // The oldest events were pushed out by the rest and the Disconnected event.
+    assert!(matches!(
+        looping.recv().await,
+        Err(subscription::Lagged(11))
+    ));
+    assert_eq!(looping.recv().await.unwrap().unwrap().frame, 12);
+
+    let mut received = 0;
+    while stream.next().await.is_some() {
+        received += 1;
+    }
+    assert_eq!(received, EVENT_BUFFER_SIZE);
+    assert_eq!(stream.missed(), 11);
+}
+
+#[tokio::test]
+async fn test_event_stream() {
+    let path = "./src/test_data/session.log";
+    let (phd2, _events): (Phd2Connection<File>, _) =
+        Phd2Connection::from(File::open(path).await.unwrap());
+    let stream = phd2.event_stream();
+
+    let frames: Vec<u32> = stream
+        .filter_map(|event| match &event.event {
+            Event::GuideStep(step) => Some(step.frame),
+            _ => None,
+        })
+        .collect()
+        .await;
+    assert_eq!(frames.len(), 147);
+    assert_eq!(frames[0], 22);
+}
+
+#[tokio::test]
+async fn test_method_timeout() {
+    let (client, _server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    let phd2 = phd2
+        .with_timeout(Duration::from_millis(10))
+        .with_method_timeout("guide", Duration::from_secs(60));
+
+    assert_eq!(phd2.timeout_for("get_app_state"), Duration::from_millis(10));
+    assert_eq!(phd2.timeout_for("guide"), Duration::from_secs(60));
+
+    let result = phd2.get_app_state().await;
+    assert!(matches!(result, Err(ClientError::Timeout(_))));
+
+    let result = phd2
+        .call_with_timeout("get_app_state", json!([]), Duration::from_millis(20))
+        .await;
+    assert!(matches!(result, Err(ClientError::Timeout(_))));
+}
+
+#[tokio::test]
+async fn test_rpc_error() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+
+    tokio::spawn(async move {
+        let (read, mut write) = tokio::io::split(server);
+        let mut lines = BufReader::new(read).lines();
+        let request: serde_json::Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "error": {"code": 1, "message": "cannot pause while not guiding"}
+        });
+        write
+            .write_all(format!("{}\n", response).as_bytes())
+            .await
+            .unwrap();
+    });
+
+    let err = phd2.set_paused(true, false).await.unwrap_err();
+    match &err {
+        ClientError::RpcError(e) => {
+            assert_eq!(e.code, 1);
+            assert_eq!(e.message, "cannot pause while not guiding");
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+    assert_eq!(
+        err.to_string(),
+        "phd2 returned an error: cannot pause while not guiding (code 1)"
+    );
+}
+
+#[test]
+fn test_star_image_pixels() {
+    let image: StarImage = serde_json::from_value(json!({
+        "frame": 3,
+        "width": 2,
+        "height": 2,
+        "star_pos": [1.0, 1.0],
+        "pixels": "AQACAAAB//8="
+    }))
+    .unwrap();
+
+    assert_eq!(image.pixel_values(), &[1, 2, 256, 65535]);
+    assert_eq!(image.get(1, 0), Some(2));
+    assert_eq!(image.get(0, 1), Some(256));
+    assert_eq!(image.get(2, 0), None);
+    assert_eq!(
+        image.to_array().unwrap(),
+        ndarray::arr2(&[[1, 2], [256, 65535]])
+    );
+}
+
+#[tokio::test]
+async fn test_dither_and_settle() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    let request = respond_with_events(
+        server,
+        json!({"result": 0}),
+        vec![
+            json!({"Event": "SettleBegin"}),
+            json!({"Event": "SettleDone", "Status": 0, "TotalFrames": 4, "DroppedFrames": 1}),
+        ],
+    );
+
+    let settle = Settle::new(1.5, Duration::from_secs(1), Duration::from_secs(60));
+    let done = phd2.dither_and_settle(10.0, true, settle).await.unwrap();
+    assert_eq!(done.total_frames, 4);
+    assert_eq!(done.dropped_frames, 1);
+
+    let request = request.await.unwrap();
+    assert_eq!(request["method"], json!("dither"));
+    assert_eq!(
+        request["params"],
+        json!({"amount": 10.0, "raOnly": true, "settle": {"pixels": 1.5, "time": 1.0, "timeout": 60.0}})
+    );
+}
+
+#[tokio::test]
+async fn test_dither_and_settle_failed() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    respond_with_events(
+        server,
+        json!({"result": 0}),
+        vec![json!({
+            "Event": "SettleDone",
+            "Status": 1,
+            "Error": "timed-out waiting for guider to settle",
+            "TotalFrames": 60,
+            "DroppedFrames": 0,
+        })],
+    );
+
+    let settle = Settle::new(1.5, Duration::from_secs(1), Duration::from_secs(60));
+    match phd2.dither_and_settle(10.0, false, settle).await {
+        Err(ClientError::SettleFailed(done)) => assert_eq!(
+            done.error,
+            Some(String::from("timed-out waiting for guider to settle"))
+        ),
+        other => panic!("expected SettleFailed, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_dither_and_settle_timeout() {
+    let server = testing::MockServer::bind().await.unwrap();
+    // phd2 accepts the dither but never reports settling.
+    server.respond("dither", json!(0));
+    let (phd2, _events) = server.connect().await.unwrap();
+    let phd2 = phd2.with_timeout(Duration::from_millis(50));
+
+    let settle = Settle::new(1.5, Duration::from_millis(10), Duration::from_millis(50));
+    match phd2.dither_and_settle(10.0, false, settle).await {
+        Err(ClientError::Timeout(_)) => {}
+        other => panic!("expected Timeout, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_guide_and_wait() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    let request = respond_with_events(
+        server,
+        json!({"result": 0}),
+        vec![
+            json!({"Event": "StartCalibration", "Mount": "Mount"}),
+            json!({"Event": "CalibrationComplete", "Mount": "Mount"}),
+            json!({"Event": "StartGuiding"}),
+            json!({"Event": "SettleBegin"}),
+            json!({"Event": "Settling", "Distance": 0.8, "Time": 1.0, "SettleTime": 1.0, "StarLocked": true}),
+            json!({"Event": "SettleDone", "Status": 0, "TotalFrames": 2, "DroppedFrames": 0}),
+        ],
+    );
+
+    let settle = Settle::new(1.5, Duration::from_secs(1), Duration::from_secs(60));
+    let done = phd2
+        .guide_and_wait(settle, Some(true), Some([1, 2, 3, 4]))
+        .await
+        .unwrap();
+    assert_eq!(done.total_frames, 2);
+
+    let request = request.await.unwrap();
+    assert_eq!(request["method"], json!("guide"));
+    assert_eq!(
+        request["params"],
+        json!({
+            "settle": {"pixels": 1.5, "time": 1.0, "timeout": 60.0},
+            "recalibrate": true,
+            "roi": [1, 2, 3, 4],
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_guide_and_wait_calibration_failed() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    respond_with_events(
+        server,
+        json!({"result": 0}),
+        vec![
+            json!({"Event": "StartCalibration", "Mount": "Mount"}),
+            json!({"Event": "CalibrationFailed", "Reason": "star did not move enough"}),
+        ],
+    );
+
+    let settle = Settle::new(1.5, Duration::from_secs(1), Duration::from_secs(60));
+    match phd2.guide_and_wait(settle, None, None).await {
+        Err(ClientError::CalibrationFailed(reason)) => {
+            assert_eq!(reason, "star did not move enough")
+        }
+        other => panic!("expected CalibrationFailed, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_guider() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    let request = respond_with_events(
+        server,
+        json!({"result": "Looping"}),
+        vec![
+            json!({"Event": "StartCalibration", "Mount": "Mount"}),
+            json!({"Event": "CalibrationComplete", "Mount": "Mount"}),
+            json!({"Event": "StartGuiding"}),
+            json!({"Event": "SettleBegin"}),
+            json!({"Event": "SettleDone", "Status": 0, "TotalFrames": 2, "DroppedFrames": 0}),
+            json!({"Event": "GuidingStopped"}),
+        ],
+    );
+
+    let guider = Guider::new(&phd2).await.unwrap();
+    assert_eq!(request.await.unwrap()["method"], json!("get_app_state"));
+
+    let mut states = guider.subscribe();
+    while states.changed().await.is_ok() {}
+    assert_eq!(guider.state(), GuiderState::Stopped);
+}
+
+#[test]
+fn test_guider_state_transitions() {
+    let events = [
+        json!({"Event": "AppState", "State": "Looping"}),
+        json!({"Event": "StarSelected", "X": 1.0, "Y": 2.0}),
+        json!({"Event": "StartCalibration", "Mount": "Mount"}),
+        json!({"Event": "CalibrationComplete", "Mount": "Mount"}),
+        json!({"Event": "StartGuiding"}),
+        json!({"Event": "SettleBegin"}),
+        json!({"Event": "SettleDone", "Status": 0, "TotalFrames": 2, "DroppedFrames": 0}),
+        json!({"Event": "Alert", "Msg": "hello", "Type": "info"}),
+        json!({"Event": "Paused"}),
+        json!({"Event": "Resumed"}),
+        json!({"Event": "LoopingExposuresStopped"}),
+    ];
+    let expected = [
+        GuiderState::Looping,
+        GuiderState::Selected,
+        GuiderState::Calibrating,
+        GuiderState::Calibrating,
+        GuiderState::Guiding,
+        GuiderState::Settling,
+        GuiderState::Guiding,
+        GuiderState::Guiding,
+        GuiderState::Paused,
+        GuiderState::Guiding,
+        GuiderState::Stopped,
+    ];
+
+    let mut state = GuiderState::Stopped;
+    for (event, expected) in events.into_iter().zip(expected) {
+        let event: Event = serde_json::from_value(event).unwrap();
+        state = state.next(&event);
+        assert_eq!(state, expected, "after {:?}", event);
+    }
+}
+
+fn guide_step(frame: u32, ra: f64, dec: f64, snr: f64) -> serialization::GuideStep {
+    serde_json::from_value(json!({
+        "Frame": frame,
+        "Time": frame as f64,
+        "Mount": "Mount",
+        "dx": 0.0,
+        "dy": 0.0,
+        "RADistanceRaw": ra,
+        "DECDistanceRaw": dec,
+        "RADistanceGuide": ra,
+        "DECDistanceGuide": dec,
+        "StarMass": 1000.0,
+        "SNR": snr,
+        "HFD": 2.0,
+        "AvgDist": 0.5,
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_guide_stats() {
+    let mut guide_stats = stats::GuideStats::new(4);
+    assert_eq!(guide_stats.rms_total(), None);
+    assert_eq!(guide_stats.snr_trend(), None);
+
+    // Pushed out of the window by the next four steps.
+    guide_stats.add(&guide_step(1, 10.0, 10.0, 50.0));
+    guide_stats.add(&guide_step(2, 1.0, 0.0, 20.0));
+    guide_stats.add(&guide_step(3, -1.0, 2.0, 22.0));
+    guide_stats.add(&guide_step(5, 1.0, 0.0, 26.0));
+    guide_stats.add(&guide_step(6, -1.0, 2.0, 28.0));
+
+    assert_eq!(guide_stats.len(), 4);
+    assert_eq!(guide_stats.rms_ra(), Some(1.0));
+    assert_eq!(guide_stats.rms_dec(), Some(2.0f64.sqrt()));
+    assert!((guide_stats.rms_total().unwrap() - 3.0f64.sqrt()).abs() < 1e-9);
+    assert_eq!(guide_stats.peak(), Some(5.0f64.sqrt()));
+    assert!((guide_stats.snr_trend().unwrap() - 2.0).abs() < 1e-9);
+    assert_eq!(guide_stats.dropped_frames(), 1);
+
+    // A new guiding session starts over.
+    guide_stats.add(&guide_step(1, 3.0, 4.0, 20.0));
+    assert_eq!(guide_stats.len(), 1);
+    assert_eq!(guide_stats.peak(), Some(5.0));
+    assert_eq!(guide_stats.dropped_frames(), 0);
+}
+
+#[test]
+fn test_guide_log() {
+    use guide_log::{GuideLog, Section};
+
+    let log = GuideLog::open("./src/test_data/PHD2_GuideLog_2023-05-21_210234.txt").unwrap();
+    assert_eq!(log.sections.len(), 2);
+    assert!(matches!(log.sections[0], Section::Calibration(_)));
+
+    let calibration = log.calibrations().next().unwrap();
+    assert_eq!(calibration.started_at, "2023-05-21 21:05:12");
+    assert_eq!(calibration.mount, Some(String::from("Mount")));
+    assert_eq!(
+        calibration.calibration,
+        Calibration {
+            calibrated: true,
+            data: Some(serialization::CalibrationData {
+                x_angle: 1.1,
+                x_rate: 7.017,
+                x_parity: serialization::Parity::Pos,
+                y_angle: 91.1,
+                y_rate: 7.009,
+                y_parity: serialization::Parity::Neg,
+            }),
+        }
+    );
+
+    let guiding = log.guiding().next().unwrap();
+    assert_eq!(guiding.started_at, "2023-05-21 21:07:40");
+    // Frame 3 was dropped.
+    let frames: Vec<u32> = guiding.steps.iter().map(|step| step.frame).collect();
+    assert_eq!(frames, vec![1, 2, 4, 5, 6]);
+
+    let step = &guiding.steps[0];
+    assert_eq!(step.mount, "Mount");
+    assert_eq!(step.time, 1.021);
+    assert_eq!(step.ra_distance_raw, 0.223);
+    assert_eq!(step.de_distance_raw, 0.126);
+    assert_eq!(step.ra_duration, Some(22.0));
+    assert!(matches!(
+        step.ra_direction,
+        Some(serialization::EastWest::West)
+    ));
+    assert_eq!(step.dec_duration, None);
+    assert!(step.dec_direction.is_none());
+    assert_eq!(step.snr, 35.21);
+    assert_eq!(step.error_code, Some(0));
+
+    assert!(matches!(
+        guiding.steps[2].dec_direction,
+        Some(serialization::NorthSouth::North)
+    ));
+}
+
+#[test]
+fn test_guide_log_parse_error() {
+    use guide_log::{GuideLog, GuideLogError};
+
+    let log = "Guiding Begins at 2023-05-21 21:07:40\n\
+        Frame,Time,mount,dx,dy,RARawDistance,DECRawDistance,RAGuideDistance,DECGuideDistance,StarMass,SNR\n\
+        1,1.021,\"Mount\",0.221,-0.130,abc,0.126,0.156,0.000,24113,35.21\n";
+    match GuideLog::read(log.as_bytes()) {
+        Err(GuideLogError::Parse { line, reason }) => {
+            assert_eq!(line, 3);
+            assert_eq!(reason, "invalid RARawDistance");
+        }
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_replay_session() {
+    let recording =
+        tokio::io::BufReader::new(File::open("./src/test_data/session.log").await.unwrap());
+    let (_phd2, mut events) = Phd2Connection::from(recording::replay(recording, f64::INFINITY));
+
+    let mut guide_steps = 0;
+    while let Some(event) = events.recv().await {
+        if let Event::GuideStep(_) = event.event {
+            guide_steps += 1;
+        }
+    }
+    assert_eq!(guide_steps, 147);
+}
+
+#[tokio::test]
+async fn test_replay_speed() {
+    let recording = [0.0, 1.0, 2.0]
+        .iter()
+        .map(|time| {
+            format!(
+                "{}\n",
+                json!({"Event": "LoopingExposures", "Timestamp": time, "Host": "astro", "Inst": 1, "Frame": 1})
+            )
+        })
+        .collect::<String>();
+
+    let start = std::time::Instant::now();
+    let (_phd2, mut events) = Phd2Connection::from(recording::replay(
+        std::io::Cursor::new(recording.into_bytes()),
+        20.0,
+    ));
+    let mut count = 0;
+    while let Some(event) = events.recv().await {
+        if let Event::LoopingExposures(_) = event.event {
+            count += 1;
+        }
+    }
+    assert_eq!(count, 3);
+    assert!(start.elapsed() >= Duration::from_millis(90));
+}
+
+#[tokio::test]
+async fn test_record_and_replay() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (client, mut server) = tokio::io::duplex(1024);
+    let (recording_write, recording_read) = tokio::io::duplex(1024 * 1024);
+    let (phd2, mut events) =
+        Phd2Connection::from(recording::Recorder::new(client, recording_write));
+
+    for frame in 1..=3 {
+        let event = json!({"Event": "LoopingExposures", "Timestamp": 1.0, "Host": "astro", "Inst": 1, "Frame": frame});
+        server
+            .write_all(format!("{}\n", event).as_bytes())
+            .await
+            .unwrap();
+    }
+    drop(server);
+    while events.recv().await.is_some() {}
+    drop(phd2);
+
+    let mut lines = BufReader::new(recording_read).lines();
+    let mut recording = String::new();
+    while let Some(line) = lines.next_line().await.unwrap() {
+        let recorded: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert!(recorded["received"].as_f64().unwrap() > 1684470047.0);
+        assert_eq!(recorded["message"]["Event"], json!("LoopingExposures"));
+        recording.push_str(&line);
+        recording.push('\n');
+    }
+
+    let (_phd2, mut events) = Phd2Connection::from(recording::replay(
+        std::io::Cursor::new(recording.into_bytes()),
+        f64::INFINITY,
+    ));
+    let mut frames = vec![];
+    while let Some(event) = events.recv().await {
+        if let Event::LoopingExposures(looping) = event.event {
+            frames.push(looping.frame);
+        }
+    }
+    assert_eq!(frames, vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn test_mock_server() {
+    let server = testing::MockServer::bind().await.unwrap();
+    server.on_connect(vec![json!({
+        "Event": "Version",
+        "PHDVersion": "2.6.11",
+        "PHDSubver": "",
+        "OverlapSupport": true,
+        "MsgVersion": 1,
+    })]);
+    server.respond("get_pixel_scale", json!(1.5));
+    server.respond_with("set_exposure", |params| Ok(json!(params[0])));
+    server.respond_error("guide", 1, "cannot guide while calibrating");
+
+    let (phd2, mut events) = server.connect().await.unwrap();
+    let connected = events.recv().await.unwrap();
+    assert!(matches!(connected.event, Event::Connected(_)));
+    let version = events.recv().await.unwrap();
+    assert!(matches!(version.event, Event::Version(_)));
+
+    assert_eq!(phd2.get_pixel_scale().await.unwrap(), 1.5);
+    phd2.set_exposure(Duration::from_millis(1500))
+        .await
+        .unwrap();
+
+    let settle = Settle::new(1.5, Duration::from_secs(1), Duration::from_secs(60));
+    match phd2.guide(settle, None, None).await {
+        Err(ClientError::RpcError(error)) => {
+            assert_eq!(error.message, "cannot guide while calibrating")
+        }
+        other => panic!("expected RpcError, got {:?}", other),
+    }
+    match phd2.get_paused().await {
+        Err(ClientError::RpcError(error)) => assert_eq!(error.code, testing::METHOD_NOT_FOUND),
+        other => panic!("expected RpcError, got {:?}", other),
+    }
+
+    server.emit(json!({"Event": "StartGuiding"}));
+    let event = events.recv().await.unwrap();
+    assert!(matches!(event.event, Event::StartGuiding(_)));
+    assert_eq!(event.host, "localhost");
+
+    let methods: Vec<serde_json::Value> = server
+        .requests()
+        .into_iter()
+        .map(|request| request["method"].clone())
+        .collect();
+    assert_eq!(
+        methods,
+        vec![
+            json!("get_pixel_scale"),
+            json!("set_exposure"),
+            json!("guide"),
+            json!("get_paused")
+        ]
+    );
+    assert_eq!(server.requests()[1]["params"], json!([1500]));
+}
+
+#[tokio::test]
+async fn test_heartbeat() {
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond("get_app_state", json!("Guiding"));
+    let (phd2, _events) = server.connect().await.unwrap();
+
+    tokio::select! {
+        lost = phd2.heartbeat(Duration::from_millis(10)) => panic!("heartbeat failed: {}", lost),
+        _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+    }
+    assert!(server.requests().len() > 1);
+}
+
+#[tokio::test]
+async fn test_heartbeat_lost() {
+    // Nothing answers on the other end, like a phd2 that hung without closing the socket.
+    let (client, _server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    let phd2 = phd2.with_timeout(Duration::from_millis(10));
+
+    let lost = tokio::time::timeout(
+        Duration::from_secs(1),
+        phd2.heartbeat(Duration::from_millis(10)),
+    )
+    .await
+    .expect("heartbeat to notice phd2 stopped responding");
+    assert!(
+        matches!(lost, ClientError::ConnectionLost(e) if matches!(*e, ClientError::Timeout(_)))
+    );
+}
+
+#[tokio::test]
+async fn test_connection_events() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, mut events) = Phd2Connection::from(client);
+    let mut disconnected = phd2.subscribe_to::<serialization::Disconnected>();
+
+    let connected = events.recv().await.unwrap();
+    assert!(matches!(connected.event, Event::Connected(_)));
+    assert_eq!(connected.host, "");
+
+    drop(server);
+    assert!(disconnected.recv().await.unwrap().is_some());
+    assert!(matches!(
+        events.recv().await.unwrap().event,
+        Event::Disconnected(_)
+    ));
+    assert!(events.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn test_subscribe() {
+    let server = testing::MockServer::bind().await.unwrap();
+    let (phd2, _events) = server.connect().await.unwrap();
+    let mut first = phd2.subscribe();
+    let mut second = phd2.subscribe();
+
+    server.emit(json!({"Event": "StartGuiding"}));
+    for sub in [&mut first, &mut second] {
+        let event = sub.recv().await.unwrap();
+        assert!(matches!(event.event, Event::StartGuiding(_)));
+    }
+}
+
+#[tokio::test]
+async fn test_disconnect_fails_pending_calls() {
+    let (client, mut server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+
+    let closing = async move {
+        // Close once the request has been sent.
+        let mut buf = [0; 1024];
+        tokio::io::AsyncReadExt::read(&mut server, &mut buf)
+            .await
+            .unwrap();
+    };
+    let (result, _) = tokio::join!(phd2.get_app_state(), closing);
+    assert!(matches!(result, Err(ClientError::Disconnected)));
+
+    assert!(matches!(
+        phd2.get_app_state().await,
+        Err(ClientError::Disconnected)
+    ));
+}
+
+#[tokio::test]
+async fn test_close() {
+    let (client, _server) = tokio::io::duplex(1024);
+    let (phd2, mut events) = Phd2Connection::from(client);
+    let mut sub = phd2.subscribe();
+
+    let (result, closed) = tokio::join!(phd2.get_app_state(), async {
+        tokio::task::yield_now().await;
+        phd2.close().await
+    });
+    closed.unwrap();
+    assert!(matches!(result, Err(ClientError::Disconnected)));
+    assert!(matches!(
+        phd2.get_app_state().await,
+        Err(ClientError::Disconnected)
+    ));
+
+    assert!(matches!(
+        events.recv().await.unwrap().event,
+        Event::Connected(_)
+    ));
+    assert!(events.recv().await.is_none());
+    assert!(sub.recv().await.is_err());
+}
+
+#[tokio::test]
+async fn test_drop_stops_reader() {
+    let (client, _server) = tokio::io::duplex(1024);
+    let (phd2, mut events) = Phd2Connection::from(client);
+    events.recv().await.unwrap();
+
+    drop(phd2);
+    tokio::time::timeout(Duration::from_secs(1), events.recv())
+        .await
+        .expect("reader to stop when the connection is dropped");
+}
+
+#[tokio::test]
+async fn test_call_raw() {
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond_with("get_algo_param", |params| {
+        assert_eq!(params["name"], json!("MinMove"));
+        Ok(json!(0.15))
+    });
+    let (phd2, _events) = server.connect().await.unwrap();
+
+    let result = phd2
+        .call_raw("get_algo_param", json!({"axis": "ra", "name": "MinMove"}))
+        .await
+        .unwrap();
+    assert_eq!(result, json!(0.15));
+    assert!(matches!(
+        phd2.call_raw("not_a_method", json!([])).await,
+        Err(ClientError::RpcError(RpcError {
+            code: testing::METHOD_NOT_FOUND,
+            ..
+        }))
+    ));
+}
+
+#[tokio::test]
+async fn test_builder() {
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond("get_pixel_scale", json!(1.5));
+
+    let read = Arc::new(std::sync::Mutex::new(vec![]));
+    let written = Arc::new(std::sync::Mutex::new(vec![]));
+    let (phd2, _events) = Phd2ConnectionBuilder::new()
+        .host("127.0.0.1")
+        .port(server.addr().port())
+        .method_timeout("find_star", Duration::from_secs(30))
+        .inspect_read({
+            let read = read.clone();
+            move |line| read.lock().unwrap().push(String::from(line))
+        })
+        .inspect_write({
+            let written = written.clone();
+            move |line| written.lock().unwrap().push(String::from(line))
+        })
+        .connect()
+        .await
+        .unwrap();
+
+    assert_eq!(phd2.get_pixel_scale().await.unwrap(), 1.5);
+    assert_eq!(phd2.timeout_for("find_star"), Duration::from_secs(30));
+    assert_eq!(phd2.timeout_for("guide"), DEFAULT_RPC_TIMEOUT);
+
+    let written = written.lock().unwrap();
+    assert_eq!(written.len(), 1);
+    assert!(written[0].contains("get_pixel_scale"));
+    let read = read.lock().unwrap();
+    assert_eq!(read.len(), 1);
+    assert!(read[0].contains("1.5"));
+}
+
+#[tokio::test]
+async fn test_builder_reconnect() {
+    use tokio::io::AsyncWriteExt;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (phd2, mut events) = Phd2ConnectionBuilder::new()
+        .host("127.0.0.1")
+        .port(port)
+        .reconnect(ReconnectPolicy::Retry {
+            delay: Duration::from_millis(10),
+            max_attempts: Some(3),
+        })
+        .connect()
+        .await
+        .unwrap();
+
+    // Close the first connection, then send an event on the second.
+    drop(listener.accept().await.unwrap());
+    let (mut second, _) = listener.accept().await.unwrap();
+    let event = json!({"Event": "StartGuiding", "Timestamp": 1.0, "Host": "astro", "Inst": 1});
+    second
+        .write_all(format!("{}\r\n", event).as_bytes())
+        .await
+        .unwrap();
+
+    let mut received = vec![];
+    while received.len() < 4 {
+        received.push(events.recv().await.unwrap().event);
+    }
+    assert!(matches!(
+        received[..],
+        [
+            Event::Connected(_),
+            Event::Disconnected(_),
+            Event::Connected(_),
+            Event::StartGuiding(_)
+        ]
+    ));
+
+    // Give up after the last attempt once the listener is gone.
+    drop(second);
+    drop(listener);
+    while events.recv().await.is_some() {}
+    assert!(matches!(
+        phd2.get_app_state().await,
+        Err(ClientError::Disconnected)
+    ));
+}
+
+#[cfg(feature = "websocket")]
+#[tokio::test]
+async fn test_websocket() {
+    use futures::SinkExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let proxy = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let request = futures::StreamExt::next(&mut socket)
+            .await
+            .unwrap()
+            .unwrap()
+            .into_text()
+            .unwrap();
+        let request: serde_json::Value = serde_json::from_str(&request).unwrap();
+        // Split the response across messages like a proxy forwarding raw reads.
+        let response = format!(
+            "{}\r\n",
+            json!({"jsonrpc": "2.0", "id": request["id"], "result": 1.5})
+        );
+        let (first, second) = response.split_at(10);
+        socket.send(Message::Text(first.into())).await.unwrap();
+        socket.send(Message::Text(second.into())).await.unwrap();
+        request
+    });
+
+    let (phd2, _events) = websocket::connect(&url).await.unwrap();
+    assert_eq!(phd2.get_pixel_scale().await.unwrap(), 1.5);
+    assert_eq!(proxy.await.unwrap()["method"], json!("get_pixel_scale"));
+}
+
+#[tokio::test]
+async fn test_pool() {
+    let east = testing::MockServer::bind().await.unwrap();
+    east.respond("get_pixel_scale", json!(1.5));
+    let west = testing::MockServer::bind().await.unwrap();
+    west.respond("get_pixel_scale", json!(2.5));
+
+    let mut pool = pool::Phd2Pool::new();
+    let mut events = pool.subscribe();
+    assert!(pool.add("east", east.connect().await.unwrap().0).is_none());
+    assert!(pool.add("west", west.connect().await.unwrap().0).is_none());
+
+    let mut rigs: Vec<&str> = pool.rigs().collect();
+    rigs.sort();
+    assert_eq!(rigs, vec!["east", "west"]);
+    assert_eq!(
+        pool.rig("east").unwrap().get_pixel_scale().await.unwrap(),
+        1.5
+    );
+    assert_eq!(
+        pool.rig("west").unwrap().get_pixel_scale().await.unwrap(),
+        2.5
+    );
+    assert!(pool.rig("north").is_none());
+
+    west.emit(json!({"Event": "StartGuiding"}));
+    let event = events.recv().await.unwrap();
+    assert_eq!(&*event.rig, "west");
+    assert!(matches!(event.event.event, Event::StartGuiding(_)));
+
+    // Events from a removed rig are no longer forwarded.
+    let removed = pool.remove("west").unwrap();
+    west.emit(json!({"Event": "StartGuiding"}));
+    east.emit(json!({"Event": "GuidingStopped"}));
+    let event = events.recv().await.unwrap();
+    assert_eq!(&*event.rig, "east");
+    assert!(matches!(event.event.event, Event::GuidingStopped(_)));
+    drop(removed);
+}
+
+#[tokio::test]
+async fn test_pixel_scale() {
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond("get_pixel_scale", json!(1.5));
+    let (phd2, _events) = server.connect().await.unwrap();
+
+    let pixel_scale = pixel_scale::PixelScale::new(Arc::new(phd2)).await.unwrap();
+    assert_eq!(pixel_scale.get(), 1.5);
+    assert_eq!(pixel_scale.arcsec(2.0), 3.0);
+
+    let mut step = guide_step(1, 2.0, -1.0, 20.0);
+    step.dx = 3.0;
+    step.dy = 4.0;
+    let arcsec = pixel_scale.guide_step(&step);
+    assert_eq!(arcsec.dx, 4.5);
+    assert_eq!(arcsec.dy, 6.0);
+    assert_eq!(arcsec.total(), 7.5);
+    assert_eq!(arcsec.ra_distance_raw, 3.0);
+    assert_eq!(arcsec.de_distance_guide, -1.5);
+
+    let mut changes = pixel_scale.subscribe();
+    server.respond("get_pixel_scale", json!(2.5));
+    server.emit(json!({"Event": "ConfigurationChange"}));
+    changes.changed().await.unwrap();
+    assert_eq!(pixel_scale.get(), 2.5);
+}
+
+#[tokio::test]
+async fn test_ensure_profile_connected() {
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond(
+        "get_profiles",
+        json!([{"id": 1, "name": "Default"}, {"id": 2, "name": "Simulator"}]),
+    );
+    server.respond("get_profile", json!({"id": 1, "name": "Default"}));
+    server.respond("get_connected", json!(false));
+    server.respond("stop_capture", json!(0));
+    server.respond("set_profile", json!(0));
+    // The first attempt to connect fails.
+    let attempts = Arc::new(std::sync::atomic::AtomicU32::new(0));
+    server.respond_with("set_connected", {
+        let attempts = attempts.clone();
+        move |params| {
+            if params[0] == json!(true) && attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(RpcError {
+                    code: 1,
+                    message: String::from("camera not ready"),
+                })
+            } else {
+                Ok(json!(0))
             }
+        }
+    });
+    let (phd2, _events) = server.connect().await.unwrap();
 
-            assert!(phd2.get_calibrated().await?);
-            phd2.get_calibration_data(WhichDevice::Mount).await?;
-            phd2.set_guide_output_enabled(false).await?;
-            assert!(!phd2.get_guide_output_enabled().await?);
-            phd2.set_guide_output_enabled(true).await?;
+    let profile = phd2.ensure_profile_connected("Simulator").await.unwrap();
+    assert_eq!(profile.id, 2);
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    let methods: Vec<serde_json::Value> = server
+        .requests()
+        .into_iter()
+        .map(|request| request["method"].clone())
+        .collect();
+    assert_eq!(
+        methods,
+        json!([
+            "get_profiles",
+            "get_profile",
+            "stop_capture",
+            "set_connected",
+            "set_profile",
+            "set_connected",
+            "set_connected"
+        ])
+        .as_array()
+        .unwrap()
+        .clone()
+    );
 
-            phd2.get_lock_position().await?;
-            phd2.get_star_image().await?;
+    assert!(matches!(
+        phd2.ensure_profile_connected("Missing").await,
+        Err(ClientError::ProfileNotFound(name)) if name == "Missing"
+    ));
+}
 
-            println!("Dither!");
-            phd2.dither(10.0, false, settle).await?;
+#[tokio::test]
+async fn test_start_guiding_auto() {
+    let server = Arc::new(testing::MockServer::bind().await.unwrap());
+    server.respond("loop", json!(0));
+    server.respond("guide", json!(0));
+    // No star is found in the first exposure.
+    let attempts = Arc::new(std::sync::atomic::AtomicU32::new(0));
+    server.respond_with("find_star", {
+        let attempts = attempts.clone();
+        move |_| {
+            if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(RpcError {
+                    code: 1,
+                    message: String::from("could not find a suitable star"),
+                })
+            } else {
+                Ok(json!([100.0, 200.0]))
+            }
+        }
+    });
+    let (phd2, _events) = server.connect().await.unwrap();
 
-            loop {
-                let event = events.recv().await.unwrap();
-                if let Event::SettleDone(event) = &event.event {
-                    assert_eq!(event.status, 0);
-                    break;
-                }
+    tokio::spawn({
+        let server = server.clone();
+        async move {
+            while !server
+                .requests()
+                .iter()
+                .any(|request| request["method"] == "guide")
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
             }
-            phd2.flip_calibration().await?;
-            let pos = phd2.get_lock_position().await?.unwrap();
-            phd2.set_lock_position(pos[0], pos[1], None).await?;
-            phd2.set_paused(true, true).await?;
-            assert!(phd2.get_paused().await?);
-
-            phd2.stop_capture().await?;
-        }
-        {
-            phd2.shutdown().await?;
-
-            tokio::time::timeout(Duration::from_secs(5), async move {
-                loop {
-                    if let None = events.recv().await {
-                        break;
-                    }
-                }
-            })
+            server.emit(json!({"Event": "SettleBegin"}));
+            server.emit(
+                json!({"Event": "SettleDone", "Status": 0, "TotalFrames": 3, "DroppedFrames": 0}),
+            );
+        }
+    });
+
+    let settle = Settle::new(1.5, Duration::from_secs(1), Duration::from_secs(60));
+    let done = phd2.start_guiding_auto(settle).await.unwrap();
+    assert_eq!(done.total_frames, 3);
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+    let methods: Vec<serde_json::Value> = server
+        .requests()
+        .into_iter()
+        .map(|request| request["method"].clone())
+        .collect();
+    assert_eq!(
+        methods,
+        vec![
+            json!("loop"),
+            json!("find_star"),
+            json!("find_star"),
+            json!("guide")
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_save_image() {
+    let path = std::env::temp_dir().join(format!("phd2_save_image_{}.fits", std::process::id()));
+    tokio::fs::write(&path, b"SIMPLE  =                    T")
+        .await
+        .unwrap();
+
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond("save_image", json!({"filename": path}));
+    let (phd2, _events) = server.connect().await.unwrap();
+
+    let saved = phd2.save_image().await.unwrap();
+    assert_eq!(saved.filename, path);
+    assert_eq!(
+        saved.take().await.unwrap(),
+        b"SIMPLE  =                    T"
+    );
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn test_settle_progress() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    respond_with_events(
+        server,
+        json!({"result": 0}),
+        vec![
+            json!({"Event": "SettleBegin"}),
+            json!({"Event": "Settling", "Distance": 2.5, "Time": 1.0, "SettleTime": 10.0, "StarLocked": true}),
+            json!({"Event": "GuideStep", "Frame": 1, "Time": 1.0, "Mount": "Mount", "dx": 0.1, "dy": 0.2, "RADistanceRaw": 0.1, "DECDistanceRaw": 0.2, "RADistanceGuide": 0.1, "DECDistanceGuide": 0.2, "StarMass": 1000.0, "SNR": 20.0, "HFD": 2.0, "AvgDist": 0.5}),
+            json!({"Event": "Settling", "Distance": 0.8, "Time": 2.0, "SettleTime": 10.0, "StarLocked": true}),
+            json!({"Event": "SettleDone", "Status": 0, "TotalFrames": 2, "DroppedFrames": 0}),
+        ],
+    );
+
+    let settle = Settle::new(1.5, Duration::from_secs(10), Duration::from_secs(60));
+    let mut settling = phd2.dither(5.0, false, settle).await.unwrap();
+    let progress: Vec<f64> = settling
+        .progress()
+        .unwrap()
+        .map(|settling| settling.distance)
+        .collect()
+        .await;
+    assert_eq!(progress, vec![2.5, 0.8]);
+    assert!(settling.progress().is_none());
+    assert_eq!(settling.await.unwrap().total_frames, 2);
+}
+
+#[test]
+fn test_calibration_analysis() {
+    use serialization::{Calibration, PierSide};
+
+    let calibration: Calibration = serde_json::from_value(json!({
+        "calibrated": true,
+        "xAngle": 170.0,
+        "xRate": 10.0,
+        "xParity": "+",
+        "yAngle": -85.0,
+        "yRate": 8.0,
+        "yParity": "-",
+    }))
+    .unwrap();
+    assert_eq!(calibration.ra_rate_arcsec(1.5), Some(15.0));
+    assert_eq!(calibration.dec_rate_arcsec(1.5), Some(12.0));
+    assert!((calibration.orthogonality_error().unwrap() - 15.0).abs() < 1e-9);
+    assert!(calibration.flip_expected(PierSide::East, PierSide::West));
+    assert!(!calibration.flip_expected(PierSide::East, PierSide::East));
+    assert!(!calibration.flip_expected(PierSide::Unknown, PierSide::West));
+
+    let uncalibrated: Calibration = serde_json::from_value(json!({"calibrated": false})).unwrap();
+    assert_eq!(uncalibrated.ra_rate_arcsec(1.5), None);
+    assert_eq!(uncalibrated.orthogonality_error(), None);
+    assert!(!uncalibrated.flip_expected(PierSide::East, PierSide::West));
+}
+
+#[test]
+fn test_unknown_event() {
+    let message: ServerMessage = serde_json::from_value(json!({
+        "Event": "BrandNewEvent",
+        "Timestamp": 1684470047.430,
+        "Host": "astro",
+        "Inst": 1,
+        "Value": 42,
+    }))
+    .unwrap();
+    let ServerMessage::ServerEvent(event) = message else {
+        panic!("expected an event, got {:?}", message);
+    };
+    assert_eq!(event.host, "astro");
+    match event.event {
+        Event::Unknown { event, raw } => {
+            assert_eq!(event, "BrandNewEvent");
+            assert_eq!(raw["Value"], json!(42));
+        }
+        other => panic!("expected Unknown, got {:?}", other),
+    }
+
+    // Responses still aren't mistaken for events.
+    let message: ServerMessage =
+        serde_json::from_value(json!({"jsonrpc": "2.0", "id": 1, "result": 0})).unwrap();
+    assert!(matches!(message, ServerMessage::JsonRpcResponse(_)));
+}
+
+#[test]
+fn test_blocking_client() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let server = runtime.block_on(testing::MockServer::bind()).unwrap();
+    server.respond("get_pixel_scale", json!(1.5));
+
+    let mut phd2 = blocking::Client::connect(server.addr()).unwrap();
+    assert!(matches!(
+        phd2.recv_event().unwrap().event,
+        Event::Connected(_)
+    ));
+    assert_eq!(phd2.get_pixel_scale().unwrap(), 1.5);
+
+    server.emit(json!({"Event": "StartGuiding"}));
+    assert!(matches!(
+        phd2.recv_event().unwrap().event,
+        Event::StartGuiding(_)
+    ));
+    phd2.disconnect().unwrap();
+}
+
+/// Sends `events` to a connection with room for two events using `overflow`, returning the
+/// events it received once the connection closes.
+async fn overflow_events(
+    overflow: OverflowPolicy,
+    events: &[&str],
+) -> (Phd2Connection<tokio::io::DuplexStream>, Vec<String>) {
+    use tokio::io::AsyncWriteExt;
+
+    let (client, mut server) = tokio::io::duplex(4096);
+    let (phd2, mut recv) = Phd2ConnectionBuilder::new()
+        .event_buffer_size(2)
+        .overflow(overflow)
+        .build(client);
+    let mut sub = phd2.subscribe();
+    for event in events {
+        let event =
+            json!({"Event": event, "Timestamp": 1684470047.430, "Host": "astro", "Inst": 1});
+        server
+            .write_all(format!("{}\n", event).as_bytes())
             .await
-            .expect("Waiting for client to disconnect");
-            let shutdown = tokio::time::timeout(Duration::from_secs(5), phd2_instance.wait()).await;
+            .unwrap();
+    }
+    // Subscriptions have the same buffer size, so may lag before the last event.
+    let last = events.last().unwrap();
+    while !matches!(sub.recv().await, Ok(event) if event.event.name() == *last) {}
+    drop(server);
 
-            if let Ok(Ok(status)) = shutdown {
-                assert!(status.success());
-            } else {
-                dbg!(&shutdown);
-                phd2_instance.kill().await.expect("Killing phd2");
-                panic!("Shutting down phd2 didn't work");
+    let mut received = vec![];
+    while let Some(event) = recv.recv().await {
+        received.push(String::from(event.event.name()));
+    }
+    // Wait for the reader to finish counting.
+    while !matches!(
+        sub.recv().await,
+        Err(tokio::sync::broadcast::error::RecvError::Closed)
+    ) {}
+    (phd2, received)
+}
+
+#[tokio::test]
+async fn test_overflow_drop_newest() {
+    let (phd2, received) = overflow_events(
+        OverflowPolicy::DropNewest,
+        &["StartGuiding", "Paused", "Resumed"],
+    )
+    .await;
+    assert_eq!(received, ["Connected", "StartGuiding", "Disconnected"]);
+    assert_eq!(phd2.dropped_events(), 2);
+}
+
+#[tokio::test]
+async fn test_overflow_drop_event_types() {
+    let (phd2, received) = overflow_events(
+        OverflowPolicy::DropEventTypes(vec![String::from("Paused")]),
+        &["StartGuiding", "Paused", "Resumed"],
+    )
+    .await;
+    assert_eq!(
+        received,
+        ["Connected", "StartGuiding", "Resumed", "Disconnected"]
+    );
+    assert_eq!(phd2.dropped_events(), 1);
+}
+
+#[tokio::test]
+async fn test_overflow_wait() {
+    let (phd2, received) = overflow_events(OverflowPolicy::Wait, &["StartGuiding", "Paused"]).await;
+    assert_eq!(
+        received,
+        ["Connected", "StartGuiding", "Paused", "Disconnected"]
+    );
+    assert_eq!(phd2.dropped_events(), 0);
+}
+
+#[tokio::test]
+async fn test_overflow_close() {
+    let (phd2, received) = overflow_events(
+        OverflowPolicy::Close,
+        &["StartGuiding", "Paused", "Resumed"],
+    )
+    .await;
+    assert_eq!(received, ["Connected", "StartGuiding"]);
+    assert_eq!(phd2.dropped_events(), 3);
+}
+
+#[tokio::test]
+async fn test_star_lost_recovery() {
+    use recovery::{RecoveryPolicy, StarLostRecovery};
+
+    let server = Arc::new(testing::MockServer::bind().await.unwrap());
+    server.respond("get_app_state", json!("LostLock"));
+    server.respond("stop_capture", json!(0));
+    server.respond("loop", json!(0));
+    server.respond("find_star", json!([100.0, 200.0]));
+    server.respond("guide", json!(0));
+    let (phd2, _events) = server.connect().await.unwrap();
+
+    let (sender, mut progress) = tokio::sync::mpsc::unbounded_channel();
+    let settle = Settle::new(1.5, Duration::from_secs(1), Duration::from_secs(60));
+    let policy = RecoveryPolicy::new(settle)
+        .retry_delay(Duration::from_millis(10))
+        .within_roi(100)
+        .on_event(move |event| {
+            sender.send(format!("{:?}", event)).ok();
+        });
+    let _recovery = StarLostRecovery::new(Arc::new(phd2), policy);
+
+    // Lost while looping, not guiding, so ignored.
+    let star_lost = json!({
+        "Event": "StarLost", "Frame": 10, "Time": 5.0, "StarMass": 0.0, "SNR": 0.0,
+        "AvgDist": 0.5, "ErrorCode": 1, "Status": "star lost",
+    });
+    server.emit(json!({"Event": "LoopingExposures", "Frame": 1}));
+    server.emit(star_lost.clone());
+    server.emit(json!({"Event": "LockPositionSet", "X": 150.0, "Y": 250.0}));
+    server.emit(json!({"Event": "StartGuiding"}));
+    server.emit(star_lost);
+
+    assert_eq!(progress.recv().await.unwrap(), "StarLost");
+    assert_eq!(progress.recv().await.unwrap(), "Attempt(1)");
+    while !server
+        .requests()
+        .iter()
+        .any(|request| request["method"] == "guide")
+    {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    server.emit(json!({"Event": "SettleDone", "Status": 0, "TotalFrames": 3, "DroppedFrames": 0}));
+    assert_eq!(progress.recv().await.unwrap(), "Recovered { attempts: 1 }");
+
+    let requests = server.requests();
+    let methods: Vec<&serde_json::Value> =
+        requests.iter().map(|request| &request["method"]).collect();
+    assert_eq!(
+        methods,
+        [
+            "get_app_state",
+            "stop_capture",
+            "loop",
+            "find_star",
+            "guide"
+        ]
+    );
+    assert_eq!(requests[3]["params"]["roi"], json!([100, 200, 100, 100]));
+}
+
+#[tokio::test]
+async fn test_get_status_snapshot() {
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond("get_app_state", json!("Guiding"));
+    server.respond("get_connected", json!(true));
+    server.respond("get_calibrated", json!(true));
+    server.respond("get_paused", json!(false));
+    server.respond("get_pixel_scale", json!(1.5));
+    server.respond(
+        "get_current_equipment",
+        json!({"camera": {"name": "Simulator", "connected": true}}),
+    );
+    let (phd2, _events) = server.connect().await.unwrap();
+
+    let status = phd2.get_status_snapshot().await.unwrap();
+    assert_eq!(status.app_state, State::Guiding);
+    assert!(status.connected);
+    assert!(status.calibrated);
+    assert!(!status.paused);
+    assert_eq!(status.pixel_scale, 1.5);
+    assert_eq!(
+        status.equipment["camera"],
+        Equipment {
+            connected: true,
+            name: String::from("Simulator")
+        }
+    );
+    assert_eq!(server.requests().len(), 6);
+
+    // Any call failing fails the snapshot.
+    server.respond_with("get_paused", |_| {
+        Err(RpcError {
+            code: 1,
+            message: String::from("failed"),
+        })
+    });
+    assert!(matches!(
+        phd2.get_status_snapshot().await,
+        Err(ClientError::RpcError(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_server_version() {
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond(
+        "get_variable_delay_settings",
+        json!({"Enabled": false, "ShortDelaySeconds": 1, "LongDelaySeconds": 30}),
+    );
+    let (phd2, mut events) = server.connect().await.unwrap();
+    assert!(phd2.server_version().is_none());
+    // Allowed until the version is known.
+    phd2.get_variable_delay_settings().await.unwrap();
+
+    server.emit(json!({
+        "Event": "Version", "PHDVersion": "2.6.9", "PHDSubver": "dev4",
+        "OverlapSupport": true, "MsgVersion": 1,
+    }));
+    while !matches!(events.recv().await.unwrap().event, Event::Version(_)) {}
+    assert_eq!(phd2.server_version().unwrap().phd_version, "2.6.9");
+    match phd2.get_variable_delay_settings().await {
+        Err(ClientError::UnsupportedByServer {
+            method,
+            required,
+            version,
+        }) => {
+            assert_eq!(method, "get_variable_delay_settings");
+            assert_eq!(required, "2.6.10");
+            assert_eq!(version, "2.6.9");
+        }
+        other => panic!("expected UnsupportedByServer, got {:?}", other),
+    }
+    assert!(matches!(
+        phd2.call_with_timeout(
+            "get_variable_delay_settings",
+            json!([]),
+            Duration::from_secs(1)
+        )
+        .await,
+        Err(ClientError::UnsupportedByServer { .. })
+    ));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn test_version_is_at_least() {
+    let version = |phd_version: &str| Version {
+        phd_version: String::from(phd_version),
+        phd_subver: String::new(),
+        overlap_support: true,
+        msg_version: 1,
+    };
+    assert!(version("2.6.10").is_at_least("2.6.10"));
+    assert!(version("2.6.11").is_at_least("2.6.10"));
+    assert!(version("2.7").is_at_least("2.6.10"));
+    assert!(!version("2.6.9").is_at_least("2.6.10"));
+    assert!(!version("2.6").is_at_least("2.6.10"));
+    assert!(version("2.6.13dev1").is_at_least("2.6.13"));
+}
+
+#[tokio::test]
+async fn test_dither_coordinator() {
+    let server = Arc::new(testing::MockServer::bind().await.unwrap());
+    server.respond("dither", json!(0));
+    let (phd2, _events) = server.connect().await.unwrap();
+
+    // Settle after each dither.
+    let settler = tokio::spawn({
+        let server = server.clone();
+        async move {
+            let mut settled = 0;
+            loop {
+                let dithers = server
+                    .requests()
+                    .iter()
+                    .filter(|request| request["method"] == "dither")
+                    .count();
+                if dithers > settled {
+                    settled = dithers;
+                    server.emit(
+                        json!({"Event": "SettleDone", "Status": 0, "TotalFrames": 3, "DroppedFrames": 0}),
+                    );
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
             }
         }
-        Ok(())
+    });
+
+    let settle = Settle::new(1.5, Duration::from_secs(1), Duration::from_secs(60));
+    let mut coordinator = dither::DitherCoordinator::new(Arc::new(phd2), 3.0, settle)
+        .every(2)
+        .ra_only(true);
+    let mut dithers = vec![];
+    for (frame, until_dither) in [2, 1, 0, 1, 0].into_iter().enumerate() {
+        assert_eq!(coordinator.frames_until_dither(), until_dither);
+        let captured = coordinator.capture(|| async move { frame }).await.unwrap();
+        assert_eq!(captured, frame);
+        dithers.push(
+            server
+                .requests()
+                .iter()
+                .filter(|request| request["method"] == "dither")
+                .count(),
+        );
+    }
+    // Dithered before the third and fifth frames.
+    assert_eq!(dithers, [0, 0, 1, 1, 2]);
+    assert_eq!(
+        server.requests()[0]["params"],
+        json!({"amount": 3.0, "raOnly": true, "settle": {"pixels": 1.5, "time": 1.0, "timeout": 60.0}})
+    );
+    settler.abort();
+}
+
+#[tokio::test]
+async fn test_process_exits_during_startup() {
+    let options = process::ProcessOptions::new()
+        .program("false")
+        .instance(60)
+        .startup_timeout(Duration::from_secs(5));
+    assert_eq!(options.port(), 4459);
+    let result = process::Phd2Process::spawn(&options).await;
+    assert!(matches!(result, Err(process::ProcessError::Exited(_))));
+
+    let options = options.program("./not-phd2");
+    let result = process::Phd2Supervisor::spawn(options).await;
+    assert!(matches!(result, Err(process::ProcessError::IoError(_))));
+}
+
+#[tokio::test]
+async fn test_stats() {
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond("get_pixel_scale", json!(1.5));
+    let (phd2, mut events) = server.connect().await.unwrap();
+    assert_eq!(phd2.stats().requests_completed, 0);
+    assert_eq!(phd2.stats().average_round_trip, None);
+
+    for _ in 0..3 {
+        phd2.get_pixel_scale().await.unwrap();
+    }
+    server.emit(json!({"Event": "StartGuiding"}));
+    server.emit(json!({"Event": "Paused"}));
+    while !matches!(events.recv().await.unwrap().event, Event::Paused(_)) {}
+
+    let stats = phd2.stats();
+    assert_eq!(stats.requests_in_flight, 0);
+    assert_eq!(stats.requests_completed, 3);
+    assert!(stats.average_round_trip.is_some());
+    assert_eq!(stats.events_received, 2);
+    assert!(stats.events_per_second > 0.0);
+
+    // Calls count as in flight until they're answered or given up on.
+    let (client, _server) = tokio::io::duplex(1024);
+    let (phd2, _events) = Phd2Connection::from(client);
+    let phd2 = Arc::new(phd2);
+    let call = tokio::spawn({
+        let phd2 = phd2.clone();
+        async move { phd2.get_pixel_scale().await }
+    });
+    while phd2.stats().requests_in_flight == 0 {
+        tokio::task::yield_now().await;
     }
+    call.abort();
+    call.await.ok();
+    assert_eq!(phd2.stats().requests_in_flight, 0);
+    assert_eq!(phd2.stats().requests_completed, 0);
+}
+
+#[tokio::test]
+async fn test_track_moving_target() {
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond("set_lock_shift_params", json!(0));
+    server.respond("set_lock_shift_enabled", json!(0));
+    server.respond(
+        "get_lock_shift_params",
+        json!({"enabled": true, "rate": [12.5, -3.0], "units": "arcsec/hr", "axes": "RA/Dec"}),
+    );
+    let (phd2, _events) = server.connect().await.unwrap();
+
+    let params = phd2
+        .track_moving_target(12.5, -3.0, LockShiftUnits::ArcsecPerHour)
+        .await
+        .unwrap();
+    assert_eq!(params.rate, [12.5, -3.0]);
+    let requests = server.requests();
+    assert_eq!(
+        requests[0]["params"],
+        json!({"rate": [12.5, -3.0], "units": "arcsec/hr", "axes": "RA/Dec"})
+    );
+    assert_eq!(requests[1]["params"], json!([true]));
+
+    match phd2
+        .track_moving_target(12.5, -3.0, LockShiftUnits::PixelsPerHour)
+        .await
+    {
+        Err(ClientError::LockShiftNotApplied(params)) => assert_eq!(params.units, "arcsec/hr"),
+        other => panic!("expected LockShiftNotApplied, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_check_equipment() {
+    use equipment::{EquipmentMismatch, ExpectedEquipment};
+
+    let server = testing::MockServer::bind().await.unwrap();
+    server.respond(
+        "get_current_equipment",
+        json!({
+            "camera": {"name": "Simulator", "connected": true},
+            "mount": {"name": "On-camera", "connected": false},
+            "AO": {"name": "SX AO", "connected": true},
+        }),
+    );
+    let (phd2, _events) = server.connect().await.unwrap();
+
+    let expected = ExpectedEquipment::new()
+        .camera("Simulator")
+        .mount("On-camera")
+        .device("rotator", "Simulator");
+    assert_eq!(
+        phd2.check_equipment(&expected).await.unwrap(),
+        [
+            EquipmentMismatch::Disconnected {
+                kind: String::from("mount")
+            },
+            EquipmentMismatch::Missing {
+                kind: String::from("rotator"),
+                expected: String::from("Simulator")
+            },
+            EquipmentMismatch::Unexpected {
+                kind: String::from("AO"),
+                actual: String::from("SX AO")
+            },
+        ]
+    );
+
+    let expected = ExpectedEquipment::new()
+        .camera("ZWO ASI120MM")
+        .allow_others(true);
+    let mismatches = phd2.check_equipment(&expected).await.unwrap();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(
+        mismatches[0].to_string(),
+        "expected camera \"ZWO ASI120MM\", found \"Simulator\""
+    );
+}
+
+#[test]
+fn test_clock_estimator() {
+    use clock::ClockEstimator;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let message: ServerMessage = serde_json::from_value(json!({
+        "Event": "BrandNewEvent",
+        "Timestamp": 1000.0,
+        "Host": "astro",
+        "Inst": 1,
+    }))
+    .unwrap();
+    let ServerMessage::ServerEvent(event) = message else {
+        panic!("expected an event, got {:?}", message);
+    };
+    let age = SystemTime::now().duration_since(event.received).unwrap();
+    assert!(age < Duration::from_secs(1));
+
+    // The local clock is 10s ahead, events take between 0.1s and 0.3s to arrive.
+    let received = |at: f64| ServerEvent {
+        received: UNIX_EPOCH + Duration::from_secs_f64(at),
+        ..event.clone()
+    };
+    assert!((received(1010.2).offset() - 10.2).abs() < 1e-9);
+
+    let mut clock = ClockEstimator::new(3);
+    assert_eq!(clock.estimate(None), None);
+    clock.add(&received(1015.0));
+    for at in [1010.1, 1010.2, 1010.3] {
+        clock.add(&received(at));
+    }
+    // Client events don't count.
+    clock.add(&connection_event(Event::Disconnected(Disconnected {})));
+
+    let estimate = clock.estimate(Some(Duration::from_millis(200))).unwrap();
+    assert!((estimate.skew - 10.0).abs() < 1e-9, "{:?}", estimate);
+    assert!((estimate.latency - 0.2).abs() < 1e-9, "{:?}", estimate);
+    let local = estimate.to_local(1000.0).duration_since(UNIX_EPOCH).unwrap();
+    assert!((local.as_secs_f64() - 1010.0).abs() < 1e-6, "{:?}", local);
+
+    // Without a round trip all of the smallest offset counts as skew.
+    let estimate = clock.estimate(None).unwrap();
+    assert!((estimate.skew - 10.1).abs() < 1e-9, "{:?}", estimate);
 }
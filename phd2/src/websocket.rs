@@ -0,0 +1,151 @@
+//! Connecting to phd2 through a websocket, for phd2 instances behind a websocket proxy.
+//! Enabled with the `websocket` feature.
+//!
+//! [WebSocketTransport] adapts a [tokio_tungstenite] websocket into the byte stream
+//! [Phd2Connection] reads from.  The proxy is expected to forward phd2's stream as is:
+//! messages received are read as raw bytes of the stream, and each request line is sent
+//! as a text message.
+//! # Example
+//! ```no_run
+//! #[tokio::main]
+//! async fn main() {
+//!     let (phd2, mut events) = phd2::websocket::connect("ws://observatory.local:8080/phd2")
+//!         .await
+//!         .expect("Connecting to phd2");
+//!     println!("{:?}", phd2.get_app_state().await);
+//!     while let Some(event) = events.recv().await {
+//!         println!("{:?}", event.event);
+//!     }
+//! }
+//! ```
+
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures::{Sink, Stream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    sync::mpsc::Receiver,
+};
+use tokio_tungstenite::{
+    tungstenite::{self, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{serialization::ServerEvent, Phd2Connection};
+
+/// A websocket read and written as a byte stream.
+pub struct WebSocketTransport<S> {
+    inner: S,
+    read: Vec<u8>,
+    read_pos: usize,
+    /// Written bytes waiting for the end of the line.
+    write: Vec<u8>,
+    /// Bytes of the current write sent in a message that is still being flushed.
+    sending: Option<usize>,
+}
+
+impl<S> WebSocketTransport<S> {
+    pub fn new(inner: S) -> Self {
+        WebSocketTransport {
+            inner,
+            read: vec![],
+            read_pos: 0,
+            write: vec![],
+            sending: None,
+        }
+    }
+}
+
+fn io_error(e: tungstenite::Error) -> std::io::Error {
+    std::io::Error::other(e)
+}
+
+impl<S> AsyncRead for WebSocketTransport<S>
+where
+    S: Stream<Item = Result<Message, tungstenite::Error>> + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        while this.read_pos == this.read.len() {
+            this.read = match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(Ok(Message::Text(text))) => text.into_bytes(),
+                Some(Ok(Message::Binary(bytes))) => bytes,
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Poll::Ready(Err(io_error(e))),
+            };
+            this.read_pos = 0;
+        }
+        let len = buf.remaining().min(this.read.len() - this.read_pos);
+        buf.put_slice(&this.read[this.read_pos..this.read_pos + len]);
+        this.read_pos += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for WebSocketTransport<S>
+where
+    S: Sink<Message, Error = tungstenite::Error> + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        let this = &mut *self;
+        if this.sending.is_none() {
+            let end = match buf.iter().position(|byte| *byte == b'\n') {
+                Some(newline) => newline + 1,
+                None => {
+                    this.write.extend_from_slice(buf);
+                    return Poll::Ready(Ok(buf.len()));
+                }
+            };
+            ready!(Pin::new(&mut this.inner).poll_ready(cx)).map_err(io_error)?;
+            let mut line = std::mem::take(&mut this.write);
+            line.extend_from_slice(&buf[..end]);
+            let line = String::from_utf8(line)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            Pin::new(&mut this.inner)
+                .start_send(Message::Text(line))
+                .map_err(io_error)?;
+            this.sending = Some(end);
+        }
+        // Requests aren't flushed by the caller, so each line is flushed as it's sent.
+        ready!(Pin::new(&mut this.inner).poll_flush(cx)).map_err(io_error)?;
+        Poll::Ready(Ok(this.sending.take().unwrap_or_default()))
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx).map_err(io_error)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx).map_err(io_error)
+    }
+}
+
+/// The transport used by connections from [connect].
+pub type WebSocket = WebSocketTransport<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+
+/// Connects to phd2 through the websocket at `url`.
+pub async fn connect(
+    url: &str,
+) -> Result<(Phd2Connection<WebSocket>, Receiver<ServerEvent>), tungstenite::Error> {
+    let (socket, _) = tokio_tungstenite::connect_async(url).await?;
+    Ok(Phd2Connection::from(WebSocketTransport::new(socket)))
+}
@@ -0,0 +1,133 @@
+// #![cfg(feature = "test_phd2_simulator")]
+use phd2::serialization::*;
+use std::time::Duration;
+use twinkle_testkit::phd2_simulator::{Phd2Simulator, SIMULATOR_PROFILE};
+
+#[tokio::test]
+async fn test_integration_phd2_simulator() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Starting phd2");
+    let phd2_instance = Phd2Simulator::spawn().await?;
+    let (phd2, mut events) = phd2_instance.connect_simulator().await?;
+
+    assert_eq!(
+        phd2.get_profile().await?.name,
+        String::from(SIMULATOR_PROFILE)
+    );
+
+    phd2.set_connected(true).await?;
+    assert!(phd2.get_connected().await?);
+
+    assert_eq!(phd2.get_app_state().await?, State::Stopped);
+
+    phd2.clear_calibration(ClearCalibrationParam::Both).await?;
+    assert!(!phd2.get_calibrated().await?);
+
+    phd2.get_camera_frame_size().await?;
+    phd2.get_current_equipment().await?;
+
+    phd2.get_cooler_status().await?;
+    assert!(!phd2.export_config_settings().await?.filename.is_empty());
+
+    phd2.set_dec_guide_mode(DecGuideMode::Auto).await?;
+    assert_eq!(phd2.get_dec_guide_mode().await?, DecGuideMode::Auto);
+
+    let exp = phd2.get_exposure_durations().await?[0];
+    phd2.set_exposure(exp).await?;
+    assert_eq!(phd2.get_exposure().await?, exp);
+
+    phd2.set_guide_output_enabled(true).await?;
+    assert!(phd2.get_guide_output_enabled().await?);
+
+    phd2.get_lock_shift_params().await?;
+    phd2.set_lock_shift_params([1.0, 1.0], "arcsec/hr", "RA/Dec")
+        .await?;
+    phd2.set_lock_shift_enabled(false).await?;
+    assert!(!phd2.get_lock_shift_enabled().await?);
+
+    let param = &phd2.get_algo_param_names(Axis::Dec).await?[1];
+    let value = phd2.get_algo_param(Axis::Dec, param).await?;
+    phd2.set_algo_param(Axis::Dec, param, value).await?;
+
+    phd2.get_pixel_scale().await?;
+    phd2.get_search_region().await?;
+    assert!(!phd2.get_settling().await?);
+    phd2.get_ccd_temperature().await?;
+    let delay = phd2.get_variable_delay_settings().await?;
+    phd2.set_variable_delay_settings(delay).await?;
+    assert_eq!(phd2.get_variable_delay_settings().await?, delay);
+    phd2.get_use_subframes().await?;
+
+    // Start doing frame-things
+    phd2.capture_single_frame(Duration::from_secs(1), None)
+        .await?;
+
+    phd2.set_exposure(Duration::from_secs(1)).await?;
+    {
+        println!("Starting looping");
+        phd2.loop_().await?;
+
+        let mut frame_count = 0;
+        loop {
+            dbg!(frame_count);
+            let event = events.recv().await.unwrap();
+            if let Event::LoopingExposures(_) = &event.event {
+                frame_count += 1;
+                if frame_count > 5 {
+                    break;
+                }
+            }
+        }
+        phd2.guide_pulse(10, PulseDirection::E, None).await?;
+        phd2.find_star(Some([621, 356, 50, 50])).await?;
+    }
+    {
+        println!("Starting guiding");
+        let settle = Settle::new(1.5, Duration::from_secs(1), Duration::from_secs(60));
+        phd2.guide_and_wait(settle, Some(true), None).await?;
+
+        assert!(phd2.get_calibrated().await?);
+        phd2.get_calibration_data(WhichDevice::Mount).await?;
+        phd2.set_guide_output_enabled(false).await?;
+        assert!(!phd2.get_guide_output_enabled().await?);
+        phd2.set_guide_output_enabled(true).await?;
+
+        phd2.get_lock_position().await?;
+        let image = phd2.get_star_image(Some(32)).await?;
+        let pixels = image.to_array().unwrap();
+        assert_eq!(pixels.dim(), (32, 32));
+        // The brightest pixel should be at the guide star, which won't hold if the pixels
+        // are decoded with the wrong byte order.
+        let (brightest, _) = pixels
+            .indexed_iter()
+            .max_by_key(|(_, value)| **value)
+            .unwrap();
+        assert!((brightest.1 as f64 - image.star_pos[0]).abs() < 3.0);
+        assert!((brightest.0 as f64 - image.star_pos[1]).abs() < 3.0);
+
+        println!("Dither!");
+        phd2.dither_and_settle(10.0, false, settle).await?;
+        phd2.flip_calibration().await?;
+        let pos = phd2.get_lock_position().await?.unwrap();
+        phd2.set_lock_position(pos[0], pos[1], None).await?;
+        phd2.set_paused(true, true).await?;
+        assert!(phd2.get_paused().await?);
+
+        phd2.stop_capture().await?;
+    }
+    {
+        phd2.shutdown().await?;
+
+        tokio::time::timeout(Duration::from_secs(5), async move {
+            loop {
+                if let None = events.recv().await {
+                    break;
+                }
+            }
+        })
+        .await
+        .expect("Waiting for client to disconnect");
+        let status = phd2_instance.shutdown().await.expect("Shutting down phd2");
+        assert!(status.success());
+    }
+    Ok(())
+}
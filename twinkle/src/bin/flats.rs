@@ -124,9 +124,12 @@ impl FlatApp {
                 let bins = self.telescope.block_on(async {
                     let camera = self.telescope.get_primary_camera().await.unwrap();
                     let bin_param = camera.get_parameter("CCD_BINNING").await.unwrap();
-                    let lock = bin_param.lock().await;
-                    let values = lock.get_values::<HashMap<String, Number>>().unwrap();
-                    (values["HOR_BIN"].min as u8)..=(values["HOR_BIN"].max as u8)
+                    let horizontal = bin_param
+                        .lock()
+                        .await
+                        .value_of::<Number>("HOR_BIN")
+                        .unwrap();
+                    (horizontal.min as u8)..=(horizontal.max as u8)
                 });
                 for bin in bins {
                     let entry = self.config.binnings.entry(bin).or_insert_with(|| false);
@@ -2,7 +2,7 @@ use fits_inspect::analysis::sep::CatalogEntry;
 use fits_inspect::analysis::{sep, HyperbolicFit, Star, Statistics};
 use indi::*;
 use std::time::Duration;
-use std::{collections::HashMap, env};
+use std::env;
 use twinkle::*;
 
 pub struct FocusMeasurement {
@@ -89,12 +89,8 @@ async fn main() {
         .unwrap()
         .lock()
         .await
-        .get_values::<HashMap<String, Number>>()
-        .unwrap()
-        .get("FOCUS_ABSOLUTE_POSITION")
-        .unwrap()
-        .value
-        .into();
+        .value_of("FOCUS_ABSOLUTE_POSITION")
+        .unwrap();
 
     let focus_config = AutoFocusConfig {
         exposure: Duration::from_secs(1),
@@ -119,12 +115,8 @@ async fn main() {
             .unwrap()
             .lock()
             .await
-            .get_values::<HashMap<String, Number>>()
-            .unwrap()
-            .get("FOCUS_ABSOLUTE_POSITION")
-            .unwrap()
-            .value
-            .into();
+            .value_of("FOCUS_ABSOLUTE_POSITION")
+            .unwrap();
 
         let fits_data = camera
             .capture_image_from_param(focus_config.exposure, &ccd)
@@ -0,0 +1,55 @@
+use indi::{client::Client, testing::MockServer};
+
+use crate::Error;
+
+/// The devices of the [SIMULATOR_DRIVERS](crate::indi_simulator::SIMULATOR_DRIVERS), and the
+/// `DRIVER_INTERFACE` each of them reports.
+pub static SIMULATOR_DEVICES: &[(&str, u32)] = &[
+    ("CCD Simulator", 6),
+    ("Focuser Simulator", 8),
+    ("Guide Simulator", 6),
+    ("Rotator Simulator", 4096),
+    ("Telescope Simulator", 5),
+    ("Filter Simulator", 16),
+];
+
+/// A [MockServer] that stands in for [IndiSimulator](crate::indi_simulator::IndiSimulator)
+/// when no INDI server is available.  It defines the [SIMULATOR_DEVICES], each with only its
+/// `CONNECTION` and `DRIVER_INFO` parameters.  Use [server](IndiMock::server) to define the
+/// parameters under test.
+pub struct IndiMock {
+    server: MockServer,
+}
+
+impl IndiMock {
+    /// Starts the mock server with the simulator devices defined.
+    pub async fn start() -> IndiMock {
+        let server = MockServer::new();
+        for (device, interface) in SIMULATOR_DEVICES {
+            server
+                .send(&format!(
+                    r#"<defSwitchVector device="{device}" name="CONNECTION" label="Connection" group="Main Control" state="Idle" perm="rw" rule="OneOfMany" timeout="60">
+    <defSwitch name="CONNECT" label="Connect">Off</defSwitch>
+    <defSwitch name="DISCONNECT" label="Disconnect">On</defSwitch>
+</defSwitchVector>
+<defTextVector device="{device}" name="DRIVER_INFO" label="Driver Info" group="General Info" state="Idle" perm="ro" timeout="60">
+    <defText name="DRIVER_NAME" label="Name">{device}</defText>
+    <defText name="DRIVER_INTERFACE" label="Interface">{interface}</defText>
+</defTextVector>
+"#
+                ))
+                .await;
+        }
+        IndiMock { server }
+    }
+
+    /// The mock server, for defining parameters and looking at the changes sent to it.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Returns a new [Client] tracking every device on the mock server.
+    pub fn client(&self) -> Result<Client, Error> {
+        Ok(self.server.connect()?)
+    }
+}
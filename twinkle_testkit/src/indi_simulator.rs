@@ -0,0 +1,78 @@
+use tokio::{net::TcpStream, process::Child};
+
+use crate::{wait_for_tcp, Error, DEFAULT_STARTUP_TIMEOUT};
+
+/// Address of the `indi` service defined in `docker-compose.yml`.
+pub static DEFAULT_INDI_ADDR: &str = "indi:7624";
+
+/// Environment variable used by [IndiSimulator::from_env] to override [DEFAULT_INDI_ADDR].
+pub static INDI_ADDR_ENV: &str = "INDI_SERVER";
+
+/// The simulator drivers loaded by the `indi` service in `docker-compose.yml`.
+pub static SIMULATOR_DRIVERS: &[&str] = &[
+    "indi_simulator_ccd",
+    "indi_simulator_focus",
+    "indi_simulator_guide",
+    "indi_simulator_rotator",
+    "indi_simulator_telescope",
+    "indi_simulator_wheel",
+];
+
+/// An INDI server with the simulator drivers loaded that is ready to accept connections.
+/// If the server was spawned by this struct it is killed when the struct is dropped.
+pub struct IndiSimulator {
+    addr: String,
+    _process: Option<Child>,
+}
+
+impl IndiSimulator {
+    /// Waits for an already running INDI server at `addr` to accept connections.
+    pub async fn connect(addr: impl Into<String>) -> Result<IndiSimulator, Error> {
+        let addr = addr.into();
+        wait_for_tcp(addr.as_str(), DEFAULT_STARTUP_TIMEOUT).await?;
+        Ok(IndiSimulator {
+            addr,
+            _process: None,
+        })
+    }
+
+    /// Waits for the INDI server named by the `INDI_SERVER` environment variable, falling
+    /// back to the docker-compose `indi` service.
+    pub async fn from_env() -> Result<IndiSimulator, Error> {
+        let addr = std::env::var(INDI_ADDR_ENV).unwrap_or_else(|_| String::from(DEFAULT_INDI_ADDR));
+        IndiSimulator::connect(addr).await
+    }
+
+    /// Spawns a local `indiserver` listening on `port` with all of the [SIMULATOR_DRIVERS]
+    /// loaded, and waits for it to accept connections.  `indiserver` must be on the `PATH`.
+    pub async fn spawn(port: u16) -> Result<IndiSimulator, Error> {
+        let process = tokio::process::Command::new("indiserver")
+            .arg("-p")
+            .arg(port.to_string())
+            .args(SIMULATOR_DRIVERS)
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let addr = format!("localhost:{}", port);
+        wait_for_tcp(addr.as_str(), DEFAULT_STARTUP_TIMEOUT).await?;
+        Ok(IndiSimulator {
+            addr,
+            _process: Some(process),
+        })
+    }
+
+    /// Address of the running INDI server.
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Opens a new tcp connection to the INDI server.
+    pub async fn connection(&self) -> Result<TcpStream, Error> {
+        Ok(TcpStream::connect(self.addr.as_str()).await?)
+    }
+
+    /// Returns a new [Client](indi::client::Client) tracking every device on the INDI server.
+    pub async fn client(&self) -> Result<indi::client::Client, Error> {
+        Ok(indi::client::new(self.connection().await?, None, None)?)
+    }
+}
@@ -0,0 +1,162 @@
+//! # Shared fixtures for integration tests in the twinkle workspace.
+//!
+//! Several crates in this workspace can only be meaningfully tested against a running
+//! INDI server or a running instance of phd2.  This crate collects the setup needed to
+//! get those running, wait until they are accepting connections, and hand back
+//! connected clients so individual tests don't need to repeat it.
+//!
+//! * [IndiSimulator](crate::indi_simulator::IndiSimulator) either attaches to an already
+//!   running INDI server (such as the `indi` service in `docker-compose.yml`) or spawns a local
+//!   `indiserver` loaded with the simulator drivers.
+//! * [IndiMock](crate::indi_mock::IndiMock) stands in for the INDI simulators with a
+//!   [MockServer](indi::testing::MockServer) when no INDI server is available.
+//! * [Phd2Simulator](crate::phd2_simulator::Phd2Simulator) spawns phd2, waits for its
+//!   EventMonitoring server and selects the built-in "Simulator" equipment profile.
+//! * [Phd2Mock](crate::phd2_mock::Phd2Mock) stands in for phd2 with a
+//!   [MockServer](phd2::testing::MockServer) when phd2 isn't installed.
+//!
+//! Both halves are behind the `indi` and `phd2` features (enabled by default) so crates only
+//! pull in what they need.
+//!
+//! #### Example
+//! ```no_run
+//! use twinkle_testkit::indi_simulator::IndiSimulator;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let indi = IndiSimulator::from_env().await.expect("Waiting for INDI server");
+//!     let client = indi.client().await.expect("Connecting to INDI server");
+//!     let camera = client
+//!         .get_device::<()>("CCD Simulator")
+//!         .await
+//!         .expect("Getting camera");
+//! }
+//! ```
+
+#[cfg(feature = "indi")]
+pub mod indi_mock;
+#[cfg(feature = "indi")]
+pub mod indi_simulator;
+#[cfg(feature = "phd2")]
+pub mod phd2_mock;
+#[cfg(feature = "phd2")]
+pub mod phd2_simulator;
+
+use std::time::Duration;
+
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+#[cfg(test)]
+mod tests;
+
+/// How long to wait for a simulator to start accepting connections before giving up.
+pub const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Errors encountered while starting or connecting to a simulator.
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    /// The simulator did not become ready in time.
+    Timeout,
+    /// phd2 does not have an equipment profile with the given name.
+    MissingProfile(String),
+    /// phd2 instance numbers start at 1.
+    InvalidInstance(u16),
+    /// The simulator exited while starting up.
+    Exited(std::process::ExitStatus),
+    #[cfg(feature = "indi")]
+    IndiError(indi::serialization::DeError),
+    #[cfg(feature = "phd2")]
+    Phd2Error(phd2::ClientError),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IoError(e) => write!(f, "io error: {}", e),
+            Error::Timeout => write!(f, "timed out waiting for simulator"),
+            Error::MissingProfile(name) => write!(f, "phd2 has no profile named {:?}", name),
+            Error::InvalidInstance(instance) => {
+                write!(
+                    f,
+                    "invalid phd2 instance {}, instances start at 1",
+                    instance
+                )
+            }
+            Error::Exited(status) => write!(f, "simulator exited: {}", status),
+            #[cfg(feature = "indi")]
+            Error::IndiError(e) => write!(f, "indi error: {}", e),
+            #[cfg(feature = "phd2")]
+            Error::Phd2Error(e) => write!(f, "phd2 error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IoError(e) => Some(e),
+            #[cfg(feature = "indi")]
+            Error::IndiError(e) => Some(e),
+            #[cfg(feature = "phd2")]
+            Error::Phd2Error(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::IoError(value)
+    }
+}
+
+impl From<tokio::time::error::Elapsed> for Error {
+    fn from(_: tokio::time::error::Elapsed) -> Self {
+        Error::Timeout
+    }
+}
+
+#[cfg(feature = "indi")]
+impl From<indi::serialization::DeError> for Error {
+    fn from(value: indi::serialization::DeError) -> Self {
+        Error::IndiError(value)
+    }
+}
+
+#[cfg(feature = "phd2")]
+impl From<phd2::ClientError> for Error {
+    fn from(value: phd2::ClientError) -> Self {
+        Error::Phd2Error(value)
+    }
+}
+
+#[cfg(feature = "phd2")]
+impl From<phd2::process::ProcessError> for Error {
+    fn from(value: phd2::process::ProcessError) -> Self {
+        match value {
+            phd2::process::ProcessError::IoError(e) => Error::IoError(e),
+            phd2::process::ProcessError::Timeout => Error::Timeout,
+            phd2::process::ProcessError::Exited(status) => Error::Exited(status),
+            phd2::process::ProcessError::ClientError(e) => Error::Phd2Error(e),
+        }
+    }
+}
+
+/// Repeatedly tries to open a tcp connection to `addr` until it succeeds or `timeout` elapses.
+/// Simulators take a moment to start listening after their process is spawned, this is used
+/// to wait for them to become ready.
+pub async fn wait_for_tcp<A: ToSocketAddrs + Clone>(
+    addr: A,
+    timeout: Duration,
+) -> Result<TcpStream, Error> {
+    Ok(tokio::time::timeout(timeout, async move {
+        loop {
+            match TcpStream::connect(addr.clone()).await {
+                Ok(connection) => break connection,
+                Err(_) => tokio::time::sleep(Duration::from_millis(250)).await,
+            }
+        }
+    })
+    .await?)
+}
@@ -0,0 +1,94 @@
+use std::sync::{Arc, Mutex};
+
+use phd2::{serialization::ServerEvent, testing::MockServer, Phd2Connection};
+use serde_json::json;
+use tokio::{net::TcpStream, sync::mpsc::Receiver};
+
+use crate::{
+    phd2_simulator::{select_simulator_profile, SIMULATOR_PROFILE},
+    Error,
+};
+
+/// A [MockServer] that stands in for [Phd2Simulator](crate::phd2_simulator::Phd2Simulator)
+/// when phd2 isn't installed.  It answers the calls made while selecting the
+/// [SIMULATOR_PROFILE] and checking the equipment the way phd2 does after a fresh start.
+/// Use [server](Phd2Mock::server) to add responses for the calls under test.
+pub struct Phd2Mock {
+    server: MockServer,
+}
+
+impl Phd2Mock {
+    /// Starts the mock server on a free local port.
+    pub async fn start() -> Result<Phd2Mock, Error> {
+        let server = MockServer::bind().await?;
+        server.on_connect(vec![json!({
+            "Event": "Version",
+            "PHDVersion": "2.6.11",
+            "PHDSubver": "",
+            "OverlapSupport": true,
+            "MsgVersion": 1,
+        })]);
+        server.respond("stop_capture", json!(0));
+        server.respond("get_app_state", json!("Stopped"));
+
+        // phd2 starts with its last profile selected and the equipment disconnected.
+        let profiles = [(1, "Default"), (2, SIMULATOR_PROFILE)];
+        server.respond(
+            "get_profiles",
+            json!(profiles.map(|(id, name)| json!({"id": id, "name": name}))),
+        );
+        let profile = Arc::new(Mutex::new(1));
+        let connected = Arc::new(Mutex::new(false));
+        server.respond_with("get_profile", {
+            let profile = profile.clone();
+            move |_| {
+                let id = *profile.lock().unwrap();
+                let (id, name) = profiles[id as usize - 1];
+                Ok(json!({"id": id, "name": name}))
+            }
+        });
+        server.respond_with("set_profile", {
+            let profile = profile.clone();
+            move |params| {
+                *profile.lock().unwrap() = params[0].as_i64().unwrap_or(1).clamp(1, 2);
+                Ok(json!(0))
+            }
+        });
+        server.respond_with("get_connected", {
+            let connected = connected.clone();
+            move |_| Ok(json!(*connected.lock().unwrap()))
+        });
+        server.respond_with("set_connected", move |params| {
+            *connected.lock().unwrap() = params[0].as_bool().unwrap_or_default();
+            Ok(json!(0))
+        });
+        Ok(Phd2Mock { server })
+    }
+
+    /// The mock server, for adding responses and sending events.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Port the mock server is listening on.
+    pub fn port(&self) -> u16 {
+        self.server.addr().port()
+    }
+
+    /// Opens a new connection to the mock server.
+    pub async fn connect(
+        &self,
+    ) -> Result<(Phd2Connection<TcpStream>, Receiver<ServerEvent>), Error> {
+        Ok(self.server.connect().await?)
+    }
+
+    /// Opens a new connection and selects the [SIMULATOR_PROFILE], making the same calls as
+    /// [Phd2Simulator::connect_simulator](crate::phd2_simulator::Phd2Simulator::connect_simulator).
+    pub async fn connect_simulator(
+        &self,
+    ) -> Result<(Phd2Connection<TcpStream>, Receiver<ServerEvent>), Error> {
+        let (phd2, events) = self.connect().await?;
+        select_simulator_profile(&phd2).await?;
+        Ok((phd2, events))
+    }
+}
@@ -0,0 +1,77 @@
+use phd2::{
+    process::{Phd2Process, ProcessOptions},
+    serialization::ServerEvent,
+    ClientError, Phd2Connection,
+};
+use tokio::{net::TcpStream, sync::mpsc::Receiver};
+
+use crate::{Error, DEFAULT_STARTUP_TIMEOUT};
+
+/// Name of the equipment profile phd2 ships with that uses simulated devices.
+pub static SIMULATOR_PROFILE: &str = "Simulator";
+
+/// A phd2 process that is accepting EventMonitoring connections.  The process is killed
+/// when the struct is dropped, use [Phd2Simulator::shutdown] to stop it cleanly.
+pub struct Phd2Simulator {
+    process: Phd2Process,
+}
+
+impl Phd2Simulator {
+    /// Spawns phd2 as instance `1` and waits for it to accept connections.  `phd2` must be
+    /// on the `PATH`.
+    pub async fn spawn() -> Result<Phd2Simulator, Error> {
+        Phd2Simulator::spawn_instance(1).await
+    }
+
+    /// Spawns phd2 with the given instance number and waits for it to accept connections.
+    /// phd2 listens on port `4400 + instance - 1`, so distinct instances can run side by side.
+    /// Instance numbers start at 1.
+    pub async fn spawn_instance(instance: u16) -> Result<Phd2Simulator, Error> {
+        if instance == 0 {
+            return Err(Error::InvalidInstance(instance));
+        }
+        let options = ProcessOptions::new()
+            .instance(instance)
+            .startup_timeout(DEFAULT_STARTUP_TIMEOUT);
+        let process = Phd2Process::spawn(&options).await?;
+        Ok(Phd2Simulator { process })
+    }
+
+    /// Port phd2 is listening on.
+    pub fn port(&self) -> u16 {
+        self.process.port()
+    }
+
+    /// Opens a new connection to phd2.
+    pub async fn connect(
+        &self,
+    ) -> Result<(Phd2Connection<TcpStream>, Receiver<ServerEvent>), Error> {
+        Ok(self.process.connect().await?)
+    }
+
+    /// Opens a new connection to phd2, selects the [SIMULATOR_PROFILE] and connects its
+    /// equipment.  Guiding is stopped first since the profile can't be changed while capturing.
+    pub async fn connect_simulator(
+        &self,
+    ) -> Result<(Phd2Connection<TcpStream>, Receiver<ServerEvent>), Error> {
+        let (phd2, events) = self.connect().await?;
+        select_simulator_profile(&phd2).await?;
+        Ok((phd2, events))
+    }
+
+    /// Asks phd2 to shut down and waits up to 5 seconds for it to exit before killing it.
+    pub async fn shutdown(self) -> Result<std::process::ExitStatus, Error> {
+        Ok(self.process.shutdown().await?)
+    }
+}
+
+/// Selects the [SIMULATOR_PROFILE] and connects its equipment.
+pub(crate) async fn select_simulator_profile(
+    phd2: &Phd2Connection<TcpStream>,
+) -> Result<(), Error> {
+    match phd2.ensure_profile_connected(SIMULATOR_PROFILE).await {
+        Ok(_) => Ok(()),
+        Err(ClientError::ProfileNotFound(name)) => Err(Error::MissingProfile(name)),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,71 @@
+use super::*;
+
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn test_wait_for_tcp_connects() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    wait_for_tcp(addr, Duration::from_secs(1))
+        .await
+        .expect("Connecting to listener");
+}
+
+#[tokio::test]
+async fn test_wait_for_tcp_timeout() {
+    let addr = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    };
+
+    let result = wait_for_tcp(addr, Duration::from_millis(500)).await;
+    assert!(matches!(result, Err(Error::Timeout)));
+}
+
+#[cfg(feature = "phd2")]
+#[tokio::test]
+async fn test_spawn_instance_zero() {
+    let result = phd2_simulator::Phd2Simulator::spawn_instance(0).await;
+    assert!(matches!(result, Err(Error::InvalidInstance(0))));
+}
+
+#[cfg(feature = "phd2")]
+#[tokio::test]
+async fn test_phd2_mock_connect_simulator() {
+    let mock = phd2_mock::Phd2Mock::start().await.unwrap();
+    let (phd2, mut events) = mock.connect_simulator().await.unwrap();
+
+    assert!(events.recv().await.is_some());
+    assert_eq!(
+        phd2.get_profile().await.unwrap().name,
+        phd2_simulator::SIMULATOR_PROFILE
+    );
+    let set_profile = mock
+        .server()
+        .requests()
+        .into_iter()
+        .find(|request| request["method"] == "set_profile")
+        .unwrap();
+    assert_eq!(set_profile["params"], serde_json::json!([2]));
+}
+
+#[cfg(feature = "indi")]
+#[tokio::test]
+async fn test_indi_mock_simulator_devices() {
+    let mock = indi_mock::IndiMock::start().await;
+    let client = mock.client().unwrap();
+    // The server sends the devices sorted by name, so the others are defined by now.
+    client
+        .get_device::<()>("Telescope Simulator")
+        .await
+        .unwrap()
+        .get_parameter("DRIVER_INFO")
+        .await
+        .unwrap();
+    let mounts = client
+        .devices_with(indi::client::device::DeviceInterfaces::TELESCOPE)
+        .await;
+    let names: Vec<&str> = mounts.iter().map(|mount| mount.name()).collect();
+    assert_eq!(names, vec!["Telescope Simulator"]);
+}